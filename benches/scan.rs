@@ -0,0 +1,61 @@
+// Compares the parser's line-end search against a plain iterator search. The
+// `active` entries are what the parser calls: the portable SWAR code by default,
+// memchr's SIMD routines with `cargo bench --features memchr`.
+
+use std::hint::black_box;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rawhttp::http::scan;
+
+// A request head with header lines of typical length.
+fn head() -> Vec<u8> {
+    let mut head = b"GET /api/items?page=2&sort=name HTTP/1.1\r\nHost: example.com\r\n".to_vec();
+    for i in 0..12 {
+        head.extend_from_slice(format!("X-Header-{}: {}\r\n", i, "v".repeat(80)).as_bytes());
+    }
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+fn find_byte(c: &mut Criterion) {
+    let head = head();
+    let mut long = vec![b'a'; 8192];
+    long.push(b'\n');
+    // One header line's worth, and a line as long as the default head limit.
+    for (name, data) in [("line", &head[head.len() / 2..]), ("8k", &long[..])] {
+        let len = scan::find_byte(b'\n', data).unwrap() + 1;
+        let mut group = c.benchmark_group(format!("find_byte/{}", name));
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_function("naive", |b| {
+            b.iter(|| black_box(data).iter().position(|&b| b == b'\n'))
+        });
+        group.bench_function("portable", |b| {
+            b.iter(|| scan::find_byte_portable(b'\n', black_box(data)))
+        });
+        group.bench_function("active", |b| {
+            b.iter(|| scan::find_byte(b'\n', black_box(data)))
+        });
+        group.finish();
+    }
+}
+
+fn split_head(c: &mut Criterion) {
+    let head = head();
+    let mut group = c.benchmark_group("split_head");
+    group.throughput(Throughput::Bytes(head.len() as u64));
+    group.bench_function("active", |b| {
+        b.iter(|| {
+            let mut rest = black_box(&head[..]);
+            let mut lines = 0;
+            while let Some(i) = scan::find_crlf(rest) {
+                rest = &rest[i + 2..];
+                lines += 1;
+            }
+            lines
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, find_byte, split_head);
+criterion_main!(benches);
@@ -0,0 +1,508 @@
+use std::{
+    future::Future,
+    io::{self, Cursor},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream, tcp::OwnedWriteHalf},
+    sync::Notify,
+    time::timeout,
+};
+
+use crate::http::{
+    ConnectionContext, ConnectionInfo, ParseError, ParseOptions, Request, Response, ServerTiming,
+    request::{overlong_request_line, request_from_buf_reader_in},
+};
+use crate::logging::{log_debug, log_error, log_info};
+use crate::server::{
+    ServerConfig, bad_request, finalize_response, handler_panicked, wants_keep_alive,
+};
+
+const MAX_CHUNK_LINE: usize = 1024;
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+const DRAIN_LIMIT: usize = 64 * 1024; // 64KB
+const DRAIN_DEADLINE: Duration = Duration::from_secs(2);
+const LINGER_QUIET: Duration = Duration::from_millis(100);
+
+// The async counterpart of Handler. Implementations can be written as
+// `async fn handle(&self, request: &Request) -> Response`.
+pub trait AsyncHandler: Send + Sync + 'static {
+    fn handle(&self, request: &Request) -> impl Future<Output = Response> + Send;
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        bad_request(e)
+    }
+}
+
+// Polls `future` to completion, catching a panic from any poll so the server can
+// still answer with a 500.
+async fn catch_panic<F: Future>(future: F) -> std::thread::Result<F::Output> {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx| {
+        match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(payload)),
+        }
+    })
+    .await
+}
+
+enum Framing {
+    Length(usize),
+    Chunked,
+}
+
+async fn read_line_limited<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> Result<usize, ParseError> {
+    let mut read = 0;
+    loop {
+        let available = reader.fill_buf().await?;
+        if available.is_empty() {
+            return Ok(read);
+        }
+
+        let (done, used) = match available.iter().position(|&b| b == b'\n') {
+            Some(i) => (true, i + 1),
+            None => (false, available.len()),
+        };
+        if read + used > max {
+            return Err(ParseError::HeaderTooLarge);
+        }
+
+        buf.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        read += used;
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+// Collects the request head, blank line included, and works out how the body is
+// framed. Validation is left to the sync parser that runs over the collected bytes.
+async fn read_head<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    raw: &mut Vec<u8>,
+    options: &ParseOptions,
+) -> Result<Framing, ParseError> {
+    loop {
+        let line_start = raw.len();
+        let remaining = options.limits.max_header_bytes.saturating_sub(line_start);
+        if line_start == 0 && options.limits.max_request_line < remaining {
+            let read = read_line_limited(reader, raw, options.limits.max_request_line).await;
+            if let Err(ParseError::HeaderTooLarge) = read {
+                let pending = reader.fill_buf().await.unwrap_or_default();
+                return Err(overlong_request_line(raw, pending, &options.limits));
+            }
+            if read? == 0 {
+                break;
+            }
+        } else if read_line_limited(reader, raw, remaining).await? == 0 {
+            break;
+        }
+        let line = &raw[line_start..];
+        if line == b"\r\n" || line == b"\n" {
+            break;
+        }
+    }
+
+    let head = String::from_utf8_lossy(raw).to_lowercase();
+    let header = |name: &str| {
+        head.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(|value| value.trim().to_string())
+    };
+    if header("transfer-encoding:").is_some_and(|te| te.contains("chunked")) {
+        return Ok(Framing::Chunked);
+    }
+    let length = header("content-length:")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    Ok(Framing::Length(length))
+}
+
+// Copies the body as it appears on the wire, chunk framing and trailers included.
+async fn read_body<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    raw: &mut Vec<u8>,
+    framing: Framing,
+    options: &ParseOptions,
+) -> Result<(), ParseError> {
+    let within_limit = |size: usize| match options.limits.max_body_bytes {
+        Some(limit) if size > limit => Err(ParseError::BodyTooLarge { limit }),
+        _ => Ok(()),
+    };
+    // Counts payload only, so the check matches the sync parser's.
+    let mut payload = 0usize;
+
+    match framing {
+        Framing::Length(length) => {
+            within_limit(length)?;
+            let start = raw.len();
+            raw.resize(start + length, 0);
+            reader.read_exact(&mut raw[start..]).await?;
+        }
+        Framing::Chunked => loop {
+            let line_start = raw.len();
+            if read_line_limited(reader, raw, MAX_CHUNK_LINE).await? == 0 {
+                return Err(ParseError::IncompleteRequest);
+            }
+            let line = String::from_utf8_lossy(&raw[line_start..]);
+            let size = line.trim().split(';').next().unwrap_or("");
+            if size.is_empty() {
+                continue;
+            }
+            let size =
+                usize::from_str_radix(size, 16).map_err(|_| ParseError::InvalidChunkFormat)?;
+            payload = payload.saturating_add(size);
+            within_limit(payload)?;
+
+            if size == 0 {
+                loop {
+                    let trailer_start = raw.len();
+                    let n = read_line_limited(reader, raw, MAX_CHUNK_LINE).await?;
+                    let trailer = &raw[trailer_start..];
+                    if n == 0 || trailer == b"\r\n" || trailer == b"\n" {
+                        return Ok(());
+                    }
+                }
+            }
+
+            let start = raw.len();
+            raw.resize(start + size, 0);
+            reader.read_exact(&mut raw[start..]).await?;
+            read_line_limited(reader, raw, 2).await?;
+        },
+    }
+    Ok(())
+}
+
+fn parse_collected(
+    raw: Vec<u8>,
+    options: &ParseOptions,
+    context: &mut ConnectionContext,
+) -> Result<Request, ParseError> {
+    request_from_buf_reader_in(&mut Cursor::new(raw), options, context)
+}
+
+// Async variant of request_from_buf_reader_in. The bytes of one request are
+// gathered without blocking, then parsed by the same code as the sync server.
+pub async fn request_from_async_reader<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    options: &ParseOptions,
+    context: &mut ConnectionContext,
+) -> Result<Request, ParseError> {
+    let mut raw = Vec::new();
+    let framing = read_head(reader, &mut raw, options).await?;
+    read_body(reader, &mut raw, framing, options).await?;
+    parse_collected(raw, options, context)
+}
+
+pub struct AsyncServer<H: AsyncHandler> {
+    addr: String,
+    handler: Arc<H>,
+    config: Arc<ServerConfig>,
+    closed: Arc<AtomicBool>,
+    wake: Arc<Notify>,
+    active: Arc<AtomicUsize>,
+}
+
+impl<H: AsyncHandler> AsyncServer<H> {
+    pub fn new(addr: String, handler: H) -> Self {
+        AsyncServer {
+            addr,
+            handler: Arc::new(handler),
+            config: Arc::new(ServerConfig::default()),
+            closed: Arc::new(AtomicBool::new(false)),
+            wake: Arc::new(Notify::new()),
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.config).shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn active_connections(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    // Stops accepting; run() returns once in-flight connections finish or the
+    // shutdown timeout passes.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.wake.notify_one();
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        let listener = TcpListener::bind(&self.addr)
+            .await
+            .context(format!("Failed to bind the address: {}", self.addr))?;
+
+        log_info!("Server listening on {}", self.addr);
+
+        while !self.closed.load(Ordering::SeqCst) {
+            let accepted = tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = self.wake.notified() => continue,
+            };
+
+            match accepted {
+                Ok((stream, _)) => {
+                    let handler = self.handler.clone();
+                    let closed = self.closed.clone();
+                    let active = self.active.clone();
+                    let config = self.config.clone();
+                    active.fetch_add(1, Ordering::SeqCst);
+                    if let Some(metrics) = &config.metrics {
+                        metrics.connection_opened();
+                    }
+                    #[cfg(feature = "tracing")]
+                    let span = tracing::info_span!(
+                        "connection",
+                        peer = stream.peer_addr().ok().map(tracing::field::display)
+                    );
+                    let connection = async move {
+                        if let Err(e) = serve_connection(stream, handler, &config, &closed).await {
+                            match &config.on_error {
+                                Some(hook) => hook(&e.into()),
+                                None => log_error!("Error handling connection: {}", e),
+                            }
+                        }
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        if let Some(metrics) = &config.metrics {
+                            metrics.connection_closed();
+                        }
+                    };
+                    #[cfg(feature = "tracing")]
+                    let connection = tracing::Instrument::instrument(connection, span);
+                    tokio::spawn(connection);
+                }
+                Err(e) => log_error!("Error accepting connection: {}", e),
+            }
+        }
+
+        drop(listener);
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        while self.active_connections() > 0 && Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
+}
+
+async fn send(
+    response: &Response,
+    writer: &mut OwnedWriteHalf,
+    config: &ServerConfig,
+) -> io::Result<()> {
+    match timeout(config.write_timeout, response.send_async(writer)).await {
+        Ok(result) => result,
+        Err(_) => Err(io::ErrorKind::TimedOut.into()),
+    }
+}
+
+// Waits for the first byte of the next request, in slices so a shutdown is noticed.
+async fn await_request<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    wait: Duration,
+    closed: &AtomicBool,
+) -> io::Result<Option<bool>> {
+    let deadline = Instant::now() + wait;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        if closed.load(Ordering::SeqCst) {
+            return Ok(Some(false));
+        }
+        if let Ok(available) = timeout(remaining.min(SHUTDOWN_POLL), reader.fill_buf()).await {
+            return Ok(Some(!available?.is_empty()));
+        }
+    }
+}
+
+async fn serve_connection<H: AsyncHandler>(
+    stream: TcpStream,
+    handler: Arc<H>,
+    config: &ServerConfig,
+    closed: &AtomicBool,
+) -> io::Result<()> {
+    let info = ConnectionInfo {
+        peer: stream.peer_addr().ok(),
+        local: stream.local_addr().ok(),
+        ..ConnectionInfo::default()
+    };
+    let (read, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(read);
+    let mut context = ConnectionContext::new().with_info(info);
+    let keep_alive = config.keep_alive;
+    let options = config.parse_options();
+
+    loop {
+        let first = context.requests() == 0;
+        let wait = if first {
+            config.header_read_timeout
+        } else {
+            keep_alive.idle_timeout
+        };
+        match await_request(&mut reader, wait, closed).await? {
+            Some(true) => {}
+            Some(false) => return Ok(()),
+            // Only a client that never started its first request is owed a 408.
+            None if first => {
+                let response = handler.handle_bad_request(&ParseError::HeaderTimeout);
+                send(&response.close(), &mut writer, config).await?;
+                linger(&mut reader, &mut writer, false).await;
+                return Ok(());
+            }
+            None => return Ok(()),
+        }
+
+        let started = Instant::now();
+        let mut raw = Vec::new();
+        let parsed = match timeout(
+            config.header_read_timeout,
+            read_head(&mut reader, &mut raw, &options),
+        )
+        .await
+        {
+            Err(_) => Err(ParseError::HeaderTimeout),
+            Ok(Err(e)) => Err(e),
+            Ok(Ok(framing)) => {
+                match timeout(
+                    config.body_read_timeout,
+                    read_body(&mut reader, &mut raw, framing, &options),
+                )
+                .await
+                {
+                    Err(_) => Err(ParseError::BodyTimeout),
+                    Ok(Err(e)) => Err(e),
+                    Ok(Ok(())) => parse_collected(raw, &options, &mut context),
+                }
+            }
+        };
+
+        let may_keep_alive =
+            context.requests() < keep_alive.max_requests as u64 && !closed.load(Ordering::SeqCst);
+        let (response, request) = match parsed {
+            Ok(mut request) => {
+                request.extensions_mut().insert(ServerTiming::new());
+                #[cfg(feature = "otel")]
+                {
+                    let context = crate::otel::TraceContext::for_request(&request);
+                    request.extensions_mut().insert(context);
+                }
+                #[cfg(feature = "tracing")]
+                let span = tracing::info_span!(
+                    "request",
+                    method = request.method().as_str(),
+                    target = request.target()
+                );
+                #[cfg(feature = "tracing")]
+                let _entered = span.enter();
+                if !config.logs_requests() {
+                    log_info!(
+                        "{:?} {} {}",
+                        request.method(),
+                        request.target(),
+                        request.http_version()
+                    );
+                }
+                log_debug!("Request headers: {:?}", request.headers);
+                #[cfg(feature = "tracing")]
+                drop(_entered);
+                let response =
+                    match panic::catch_unwind(AssertUnwindSafe(|| handler.handle(&request))) {
+                        Ok(response) => {
+                            #[cfg(feature = "tracing")]
+                            let response = tracing::Instrument::instrument(response, span);
+                            catch_panic(response).await.unwrap_or_else(|payload| {
+                                handler_panicked(&request, payload.as_ref())
+                            })
+                        }
+                        Err(payload) => handler_panicked(&request, payload.as_ref()),
+                    };
+                let response = match request.server_timing().and_then(ServerTiming::header_value) {
+                    Some(value) => response.with_header("Server-Timing", value),
+                    None => response,
+                };
+                (response, Some(request))
+            }
+            Err(e) => (handler.handle_bad_request(&e), None),
+        };
+
+        let keep = may_keep_alive && request.as_ref().is_some_and(wants_keep_alive);
+        let (mut response, keep) =
+            finalize_response(response, request.as_ref(), config.length_mismatch, keep);
+        if response.take_takeover().is_some() {
+            log_error!("Connection takeover is not supported by the async server");
+            response = Response::internal_server_error().close();
+        }
+        if response.take_duplex().is_some() {
+            log_error!("Duplex bodies are not supported by the async server");
+            response = Response::internal_server_error().close();
+        }
+        if let Some(request) = &request {
+            config.observe(request, &response, started.elapsed());
+        }
+
+        send(&response, &mut writer, config).await?;
+        if response.abort.is_some() {
+            return Ok(());
+        }
+        if keep {
+            continue;
+        }
+
+        linger(&mut reader, &mut writer, request.is_none()).await;
+        return Ok(());
+    }
+}
+
+// As in the sync server: shut the write side so the client knows the response is
+// complete, then swallow what it still sends so closing does not reset the
+// connection under the response.
+async fn linger<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    writer: &mut OwnedWriteHalf,
+    unread_input: bool,
+) {
+    if writer.shutdown().await.is_err() {
+        return;
+    }
+    let quiet = if unread_input {
+        DRAIN_DEADLINE
+    } else {
+        LINGER_QUIET
+    };
+    let mut sink = vec![0; 4096];
+    let mut total = 0;
+    let deadline = Instant::now() + DRAIN_DEADLINE;
+    while total < DRAIN_LIMIT {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match timeout(remaining.min(quiet), reader.read(&mut sink)).await {
+            Ok(Ok(n)) if n > 0 => total += n,
+            _ => break,
+        }
+    }
+}
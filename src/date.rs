@@ -0,0 +1,217 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+    pub millis: u32,
+}
+
+impl DateTime {
+    pub fn from_unix_millis(millis: i64) -> Self {
+        let secs = millis.div_euclid(1000);
+        let days = secs.div_euclid(86_400);
+        let rem = secs.rem_euclid(86_400);
+
+        // Howard Hinnant's civil_from_days.
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z.rem_euclid(146_097);
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+        DateTime {
+            year,
+            month,
+            day,
+            hour: (rem / 3600) as u32,
+            minute: (rem % 3600 / 60) as u32,
+            second: (rem % 60) as u32,
+            millis: millis.rem_euclid(1000) as u32,
+        }
+    }
+
+    pub fn to_unix_millis(&self) -> i64 {
+        // Howard Hinnant's days_from_civil.
+        let year = if self.month <= 2 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = (self.month as i64 + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day as i64 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        let days = era * 146_097 + doe - 719_468;
+        let secs = days * 86_400 + self.hour as i64 * 3600 + self.minute as i64 * 60;
+        (secs + self.second as i64) * 1000 + self.millis as i64
+    }
+
+    // Accepts the three HTTP-date formats recipients must understand (RFC 9110
+    // 5.6.7): IMF-fixdate, the obsolete RFC 850 form and asctime.
+    pub fn parse_http_date(value: &str) -> Option<Self> {
+        let fields: Vec<&str> = value.split_whitespace().collect();
+        let (day, month, year, time) = match fields.as_slice() {
+            [_, day, month, year, time, "GMT"] => (*day, *month, year.parse().ok()?, *time),
+            [_, date, time, "GMT"] => {
+                let mut parts = date.split('-');
+                let (day, month) = (parts.next()?, parts.next()?);
+                let year: i64 = parts.next()?.parse().ok()?;
+                // Two-digit years more than 50 years ahead are in the past.
+                let year = if year < 70 { 2000 + year } else { 1900 + year };
+                (day, month, year, *time)
+            }
+            [_, month, day, time, year] => (*day, *month, year.parse().ok()?, *time),
+            _ => return None,
+        };
+        let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+        let day: u32 = day.parse().ok()?;
+        let mut clock = time.split(':').map(|n| n.parse::<u32>().ok());
+        let (hour, minute, second) = (clock.next()??, clock.next()??, clock.next()??);
+        if clock.next().is_some()
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return None;
+        }
+        Some(DateTime {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            millis: 0,
+        })
+    }
+
+    pub fn from_system_time(time: SystemTime) -> Self {
+        let millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_millis() as i64,
+            Err(e) => -(e.duration().as_millis() as i64),
+        };
+        Self::from_unix_millis(millis)
+    }
+
+    pub fn now() -> Self {
+        Self::from_system_time(SystemTime::now())
+    }
+
+    pub fn to_rfc3339(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+            self.year, self.month, self.day, self.hour, self.minute, self.second, self.millis
+        )
+    }
+
+    // The Common Log Format timestamp, e.g. 10/Oct/2000:13:55:36 +0000.
+    pub fn to_clf(&self) -> String {
+        format!(
+            "{:02}/{}/{:04}:{:02}:{:02}:{:02} +0000",
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    // The IMF-fixdate form of HTTP dates, e.g. Sun, 06 Nov 1994 08:49:37 GMT.
+    pub fn to_http_date(&self) -> String {
+        const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        // Sakamoto's day of the week.
+        const OFFSETS: [i64; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+        let year = if self.month < 3 {
+            self.year - 1
+        } else {
+            self.year
+        };
+        let weekday = (year + year.div_euclid(4) - year.div_euclid(100)
+            + year.div_euclid(400)
+            + OFFSETS[self.month as usize - 1]
+            + self.day as i64)
+            .rem_euclid(7);
+        format!(
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+            DAYS[weekday as usize],
+            self.day,
+            MONTHS[self.month as usize - 1],
+            self.year,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch() {
+        let dt = DateTime::from_unix_millis(0);
+        assert_eq!(dt.to_rfc3339(), "1970-01-01T00:00:00.000Z");
+    }
+
+    #[test]
+    fn test_known_dates() {
+        assert_eq!(
+            DateTime::from_unix_millis(951_782_400_000).to_rfc3339(),
+            "2000-02-29T00:00:00.000Z"
+        );
+        assert_eq!(
+            DateTime::from_unix_millis(1_700_000_000_123).to_rfc3339(),
+            "2023-11-14T22:13:20.123Z"
+        );
+        assert_eq!(
+            DateTime::from_unix_millis(1_700_000_000_123).to_clf(),
+            "14/Nov/2023:22:13:20 +0000"
+        );
+        assert_eq!(
+            DateTime::from_unix_millis(1_700_000_000_123).to_http_date(),
+            "Tue, 14 Nov 2023 22:13:20 GMT"
+        );
+        assert_eq!(
+            DateTime::from_unix_millis(784_111_777_000).to_http_date(),
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+        assert_eq!(
+            DateTime::from_unix_millis(1_700_000_000_123).to_unix_millis(),
+            1_700_000_000_123
+        );
+    }
+
+    #[test]
+    fn test_parses_http_dates() {
+        for value in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            let date = DateTime::parse_http_date(value).unwrap();
+            assert_eq!(date.to_unix_millis(), 784_111_777_000, "{}", value);
+        }
+        assert_eq!(
+            DateTime::parse_http_date("Sun, 06 Nov 1994 25:00:00 GMT"),
+            None
+        );
+        assert_eq!(DateTime::parse_http_date("yesterday"), None);
+    }
+}
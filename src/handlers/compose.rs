@@ -0,0 +1,223 @@
+#[cfg(feature = "arena")]
+use crate::http::{ArenaRequest, with_arena};
+use crate::{
+    http::{ParseError, Request, Response, StatusCode},
+    server::Handler,
+};
+
+// Builds a handler from a closure, for composing small apps inline.
+pub fn handler_fn<F>(f: F) -> FnHandler<F>
+where
+    F: Fn(&Request) -> Response + Send + Sync,
+{
+    FnHandler(f)
+}
+
+pub struct FnHandler<F>(F);
+
+impl<F> Handler for FnHandler<F>
+where
+    F: Fn(&Request) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Response {
+        (self.0)(request)
+    }
+}
+
+// Like handler_fn, but the closure sees the request through the worker thread's
+// arena, which is emptied as soon as the response is built.
+#[cfg(feature = "arena")]
+pub fn arena_fn<F>(f: F) -> ArenaFn<F>
+where
+    F: Fn(&ArenaRequest<'_>) -> Response + Send + Sync,
+{
+    ArenaFn(f)
+}
+
+#[cfg(feature = "arena")]
+pub struct ArenaFn<F>(F);
+
+#[cfg(feature = "arena")]
+impl<F> Handler for ArenaFn<F>
+where
+    F: Fn(&ArenaRequest<'_>) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Response {
+        with_arena(request, |bound| (self.0)(bound)).unwrap_or_else(|e| {
+            Response::problem(
+                StatusCode::BadRequest,
+                "Bad Request",
+                &e.to_string(),
+                "about:blank",
+            )
+        })
+    }
+}
+
+// Combinators available on every handler. A 404 means "not mine": guard produces
+// one when its predicate fails, and or moves on to the next handler when it sees
+// one, so guarded handlers chained with or behave like a tiny router.
+pub trait HandlerExt: Handler + Sized {
+    // Tries `next` when this handler answers 404.
+    fn or<B: Handler>(self, next: B) -> Or<Self, B> {
+        Or { first: self, next }
+    }
+
+    fn map_response<F>(self, f: F) -> MapResponse<Self, F>
+    where
+        F: Fn(Response) -> Response + Send + Sync,
+    {
+        MapResponse { inner: self, f }
+    }
+
+    // Runs `f` first; a response from it is sent instead of calling this handler.
+    fn before<F>(self, f: F) -> Before<Self, F>
+    where
+        F: Fn(&Request) -> Option<Response> + Send + Sync,
+    {
+        Before { inner: self, f }
+    }
+
+    // Only lets requests matching `predicate` through; others get 404.
+    fn guard<P>(self, predicate: P) -> Guard<Self, P>
+    where
+        P: Fn(&Request) -> bool + Send + Sync,
+    {
+        Guard {
+            inner: self,
+            predicate,
+        }
+    }
+}
+
+impl<H: Handler> HandlerExt for H {}
+
+pub struct Or<A, B> {
+    first: A,
+    next: B,
+}
+
+impl<A: Handler, B: Handler> Handler for Or<A, B> {
+    fn handle(&self, request: &Request) -> Response {
+        let response = self.first.handle(request);
+        if response.status_code() == StatusCode::NotFound {
+            return self.next.handle(request);
+        }
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.first.handle_bad_request(e)
+    }
+}
+
+pub struct MapResponse<H, F> {
+    inner: H,
+    f: F,
+}
+
+impl<H, F> Handler for MapResponse<H, F>
+where
+    H: Handler,
+    F: Fn(Response) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Response {
+        (self.f)(self.inner.handle(request))
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        (self.f)(self.inner.handle_bad_request(e))
+    }
+}
+
+pub struct Before<H, F> {
+    inner: H,
+    f: F,
+}
+
+impl<H, F> Handler for Before<H, F>
+where
+    H: Handler,
+    F: Fn(&Request) -> Option<Response> + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Response {
+        (self.f)(request).unwrap_or_else(|| self.inner.handle(request))
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+pub struct Guard<H, P> {
+    inner: H,
+    predicate: P,
+}
+
+impl<H, P> Handler for Guard<H, P>
+where
+    H: Handler,
+    P: Fn(&Request) -> bool + Send + Sync,
+{
+    fn handle(&self, request: &Request) -> Response {
+        if (self.predicate)(request) {
+            self.inner.handle(request)
+        } else {
+            Response::not_found()
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Body, Method};
+
+    fn request(method: &str, path: &str) -> Request {
+        let raw = format!("{} {} HTTP/1.1\r\nHost: x\r\n\r\n", method, path);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    fn text(body: &'static str) -> impl Fn(&Request) -> Response + Send + Sync {
+        move |_| Response::ok().with_body(Body::from(body))
+    }
+
+    #[test]
+    fn test_guarded_handlers_chain_like_routes() {
+        let app = handler_fn(text("users"))
+            .guard(|r| r.path() == "/users")
+            .or(handler_fn(text("posts")).guard(|r| r.path().starts_with("/posts")))
+            .or(handler_fn(|_| {
+                Response::not_found().with_body(Body::from("nothing here"))
+            }));
+
+        let body = |path| {
+            app.handle(&request("GET", path))
+                .body()
+                .as_str()
+                .map(String::from)
+        };
+        assert_eq!(body("/users"), Ok("users".to_string()));
+        assert_eq!(body("/posts/1"), Ok("posts".to_string()));
+        assert_eq!(body("/other"), Ok("nothing here".to_string()));
+    }
+
+    #[test]
+    fn test_before_short_circuits_and_map_response_decorates() {
+        let app = handler_fn(text("secret"))
+            .before(|r| (r.method() != &Method::GET).then(Response::method_not_allowed))
+            .map_response(|response| response.with_header("X-App", "demo"));
+
+        let response = app.handle(&request("GET", "/"));
+        assert_eq!(response.body().as_str(), Ok("secret"));
+        assert_eq!(response.headers().get("x-app"), Some("demo"));
+
+        let response = app.handle(&request("POST", "/"));
+        assert_eq!(response.status_code(), StatusCode::MethodNotAllowed);
+        assert_eq!(response.headers().get("x-app"), Some("demo"));
+    }
+}
@@ -0,0 +1,302 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::http::{Method, Request};
+
+// Ready-made predicates for HandlerExt::guard. A guarded handler answers 404 when
+// its predicate fails, so chaining guarded handlers with `or` falls through to the
+// next one, e.g.
+//
+//     api.guard(guards::content_type("application/json"))
+//         .or(admin.guard(guards::both(guards::https(), guards::internal_client())))
+
+pub fn method(method: Method) -> impl Fn(&Request) -> bool + Send + Sync {
+    move |request| request.method() == &method
+}
+
+pub fn has_header(name: &str) -> impl Fn(&Request) -> bool + Send + Sync + use<> {
+    let name = name.to_string();
+    move |request| request.header(&name).is_some()
+}
+
+// Header values compare case-insensitively.
+pub fn header(name: &str, value: &str) -> impl Fn(&Request) -> bool + Send + Sync + use<> {
+    let (name, value) = (name.to_string(), value.to_string());
+    move |request| {
+        request
+            .header(&name)
+            .is_some_and(|actual| actual.trim().eq_ignore_ascii_case(&value))
+    }
+}
+
+pub fn cookie(name: &str, value: &str) -> impl Fn(&Request) -> bool + Send + Sync + use<> {
+    let (name, value) = (name.to_string(), value.to_string());
+    move |request| request.cookie(&name) == Some(value.as_str())
+}
+
+// Matches the media type alone, so parameters such as charset are ignored.
+pub fn content_type(media_type: &str) -> impl Fn(&Request) -> bool + Send + Sync + use<> {
+    let media_type = media_type.to_string();
+    move |request| {
+        request.header("Content-Type").is_some_and(|value| {
+            let essence = value.split(';').next().unwrap_or("").trim();
+            essence.eq_ignore_ascii_case(&media_type)
+        })
+    }
+}
+
+// Compares against the Host header with any port removed. IPv6 literals match
+// with or without their brackets: host("::1") and host("[::1]") are the same.
+pub fn host(host: &str) -> impl Fn(&Request) -> bool + Send + Sync + use<> {
+    let host = unbracket(host.trim()).to_string();
+    move |request| {
+        request
+            .header("Host")
+            .is_some_and(|value| host_name(value.trim()).eq_ignore_ascii_case(&host))
+    }
+}
+
+// "example.com:8080" -> "example.com", "[::1]:8080" -> "::1".
+fn host_name(value: &str) -> &str {
+    match value.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(value, |(addr, _)| addr),
+        None => value.split_once(':').map_or(value, |(name, _)| name),
+    }
+}
+
+fn unbracket(host: &str) -> &str {
+    host.strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+        .unwrap_or(host)
+}
+
+// True for requests that arrived over TLS. Neither the target nor any header
+// counts, since a plaintext client can send whatever it likes; behind a
+// TLS-terminating proxy use https_via.
+pub fn https() -> impl Fn(&Request) -> bool + Send + Sync {
+    |request| request.connection().tls
+}
+
+// Like https, but also believes X-Forwarded-Proto: https on requests whose peer
+// is one of `proxies`, the proxies that terminate TLS in front of the server.
+pub fn https_via(proxies: Vec<Network>) -> impl Fn(&Request) -> bool + Send + Sync {
+    move |request| {
+        let from_proxy = request
+            .remote_addr()
+            .is_some_and(|addr| proxies.iter().any(|net| net.contains(addr.ip())));
+        request.connection().tls
+            || from_proxy
+                && request
+                    .header("X-Forwarded-Proto")
+                    .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"))
+    }
+}
+
+// Matches on the connection's peer address; requests without one never match.
+pub fn client_in(networks: Vec<Network>) -> impl Fn(&Request) -> bool + Send + Sync {
+    move |request| {
+        request
+            .remote_addr()
+            .is_some_and(|addr| networks.iter().any(|net| net.contains(addr.ip())))
+    }
+}
+
+// Loopback, private (RFC 1918), link-local and unique local addresses.
+pub fn internal_client() -> impl Fn(&Request) -> bool + Send + Sync {
+    |request| {
+        request
+            .remote_addr()
+            .is_some_and(|addr| is_internal(addr.ip()))
+    }
+}
+
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_internal(IpAddr::V4(ip)),
+            None => ip.is_loopback() || ip.is_unique_local() || ip.is_unicast_link_local(),
+        },
+    }
+}
+
+pub fn both<A, B>(a: A, b: B) -> impl Fn(&Request) -> bool + Send + Sync
+where
+    A: Fn(&Request) -> bool + Send + Sync,
+    B: Fn(&Request) -> bool + Send + Sync,
+{
+    move |request| a(request) && b(request)
+}
+
+pub fn either<A, B>(a: A, b: B) -> impl Fn(&Request) -> bool + Send + Sync
+where
+    A: Fn(&Request) -> bool + Send + Sync,
+    B: Fn(&Request) -> bool + Send + Sync,
+{
+    move |request| a(request) || b(request)
+}
+
+pub fn not<P>(predicate: P) -> impl Fn(&Request) -> bool + Send + Sync
+where
+    P: Fn(&Request) -> bool + Send + Sync,
+{
+    move |request| !predicate(request)
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("Invalid network: {0}")]
+pub struct NetworkError(String);
+
+// An address block in CIDR notation, e.g. "10.0.0.0/8" or "::1/128". A bare
+// address is a block of one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            ip => ip,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Network {
+    type Err = NetworkError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || NetworkError(s.to_string());
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix.trim().parse().map_err(|_| invalid())?,
+            None => max,
+        };
+        if prefix > max {
+            return Err(invalid());
+        }
+        Ok(Network { addr, prefix })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::{HandlerExt, handler_fn};
+    use crate::http::{
+        ConnectionContext, Response, StatusCode, request::request_from_buf_reader_in,
+    };
+    use crate::server::Handler;
+
+    fn request_from(raw: &str, peer: &str) -> Request {
+        let mut context = ConnectionContext::new().with_peer(peer.parse().ok());
+        let options = Default::default();
+        request_from_buf_reader_in(&mut raw.as_bytes(), &options, &mut context).unwrap()
+    }
+
+    #[test]
+    fn test_networks_match_by_prefix() {
+        let net: Network = "10.1.0.0/16".parse().unwrap();
+        assert!(net.contains("10.1.200.3".parse().unwrap()));
+        assert!(!net.contains("10.2.0.1".parse().unwrap()));
+        assert!(net.contains("::ffff:10.1.0.9".parse().unwrap()));
+
+        let host: Network = "2001:db8::1".parse().unwrap();
+        assert!(host.contains("2001:db8::1".parse().unwrap()));
+        assert!(!host.contains("2001:db8::2".parse().unwrap()));
+
+        assert!(
+            "0.0.0.0/0"
+                .parse::<Network>()
+                .unwrap()
+                .contains("8.8.8.8".parse().unwrap())
+        );
+        assert!("10.0.0.0/33".parse::<Network>().is_err());
+        assert!("example.com".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn test_failed_guards_fall_through_to_the_next_route() {
+        let proxies = vec!["192.168.1.0/24".parse().unwrap()];
+        let app = handler_fn(|_| Response::ok().with_header("X-Route", "admin"))
+            .guard(both(https_via(proxies), internal_client()))
+            .or(
+                handler_fn(|_| Response::ok().with_header("X-Route", "json"))
+                    .guard(content_type("application/json")),
+            )
+            .or(
+                handler_fn(|_| Response::ok().with_header("X-Route", "other"))
+                    .guard(client_in(vec!["203.0.113.0/24".parse().unwrap()])),
+            );
+        let route = |raw: &str, peer: &str| {
+            let response = app.handle(&request_from(raw, peer));
+            (
+                response.status_code(),
+                response.headers().get("x-route").map(String::from),
+            )
+        };
+
+        let admin = "GET / HTTP/1.1\r\nHost: x\r\nX-Forwarded-Proto: https\r\n\r\n";
+        assert_eq!(route(admin, "192.168.1.4:5000").1.as_deref(), Some("admin"));
+        assert_eq!(route(admin, "203.0.113.9:5000").1.as_deref(), Some("other"));
+
+        let json =
+            "POST / HTTP/1.1\r\nHost: x\r\nContent-Type: Application/JSON; charset=utf-8\r\n\r\n";
+        assert_eq!(route(json, "198.51.100.1:5000").1.as_deref(), Some("json"));
+
+        let plain = "GET / HTTP/1.1\r\nHost: x\r\n\r\n";
+        assert_eq!(
+            route(plain, "198.51.100.1:5000"),
+            (StatusCode::NotFound, None)
+        );
+    }
+
+    #[test]
+    fn test_https_is_not_taken_from_the_client() {
+        let forwarded = "GET / HTTP/1.1\r\nHost: x\r\nX-Forwarded-Proto: https\r\n\r\n";
+        let absolute = "GET https://x/ HTTP/1.1\r\nHost: x\r\n\r\n";
+        for raw in [forwarded, absolute] {
+            assert!(!https()(&request_from(raw, "10.0.0.2:5000")), "{}", raw);
+        }
+
+        let via = https_via(vec!["10.0.0.1".parse().unwrap()]);
+        assert!(via(&request_from(forwarded, "10.0.0.1:5000")));
+        assert!(!via(&request_from(forwarded, "10.0.0.2:5000")));
+        assert!(!via(&request_from(absolute, "10.0.0.1:5000")));
+    }
+
+    #[test]
+    fn test_host_strips_ports_from_ipv6_literals() {
+        let request = |host: &str| {
+            request_from(
+                &format!("GET / HTTP/1.1\r\nHost: {}\r\n\r\n", host),
+                "10.0.0.2:5000",
+            )
+        };
+        assert!(host("::1")(&request("[::1]:8080")));
+        assert!(host("[::1]")(&request("[::1]")));
+        assert!(!host("::1")(&request("[::2]:8080")));
+        assert!(host("Example.com")(&request("example.com:80")));
+        assert!(!host("example.com")(&request("example.com.evil:80")));
+    }
+}
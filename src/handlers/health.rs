@@ -0,0 +1,312 @@
+use std::sync::{Arc, RwLock};
+
+use crate::{
+    http::{Body, Method, Request, Response, StatusCode},
+    json::Value,
+    server::{Handler, ShutdownHandle},
+};
+
+// Probe outcomes, named after the IETF health check response format. Warn keeps
+// the endpoint at 200 but shows up in the body; any Fail turns it into a 503.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl HealthStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthStatus::Pass => "pass",
+            HealthStatus::Warn => "warn",
+            HealthStatus::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub status: HealthStatus,
+    pub detail: Option<String>,
+}
+
+impl Check {
+    pub fn pass() -> Self {
+        Check {
+            status: HealthStatus::Pass,
+            detail: None,
+        }
+    }
+
+    pub fn warn(detail: impl Into<String>) -> Self {
+        Check {
+            status: HealthStatus::Warn,
+            detail: Some(detail.into()),
+        }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Check {
+            status: HealthStatus::Fail,
+            detail: Some(detail.into()),
+        }
+    }
+}
+
+type Probe = Arc<dyn Fn() -> Check + Send + Sync>;
+
+#[derive(Default)]
+struct Probes {
+    liveness: Vec<(String, Probe)>,
+    readiness: Vec<(String, Probe)>,
+}
+
+// Named liveness and readiness probes, shared between the application that
+// registers them and the endpoints that run them. Probes run on every request to
+// /healthz or /readyz, so they should be cheap or cache their own results.
+#[derive(Clone, Default)]
+pub struct HealthRegistry {
+    probes: Arc<RwLock<Probes>>,
+    shutdown: Option<ShutdownHandle>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Readiness fails once the server starts shutting down, so load balancers stop
+    // routing to it while in-flight requests drain.
+    pub fn with_shutdown(mut self, handle: ShutdownHandle) -> Self {
+        self.shutdown = Some(handle);
+        self
+    }
+
+    // Registering a name again replaces the earlier probe.
+    pub fn liveness(&self, name: &str, probe: impl Fn() -> Check + Send + Sync + 'static) {
+        let mut probes = self.probes.write().unwrap_or_else(|e| e.into_inner());
+        Self::register(&mut probes.liveness, name, Arc::new(probe));
+    }
+
+    pub fn readiness(&self, name: &str, probe: impl Fn() -> Check + Send + Sync + 'static) {
+        let mut probes = self.probes.write().unwrap_or_else(|e| e.into_inner());
+        Self::register(&mut probes.readiness, name, Arc::new(probe));
+    }
+
+    fn register(probes: &mut Vec<(String, Probe)>, name: &str, probe: Probe) {
+        match probes.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, slot)) => *slot = probe,
+            None => probes.push((name.to_string(), probe)),
+        }
+    }
+
+    pub fn check_liveness(&self) -> (HealthStatus, Vec<(String, Check)>) {
+        let probes = self.snapshot(|probes| &probes.liveness);
+        Self::run(probes)
+    }
+
+    pub fn check_readiness(&self) -> (HealthStatus, Vec<(String, Check)>) {
+        let probes = self.snapshot(|probes| &probes.readiness);
+        let (status, mut checks) = Self::run(probes);
+        if self
+            .shutdown
+            .as_ref()
+            .is_some_and(ShutdownHandle::is_shutdown)
+        {
+            checks.push((
+                "shutdown".to_string(),
+                Check::fail("server is shutting down"),
+            ));
+            return (HealthStatus::Fail, checks);
+        }
+        (status, checks)
+    }
+
+    // Probes run outside the lock so a slow one cannot block registration.
+    fn snapshot(&self, select: impl Fn(&Probes) -> &Vec<(String, Probe)>) -> Vec<(String, Probe)> {
+        let probes = self.probes.read().unwrap_or_else(|e| e.into_inner());
+        select(&probes).clone()
+    }
+
+    fn run(probes: Vec<(String, Probe)>) -> (HealthStatus, Vec<(String, Check)>) {
+        let checks: Vec<_> = probes
+            .into_iter()
+            .map(|(name, probe)| (name, probe()))
+            .collect();
+        let status = checks
+            .iter()
+            .map(|(_, check)| check.status)
+            .max()
+            .unwrap_or(HealthStatus::Pass);
+        (status, checks)
+    }
+
+    fn respond(status: HealthStatus, checks: Vec<(String, Check)>) -> Response {
+        let checks = checks
+            .into_iter()
+            .map(|(name, check)| {
+                let mut members = vec![("status".to_string(), Value::from(check.status.as_str()))];
+                if let Some(detail) = check.detail {
+                    members.push(("detail".to_string(), Value::from(detail)));
+                }
+                (name, Value::Object(members))
+            })
+            .collect();
+        let body = Value::Object(vec![
+            ("status".to_string(), Value::from(status.as_str())),
+            ("checks".to_string(), Value::Object(checks)),
+        ]);
+
+        let code = match status {
+            HealthStatus::Fail => StatusCode::ServiceUnavailable,
+            _ => StatusCode::OK,
+        };
+        Response::new(code)
+            .with_header("Content-Type", "application/health+json")
+            .with_header("Cache-Control", "no-store")
+            .with_body(Body::from(body.to_string()))
+    }
+
+    // Answers /healthz and /readyz; None for any other path.
+    pub fn endpoint(&self, request: &Request) -> Option<Response> {
+        let readiness = match request.path() {
+            "/healthz" => false,
+            "/readyz" => true,
+            _ => return None,
+        };
+        // Refused before probing, so a stray POST cannot trigger the checks.
+        if !matches!(request.method(), Method::GET | Method::HEAD) {
+            return Some(Response::method_not_allowed().with_header("Allow", "GET, HEAD"));
+        }
+        let (status, checks) = if readiness {
+            self.check_readiness()
+        } else {
+            self.check_liveness()
+        };
+        Some(Self::respond(status, checks))
+    }
+}
+
+// Serves the health endpoints alone, e.g. on a separate admin listener.
+impl Handler for HealthRegistry {
+    fn handle(&self, request: &Request) -> Response {
+        self.endpoint(request).unwrap_or_else(Response::not_found)
+    }
+}
+
+// Serves the health endpoints in front of an application handler.
+pub struct Health<H: Handler> {
+    inner: H,
+    registry: HealthRegistry,
+}
+
+impl<H: Handler> Health<H> {
+    pub fn new(inner: H, registry: HealthRegistry) -> Self {
+        Health { inner, registry }
+    }
+
+    pub fn registry(&self) -> &HealthRegistry {
+        &self.registry
+    }
+}
+
+impl<H: Handler> Handler for Health<H> {
+    fn handle(&self, request: &Request) -> Response {
+        self.registry
+            .endpoint(request)
+            .unwrap_or_else(|| self.inner.handle(request))
+    }
+
+    fn handle_bad_request(&self, e: &crate::http::ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    fn get(handler: &impl Handler, path: &str) -> (StatusCode, Value) {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+        let response = handler.handle(&Request::try_from(raw.as_bytes()).unwrap());
+        let body = json::parse(response.body().as_str().unwrap()).unwrap();
+        (response.status_code(), body)
+    }
+
+    #[test]
+    fn test_aggregates_probes_per_endpoint() {
+        let registry = HealthRegistry::new();
+        let database_up = Arc::new(AtomicBool::new(true));
+        let flag = database_up.clone();
+        registry.liveness("event_loop", Check::pass);
+        registry.readiness("database", move || {
+            if flag.load(Ordering::SeqCst) {
+                Check::pass()
+            } else {
+                Check::fail("connection refused")
+            }
+        });
+        registry.readiness("cache", || Check::warn("cold"));
+
+        let (status, body) = get(&registry, "/readyz");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.get("status").and_then(Value::as_str), Some("warn"));
+
+        database_up.store(false, Ordering::SeqCst);
+        let (status, body) = get(&registry, "/readyz");
+        assert_eq!(status, StatusCode::ServiceUnavailable);
+        let database = body.get("checks").and_then(|c| c.get("database")).unwrap();
+        assert_eq!(
+            database.get("detail").and_then(Value::as_str),
+            Some("connection refused")
+        );
+
+        // Liveness only runs liveness probes.
+        let (status, body) = get(&registry, "/healthz");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.get("status").and_then(Value::as_str), Some("pass"));
+    }
+
+    #[test]
+    fn test_other_methods_are_refused_without_probing() {
+        let registry = HealthRegistry::new();
+        let probed = Arc::new(AtomicBool::new(false));
+        let flag = probed.clone();
+        registry.readiness("database", move || {
+            flag.store(true, Ordering::SeqCst);
+            Check::pass()
+        });
+
+        let request = Request::try_from(
+            &b"POST /readyz HTTP/1.1\r\nHost: x\r\nContent-Length: 0\r\n\r\n"[..],
+        )
+        .unwrap();
+        let response = registry.handle(&request);
+        assert_eq!(response.status_code(), StatusCode::MethodNotAllowed);
+        assert_eq!(response.headers().get("allow"), Some("GET, HEAD"));
+        assert!(!probed.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_wraps_an_application_handler() {
+        struct App;
+
+        impl Handler for App {
+            fn handle(&self, _request: &Request) -> Response {
+                Response::ok().with_body(Body::from("{}"))
+            }
+        }
+
+        let health = Health::new(App, HealthRegistry::new());
+        let (status, body) = get(&health, "/healthz");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.get("status").and_then(Value::as_str), Some("pass"));
+
+        let (status, body) = get(&health, "/other");
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body.get("status"), None);
+    }
+}
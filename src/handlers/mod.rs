@@ -0,0 +1,21 @@
+pub mod compose;
+pub mod guards;
+pub mod health;
+pub mod router;
+pub mod split;
+pub mod static_files;
+pub mod stub;
+pub mod upload;
+pub mod webdav;
+
+#[cfg(feature = "arena")]
+pub use compose::{ArenaFn, arena_fn};
+pub use compose::{FnHandler, HandlerExt, handler_fn};
+pub use guards::Network;
+pub use health::{Check, Health, HealthRegistry, HealthStatus};
+pub use router::Router;
+pub use split::{Split, SplitKey};
+pub use static_files::{AssetManifest, StaticFiles};
+pub use stub::{Fixture, Matcher, StubError, Stubs};
+pub use upload::{FileUploads, InMemoryUploads, UploadError, UploadInfo, UploadStore, Uploads};
+pub use webdav::WebDav;
@@ -0,0 +1,232 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    http::{Body, ETag, Method, Request, Response, StatusCode},
+    server::Handler,
+};
+
+use super::static_files::{REVALIDATE, content_type};
+
+struct Route {
+    // None matches every method.
+    method: Option<Method>,
+    pattern: String,
+    handler: Box<dyn Handler>,
+}
+
+impl Route {
+    // Exact paths, or a prefix when the pattern ends in '*'.
+    fn matches_path(&self, path: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.pattern,
+        }
+    }
+
+    // HEAD is answered by GET routes; the server drops the body.
+    fn matches_method(&self, method: &Method) -> bool {
+        match &self.method {
+            None => true,
+            Some(Method::GET) => matches!(method, Method::GET | Method::HEAD),
+            Some(expected) => expected == method,
+        }
+    }
+}
+
+// Dispatches on method and path, first matching route wins. A path that matches
+// but with the wrong method gets 405 listing the allowed ones; no match is a 404,
+// so a router can be chained with HandlerExt::or.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route(mut self, method: Method, path: &str, handler: impl Handler + 'static) -> Self {
+        self.push(Some(method), path, handler);
+        self
+    }
+
+    pub fn get(self, path: &str, handler: impl Handler + 'static) -> Self {
+        self.route(Method::GET, path, handler)
+    }
+
+    pub fn post(self, path: &str, handler: impl Handler + 'static) -> Self {
+        self.route(Method::POST, path, handler)
+    }
+
+    pub fn any(mut self, path: &str, handler: impl Handler + 'static) -> Self {
+        self.push(None, path, handler);
+        self
+    }
+
+    // Serves a fixed body, e.g. robots.txt, with an ETag computed once up front.
+    pub fn static_response(self, path: &str, body: impl Into<Body>, content_type: &str) -> Self {
+        self.get(path, StaticAsset::new(body.into(), content_type))
+    }
+
+    // Reads `file` once, now, and serves its contents from memory. The content type
+    // follows the file extension.
+    pub fn file(self, path: &str, file: impl AsRef<Path>) -> io::Result<Self> {
+        let file = file.as_ref();
+        let asset = StaticAsset::new(Body::from(fs::read(file)?), content_type(file));
+        Ok(self.get(path, asset))
+    }
+
+    fn push(&mut self, method: Option<Method>, path: &str, handler: impl Handler + 'static) {
+        self.routes.push(Route {
+            method,
+            pattern: path.to_string(),
+            handler: Box::new(handler),
+        });
+    }
+}
+
+impl Handler for Router {
+    fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in self
+            .routes
+            .iter()
+            .filter(|r| r.matches_path(request.path()))
+        {
+            if route.matches_method(request.method()) {
+                return route.handler.handle(request);
+            }
+            let methods: &[&str] = match &route.method {
+                Some(Method::GET) => &["GET", "HEAD"],
+                Some(method) => &[method.as_str()],
+                None => &[],
+            };
+            for method in methods {
+                if !allowed.contains(method) {
+                    allowed.push(method);
+                }
+            }
+        }
+        if allowed.is_empty() {
+            return Response::not_found();
+        }
+        Response::method_not_allowed().with_header("Allow", allowed.join(", "))
+    }
+}
+
+// A preloaded body. Clones of the response share the bytes rather than copying them.
+struct StaticAsset {
+    body: Body,
+    content_type: String,
+    etag: ETag,
+}
+
+impl StaticAsset {
+    fn new(mut body: Body, content_type: &str) -> Self {
+        let etag = ETag::from_content(body.as_bytes());
+        StaticAsset {
+            body: body.share(),
+            content_type: content_type.to_string(),
+            etag,
+        }
+    }
+}
+
+impl Handler for StaticAsset {
+    fn handle(&self, request: &Request) -> Response {
+        if request
+            .if_none_match()
+            .is_some_and(|list| list.matches_weak(&self.etag))
+        {
+            return Response::new(StatusCode::NotModified)
+                .with_etag(&self.etag)
+                .with_header("Cache-Control", REVALIDATE);
+        }
+        Response::ok()
+            .with_header("Content-Type", &self.content_type)
+            .with_header("Cache-Control", REVALIDATE)
+            .with_etag(&self.etag)
+            .with_body(self.body.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{handlers::handler_fn, testing::TempDir};
+
+    fn send(router: &Router, method: &str, path: &str, headers: &str) -> Response {
+        let raw = format!("{} {} HTTP/1.1\r\nHost: x\r\n{}\r\n", method, path, headers);
+        router.handle(&Request::try_from(raw.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_routes_by_method_and_path() {
+        let router = Router::new()
+            .get(
+                "/users",
+                handler_fn(|_| Response::ok().with_body(Body::from("list"))),
+            )
+            .post("/users", handler_fn(|_| Response::new(StatusCode::Created)))
+            .any(
+                "/api/*",
+                handler_fn(|r| Response::ok().with_body(Body::from(r.path()))),
+            );
+
+        assert_eq!(
+            send(&router, "GET", "/users", "").body().as_str(),
+            Ok("list")
+        );
+        assert_eq!(
+            send(&router, "HEAD", "/users", "").status_code(),
+            StatusCode::OK
+        );
+        assert_eq!(
+            send(&router, "POST", "/users", "").status_code(),
+            StatusCode::Created
+        );
+        assert_eq!(
+            send(&router, "DELETE", "/api/v1/x", "").body().as_str(),
+            Ok("/api/v1/x")
+        );
+
+        let response = send(&router, "DELETE", "/users", "");
+        assert_eq!(response.status_code(), StatusCode::MethodNotAllowed);
+        assert_eq!(response.headers().get("allow"), Some("GET, HEAD, POST"));
+        assert_eq!(
+            send(&router, "GET", "/other", "").status_code(),
+            StatusCode::NotFound
+        );
+    }
+
+    #[test]
+    fn test_static_shortcuts_serve_preloaded_bodies_with_etags() {
+        let dir = TempDir::new("router");
+        let icon = dir.join("favicon.ico");
+        std::fs::write(&icon, [0u8, 0, 1, 0]).unwrap();
+
+        let router = Router::new()
+            .static_response("/robots.txt", "User-agent: *\nDisallow:\n", "text/plain")
+            .file("/favicon.ico", &icon)
+            .unwrap();
+        // Served from memory once loaded.
+        drop(dir);
+
+        let response = send(&router, "GET", "/robots.txt", "");
+        assert_eq!(response.headers().get("content-type"), Some("text/plain"));
+        assert_eq!(response.body().as_str(), Ok("User-agent: *\nDisallow:\n"));
+        let etag = response.headers().get("etag").unwrap().to_string();
+        let revalidated = send(
+            &router,
+            "GET",
+            "/robots.txt",
+            &format!("If-None-Match: {}\r\n", etag),
+        );
+        assert_eq!(revalidated.status_code(), StatusCode::NotModified);
+
+        let response = send(&router, "GET", "/favicon.ico", "");
+        assert_eq!(response.headers().get("content-type"), Some("image/x-icon"));
+        assert_eq!(response.body().as_bytes(), [0, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    http::{ParseError, Request, Response},
+    server::Handler,
+};
+
+// What a request is bucketed by. The same value always lands on the same side for
+// a given weight, so a user keeps seeing one version across requests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitKey {
+    Header(String),
+    Cookie(String),
+    ClientIp,
+}
+
+type Pin = Box<dyn Fn(&Request) -> bool + Send + Sync>;
+
+// Canary routing: sends a weighted share of traffic to `canary` and the rest to
+// `primary`. Requests matching a pin (see guards, e.g. `guards::header("X-Canary",
+// "1")`) always reach the canary. Requests without the split key are spread by a
+// counter instead of a hash.
+pub struct Split<A: Handler, B: Handler> {
+    primary: A,
+    canary: B,
+    // Share of traffic for the canary, in basis points (1/100 of a percent).
+    weight: u32,
+    key: SplitKey,
+    pins: Vec<Pin>,
+    keyless: AtomicU64,
+}
+
+impl<A: Handler, B: Handler> Split<A, B> {
+    pub fn new(primary: A, canary: B) -> Self {
+        Split {
+            primary,
+            canary,
+            weight: 0,
+            key: SplitKey::ClientIp,
+            pins: Vec::new(),
+            keyless: AtomicU64::new(0),
+        }
+    }
+
+    // Percentage of traffic for the canary, e.g. 5.0; clamped to 0-100.
+    pub fn weight(mut self, percent: f64) -> Self {
+        self.weight = (percent.clamp(0.0, 100.0) * 100.0).round() as u32;
+        self
+    }
+
+    pub fn key(mut self, key: SplitKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn pin<P>(mut self, predicate: P) -> Self
+    where
+        P: Fn(&Request) -> bool + Send + Sync + 'static,
+    {
+        self.pins.push(Box::new(predicate));
+        self
+    }
+
+    pub fn to_canary(&self, request: &Request) -> bool {
+        if self.pins.iter().any(|pin| pin(request)) {
+            return true;
+        }
+        let ip = request.remote_addr().map(|addr| addr.ip().to_string());
+        let value = match &self.key {
+            SplitKey::Header(name) => request.header(name),
+            SplitKey::Cookie(name) => request.cookie(name),
+            SplitKey::ClientIp => ip.as_deref(),
+        };
+        let weight = self.weight as u64;
+        match value {
+            Some(value) => bucket(value) < weight,
+            // Every n-th keyless request, so the share holds over short runs too.
+            None => {
+                let n = self.keyless.fetch_add(1, Ordering::Relaxed);
+                (n + 1) * weight / 10_000 > n * weight / 10_000
+            }
+        }
+    }
+}
+
+// FNV-1a, which unlike the std hasher is stable across processes and restarts.
+fn bucket(value: &str) -> u64 {
+    let hash = value.bytes().fold(0xcbf29ce484222325u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    hash % 10_000
+}
+
+impl<A: Handler, B: Handler> Handler for Split<A, B> {
+    fn handle(&self, request: &Request) -> Response {
+        match self.to_canary(request) {
+            true => self.canary.handle(request),
+            false => self.primary.handle(request),
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.primary.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::{guards, handler_fn};
+    use crate::http::StatusCode;
+
+    fn request(extra: &str) -> Request {
+        let raw = format!("GET / HTTP/1.1\r\nHost: x\r\n{}\r\n", extra);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_splits_by_weight_deterministically_and_honours_pins() {
+        let split = Split::new(
+            handler_fn(|_| Response::ok()),
+            handler_fn(|_| Response::new(StatusCode::Accepted)),
+        )
+        .weight(5.0)
+        .key(SplitKey::Cookie("uid".to_string()))
+        .pin(guards::header("X-Canary", "1"));
+
+        let cookie = |i: &i32| request(&format!("Cookie: theme=dark; uid={}\r\n", i));
+        let canaries = (0..2000).filter(|i| split.to_canary(&cookie(i))).count();
+        assert!((60..=140).contains(&canaries), "got {}", canaries);
+
+        let user = request("Cookie: uid=42\r\n");
+        let first = split.to_canary(&user);
+        assert!((0..10).all(|_| split.to_canary(&user) == first));
+
+        assert_eq!(
+            split.handle(&request("X-Canary: 1\r\n")).status_code(),
+            StatusCode::Accepted
+        );
+        let everyone = Split::new(
+            handler_fn(|_| Response::ok()),
+            handler_fn(|_| Response::new(StatusCode::Accepted)),
+        )
+        .weight(100.0);
+        assert_eq!(
+            everyone.handle(&request("")).status_code(),
+            StatusCode::Accepted
+        );
+        let keyless = (0..40).filter(|_| split.to_canary(&request(""))).count();
+        assert_eq!(keyless, 2);
+    }
+}
@@ -0,0 +1,470 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    date::DateTime,
+    digest::content_hash,
+    http::{
+        Body, ETag, Method, Request, Response, StatusCode, conditional, path::encode_segment,
+        problem::escape_xml,
+    },
+    json::Value,
+    server::Handler,
+};
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+pub(crate) const REVALIDATE: &str = "no-cache";
+const INDEX: &str = "index.html";
+
+// Maps logical asset paths ("/static/app.js") to their content-hashed names
+// ("/static/app.3f2a9c0d1e4b5a69.js") so templates can reference the hashed URL.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssetManifest {
+    entries: HashMap<String, String>,
+}
+
+impl AssetManifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn generate(root: impl AsRef<Path>, prefix: &str) -> io::Result<Self> {
+        let root = root.as_ref();
+        let mut manifest = AssetManifest::new();
+        let mut pending = vec![root.to_path_buf()];
+
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                    continue;
+                }
+
+                let Ok(relative) = path.strip_prefix(root) else {
+                    continue;
+                };
+                let relative = relative
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let logical = format!("{}/{}", prefix.trim_end_matches('/'), relative);
+                let hash = content_hash(&fs::read(&path)?);
+                manifest.insert(&logical, &hashed_name(&logical, &hash));
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    pub fn insert(&mut self, logical: &str, hashed: &str) {
+        self.entries.insert(logical.to_string(), hashed.to_string());
+    }
+
+    pub fn hashed_path(&self, logical: &str) -> Option<&str> {
+        self.entries.get(logical).map(String::as_str)
+    }
+
+    pub fn resolve(&self, hashed: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, h)| h.as_str() == hashed)
+            .map(|(logical, _)| logical.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut entries: Vec<(&String, &String)> = self.entries.iter().collect();
+        entries.sort();
+        Value::Object(
+            entries
+                .into_iter()
+                .map(|(logical, hashed)| (logical.clone(), Value::from(hashed.as_str())))
+                .collect(),
+        )
+        .to_string()
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json())
+    }
+}
+
+pub struct StaticFiles {
+    root: PathBuf,
+    prefix: String,
+    manifest: Option<AssetManifest>,
+    listing: bool,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>, prefix: &str) -> Self {
+        StaticFiles {
+            root: root.into(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+            manifest: None,
+            listing: false,
+        }
+    }
+
+    pub fn with_manifest(mut self, manifest: AssetManifest) -> Self {
+        self.manifest = Some(manifest);
+        self
+    }
+
+    pub fn manifest(&self) -> Option<&AssetManifest> {
+        self.manifest.as_ref()
+    }
+
+    // Directories without an index.html are listed as HTML instead of answering
+    // 404. Off by default, as a listing exposes every file name under the root.
+    pub fn with_listing(mut self, enabled: bool) -> Self {
+        self.listing = enabled;
+        self
+    }
+
+    // Hashed URLs listed in the manifest are served with a year-long immutable
+    // lifetime; everything else must be revalidated against its ETag.
+    pub(crate) fn locate(&self, path: &str) -> Option<(PathBuf, bool)> {
+        let (logical, immutable) = match self.manifest.as_ref().and_then(|m| m.resolve(path)) {
+            Some(logical) => (logical, true),
+            None => (path, false),
+        };
+
+        // The prefix itself names the root directory; a trailing slash is allowed
+        // on directories.
+        let relative = match logical.strip_prefix(&self.prefix)? {
+            "" => "",
+            rest => rest.strip_prefix('/')?,
+        };
+        let relative = relative.strip_suffix('/').unwrap_or(relative);
+        if relative.split('/').any(|s| s == "..")
+            || (!relative.is_empty() && relative.split('/').any(str::is_empty))
+        {
+            return None;
+        }
+
+        Some((self.root.join(relative), immutable))
+    }
+}
+
+impl StaticFiles {
+    fn serve_file(&self, request: &Request, file: &Path, immutable: bool) -> Response {
+        let Ok(contents) = fs::read(file) else {
+            return Response::not_found();
+        };
+
+        let cache_control = if immutable { IMMUTABLE } else { REVALIDATE };
+        let mut response = Response::ok()
+            .with_header("Content-Type", content_type(file))
+            .with_header("Cache-Control", cache_control)
+            .with_etag(&ETag::from_content(&contents));
+        if let Ok(modified) = fs::metadata(file).and_then(|m| m.modified()) {
+            response = response.with_last_modified(modified);
+        }
+        let response = response.with_body(Body::Content(contents));
+        conditional::not_modified(request, &response).unwrap_or(response)
+    }
+
+    fn serve_directory(&self, request: &Request, dir: &Path) -> Response {
+        // Relative links in an index or listing resolve against the slash.
+        // Rebuilt from the sanitized path: the raw one may start with `//`, which a
+        // browser would follow to another host.
+        if !request.path().ends_with('/') {
+            let mut path = String::new();
+            for segment in request.path().split('/').filter(|s| !s.is_empty()) {
+                path.push('/');
+                path.push_str(&encode_segment(segment));
+            }
+            let location = match request.target().split_once('?') {
+                Some((_, query)) => format!("{}/?{}", path, query),
+                None => format!("{}/", path),
+            };
+            return Response::new(StatusCode::MovedPermanently).with_header("Location", &location);
+        }
+
+        let index = dir.join(INDEX);
+        if index.is_file() {
+            return self.serve_file(request, &index, false);
+        }
+        if !self.listing {
+            return Response::not_found();
+        }
+        match render_listing(
+            dir,
+            request.path(),
+            request.path() != format!("{}/", self.prefix),
+        ) {
+            Ok(html) => Response::ok()
+                .with_header("Content-Type", "text/html; charset=utf-8")
+                .with_header("Cache-Control", REVALIDATE)
+                .with_body(Body::from(html)),
+            Err(_) => Response::not_found(),
+        }
+    }
+}
+
+impl Handler for StaticFiles {
+    fn handle(&self, request: &Request) -> Response {
+        if !matches!(request.method(), Method::GET | Method::HEAD) {
+            return Response::method_not_allowed().with_header("Allow", "GET, HEAD");
+        }
+
+        let Some((file, immutable)) = self.locate(request.path()) else {
+            return Response::not_found();
+        };
+        if file.is_dir() {
+            return self.serve_directory(request, &file);
+        }
+        self.serve_file(request, &file, immutable)
+    }
+}
+
+// An HTML table of a directory's entries, subdirectories first. Dotfiles are
+// left out, as are names that are not valid UTF-8.
+fn render_listing(dir: &Path, path: &str, has_parent: bool) -> io::Result<String> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        entries.push((!metadata.is_dir(), name, metadata));
+    }
+    entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+    let title = escape_xml(&format!("Index of {}", path));
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{0}</title></head>\n\
+         <body><h1>{0}</h1>\n<table>\n\
+         <tr><th>Name</th><th>Size</th><th>Last modified</th></tr>\n",
+        title
+    );
+    if has_parent {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+    for (is_file, name, metadata) in entries {
+        let slash = if is_file { "" } else { "/" };
+        let size = match is_file {
+            true => metadata.len().to_string(),
+            false => "-".to_string(),
+        };
+        let modified = metadata
+            .modified()
+            .map(|time| {
+                let t = DateTime::from_system_time(time);
+                format!(
+                    "{:04}-{:02}-{:02} {:02}:{:02}",
+                    t.year, t.month, t.day, t.hour, t.minute
+                )
+            })
+            .unwrap_or_default();
+        html.push_str(&format!(
+            "<tr><td><a href=\"{}{}\">{}{}</a></td><td>{}</td><td>{}</td></tr>\n",
+            escape_xml(&encode_segment(&name)),
+            slash,
+            escape_xml(&name),
+            slash,
+            size,
+            modified
+        ));
+    }
+    html.push_str("</table>\n</body></html>\n");
+    Ok(html)
+}
+
+// "/static/app.js" + "3f2a..." -> "/static/app.3f2a....js"
+pub fn hashed_name(logical: &str, hash: &str) -> String {
+    let (dir, file) = logical.rsplit_once('/').unwrap_or(("", logical));
+    let hashed_file = match file.split_once('.') {
+        Some((stem, ext)) => format!("{}.{}.{}", stem, hash, ext),
+        None => format!("{}.{}", file, hash),
+    };
+    if logical.contains('/') {
+        format!("{}/{}", dir, hashed_file)
+    } else {
+        hashed_file
+    }
+}
+
+pub(crate) fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "wasm" => "application/wasm",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn temp_root(name: &str) -> TempDir {
+        let dir = TempDir::new(name);
+        fs::create_dir_all(dir.join("css")).unwrap();
+        fs::write(dir.join("app.js"), "console.log(1)").unwrap();
+        fs::write(dir.join("css/site.min.css"), "body{}").unwrap();
+        dir
+    }
+
+    fn get(path: &str, extra: &str) -> Request {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n", path, extra);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_hashed_name() {
+        assert_eq!(hashed_name("/static/app.js", "abc"), "/static/app.abc.js");
+        assert_eq!(
+            hashed_name("/static/site.min.css", "abc"),
+            "/static/site.abc.min.css"
+        );
+        assert_eq!(hashed_name("LICENSE", "abc"), "LICENSE.abc");
+    }
+
+    #[test]
+    fn test_manifest_generation() {
+        let root = temp_root("static-manifest");
+        let manifest = AssetManifest::generate(root.path(), "/static/").unwrap();
+
+        let hashed = manifest.hashed_path("/static/app.js").unwrap();
+        assert_eq!(
+            hashed,
+            format!("/static/app.{}.js", content_hash(b"console.log(1)"))
+        );
+        assert_eq!(manifest.resolve(hashed), Some("/static/app.js"));
+        assert!(manifest.hashed_path("/static/css/site.min.css").is_some());
+        assert!(manifest.to_json().starts_with("{\"/static/app.js\":"));
+    }
+
+    #[test]
+    fn test_serves_hashed_assets_as_immutable() {
+        let root = temp_root("static-serve");
+        let manifest = AssetManifest::generate(root.path(), "/static").unwrap();
+        let hashed = manifest.hashed_path("/static/app.js").unwrap().to_string();
+        let files = StaticFiles::new(root.path(), "/static").with_manifest(manifest);
+
+        let response = files.handle(&get(&hashed, ""));
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.headers().get("cache-control"), Some(IMMUTABLE));
+        assert_eq!(
+            response.headers().get("content-type"),
+            Some("text/javascript; charset=utf-8")
+        );
+        assert_eq!(response.body().as_bytes(), b"console.log(1)");
+
+        let response = files.handle(&get("/static/app.js", ""));
+        assert_eq!(response.headers().get("cache-control"), Some(REVALIDATE));
+
+        let etag = response.headers().get("etag").unwrap().to_string();
+        let response = files.handle(&get(
+            "/static/app.js",
+            &format!("If-None-Match: {}\r\n", etag),
+        ));
+        assert_eq!(response.status_code(), StatusCode::NotModified);
+
+        let response = files.handle(&get("/static/app.0000000000000000.js", ""));
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+    }
+
+    #[test]
+    fn test_revalidates_by_modification_time() {
+        let root = temp_root("static-modified");
+        let files = StaticFiles::new(root.path(), "/static");
+
+        let response = files.handle(&get("/static/app.js", ""));
+        let modified = response.headers().get("last-modified").unwrap().to_string();
+        let response = files.handle(&get(
+            "/static/app.js",
+            &format!("If-Modified-Since: {}\r\n", modified),
+        ));
+        assert_eq!(response.status_code(), StatusCode::NotModified);
+        assert!(response.body().is_empty());
+        assert_eq!(
+            response.headers().get("last-modified"),
+            Some(modified.as_str())
+        );
+
+        let response = files.handle(&get(
+            "/static/app.js",
+            "If-Modified-Since: Thu, 01 Jan 1970 00:00:00 GMT\r\n",
+        ));
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_directory_redirect_stays_on_this_host() {
+        let root = temp_root("static-redirect");
+        fs::create_dir_all(root.join("a b")).unwrap();
+        let files = StaticFiles::new(root.path(), "/static");
+
+        let response = files.handle(&get("//evil.com/../static/css", ""));
+        assert_eq!(response.status_code(), StatusCode::MovedPermanently);
+        assert_eq!(response.headers().get("location"), Some("/static/css/"));
+
+        let response = files.handle(&get("/static/a%20b?x=1", ""));
+        assert_eq!(
+            response.headers().get("location"),
+            Some("/static/a%20b/?x=1")
+        );
+    }
+
+    #[test]
+    fn test_lists_directories_without_an_index() {
+        let root = temp_root("static-listing");
+        fs::write(root.join("a <b>.txt"), "12345").unwrap();
+        fs::write(root.join(".env"), "SECRET=1").unwrap();
+        let hidden = StaticFiles::new(root.path(), "/static");
+        assert_eq!(
+            hidden.handle(&get("/static/", "")).status_code(),
+            StatusCode::NotFound
+        );
+
+        let files = StaticFiles::new(root.path(), "/static").with_listing(true);
+        let response = files.handle(&get("/static/", ""));
+        assert_eq!(response.status_code(), StatusCode::OK);
+        let html = response.body().as_str().unwrap();
+        assert!(html.contains("<a href=\"css/\">css/</a>"));
+        assert!(html.contains("<a href=\"a%20%3Cb%3E.txt\">a &lt;b&gt;.txt</a></td><td>5</td>"));
+        assert!(html.find("css/").unwrap() < html.find("app.js").unwrap());
+        assert!(!html.contains(".env") && !html.contains("../"));
+
+        let response = files.handle(&get("/static/css?v=1", ""));
+        assert_eq!(response.status_code(), StatusCode::MovedPermanently);
+        assert_eq!(response.headers().get("location"), Some("/static/css/?v=1"));
+        let html = files.handle(&get("/static/css/", ""));
+        assert!(html.body().as_str().unwrap().contains("<a href=\"../\">"));
+
+        fs::write(root.join("css/index.html"), "<p>css</p>").unwrap();
+        let response = files.handle(&get("/static/css/", ""));
+        assert_eq!(response.body().as_bytes(), b"<p>css</p>");
+    }
+}
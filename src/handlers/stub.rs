@@ -0,0 +1,347 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    http::{Method, Problem, Request, Response, ResponseRecord, StatusCode, record::RecordError},
+    json::{self, Value},
+    server::Handler,
+};
+
+#[derive(Debug, Error)]
+pub enum StubError {
+    #[error("Failed to read fixtures")]
+    Io(#[from] io::Error),
+
+    #[error("Invalid fixture in {}: {source}", path.display())]
+    Fixture { path: PathBuf, source: RecordError },
+}
+
+// Which requests a fixture answers. Unset fields match anything; a path ending
+// in '*' matches by prefix.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Matcher {
+    pub method: Option<Method>,
+    pub path: Option<String>,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+}
+
+impl Matcher {
+    pub fn matches(&self, request: &Request) -> bool {
+        if self.method.as_ref().is_some_and(|m| m != request.method()) {
+            return false;
+        }
+
+        let path_matches = match self.path.as_deref() {
+            None => true,
+            Some(pattern) => match pattern.strip_suffix('*') {
+                Some(prefix) => request.path().starts_with(prefix),
+                None => request.path() == pattern,
+            },
+        };
+
+        path_matches
+            && self
+                .query
+                .iter()
+                .all(|(name, value)| request.query().get(name) == Some(value.as_str()))
+            && self
+                .headers
+                .iter()
+                .all(|(name, value)| request.header(name) == Some(value.as_str()))
+    }
+
+    fn from_json(value: &Value) -> Result<Self, RecordError> {
+        let method = match value.get("method") {
+            None => None,
+            Some(method) => Some(
+                method
+                    .as_str()
+                    .and_then(|m| m.parse::<Method>().ok())
+                    .ok_or(RecordError::InvalidField("method"))?,
+            ),
+        };
+        let path = match value.get("path") {
+            None => None,
+            Some(path) => Some(
+                path.as_str()
+                    .map(str::to_string)
+                    .ok_or(RecordError::InvalidField("path"))?,
+            ),
+        };
+
+        Ok(Matcher {
+            method,
+            path,
+            query: string_pairs(value, "query")?,
+            headers: string_pairs(value, "headers")?,
+        })
+    }
+
+    fn to_json(&self) -> Value {
+        let mut members = Vec::new();
+        if let Some(method) = &self.method {
+            members.push(("method".to_string(), Value::from(method.as_str())));
+        }
+        if let Some(path) = &self.path {
+            members.push(("path".to_string(), Value::from(path.as_str())));
+        }
+        for (field, pairs) in [("query", &self.query), ("headers", &self.headers)] {
+            if !pairs.is_empty() {
+                let object = pairs
+                    .iter()
+                    .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                    .collect();
+                members.push((field.to_string(), Value::Object(object)));
+            }
+        }
+        Value::Object(members)
+    }
+}
+
+// A canned exchange: {"request": {matcher}, "response": {response record}}.
+// Header values and text bodies of the response may reference the request
+// through {{method}}, {{path}}, {{query.NAME}}, {{header.NAME}} and {{body}}.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fixture {
+    pub matcher: Matcher,
+    pub response: ResponseRecord,
+}
+
+impl Fixture {
+    pub fn from_json(value: &Value) -> Result<Self, RecordError> {
+        let matcher = match value.get("request") {
+            Some(request) => Matcher::from_json(request)?,
+            None => Matcher::default(),
+        };
+        let response = value
+            .get("response")
+            .ok_or(RecordError::InvalidField("response"))?;
+
+        Ok(Fixture {
+            matcher,
+            response: ResponseRecord::from_json(response)?,
+        })
+    }
+
+    // Captures a live exchange as a fixture that replays the same response for
+    // the same method, path and query.
+    pub fn record(request: &Request, response: &Response) -> Self {
+        Fixture {
+            matcher: Matcher {
+                method: Some(request.method().clone()),
+                path: Some(request.path().to_string()),
+                query: request
+                    .query()
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                headers: Vec::new(),
+            },
+            response: ResponseRecord::from(response),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        Value::Object(vec![
+            ("request".to_string(), self.matcher.to_json()),
+            ("response".to_string(), self.response.to_json()),
+        ])
+    }
+
+    fn render(&self, request: &Request) -> Response {
+        let mut record = self.response.clone();
+        for (_, value) in &mut record.headers {
+            *value = render_template(value, request);
+        }
+        if let Ok(text) = std::str::from_utf8(&record.body) {
+            record.body = render_template(text, request).into_bytes();
+        }
+        record.into_response()
+    }
+}
+
+// Serves canned responses from fixtures, first match wins. Loading a directory
+// reads every *.json file in name order; each holds one fixture or an array.
+#[derive(Debug, Clone, Default)]
+pub struct Stubs {
+    fixtures: Vec<Fixture>,
+}
+
+impl Stubs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<Self, StubError> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<io::Result<_>>()?;
+        paths.retain(|p| p.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+
+        let mut stubs = Stubs::new();
+        for path in paths {
+            let contents = fs::read_to_string(&path)?;
+            stubs
+                .load_str(&contents)
+                .map_err(|source| StubError::Fixture { path, source })?;
+        }
+        Ok(stubs)
+    }
+
+    pub fn load_str(&mut self, input: &str) -> Result<(), RecordError> {
+        match json::parse(input)? {
+            Value::Array(items) => {
+                for item in &items {
+                    self.fixtures.push(Fixture::from_json(item)?);
+                }
+            }
+            value => self.fixtures.push(Fixture::from_json(&value)?),
+        }
+        Ok(())
+    }
+
+    pub fn with_fixture(mut self, fixture: Fixture) -> Self {
+        self.fixtures.push(fixture);
+        self
+    }
+
+    pub fn fixtures(&self) -> &[Fixture] {
+        &self.fixtures
+    }
+}
+
+impl Handler for Stubs {
+    fn handle(&self, request: &Request) -> Response {
+        match self.fixtures.iter().find(|f| f.matcher.matches(request)) {
+            Some(fixture) => fixture.render(request),
+            None => Problem::new(StatusCode::NotFound)
+                .with_detail(format!(
+                    "No fixture matches {} {}",
+                    request.method().as_str(),
+                    request.path()
+                ))
+                .into_response(request.problem_format()),
+        }
+    }
+}
+
+fn render_template(template: &str, request: &Request) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        out.push_str(&rest[..start]);
+
+        let name = rest[start + 2..start + end].trim();
+        let value = match name {
+            "method" => Some(request.method().as_str()),
+            "path" => Some(request.path()),
+            "body" => request.body_as_str().ok(),
+            _ => match name.split_once('.') {
+                Some(("query", key)) => request.query().get(key),
+                Some(("header", key)) => request.header(key),
+                _ => None,
+            },
+        };
+        out.push_str(value.unwrap_or(""));
+        rest = &rest[start + end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn string_pairs(value: &Value, field: &'static str) -> Result<Vec<(String, String)>, RecordError> {
+    match value.get(field) {
+        None => Ok(Vec::new()),
+        Some(Value::Object(members)) => members
+            .iter()
+            .map(|(name, value)| {
+                value
+                    .as_str()
+                    .map(|v| (name.clone(), v.to_string()))
+                    .ok_or(RecordError::InvalidField(field))
+            })
+            .collect(),
+        Some(_) => Err(RecordError::InvalidField(field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::Body, testing::TempDir};
+
+    fn get(target: &str, extra: &str) -> Request {
+        let raw = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n{}\r\n",
+            target, extra
+        );
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_matching_and_templating() {
+        let mut stubs = Stubs::new();
+        stubs
+            .load_str(
+                r#"[
+                    {"request": {"method": "GET", "path": "/users/*", "query": {"v": "2"}},
+                     "response": {"status": 200,
+                                  "headers": [{"name": "X-Path", "value": "{{path}}"}],
+                                  "body": "hi {{header.X-User}} v{{ query.v }}{{missing}}"}},
+                    {"request": {"path": "/users/*"}, "response": {"status": 410}}
+                ]"#,
+            )
+            .unwrap();
+
+        let response = stubs.handle(&get("/users/7?v=2", "X-User: ana\r\n"));
+        assert_eq!(response.status_code(), StatusCode::OK);
+        assert_eq!(response.headers().get("x-path"), Some("/users/7"));
+        assert_eq!(response.body().as_str().unwrap(), "hi ana v2");
+
+        let response = stubs.handle(&get("/users/7", ""));
+        assert_eq!(response.status_code(), StatusCode::Gone);
+
+        let response = stubs.handle(&get("/other", ""));
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+    }
+
+    #[test]
+    fn test_record_and_load_dir() {
+        let dir = TempDir::new("stub");
+
+        let request = get("/items?page=1", "");
+        let response = Response::ok().with_body(Body::from("[1,2]"));
+        let fixture = Fixture::record(&request, &response);
+        fs::write(dir.join("items.json"), fixture.to_json().to_string()).unwrap();
+        fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let stubs = Stubs::load_dir(dir.path()).unwrap();
+        assert_eq!(stubs.fixtures(), &[fixture]);
+        let replayed = stubs.handle(&get("/items?page=1", ""));
+        assert_eq!(replayed.body().as_bytes(), b"[1,2]");
+        assert_eq!(
+            stubs.handle(&get("/items?page=2", "")).status_code(),
+            StatusCode::NotFound
+        );
+
+        fs::write(dir.join("broken.json"), r#"{"request": {}}"#).unwrap();
+        assert!(matches!(
+            Stubs::load_dir(dir.path()),
+            Err(StubError::Fixture {
+                source: RecordError::InvalidField("response"),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,679 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use thiserror::Error;
+
+use crate::{
+    http::{Method, Request, Response, StatusCode},
+    logging::log_warn,
+    random::random_u64,
+    server::Handler,
+};
+
+// Resumable uploads after the tus 1.0 core protocol: POST creates an upload,
+// HEAD reports how many bytes arrived, and PATCH appends at that offset. A
+// client that loses its connection asks for the offset and sends the rest.
+
+const TUS_VERSION: &str = "1.0.0";
+const OFFSET_STREAM: &str = "application/offset+octet-stream";
+// Upper bound on how often creating an upload sweeps abandoned ones.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Error)]
+pub enum UploadError {
+    #[error("No such upload")]
+    NotFound,
+
+    #[error("Upload is at offset {expected}")]
+    OffsetMismatch { expected: u64 },
+
+    #[error("Upload would exceed its declared length")]
+    TooLarge,
+
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("Upload data must be sent as application/offset+octet-stream")]
+    MediaType,
+
+    #[error("IO error while storing upload")]
+    Io(#[from] io::Error),
+}
+
+impl UploadError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            UploadError::NotFound => StatusCode::NotFound,
+            UploadError::OffsetMismatch { .. } => StatusCode::Conflict,
+            UploadError::TooLarge => StatusCode::ContentTooLarge,
+            UploadError::Invalid(_) => StatusCode::BadRequest,
+            UploadError::MediaType => StatusCode::UnsupportedMediaType,
+            UploadError::Io(_) => StatusCode::InternalServerError,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadInfo {
+    pub offset: u64,
+    // None until the client declares it, for uploads created with a deferred length.
+    pub length: Option<u64>,
+}
+
+impl UploadInfo {
+    pub fn is_complete(&self) -> bool {
+        self.length == Some(self.offset)
+    }
+}
+
+// Where partial uploads live between requests. append must refuse data at any
+// offset but the current end, so two racing PATCHes cannot interleave.
+pub trait UploadStore: Send + Sync {
+    fn create(&self, id: &str, length: Option<u64>) -> Result<(), UploadError>;
+
+    fn info(&self, id: &str) -> Result<UploadInfo, UploadError>;
+
+    fn set_length(&self, id: &str, length: u64) -> Result<(), UploadError>;
+
+    // Returns the new offset.
+    fn append(&self, id: &str, offset: u64, data: &[u8]) -> Result<u64, UploadError>;
+
+    fn delete(&self, id: &str) -> Result<(), UploadError>;
+
+    // Deletes unfinished uploads that received nothing for `idle`, returning how
+    // many went. Finished ones are left for whoever collects them.
+    fn expire(&self, idle: Duration) -> Result<usize, UploadError>;
+}
+
+fn check_append(info: &UploadInfo, offset: u64, len: usize) -> Result<(), UploadError> {
+    if offset != info.offset {
+        return Err(UploadError::OffsetMismatch {
+            expected: info.offset,
+        });
+    }
+    match info.length {
+        Some(length) if offset.saturating_add(len as u64) > length => Err(UploadError::TooLarge),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+struct Partial {
+    length: Option<u64>,
+    data: Vec<u8>,
+    touched: Instant,
+}
+
+impl Partial {
+    fn info(&self) -> UploadInfo {
+        UploadInfo {
+            offset: self.data.len() as u64,
+            length: self.length,
+        }
+    }
+}
+
+// Process-local store, for tests and small files.
+#[derive(Debug, Default)]
+pub struct InMemoryUploads {
+    uploads: Mutex<HashMap<String, Partial>>,
+}
+
+impl InMemoryUploads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Removes a finished upload and returns its bytes.
+    pub fn take(&self, id: &str) -> Option<Vec<u8>> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let complete = uploads.get(id).is_some_and(|p| p.info().is_complete());
+        complete.then(|| uploads.remove(id).unwrap().data)
+    }
+}
+
+impl UploadStore for InMemoryUploads {
+    fn create(&self, id: &str, length: Option<u64>) -> Result<(), UploadError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.insert(
+            id.to_string(),
+            Partial {
+                length,
+                data: Vec::new(),
+                touched: Instant::now(),
+            },
+        );
+        Ok(())
+    }
+
+    fn info(&self, id: &str) -> Result<UploadInfo, UploadError> {
+        let uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads
+            .get(id)
+            .map(Partial::info)
+            .ok_or(UploadError::NotFound)
+    }
+
+    fn set_length(&self, id: &str, length: u64) -> Result<(), UploadError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let upload = uploads.get_mut(id).ok_or(UploadError::NotFound)?;
+        upload.length = Some(length);
+        upload.touched = Instant::now();
+        Ok(())
+    }
+
+    fn append(&self, id: &str, offset: u64, data: &[u8]) -> Result<u64, UploadError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let upload = uploads.get_mut(id).ok_or(UploadError::NotFound)?;
+        check_append(&upload.info(), offset, data.len())?;
+        upload.data.extend_from_slice(data);
+        upload.touched = Instant::now();
+        Ok(upload.data.len() as u64)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), UploadError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.remove(id).map(|_| ()).ok_or(UploadError::NotFound)
+    }
+
+    fn expire(&self, idle: Duration) -> Result<usize, UploadError> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        let before = uploads.len();
+        uploads.retain(|_, upload| upload.info().is_complete() || upload.touched.elapsed() < idle);
+        Ok(before - uploads.len())
+    }
+}
+
+// Keeps each upload in a file under a directory, so uploads survive restarts.
+// The data file's size is the offset; the declared length sits next to it in
+// `<id>.length`, empty while deferred.
+#[derive(Debug)]
+pub struct FileUploads {
+    dir: PathBuf,
+    // Serializes appends, which check the file size before writing.
+    lock: Mutex<()>,
+}
+
+impl FileUploads {
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileUploads {
+            dir,
+            lock: Mutex::new(()),
+        })
+    }
+
+    // The data file of an upload, to move into place once it is complete.
+    pub fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    fn length_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.length", id))
+    }
+
+    fn read_info(&self, id: &str) -> Result<UploadInfo, UploadError> {
+        let not_found = |e: io::Error| match e.kind() {
+            io::ErrorKind::NotFound => UploadError::NotFound,
+            _ => UploadError::Io(e),
+        };
+        let offset = fs::metadata(self.path(id)).map_err(not_found)?.len();
+        let length = fs::read_to_string(self.length_path(id)).map_err(not_found)?;
+        Ok(UploadInfo {
+            offset,
+            length: length.trim().parse().ok(),
+        })
+    }
+}
+
+impl UploadStore for FileUploads {
+    fn create(&self, id: &str, length: Option<u64>) -> Result<(), UploadError> {
+        let length = length.map(|n| n.to_string()).unwrap_or_default();
+        fs::write(self.length_path(id), length)?;
+        fs::File::create(self.path(id))?;
+        Ok(())
+    }
+
+    fn info(&self, id: &str) -> Result<UploadInfo, UploadError> {
+        self.read_info(id)
+    }
+
+    fn set_length(&self, id: &str, length: u64) -> Result<(), UploadError> {
+        self.read_info(id)?;
+        fs::write(self.length_path(id), length.to_string())?;
+        Ok(())
+    }
+
+    fn append(&self, id: &str, offset: u64, data: &[u8]) -> Result<u64, UploadError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let info = self.read_info(id)?;
+        check_append(&info, offset, data.len())?;
+        let mut file = OpenOptions::new().append(true).open(self.path(id))?;
+        file.write_all(data)?;
+        file.sync_data()?;
+        Ok(info.offset + data.len() as u64)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), UploadError> {
+        self.read_info(id)?;
+        fs::remove_file(self.path(id))?;
+        fs::remove_file(self.length_path(id))?;
+        Ok(())
+    }
+
+    // An upload was last touched when its data or its length file last changed.
+    fn expire(&self, idle: Duration) -> Result<usize, UploadError> {
+        let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let now = SystemTime::now();
+        let mut expired = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let name = entry?.file_name();
+            let Some(id) = name.to_str().filter(|id| is_upload_id(id)) else {
+                continue;
+            };
+            let Ok(info) = self.read_info(id) else {
+                continue;
+            };
+            let touched = [self.path(id), self.length_path(id)]
+                .iter()
+                .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+                .max();
+            let idle_for =
+                touched.map_or(Duration::MAX, |t| now.duration_since(t).unwrap_or_default());
+            if !info.is_complete() && idle_for >= idle {
+                fs::remove_file(self.path(id))?;
+                fs::remove_file(self.length_path(id))?;
+                expired += 1;
+            }
+        }
+        Ok(expired)
+    }
+}
+
+// Ids are hex, which also keeps them safe as file names.
+fn is_upload_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+type CompleteHook = Box<dyn Fn(&str) + Send + Sync>;
+
+// Serves uploads under `base`: POST to it creates one and answers with its
+// Location, the rest address `<base>/<id>`. Each PATCH body is buffered like any
+// request body, so clients should send large files in several PATCHes; an
+// interrupted one is lost whole and resent from the last reported offset.
+pub struct Uploads<S: UploadStore> {
+    base: String,
+    store: S,
+    max_size: Option<u64>,
+    on_complete: Option<CompleteHook>,
+    expire_after: Option<Duration>,
+    swept: Mutex<Instant>,
+}
+
+impl<S: UploadStore> Uploads<S> {
+    pub fn new(base: &str, store: S) -> Self {
+        Uploads {
+            base: base.trim_end_matches('/').to_string(),
+            store,
+            max_size: None,
+            on_complete: None,
+            expire_after: None,
+            swept: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    // Called with the upload id once the last byte is stored.
+    pub fn on_complete(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_complete = Some(Box::new(hook));
+        self
+    }
+
+    // Deletes unfinished uploads idle this long. The sweep runs as new uploads
+    // are created, at most once a minute.
+    pub fn expire_after(mut self, idle: Duration) -> Self {
+        self.expire_after = Some(idle);
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    fn sweep(&self) {
+        let Some(idle) = self.expire_after else {
+            return;
+        };
+        {
+            let mut swept = self.swept.lock().unwrap_or_else(|e| e.into_inner());
+            if swept.elapsed() < SWEEP_INTERVAL.min(idle) {
+                return;
+            }
+            *swept = Instant::now();
+        }
+        if let Err(e) = self.store.expire(idle) {
+            log_warn!("Failed to expire abandoned uploads: {}", e);
+        }
+    }
+
+    fn options(&self) -> Response {
+        let response = Response::no_content()
+            .with_header("Tus-Version", TUS_VERSION)
+            .with_header(
+                "Tus-Extension",
+                "creation,creation-defer-length,termination",
+            );
+        match self.max_size {
+            Some(max) => response.with_header("Tus-Max-Size", max.to_string()),
+            None => response,
+        }
+    }
+
+    fn create(&self, request: &Request) -> Result<Response, UploadError> {
+        let length = match (
+            request.header("Upload-Length"),
+            request.header("Upload-Defer-Length"),
+        ) {
+            (Some(length), _) => Some(parse_offset(length, "Upload-Length")?),
+            (None, Some("1")) => None,
+            _ => {
+                return Err(bad_request(
+                    "Upload-Length or Upload-Defer-Length: 1 is required",
+                ));
+            }
+        };
+        if let (Some(length), Some(max)) = (length, self.max_size)
+            && length > max
+        {
+            return Err(UploadError::TooLarge);
+        }
+
+        self.sweep();
+        let id = format!("{:016x}{:016x}", random_u64(), random_u64());
+        self.store.create(&id, length)?;
+
+        // creation-with-upload: the POST may carry the first bytes.
+        let mut offset = 0;
+        if !request.body().is_empty() {
+            offset = self.append(request, &id, 0)?;
+        }
+        Ok(Response::created()
+            .with_header("Location", format!("{}/{}", self.base, id))
+            .with_header("Upload-Offset", offset.to_string()))
+    }
+
+    fn patch(&self, request: &Request, id: &str) -> Result<Response, UploadError> {
+        let offset = match request.header("Upload-Offset") {
+            Some(offset) => parse_offset(offset, "Upload-Offset")?,
+            None => return Err(bad_request("Upload-Offset is required")),
+        };
+        let info = self.store.info(id)?;
+        if let Some(length) = request.header("Upload-Length") {
+            let length = parse_offset(length, "Upload-Length")?;
+            if info.length.is_some_and(|known| known != length) {
+                return Err(bad_request("Upload-Length cannot change"));
+            }
+            if self.max_size.is_some_and(|max| length > max) {
+                return Err(UploadError::TooLarge);
+            }
+            self.store.set_length(id, length)?;
+        }
+        let offset = self.append(request, id, offset)?;
+        Ok(Response::no_content().with_header("Upload-Offset", offset.to_string()))
+    }
+
+    fn append(&self, request: &Request, id: &str, offset: u64) -> Result<u64, UploadError> {
+        let is_stream = request
+            .header("Content-Type")
+            .is_some_and(|t| t.trim().eq_ignore_ascii_case(OFFSET_STREAM));
+        if !is_stream {
+            return Err(UploadError::MediaType);
+        }
+        let data = request.body().as_bytes();
+        // The offset comes from the client, so the end may not fit in a u64.
+        let end = offset.checked_add(data.len() as u64);
+        if end.is_none_or(|end| self.max_size.is_some_and(|max| end > max)) {
+            return Err(UploadError::TooLarge);
+        }
+        let offset = self.store.append(id, offset, data)?;
+        if let Some(hook) = &self.on_complete
+            && self.store.info(id).is_ok_and(|info| info.is_complete())
+        {
+            hook(id);
+        }
+        Ok(offset)
+    }
+
+    fn head(&self, id: &str) -> Result<Response, UploadError> {
+        let info = self.store.info(id)?;
+        let response = Response::ok()
+            .with_header("Upload-Offset", info.offset.to_string())
+            .with_header("Cache-Control", "no-store");
+        Ok(match info.length {
+            Some(length) => response.with_header("Upload-Length", length.to_string()),
+            None => response.with_header("Upload-Defer-Length", "1"),
+        })
+    }
+
+    // The id in `<base>/<id>`.
+    fn upload_id<'a>(&self, path: &'a str) -> Option<&'a str> {
+        let id = path.strip_prefix(&self.base)?.strip_prefix('/')?;
+        is_upload_id(id).then_some(id)
+    }
+}
+
+fn parse_offset(value: &str, header: &str) -> Result<u64, UploadError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| bad_request(&format!("{} must be a non-negative integer", header)))
+}
+
+fn bad_request(detail: &str) -> UploadError {
+    UploadError::Invalid(detail.to_string())
+}
+
+fn error_response(e: &UploadError) -> Response {
+    let response = Response::problem(
+        e.status(),
+        e.status().reason_parse(),
+        &e.to_string(),
+        "about:blank",
+    );
+    match e {
+        UploadError::OffsetMismatch { expected } => {
+            response.with_header("Upload-Offset", expected.to_string())
+        }
+        _ => response,
+    }
+}
+
+impl<S: UploadStore> Handler for Uploads<S> {
+    fn handle(&self, request: &Request) -> Response {
+        // Plain HTTP clients may leave Tus-Resumable out; a version we do not
+        // speak is refused.
+        if request.method() != &Method::OPTIONS
+            && request
+                .header("Tus-Resumable")
+                .is_some_and(|version| version.trim() != TUS_VERSION)
+        {
+            return Response::new(StatusCode::PreconditionFailed)
+                .with_header("Tus-Version", TUS_VERSION);
+        }
+
+        let path = request.path();
+        let result = match (request.method(), path == self.base) {
+            (Method::OPTIONS, _) => Ok(self.options()),
+            (Method::POST, true) => self.create(request),
+            (_, true) => Ok(Response::method_not_allowed().with_header("Allow", "POST, OPTIONS")),
+            (method, false) => match self.upload_id(path) {
+                None => Ok(Response::not_found()),
+                Some(id) => match method {
+                    Method::HEAD => self.head(id),
+                    Method::PATCH => self.patch(request, id),
+                    Method::DELETE => self.store.delete(id).map(|_| Response::no_content()),
+                    _ => Ok(Response::method_not_allowed()
+                        .with_header("Allow", "HEAD, PATCH, DELETE, OPTIONS")),
+                },
+            },
+        };
+        result
+            .unwrap_or_else(|e| error_response(&e))
+            .with_header("Tus-Resumable", TUS_VERSION)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn send(uploads: &Uploads<impl UploadStore>, head: &str, body: &[u8]) -> Response {
+        let mut raw = format!(
+            "{}\r\nHost: x\r\nContent-Length: {}\r\n\r\n",
+            head,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+        uploads.handle(&Request::try_from(raw.as_slice()).unwrap())
+    }
+
+    fn patch(
+        uploads: &Uploads<impl UploadStore>,
+        location: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Response {
+        let head = format!(
+            "PATCH {} HTTP/1.1\r\nContent-Type: application/offset+octet-stream\r\nUpload-Offset: {}",
+            location, offset
+        );
+        send(uploads, &head, data)
+    }
+
+    #[test]
+    fn test_resumes_an_interrupted_upload() {
+        let completed = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let seen = completed.clone();
+        let uploads = Uploads::new("/files", InMemoryUploads::new())
+            .max_size(1024)
+            .on_complete(move |id| seen.lock().unwrap().push(id.to_string()));
+
+        let created = send(&uploads, "POST /files HTTP/1.1\r\nUpload-Length: 11", b"");
+        assert_eq!(created.status_code(), StatusCode::Created);
+        let location = created.headers().get("location").unwrap().to_string();
+        let id = location.strip_prefix("/files/").unwrap().to_string();
+
+        let first = patch(&uploads, &location, 0, b"hello");
+        assert_eq!(first.status_code(), StatusCode::NoContent);
+        assert_eq!(first.headers().get("upload-offset"), Some("5"));
+
+        // The client lost track and retries from the start.
+        let stale = patch(&uploads, &location, 0, b"hello");
+        assert_eq!(stale.status_code(), StatusCode::Conflict);
+        let head = send(&uploads, &format!("HEAD {} HTTP/1.1", location), b"");
+        assert_eq!(head.headers().get("upload-offset"), Some("5"));
+        assert_eq!(head.headers().get("upload-length"), Some("11"));
+
+        assert_eq!(
+            patch(&uploads, &location, 5, b" world!").status_code(),
+            StatusCode::ContentTooLarge
+        );
+        assert!(completed.lock().unwrap().is_empty());
+        patch(&uploads, &location, 5, b" world");
+        assert_eq!(*completed.lock().unwrap(), vec![id.clone()]);
+        assert_eq!(uploads.store().take(&id).unwrap(), b"hello world");
+    }
+
+    #[test]
+    fn test_rejects_bad_requests() {
+        let uploads = Uploads::new("/files", InMemoryUploads::new()).max_size(10);
+        let status = |head: &str| send(&uploads, head, b"").status_code();
+
+        assert_eq!(status("POST /files HTTP/1.1"), StatusCode::BadRequest);
+        assert_eq!(
+            status("POST /files HTTP/1.1\r\nUpload-Length: 11"),
+            StatusCode::ContentTooLarge
+        );
+        assert_eq!(
+            status("POST /files HTTP/1.1\r\nUpload-Length: 1\r\nTus-Resumable: 0.2.2"),
+            StatusCode::PreconditionFailed
+        );
+        assert_eq!(status("HEAD /files/abc HTTP/1.1"), StatusCode::NotFound);
+        assert_eq!(status("HEAD /files/../x HTTP/1.1"), StatusCode::NotFound);
+
+        let options = send(&uploads, "OPTIONS /files HTTP/1.1", b"");
+        assert_eq!(options.headers().get("tus-max-size"), Some("10"));
+
+        let created = send(&uploads, "POST /files HTTP/1.1\r\nUpload-Length: 5", b"");
+        let location = created.headers().get("location").unwrap().to_string();
+        assert_eq!(
+            patch(&uploads, &location, u64::MAX, b"x").status_code(),
+            StatusCode::ContentTooLarge
+        );
+    }
+
+    #[test]
+    fn test_abandoned_uploads_expire() {
+        let uploads =
+            Uploads::new("/files", InMemoryUploads::new()).expire_after(Duration::from_millis(50));
+        let create = |length| {
+            let head = format!("POST /files HTTP/1.1\r\nUpload-Length: {}", length);
+            let created = send(&uploads, &head, b"");
+            created.headers().get("location").unwrap().to_string()
+        };
+        let abandoned = create(5);
+        let finished = create(2);
+        patch(&uploads, &finished, 0, b"ok");
+
+        std::thread::sleep(Duration::from_millis(100));
+        let fresh = create(5);
+        let status = |location: &str| {
+            send(&uploads, &format!("HEAD {} HTTP/1.1", location), b"").status_code()
+        };
+        assert_eq!(status(&abandoned), StatusCode::NotFound);
+        assert_eq!(status(&finished), StatusCode::OK);
+        assert_eq!(status(&fresh), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_file_store_keeps_deferred_uploads() {
+        let dir = TempDir::new("uploads");
+        let store = FileUploads::open(dir.path()).unwrap();
+        store.create("ab12", None).unwrap();
+        assert_eq!(store.append("ab12", 0, b"abc").unwrap(), 3);
+        assert!(matches!(
+            store.append("ab12", 1, b"x"),
+            Err(UploadError::OffsetMismatch { expected: 3 })
+        ));
+        store.set_length("ab12", 4).unwrap();
+
+        let reopened = FileUploads::open(dir.path()).unwrap();
+        assert_eq!(
+            reopened.info("ab12").unwrap(),
+            UploadInfo {
+                offset: 3,
+                length: Some(4)
+            }
+        );
+        reopened.append("ab12", 3, b"d").unwrap();
+        assert_eq!(fs::read(reopened.path("ab12")).unwrap(), b"abcd");
+        reopened.create("cd34", Some(4)).unwrap();
+        assert_eq!(reopened.expire(Duration::ZERO).unwrap(), 1);
+        assert!(matches!(reopened.info("cd34"), Err(UploadError::NotFound)));
+        reopened.delete("ab12").unwrap();
+        assert!(matches!(reopened.info("ab12"), Err(UploadError::NotFound)));
+    }
+}
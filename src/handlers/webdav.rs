@@ -0,0 +1,226 @@
+use std::{
+    fs::{self, Metadata},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    date::DateTime,
+    http::{
+        Body, Depth, Method, Request, Response, StatusCode, path::encode_segment,
+        problem::escape_xml,
+    },
+    server::Handler,
+};
+
+use super::static_files::{StaticFiles, content_type};
+
+const ALLOW: &str = "OPTIONS, GET, HEAD, PROPFIND";
+
+// Read-only WebDAV (RFC 4918 class 1) over a directory tree, enough for file
+// explorers to mount it and copy files out. GET and HEAD are served by
+// StaticFiles; PROPFIND answers with the live properties of a resource and, at
+// Depth 1, of its members. The request body is not parsed: every PROPFIND is
+// treated as allprop, which clients asking for specific properties accept.
+pub struct WebDav {
+    files: StaticFiles,
+    prefix: String,
+}
+
+impl WebDav {
+    pub fn new(root: impl Into<PathBuf>, prefix: &str) -> Self {
+        WebDav {
+            files: StaticFiles::new(root, prefix),
+            prefix: prefix.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn propfind(&self, request: &Request) -> Response {
+        // Depth defaults to infinity, which would walk the whole tree.
+        let depth = match (request.header("Depth"), request.depth()) {
+            (_, Some(depth)) => depth,
+            (None, None) => Depth::Infinity,
+            (Some(_), None) => {
+                return Response::problem(
+                    StatusCode::BadRequest,
+                    "Invalid Depth",
+                    "Depth must be 0, 1 or infinity",
+                    "about:blank",
+                );
+            }
+        };
+        if depth == Depth::Infinity {
+            return multistatus_response(
+                StatusCode::Forbidden,
+                "<D:error xmlns:D=\"DAV:\"><D:propfind-finite-depth/></D:error>\n".to_string(),
+            );
+        }
+
+        let Some((target, _)) = self.files.locate(request.path()) else {
+            return Response::not_found();
+        };
+        let Ok(metadata) = fs::metadata(&target) else {
+            return Response::not_found();
+        };
+
+        let relative = request
+            .path()
+            .strip_prefix(&self.prefix)
+            .unwrap_or("")
+            .trim_matches('/');
+        let mut body = String::from(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<D:multistatus xmlns:D=\"DAV:\">\n",
+        );
+        body.push_str(&self.entry(relative, &target, &metadata));
+        if depth == Depth::One && metadata.is_dir() {
+            match self.members(relative, &target) {
+                Ok(members) => body.push_str(&members),
+                Err(_) => return Response::not_found(),
+            }
+        }
+        body.push_str("</D:multistatus>\n");
+        multistatus_response(StatusCode::MultiStatus, body)
+    }
+
+    // Members in name order; dotfiles and names that are not UTF-8 are left out,
+    // as in StaticFiles listings.
+    fn members(&self, relative: &str, dir: &Path) -> io::Result<String> {
+        let mut members = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if !name.starts_with('.') {
+                members.push((name, entry.path()));
+            }
+        }
+        members.sort();
+
+        let mut out = String::new();
+        for (name, path) in members {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let relative = match relative {
+                "" => name,
+                parent => format!("{}/{}", parent, name),
+            };
+            out.push_str(&self.entry(&relative, &path, &metadata));
+        }
+        Ok(out)
+    }
+
+    // One <D:response> for a resource at `relative` (decoded, no outer slashes).
+    fn entry(&self, relative: &str, path: &Path, metadata: &Metadata) -> String {
+        let mut href = self.prefix.clone();
+        for segment in relative.split('/').filter(|s| !s.is_empty()) {
+            href.push('/');
+            href.push_str(&encode_segment(segment));
+        }
+        if metadata.is_dir() || href.is_empty() {
+            href.push('/');
+        }
+        let name = relative.rsplit('/').next().unwrap_or("");
+
+        let mut props = format!("<D:displayname>{}</D:displayname>", escape_xml(name));
+        if metadata.is_dir() {
+            props.push_str("<D:resourcetype><D:collection/></D:resourcetype>");
+        } else {
+            props.push_str(&format!(
+                "<D:resourcetype/><D:getcontentlength>{}</D:getcontentlength>\
+                 <D:getcontenttype>{}</D:getcontenttype>",
+                metadata.len(),
+                escape_xml(content_type(path))
+            ));
+        }
+        if let Ok(modified) = metadata.modified() {
+            props.push_str(&format!(
+                "<D:getlastmodified>{}</D:getlastmodified>",
+                DateTime::from_system_time(modified).to_http_date()
+            ));
+        }
+        format!(
+            "<D:response><D:href>{}</D:href><D:propstat><D:prop>{}</D:prop>\
+             <D:status>HTTP/1.1 200 OK</D:status></D:propstat></D:response>\n",
+            escape_xml(&href),
+            props
+        )
+    }
+}
+
+fn multistatus_response(status: StatusCode, body: String) -> Response {
+    Response::new(status)
+        .with_header("Content-Type", "application/xml; charset=utf-8")
+        .with_body(Body::from(body))
+}
+
+impl Handler for WebDav {
+    fn handle(&self, request: &Request) -> Response {
+        match request.method() {
+            // MS-Author-Via tells Windows to use WebDAV rather than FrontPage.
+            Method::OPTIONS => Response::ok()
+                .with_header("DAV", "1")
+                .with_header("Allow", ALLOW)
+                .with_header("MS-Author-Via", "DAV"),
+            Method::GET | Method::HEAD => self.files.handle(request),
+            Method::PROPFIND => self.propfind(request),
+            _ => Response::method_not_allowed().with_header("Allow", ALLOW),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn send(dav: &WebDav, head: &str) -> Response {
+        let raw = format!("{}\r\nHost: localhost\r\n\r\n", head);
+        dav.handle(&Request::try_from(raw.as_bytes()).unwrap())
+    }
+
+    #[test]
+    fn test_propfind_lists_a_collection() {
+        let root = TempDir::new("webdav");
+        fs::create_dir_all(root.join("docs/old")).unwrap();
+        fs::write(root.join("docs/a & b.txt"), "hello").unwrap();
+        fs::write(root.join("docs/.hidden"), "").unwrap();
+        let dav = WebDav::new(root.path(), "/dav");
+
+        let response = send(&dav, "PROPFIND /dav/docs HTTP/1.1\r\nDepth: 1");
+        assert_eq!(response.status_code(), StatusCode::MultiStatus);
+        let xml = response.body().as_str().unwrap();
+        assert!(xml.contains("<D:href>/dav/docs/</D:href>"));
+        assert!(xml.contains("<D:href>/dav/docs/a%20&amp;%20b.txt</D:href>"));
+        assert!(xml.contains("<D:displayname>a &amp; b.txt</D:displayname>"));
+        assert!(xml.contains("<D:getcontentlength>5</D:getcontentlength>"));
+        assert!(xml.contains("<D:href>/dav/docs/old/</D:href>"));
+        assert!(!xml.contains("hidden"));
+        assert_eq!(xml.matches("<D:response>").count(), 3);
+
+        let root_only = send(&dav, "PROPFIND /dav/ HTTP/1.1\r\nDepth: 0");
+        let xml = root_only.body().as_str().unwrap();
+        assert!(xml.contains("<D:href>/dav/</D:href><D:propstat><D:prop><D:displayname></D:displayname><D:resourcetype><D:collection/>"));
+        assert_eq!(xml.matches("<D:response>").count(), 1);
+
+        let infinite = send(&dav, "PROPFIND /dav/docs HTTP/1.1");
+        assert_eq!(infinite.status_code(), StatusCode::Forbidden);
+        assert_eq!(
+            send(&dav, "PROPFIND /dav/nope HTTP/1.1\r\nDepth: 0").status_code(),
+            StatusCode::NotFound
+        );
+        assert_eq!(
+            send(&dav, "GET /dav/docs/a%20&%20b.txt HTTP/1.1")
+                .body()
+                .as_bytes(),
+            b"hello"
+        );
+        let options = send(&dav, "OPTIONS /dav/ HTTP/1.1");
+        assert_eq!(options.headers().get("dav"), Some("1"));
+        assert_eq!(
+            send(&dav, "MKCOL /dav/new HTTP/1.1").status_code(),
+            StatusCode::MethodNotAllowed
+        );
+    }
+}
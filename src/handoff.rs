@@ -0,0 +1,120 @@
+use std::{
+    env,
+    ffi::c_int,
+    io,
+    net::TcpListener,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::process::CommandExt,
+    },
+    process::{Child, Command},
+};
+
+// Descriptor number of the inherited listening socket.
+pub const LISTEN_FD_ENV: &str = "RAWHTTP_LISTEN_FD";
+// Connections the predecessor was still serving when it handed over.
+pub const PARENT_CONNECTIONS_ENV: &str = "RAWHTTP_PARENT_CONNECTIONS";
+
+// Same values on every unix we build for.
+const F_GETFD: c_int = 1;
+const F_SETFD: c_int = 2;
+const FD_CLOEXEC: c_int = 1;
+
+unsafe extern "C" {
+    fn fcntl(fd: RawFd, cmd: c_int, ...) -> c_int;
+}
+
+// Execs `successor` with a duplicate of the listening socket left open across exec.
+// The duplicate is made close-on-exec (try_clone uses F_DUPFD_CLOEXEC), so a child
+// another thread spawns meanwhile never gets it; the flag is cleared in the forked
+// successor alone, just before exec. Ours is closed once the child is running.
+pub fn spawn_successor(
+    mut successor: Command,
+    listener: &TcpListener,
+    in_flight: usize,
+) -> io::Result<Child> {
+    let inherited = listener.try_clone()?;
+    let fd = inherited.as_raw_fd();
+
+    successor
+        .env(LISTEN_FD_ENV, fd.to_string())
+        .env(PARENT_CONNECTIONS_ENV, in_flight.to_string());
+    // Runs between fork and exec, where only async-signal-safe calls may be made;
+    // fcntl is one of them.
+    unsafe {
+        successor.pre_exec(move || {
+            if fcntl(fd, F_SETFD, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    let child = successor.spawn();
+    drop(inherited);
+    child
+}
+
+// What a predecessor passed down through spawn_successor.
+#[derive(Debug)]
+pub struct Inherited {
+    pub listener: TcpListener,
+    // Connections the predecessor was still serving, 0 if it did not say.
+    pub parent_connections: usize,
+}
+
+/// Takes over the listening socket passed down by a predecessor, if this process
+/// was started by spawn_successor. Both variables are read once and cleared so our
+/// own children do not see them, and the socket is made close-on-exec again. A
+/// number that is not an open, bound socket is refused.
+///
+/// # Safety
+///
+/// Call this once, at the top of main before any other thread exists, since it
+/// modifies the environment; and before anything else in the process could own the
+/// descriptor named there, since the returned listener takes ownership of it.
+pub unsafe fn inherit() -> Option<Inherited> {
+    let fd = env::var(LISTEN_FD_ENV).ok();
+    let parent_connections = env::var(PARENT_CONNECTIONS_ENV).ok();
+    unsafe {
+        env::remove_var(LISTEN_FD_ENV);
+        env::remove_var(PARENT_CONNECTIONS_ENV);
+    }
+
+    let fd = fd?.parse::<RawFd>().ok().filter(|&fd| fd > 2)?;
+    // F_GETFD fails on a descriptor that is not open.
+    if unsafe { fcntl(fd, F_GETFD) } < 0 || unsafe { fcntl(fd, F_SETFD, FD_CLOEXEC) } < 0 {
+        return None;
+    }
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    // Anything other than a bound socket is closed again here.
+    listener.local_addr().ok()?;
+    Some(Inherited {
+        listener,
+        parent_connections: parent_connections
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Stdio;
+
+    #[test]
+    fn test_successor_inherits_the_listening_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut successor = Command::new("sh");
+        successor
+            .arg("-c")
+            .arg("[ -e /dev/fd/$RAWHTTP_LISTEN_FD ] && echo $RAWHTTP_PARENT_CONNECTIONS")
+            .stdout(Stdio::piped());
+
+        let output = spawn_successor(successor, &listener, 7)
+            .unwrap()
+            .wait_with_output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "7");
+    }
+}
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum AcceptEncodingError {
+    #[error("Invalid content coding: {0}")]
+    InvalidCoding(String),
+
+    #[error("Invalid quality value: {0}")]
+    InvalidQuality(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodingPreference {
+    pub coding: String,
+    pub quality: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AcceptEncoding {
+    preferences: Vec<EncodingPreference>,
+}
+
+impl AcceptEncoding {
+    pub fn parse(value: &str) -> Result<Self, AcceptEncodingError> {
+        let mut preferences = Vec::new();
+
+        for item in value.split(',') {
+            let item = item.trim();
+            if item.is_empty() {
+                continue;
+            }
+
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or("").trim().to_lowercase();
+            if coding.is_empty() || !is_token(&coding) {
+                return Err(AcceptEncodingError::InvalidCoding(coding));
+            }
+
+            let mut quality = 1000;
+            for param in params {
+                let (name, value) = param.split_once('=').unwrap_or((param, ""));
+                if name.trim().eq_ignore_ascii_case("q") {
+                    quality = parse_qvalue(value.trim())?;
+                }
+            }
+
+            preferences.push(EncodingPreference { coding, quality });
+        }
+
+        Ok(AcceptEncoding { preferences })
+    }
+
+    pub fn preferences(&self) -> &[EncodingPreference] {
+        &self.preferences
+    }
+
+    // RFC 9110 12.5.3: an explicit entry wins over "*", and identity is
+    // acceptable unless excluded by "identity;q=0" or "*;q=0".
+    pub fn quality(&self, coding: &str) -> u16 {
+        let lookup = |name: &str| {
+            self.preferences
+                .iter()
+                .find(|p| p.coding.eq_ignore_ascii_case(name))
+                .map(|p| p.quality)
+        };
+
+        if let Some(q) = lookup(coding) {
+            return q;
+        }
+        if let Some(q) = lookup("*") {
+            return q;
+        }
+        if coding.eq_ignore_ascii_case("identity") {
+            return 1;
+        }
+        0
+    }
+
+    pub fn is_acceptable(&self, coding: &str) -> bool {
+        self.quality(coding) > 0
+    }
+
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&'a str, u16)> = None;
+
+        for &coding in available {
+            let q = self.quality(coding);
+            if q == 0 {
+                continue;
+            }
+            if best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((coding, q));
+            }
+        }
+
+        if let Some((coding, _)) = best {
+            return Some(coding);
+        }
+
+        if self.is_acceptable("identity") {
+            Some("identity")
+        } else {
+            None
+        }
+    }
+}
+
+impl FromStr for AcceptEncoding {
+    type Err = AcceptEncodingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn is_token(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+// qvalue = ( "0" [ "." 0*3DIGIT ] ) / ( "1" [ "." 0*3("0") ] ), scaled to 0..=1000
+pub(crate) fn parse_qvalue(s: &str) -> Result<u16, AcceptEncodingError> {
+    let invalid = || AcceptEncodingError::InvalidQuality(s.to_string());
+
+    let (int, frac) = s.split_once('.').unwrap_or((s, ""));
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+
+    let mut millis: u16 = match int {
+        "0" => 0,
+        "1" => 1000,
+        _ => return Err(invalid()),
+    };
+    for (i, digit) in frac.bytes().enumerate() {
+        millis += (digit - b'0') as u16 * [100, 10, 1][i];
+    }
+
+    if millis > 1000 {
+        return Err(invalid());
+    }
+    Ok(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_with_qvalues() {
+        let ae = AcceptEncoding::parse("gzip;q=0.8, br, deflate;q=0").unwrap();
+
+        assert_eq!(ae.quality("br"), 1000);
+        assert_eq!(ae.quality("gzip"), 800);
+        assert_eq!(ae.quality("deflate"), 0);
+        assert_eq!(ae.quality("zstd"), 0);
+    }
+
+    #[test]
+    fn test_negotiate_prefers_highest_quality() {
+        let ae = AcceptEncoding::parse("gzip;q=0.5, br;q=0.9").unwrap();
+        assert_eq!(ae.negotiate(&["gzip", "br"]), Some("br"));
+        assert_eq!(ae.negotiate(&["deflate"]), Some("identity"));
+    }
+
+    #[test]
+    fn test_identity_excluded() {
+        let ae = AcceptEncoding::parse("gzip, identity;q=0").unwrap();
+        assert_eq!(ae.negotiate(&["gzip"]), Some("gzip"));
+        assert_eq!(ae.negotiate(&["br"]), None);
+    }
+
+    #[test]
+    fn test_wildcard_zero_excludes_everything_unlisted() {
+        let ae = AcceptEncoding::parse("br, *;q=0").unwrap();
+        assert!(!ae.is_acceptable("identity"));
+        assert!(!ae.is_acceptable("gzip"));
+        assert_eq!(ae.negotiate(&["gzip"]), None);
+        assert_eq!(ae.negotiate(&["gzip", "br"]), Some("br"));
+    }
+
+    #[test]
+    fn test_empty_header_allows_identity_only() {
+        let ae = AcceptEncoding::parse("").unwrap();
+        assert_eq!(ae.negotiate(&["gzip"]), Some("identity"));
+    }
+
+    #[test]
+    fn test_invalid_qvalue() {
+        assert!(AcceptEncoding::parse("gzip;q=2").is_err());
+        assert!(AcceptEncoding::parse("gzip;q=0.1234").is_err());
+        assert!(AcceptEncoding::parse("gzip;q=abc").is_err());
+        assert!(AcceptEncoding::parse("gz ip").is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::cell::RefCell;
+use std::str;
+
+use super::{Method, QueryError, Request};
+
+// Capacity an arena keeps between requests. A request that needed more leaves
+// the extra to the allocator once it is done.
+const MAX_RETAINED: usize = 16 * 1024; // 16KB
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+}
+
+// Bump storage for the strings a handler reads off one request. Decoded query
+// keys and values are appended to a single buffer that is cleared, not freed,
+// between requests, so a warmed-up arena serves a request without allocating.
+#[derive(Debug, Default)]
+pub struct RequestArena {
+    text: String,
+    scratch: Vec<u8>,
+    query: Vec<(Span, Span)>,
+}
+
+impl RequestArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(bytes: usize) -> Self {
+        RequestArena {
+            text: String::with_capacity(bytes),
+            ..Self::default()
+        }
+    }
+
+    // Bytes the arena can hold before it has to grow.
+    pub fn capacity(&self) -> usize {
+        self.text.capacity()
+    }
+
+    // Decodes what `request` needs into the arena, replacing whatever the
+    // previous request left there.
+    pub fn bind<'a>(&'a mut self, request: &'a Request) -> Result<ArenaRequest<'a>, QueryError> {
+        self.clear();
+        let query = request.target().split_once('?').map_or("", |(_, q)| q);
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let key = self.decode(key)?;
+            let value = self.decode(value)?;
+            self.query.push((key, value));
+        }
+        Ok(ArenaRequest {
+            request,
+            arena: self,
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.text.clear();
+        self.query.clear();
+    }
+
+    fn release(&mut self) {
+        self.clear();
+        self.text.shrink_to(MAX_RETAINED);
+        self.scratch.clear();
+        self.scratch.shrink_to(MAX_RETAINED);
+    }
+
+    // Percent-decodes `raw` as Query::decode_url does, into the arena.
+    fn decode(&mut self, raw: &str) -> Result<Span, QueryError> {
+        self.scratch.clear();
+        let bytes = raw.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'%' => {
+                    let byte = bytes
+                        .get(i + 1..i + 3)
+                        .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                        .and_then(|hex| str::from_utf8(hex).ok())
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .ok_or(QueryError::InvalidEncoding)?;
+                    self.scratch.push(byte);
+                    i += 3;
+                }
+                b'+' => {
+                    self.scratch.push(b' ');
+                    i += 1;
+                }
+                byte => {
+                    self.scratch.push(byte);
+                    i += 1;
+                }
+            }
+        }
+        let decoded = str::from_utf8(&self.scratch).map_err(|_| QueryError::InvalidEncoding)?;
+        let start = self.text.len();
+        self.text.push_str(decoded);
+        Ok(Span {
+            start,
+            end: self.text.len(),
+        })
+    }
+
+    fn get(&self, span: Span) -> &str {
+        &self.text[span.start..span.end]
+    }
+}
+
+// A request seen through an arena: every string it hands out lives as long as
+// the binding, and borrows either the request or the arena instead of being
+// copied out.
+#[derive(Clone, Copy)]
+pub struct ArenaRequest<'a> {
+    request: &'a Request,
+    arena: &'a RequestArena,
+}
+
+impl<'a> ArenaRequest<'a> {
+    pub fn request(&self) -> &'a Request {
+        self.request
+    }
+
+    pub fn method(&self) -> &'a Method {
+        self.request.method()
+    }
+
+    pub fn path(&self) -> &'a str {
+        self.request.path()
+    }
+
+    pub fn header(&self, name: &str) -> Option<&'a str> {
+        self.request.header(name)
+    }
+
+    // The first value of `key`, like Query::get.
+    pub fn query(&self, key: &str) -> Option<&'a str> {
+        self.query_all(key).next()
+    }
+
+    pub fn query_all<'k>(&self, key: &'k str) -> impl Iterator<Item = &'a str> + use<'a, 'k> {
+        self.query_pairs()
+            .filter(move |(k, _)| *k == key)
+            .map(|(_, value)| value)
+    }
+
+    // Pairs in the order they appear in the target, repeats included.
+    pub fn query_pairs(&self) -> impl Iterator<Item = (&'a str, &'a str)> + use<'a> {
+        let arena = self.arena;
+        arena
+            .query
+            .iter()
+            .map(move |&(key, value)| (arena.get(key), arena.get(value)))
+    }
+
+    // Matches the path against `pattern`, where a ":name" segment captures one
+    // path segment, e.g. "/users/:id/posts/:post". Captures borrow the
+    // decoded path.
+    pub fn params<'p>(&self, pattern: &'p str) -> Option<PathParams<'a, 'p>> {
+        let path = self.path();
+        let mut segments = path.split('/');
+        for expected in pattern.split('/') {
+            let segment = segments.next()?;
+            match expected.strip_prefix(':') {
+                Some(_) if segment.is_empty() => return None,
+                Some(_) => {}
+                None if expected != segment => return None,
+                None => {}
+            }
+        }
+        if segments.next().is_some() {
+            return None;
+        }
+        Some(PathParams { pattern, path })
+    }
+}
+
+// The captures of a pattern matched by ArenaRequest::params.
+#[derive(Debug, Clone, Copy)]
+pub struct PathParams<'a, 'p> {
+    pattern: &'p str,
+    path: &'a str,
+}
+
+impl<'a> PathParams<'a, '_> {
+    pub fn get(&self, name: &str) -> Option<&'a str> {
+        self.iter()
+            .find(|(capture, _)| *capture == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &'a str)> + '_ {
+        self.pattern
+            .split('/')
+            .zip(self.path.split('/'))
+            .filter_map(|(expected, segment)| Some((expected.strip_prefix(':')?, segment)))
+    }
+}
+
+thread_local! {
+    static ARENA: RefCell<RequestArena> = RefCell::default();
+}
+
+// Runs `f` on `request` bound to this thread's arena, which is emptied once `f`
+// returns; nothing `f` borrowed from it can outlive the call. A nested call gets
+// a fresh arena of its own.
+pub fn with_arena<R>(
+    request: &Request,
+    f: impl FnOnce(&ArenaRequest<'_>) -> R,
+) -> Result<R, QueryError> {
+    ARENA.with(|arena| match arena.try_borrow_mut() {
+        Ok(mut arena) => {
+            let result = arena.bind(request).map(|bound| f(&bound));
+            arena.release();
+            result
+        }
+        Err(_) => RequestArena::new().bind(request).map(|bound| f(&bound)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Query;
+
+    // Query parsing and the arena must agree on what a query string means.
+    fn decodes_like_query(raw: &str) -> bool {
+        let mut arena = RequestArena::new();
+        match (arena.decode(raw), Query::decode_url(raw)) {
+            (Ok(span), Ok(expected)) => arena.get(span) == expected,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        }
+    }
+
+    fn request(target: &str) -> Request {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: x\r\nX-Trace: abc\r\n\r\n", target);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_strings_borrow_from_the_arena() {
+        let mut arena = RequestArena::new();
+        let first = request("/users/42/posts/a%20b?tag=x&tag=y%2Bz&q=hello+world&flag");
+        let bound = arena.bind(&first).unwrap();
+        assert_eq!(bound.query("q"), Some("hello world"));
+        assert_eq!(bound.query_all("tag").collect::<Vec<_>>(), ["x", "y+z"]);
+        assert_eq!(bound.query("flag"), Some(""));
+        assert_eq!(bound.header("x-trace"), Some("abc"));
+
+        let params = bound.params("/users/:id/posts/:post").unwrap();
+        assert_eq!(params.get("id"), Some("42"));
+        assert_eq!(params.get("post"), Some("a b"));
+        assert!(bound.params("/users/:id").is_none());
+        assert!(bound.params("/groups/:id/posts/:post").is_none());
+
+        // A second request reuses the storage the first one grew.
+        let capacity = arena.capacity();
+        let second = request("/?a=1");
+        assert_eq!(arena.bind(&second).unwrap().query("a"), Some("1"));
+        assert_eq!(arena.capacity(), capacity);
+
+        for raw in ["a%2", "%zz", "caf%C3%A9", "%FF"] {
+            assert!(decodes_like_query(raw), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn test_with_arena_nests() {
+        let outer = request("/?x=1");
+        let inner = request("/?x=2");
+        let values = with_arena(&outer, |a| {
+            let nested = with_arena(&inner, |b| b.query("x").map(str::to_string)).unwrap();
+            (a.query("x").map(str::to_string), nested)
+        })
+        .unwrap();
+        assert_eq!(values, (Some("1".to_string()), Some("2".to_string())));
+    }
+}
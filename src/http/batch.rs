@@ -0,0 +1,311 @@
+use thiserror::Error;
+
+use crate::json::{self, Value};
+
+use super::{
+    Headers, body::Body, header, record::RecordError, record::ResponseRecord, response::Response,
+    status_code::StatusCode,
+};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum BatchError {
+    #[error("Unsupported batch content type: {0}")]
+    UnsupportedContentType(String),
+
+    #[error("Missing multipart boundary")]
+    MissingBoundary,
+
+    #[error("Malformed batch part: {0}")]
+    MalformedPart(&'static str),
+
+    #[error("Invalid batch record: {0}")]
+    Record(#[from] RecordError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchFormat {
+    // multipart/mixed with one application/http part per sub-response.
+    #[default]
+    Multipart,
+    // {"responses": [...]} using the same shape as ResponseRecord::to_json.
+    Json,
+}
+
+// Several sub-responses carried in one response body, for batch endpoints that
+// report a status per operation.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Batch {
+    parts: Vec<ResponseRecord>,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, status: StatusCode, headers: Vec<(String, String)>, body: Vec<u8>) {
+        self.parts.push(ResponseRecord {
+            status,
+            headers,
+            body,
+        });
+    }
+
+    pub fn with_part(mut self, response: &Response) -> Self {
+        let mut record = ResponseRecord::from(response);
+        record
+            .headers
+            .retain(|(name, _)| !name.eq_ignore_ascii_case("connection"));
+        self.parts.push(record);
+        self
+    }
+
+    pub fn parts(&self) -> &[ResponseRecord] {
+        &self.parts
+    }
+
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    pub fn into_response(self, format: BatchFormat) -> Response {
+        let (content_type, body) = match format {
+            BatchFormat::Multipart => {
+                let boundary = self.boundary();
+                (
+                    format!("multipart/mixed; boundary={}", boundary),
+                    self.to_multipart(&boundary),
+                )
+            }
+            BatchFormat::Json => {
+                let parts = self.parts.iter().map(ResponseRecord::to_json).collect();
+                let value = Value::Object(vec![("responses".to_string(), Value::Array(parts))]);
+                (
+                    "application/json".to_string(),
+                    value.to_string().into_bytes(),
+                )
+            }
+        };
+
+        Response::new(StatusCode::MultiStatus)
+            .with_header("Content-Type", content_type)
+            .with_body(Body::Content(body))
+    }
+
+    // Picks a boundary that occurs in none of the serialized parts.
+    fn boundary(&self) -> String {
+        let encoded: Vec<Vec<u8>> = self.parts.iter().map(encode_part).collect();
+        (0u64..)
+            .map(|n| format!("batch_{:016x}", n.wrapping_mul(0x9e37_79b9_7f4a_7c15)))
+            .find(|boundary| {
+                !encoded
+                    .iter()
+                    .any(|part| contains(part, boundary.as_bytes()))
+            })
+            .expect("an unused boundary exists")
+    }
+
+    fn to_multipart(&self, boundary: &str) -> Vec<u8> {
+        let mut out = Vec::new();
+        for part in &self.parts {
+            out.extend_from_slice(b"--");
+            out.extend_from_slice(boundary.as_bytes());
+            out.extend_from_slice(b"\r\nContent-Type: application/http\r\n\r\n");
+            out.extend_from_slice(&encode_part(part));
+            out.extend_from_slice(b"\r\n");
+        }
+        out.extend_from_slice(b"--");
+        out.extend_from_slice(boundary.as_bytes());
+        out.extend_from_slice(b"--\r\n");
+        out
+    }
+
+    // Client side: reads a batch body given the Content-Type it arrived with.
+    pub fn parse(content_type: &str, body: &[u8]) -> Result<Self, BatchError> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+
+        if media_type.eq_ignore_ascii_case("application/json") {
+            let text = std::str::from_utf8(body)
+                .map_err(|_| BatchError::MalformedPart("body is not UTF-8"))?;
+            let value = json::parse(text).map_err(RecordError::from)?;
+            let parts = value
+                .get("responses")
+                .and_then(Value::as_array)
+                .ok_or(RecordError::InvalidField("responses"))?
+                .iter()
+                .map(ResponseRecord::from_json)
+                .collect::<Result<_, _>>()?;
+            return Ok(Batch { parts });
+        }
+
+        if !media_type.eq_ignore_ascii_case("multipart/mixed") {
+            return Err(BatchError::UnsupportedContentType(media_type.to_string()));
+        }
+
+        let boundary = content_type
+            .split(';')
+            .skip(1)
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("boundary"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+            .filter(|b| !b.is_empty())
+            .ok_or(BatchError::MissingBoundary)?;
+
+        let delimiter = format!("--{}", boundary);
+        let mut parts = Vec::new();
+        let mut sections = split(body, delimiter.as_bytes()).into_iter().skip(1);
+
+        for section in sections.by_ref() {
+            if section.starts_with(b"--") {
+                return Ok(Batch { parts });
+            }
+            let section = section
+                .strip_prefix(b"\r\n")
+                .and_then(|s| s.strip_suffix(b"\r\n"))
+                .ok_or(BatchError::MalformedPart("part is not delimited by CRLF"))?;
+            let (_, message) = split_head(section)?;
+            parts.push(decode_part(message)?);
+        }
+
+        Err(BatchError::MalformedPart("missing closing boundary"))
+    }
+}
+
+fn encode_part(part: &ResponseRecord) -> Vec<u8> {
+    let mut out = part.status.status_line().to_vec();
+    for (name, value) in &part.headers {
+        header::write_line(&mut out, name, value);
+    }
+    if !part.body.is_empty()
+        && !part
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+    {
+        header::write_line(&mut out, "content-length", &part.body.len().to_string());
+    }
+    out.extend_from_slice(b"\r\n");
+    out.extend_from_slice(&part.body);
+    out
+}
+
+fn decode_part(message: &[u8]) -> Result<ResponseRecord, BatchError> {
+    let (head, body) = split_head(message)?;
+    let head =
+        std::str::from_utf8(head).map_err(|_| BatchError::MalformedPart("head is not UTF-8"))?;
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.strip_prefix("HTTP/1.1 "))
+        .and_then(|rest| rest.get(..3))
+        .and_then(|code| code.parse::<u16>().ok())
+        .and_then(StatusCode::from_u16)
+        .ok_or(BatchError::MalformedPart("invalid status line"))?;
+
+    let mut headers = Vec::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(BatchError::MalformedPart("invalid header line"))?;
+        let (name, value) = (name.trim(), value.trim());
+        if Headers::validate(name, value).is_err() {
+            return Err(BatchError::MalformedPart("invalid header line"));
+        }
+        headers.push((name.to_string(), value.to_string()));
+    }
+
+    Ok(ResponseRecord {
+        status,
+        headers,
+        body: body.to_vec(),
+    })
+}
+
+// Splits at the first empty line; a part without one is all head.
+fn split_head(data: &[u8]) -> Result<(&[u8], &[u8]), BatchError> {
+    match find(data, b"\r\n\r\n") {
+        Some(i) => Ok((&data[..i], &data[i + 4..])),
+        None if data.is_empty() => Err(BatchError::MalformedPart("empty part")),
+        None => Ok((data, &[])),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    find(haystack, needle).is_some()
+}
+
+fn split<'a>(mut data: &'a [u8], delimiter: &[u8]) -> Vec<&'a [u8]> {
+    let mut pieces = Vec::new();
+    while let Some(i) = find(data, delimiter) {
+        pieces.push(&data[..i]);
+        data = &data[i + delimiter.len()..];
+    }
+    pieces.push(data);
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Batch {
+        Batch::new()
+            .with_part(
+                &Response::created()
+                    .with_header("Location", "/items/1")
+                    .with_body(Body::from("one")),
+            )
+            .with_part(&Response::not_found())
+    }
+
+    #[test]
+    fn test_multipart_round_trip() {
+        let response = sample().into_response(BatchFormat::Multipart);
+        assert_eq!(response.status_code(), StatusCode::MultiStatus);
+
+        let content_type = response.headers().get("content-type").unwrap();
+        assert!(content_type.starts_with("multipart/mixed; boundary=batch_"));
+
+        let batch = Batch::parse(content_type, response.body().as_bytes()).unwrap();
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.parts()[0].status, StatusCode::Created);
+        assert_eq!(batch.parts()[0].body, b"one");
+        assert!(
+            batch.parts()[0]
+                .headers
+                .contains(&("location".to_string(), "/items/1".to_string()))
+        );
+        assert_eq!(batch.parts()[1].status, StatusCode::NotFound);
+        assert!(batch.parts()[1].body.is_empty());
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let expected = sample();
+        let response = expected.clone().into_response(BatchFormat::Json);
+        let batch = Batch::parse("application/json", response.body().as_bytes()).unwrap();
+        assert_eq!(batch, expected);
+    }
+
+    #[test]
+    fn test_boundary_avoids_body_collisions() {
+        let first = sample().boundary();
+        let mut batch = Batch::new();
+        batch.push(StatusCode::OK, Vec::new(), first.clone().into_bytes());
+
+        assert_ne!(batch.boundary(), first);
+        assert_eq!(
+            Batch::parse("multipart/mixed", b""),
+            Err(BatchError::MissingBoundary)
+        );
+    }
+}
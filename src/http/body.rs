@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -12,10 +14,11 @@ pub enum BodyError {
     MissingContentLength,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, Clone)]
 pub enum Body {
     Empty,
     Content(Vec<u8>),
+    Shared(Arc<[u8]>),
 }
 
 impl Body {
@@ -43,6 +46,7 @@ impl Body {
         match self {
             Body::Empty => &[],
             Body::Content(data) => data.as_slice(),
+            Body::Shared(data) => data,
         }
     }
 
@@ -54,12 +58,36 @@ impl Body {
         match self {
             Body::Empty => 0,
             Body::Content(data) => data.len(),
+            Body::Shared(data) => data.len(),
         }
     }
 
     pub fn is_empty(&self) -> bool {
         matches!(self, Body::Empty)
     }
+
+    // Moves owned content behind an Arc so the returned handle and self point at
+    // the same bytes; cloning a shared body afterwards is just a refcount bump.
+    pub fn share(&mut self) -> Body {
+        if let Body::Content(data) = self {
+            *self = Body::Shared(std::mem::take(data).into());
+        }
+        self.clone()
+    }
+
+    pub fn into_vec(self) -> Vec<u8> {
+        match self {
+            Body::Empty => Vec::new(),
+            Body::Content(data) => data,
+            Body::Shared(data) => data.to_vec(),
+        }
+    }
+}
+
+impl PartialEq for Body {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_empty() == other.is_empty() && self.as_bytes() == other.as_bytes()
+    }
 }
 
 impl Default for Body {
@@ -74,6 +102,12 @@ impl From<String> for Body {
     }
 }
 
+impl From<Vec<u8>> for Body {
+    fn from(data: Vec<u8>) -> Self {
+        Body::Content(data)
+    }
+}
+
 impl From<&str> for Body {
     fn from(s: &str) -> Self {
         Body::Content(s.as_bytes().to_vec())
@@ -0,0 +1,85 @@
+use std::time::Duration;
+
+use super::Headers;
+
+// The Cache-Control directives a shared cache acts on. Unknown directives are
+// ignored, and a malformed delta-seconds value counts as absent.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
+    pub no_store: bool,
+    pub no_cache: bool,
+    pub private: bool,
+    pub public: bool,
+    pub must_revalidate: bool,
+    // Intermediaries, compression included, must not alter the content.
+    pub no_transform: bool,
+}
+
+impl CacheControl {
+    pub fn parse(value: &str) -> Self {
+        let mut cc = CacheControl::default();
+
+        for directive in value.split(',') {
+            let (name, arg) = match directive.split_once('=') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            let seconds = arg
+                .and_then(|a| a.parse::<u64>().ok())
+                .map(Duration::from_secs);
+
+            match name.to_ascii_lowercase().as_str() {
+                "max-age" => cc.max_age = seconds,
+                "s-maxage" => cc.s_maxage = seconds,
+                "stale-while-revalidate" => cc.stale_while_revalidate = seconds,
+                "stale-if-error" => cc.stale_if_error = seconds,
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                "no-transform" => cc.no_transform = true,
+                _ => {}
+            }
+        }
+
+        cc
+    }
+
+    pub fn from_headers(headers: &Headers) -> Self {
+        headers
+            .get("Cache-Control")
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+
+    // Freshness lifetime for a shared cache: s-maxage overrides max-age.
+    pub fn shared_max_age(&self) -> Option<Duration> {
+        self.s_maxage.or(self.max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_directives() {
+        let cc = CacheControl::parse(
+            "public, Max-Age=60, s-maxage=\"120\", stale-while-revalidate=30, stale-if-error=abc",
+        );
+
+        assert_eq!(cc.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(cc.shared_max_age(), Some(Duration::from_secs(120)));
+        assert_eq!(cc.stale_while_revalidate, Some(Duration::from_secs(30)));
+        assert_eq!(cc.stale_if_error, None);
+        assert!(!cc.no_store);
+
+        let cc = CacheControl::parse("no-store, private");
+        assert!(cc.no_store && cc.private);
+    }
+}
@@ -0,0 +1,126 @@
+use super::filter::BodyFilter;
+
+// Single-byte legacy charsets that can be turned into UTF-8 without tables of
+// multi-byte sequences. Labels follow the WHATWG Encoding Standard, which maps
+// ISO-8859-1 and US-ASCII to windows-1252 because that is what such content
+// actually uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Windows1252,
+    // ISO-8859-15: Latin-1 with the euro sign and a few French and Finnish letters.
+    Latin9,
+}
+
+// windows-1252 bytes 0x80-0x9F; the five unassigned ones map to C1 controls.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+impl Charset {
+    pub fn from_label(label: &str) -> Option<Self> {
+        match label.trim().trim_matches('"').to_ascii_lowercase().as_str() {
+            "windows-1252" | "cp1252" | "x-cp1252" | "iso-8859-1" | "iso8859-1" | "latin1"
+            | "l1" | "us-ascii" | "ascii" => Some(Charset::Windows1252),
+            "iso-8859-15" | "iso8859-15" | "latin9" | "l9" => Some(Charset::Latin9),
+            _ => None,
+        }
+    }
+
+    pub fn decode_byte(&self, byte: u8) -> char {
+        match (self, byte) {
+            (Charset::Windows1252, 0x80..=0x9F) => WINDOWS_1252_HIGH[(byte - 0x80) as usize],
+            (Charset::Latin9, 0xA4) => '\u{20AC}',
+            (Charset::Latin9, 0xA6) => '\u{160}',
+            (Charset::Latin9, 0xA8) => '\u{161}',
+            (Charset::Latin9, 0xB4) => '\u{17D}',
+            (Charset::Latin9, 0xB8) => '\u{17E}',
+            (Charset::Latin9, 0xBC) => '\u{152}',
+            (Charset::Latin9, 0xBD) => '\u{153}',
+            (Charset::Latin9, 0xBE) => '\u{178}',
+            _ => byte as char,
+        }
+    }
+}
+
+// The charset parameter of a Content-Type value, if any.
+pub fn content_type_charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+// Re-labels a Content-Type as UTF-8, keeping its other parameters.
+pub fn with_utf8_charset(content_type: &str) -> String {
+    let mut parts = content_type.split(';');
+    let mut out = parts.next().unwrap_or("").trim().to_string();
+    for param in parts {
+        let is_charset = param
+            .split_once('=')
+            .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case("charset"));
+        if !is_charset && !param.trim().is_empty() {
+            out.push_str("; ");
+            out.push_str(param.trim());
+        }
+    }
+    out.push_str("; charset=utf-8");
+    out
+}
+
+// Transcodes to UTF-8. Every byte is a whole character in these charsets, so
+// nothing is held back between chunks.
+pub struct ToUtf8(pub Charset);
+
+impl BodyFilter for ToUtf8 {
+    fn filter(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        let mut buf = [0; 4];
+        for &byte in chunk {
+            match byte {
+                0x00..=0x7F => out.push(byte),
+                _ => {
+                    out.extend_from_slice(self.0.decode_byte(byte).encode_utf8(&mut buf).as_bytes())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::filter;
+
+    #[test]
+    fn test_transcodes_legacy_bytes_to_utf8() {
+        let body = b"caf\xe9 \x80 5 \x93quoted\x94";
+        let out = filter::apply(&mut ToUtf8(Charset::Windows1252), body, 2);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "café € 5 \u{201C}quoted\u{201D}"
+        );
+
+        let out = filter::apply(&mut ToUtf8(Charset::Latin9), b"\xa4 \xbd\xe9", 16);
+        assert_eq!(String::from_utf8(out).unwrap(), "€ œé");
+        assert_eq!(
+            Charset::from_label("\"ISO-8859-1\""),
+            Some(Charset::Windows1252)
+        );
+        assert_eq!(Charset::from_label("shift_jis"), None);
+    }
+
+    #[test]
+    fn test_relabels_content_types() {
+        let value = "text/html; Charset=\"ISO-8859-1\"; level=1";
+        assert_eq!(content_type_charset(value), Some("ISO-8859-1"));
+        assert_eq!(
+            with_utf8_charset(value),
+            "text/html; level=1; charset=utf-8"
+        );
+        assert_eq!(content_type_charset("text/plain"), None);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::date::DateTime;
+
+use super::{Method, Request, Response, StatusCode, etag::ETag};
+
+// Headers a 304 repeats from the 200 it stands for (RFC 9110 15.4.5).
+const KEPT_HEADERS: [&str; 7] = [
+    "Cache-Control",
+    "Content-Location",
+    "Date",
+    "ETag",
+    "Expires",
+    "Last-Modified",
+    "Vary",
+];
+
+// Whether the client's cached copy of `full` is still current. If-None-Match
+// wins when present (RFC 9110 13.2.2); otherwise If-Modified-Since is compared
+// with Last-Modified to the second.
+pub fn is_not_modified(request: &Request, full: &Response) -> bool {
+    if !matches!(request.method(), Method::GET | Method::HEAD)
+        || full.status_code() != StatusCode::OK
+        || full.streams_body()
+    {
+        return false;
+    }
+    if request.header("If-None-Match").is_some() {
+        let current = full
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.parse::<ETag>().ok());
+        return match (request.if_none_match(), current) {
+            (Some(list), Some(current)) => list.matches_weak(&current),
+            _ => false,
+        };
+    }
+    let since = request
+        .header("If-Modified-Since")
+        .and_then(DateTime::parse_http_date);
+    let modified = full
+        .headers()
+        .get("Last-Modified")
+        .and_then(DateTime::parse_http_date);
+    match (since, modified) {
+        (Some(since), Some(modified)) => modified.to_unix_millis() <= since.to_unix_millis(),
+        _ => false,
+    }
+}
+
+// The 304 to send instead of a complete 200 response, or None when the full
+// response should go out. Pairs with range::partial_response for handlers that
+// build the whole representation first.
+pub fn not_modified(request: &Request, full: &Response) -> Option<Response> {
+    if !is_not_modified(request, full) {
+        return None;
+    }
+    let mut response = Response::new(StatusCode::NotModified);
+    for name in KEPT_HEADERS {
+        if let Some(value) = full.headers().get(name) {
+            response = response.with_header(name, value);
+        }
+    }
+    Some(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Body;
+
+    fn get(headers: &str) -> Request {
+        let raw = format!("GET /a HTTP/1.1\r\nHost: x\r\n{}\r\n", headers);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_answers_304_for_current_copies() {
+        let full = Response::ok()
+            .with_header("ETag", "\"v2\"")
+            .with_header("Last-Modified", "Tue, 14 Nov 2023 22:13:20 GMT")
+            .with_header("Cache-Control", "no-cache")
+            .with_header("Content-Type", "text/plain")
+            .with_body(Body::from("hello"));
+
+        let revalidated = not_modified(&get("If-None-Match: \"v1\", W/\"v2\"\r\n"), &full).unwrap();
+        assert_eq!(revalidated.status_code(), StatusCode::NotModified);
+        assert!(revalidated.body().is_empty());
+        assert_eq!(revalidated.headers().get("etag"), Some("\"v2\""));
+        assert_eq!(revalidated.headers().get("cache-control"), Some("no-cache"));
+        assert_eq!(revalidated.headers().get("content-type"), None);
+
+        assert!(not_modified(&get("If-None-Match: \"v1\"\r\n"), &full).is_none());
+        assert!(
+            not_modified(
+                &get("If-Modified-Since: Wed, 15 Nov 2023 00:00:00 GMT\r\n"),
+                &full
+            )
+            .is_some()
+        );
+        assert!(
+            not_modified(
+                &get("If-Modified-Since: Mon, 13 Nov 2023 00:00:00 GMT\r\n"),
+                &full
+            )
+            .is_none()
+        );
+        // A failed If-None-Match is not overridden by a matching date.
+        assert!(
+            not_modified(
+                &get(
+                    "If-None-Match: \"v1\"\r\nIf-Modified-Since: Wed, 15 Nov 2023 00:00:00 GMT\r\n"
+                ),
+                &full
+            )
+            .is_none()
+        );
+        assert!(not_modified(&get("If-Modified-Since: soon\r\n"), &full).is_none());
+    }
+}
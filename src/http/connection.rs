@@ -0,0 +1,153 @@
+use super::Headers;
+
+pub(crate) const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persistence {
+    KeepAlive,
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConnectionHeader {
+    tokens: Vec<String>,
+}
+
+impl ConnectionHeader {
+    pub fn parse(value: &str) -> Self {
+        let tokens = value
+            .split(',')
+            .map(|token| token.trim().to_lowercase())
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        ConnectionHeader { tokens }
+    }
+
+    pub fn from_headers(headers: &Headers) -> Self {
+        headers
+            .get("Connection")
+            .map(Self::parse)
+            .unwrap_or_default()
+    }
+
+    pub fn tokens(&self) -> impl Iterator<Item = &str> + '_ {
+        self.tokens.iter().map(|t| t.as_str())
+    }
+
+    pub fn contains(&self, token: &str) -> bool {
+        self.tokens.iter().any(|t| t.eq_ignore_ascii_case(token))
+    }
+
+    pub fn keep_alive(&self) -> bool {
+        self.contains("keep-alive")
+    }
+
+    pub fn close(&self) -> bool {
+        self.contains("close")
+    }
+
+    pub fn upgrade(&self) -> bool {
+        self.contains("upgrade")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tokens.is_empty()
+    }
+
+    // "close" wins over "keep-alive" when a sender lists both.
+    pub fn persistence(&self) -> Option<Persistence> {
+        if self.close() {
+            Some(Persistence::Close)
+        } else if self.keep_alive() {
+            Some(Persistence::KeepAlive)
+        } else {
+            None
+        }
+    }
+
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        let (keep, drop) = match persistence {
+            Persistence::KeepAlive => ("keep-alive", "close"),
+            Persistence::Close => ("close", "keep-alive"),
+        };
+        self.tokens.retain(|t| t != drop);
+        if !self.contains(keep) {
+            self.tokens.push(keep.to_string());
+        }
+    }
+
+    pub fn clear_persistence(&mut self) {
+        self.tokens.retain(|t| t != "close" && t != "keep-alive");
+    }
+
+    // Adds tokens not already listed; a persistence token replaces its opposite.
+    pub fn merge(&mut self, other: &ConnectionHeader) {
+        for token in &other.tokens {
+            match token.as_str() {
+                "close" => self.set_persistence(Persistence::Close),
+                "keep-alive" => self.set_persistence(Persistence::KeepAlive),
+                _ if !self.contains(token) => self.tokens.push(token.clone()),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn to_value(&self) -> String {
+        self.tokens.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tokens_case_insensitive() {
+        let conn = ConnectionHeader::parse("Keep-Alive, Upgrade");
+
+        assert!(conn.keep_alive());
+        assert!(conn.upgrade());
+        assert!(!conn.close());
+        assert_eq!(
+            conn.tokens().collect::<Vec<_>>(),
+            vec!["keep-alive", "upgrade"]
+        );
+    }
+
+    #[test]
+    fn test_no_substring_matching() {
+        let conn = ConnectionHeader::parse("x-not-close, closed");
+        assert!(!conn.close());
+    }
+
+    #[test]
+    fn test_empty_elements_ignored() {
+        let conn = ConnectionHeader::parse(" , close ,,");
+        assert!(conn.close());
+        assert_eq!(conn.tokens().count(), 1);
+    }
+
+    #[test]
+    fn test_merge_replaces_persistence() {
+        let mut conn = ConnectionHeader::parse("close, upgrade");
+        conn.merge(&ConnectionHeader::parse("Keep-Alive, Upgrade"));
+
+        assert_eq!(conn.to_value(), "upgrade, keep-alive");
+        assert_eq!(conn.persistence(), Some(Persistence::KeepAlive));
+
+        conn.clear_persistence();
+        assert_eq!(conn.persistence(), None);
+        assert_eq!(conn.to_value(), "upgrade");
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::{base64, digest};
+
+use super::status_code::StatusCode;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DigestError {
+    #[error("Missing digest header")]
+    Missing,
+
+    #[error("Malformed digest header")]
+    Malformed,
+
+    #[error("No supported digest algorithm offered")]
+    Unsupported,
+
+    #[error("Body does not match its {0} digest")]
+    Mismatch(DigestAlgorithm),
+}
+
+impl DigestError {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::BadRequest
+    }
+}
+
+// Algorithms from the RFC 9530 registry marked as active.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl DigestAlgorithm {
+    pub fn key(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Sha256 => "sha-256",
+            DigestAlgorithm::Sha512 => "sha-512",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "sha-256" => Some(DigestAlgorithm::Sha256),
+            "sha-512" => Some(DigestAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            DigestAlgorithm::Sha256 => digest::sha256(data).to_vec(),
+            DigestAlgorithm::Sha512 => digest::sha512(data).to_vec(),
+        }
+    }
+}
+
+impl Display for DigestAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.key())
+    }
+}
+
+// A Content-Digest or Repr-Digest value (RFC 9530): a dictionary of algorithm keys
+// to byte sequences, e.g. `sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:`.
+// Keys of unknown algorithms are kept, so a value can be passed on unchanged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DigestHeader {
+    entries: Vec<(String, Vec<u8>)>,
+}
+
+impl DigestHeader {
+    pub fn compute(algorithm: DigestAlgorithm, data: &[u8]) -> Self {
+        DigestHeader {
+            entries: vec![(algorithm.key().to_string(), algorithm.compute(data))],
+        }
+    }
+
+    pub fn get(&self, algorithm: DigestAlgorithm) -> Option<&[u8]> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key == algorithm.key())
+            .map(|(_, digest)| digest.as_slice())
+    }
+
+    // Checks every supported algorithm present; one mismatch fails the whole value.
+    // Unknown algorithms are ignored, but at least one must be known.
+    pub fn verify(&self, data: &[u8]) -> Result<(), DigestError> {
+        let mut checked = false;
+        for (key, expected) in &self.entries {
+            let Some(algorithm) = DigestAlgorithm::from_key(key) else {
+                continue;
+            };
+            if algorithm.compute(data) != *expected {
+                return Err(DigestError::Mismatch(algorithm));
+            }
+            checked = true;
+        }
+        match checked {
+            true => Ok(()),
+            false => Err(DigestError::Unsupported),
+        }
+    }
+}
+
+impl FromStr for DigestHeader {
+    type Err = DigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut entries = Vec::new();
+        for member in s.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            // Parameters after ';' carry nothing RFC 9530 defines.
+            let member = member.split(';').next().unwrap_or(member);
+            let (key, value) = member.split_once('=').ok_or(DigestError::Malformed)?;
+            let encoded = value
+                .trim()
+                .strip_prefix(':')
+                .and_then(|v| v.strip_suffix(':'))
+                .ok_or(DigestError::Malformed)?;
+            let digest = base64::decode(encoded).ok_or(DigestError::Malformed)?;
+            entries.push((key.trim().to_ascii_lowercase(), digest));
+        }
+        if entries.is_empty() {
+            return Err(DigestError::Malformed);
+        }
+        Ok(DigestHeader { entries })
+    }
+}
+
+impl Display for DigestHeader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (key, digest)) in self.entries.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}=:{}:", key, base64::encode(digest))?;
+        }
+        Ok(())
+    }
+}
+
+// The supported algorithm a Want-Content-Digest or Want-Repr-Digest value prefers
+// most, e.g. `sha-512=3, sha-256=10` picks sha-256. Preference 0 means "not
+// acceptable".
+pub fn preferred_algorithm(want: &str) -> Option<DigestAlgorithm> {
+    want.split(',')
+        .filter_map(|member| {
+            let (key, weight) = member.split(';').next()?.split_once('=')?;
+            let algorithm = DigestAlgorithm::from_key(&key.trim().to_ascii_lowercase())?;
+            let weight: u8 = weight.trim().parse().ok()?;
+            (weight > 0).then_some((weight, algorithm))
+        })
+        .max_by_key(|(weight, _)| *weight)
+        .map(|(_, algorithm)| algorithm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rfc_example_round_trips_and_verifies() {
+        // RFC 9530 appendix B.1.
+        let body = b"{\"hello\": \"world\"}";
+        let header = DigestHeader::compute(DigestAlgorithm::Sha256, body);
+        assert_eq!(
+            header.to_string(),
+            "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:"
+        );
+
+        let parsed: DigestHeader = format!("{}, md5=:AAAA:", header).parse().unwrap();
+        assert_eq!(parsed.verify(body), Ok(()));
+        assert_eq!(
+            parsed.verify(b"{\"hello\": \"there\"}"),
+            Err(DigestError::Mismatch(DigestAlgorithm::Sha256))
+        );
+
+        let unknown: DigestHeader = "md5=:AAAA:".parse().unwrap();
+        assert_eq!(unknown.verify(body), Err(DigestError::Unsupported));
+        assert_eq!(
+            "sha-256=abc".parse::<DigestHeader>(),
+            Err(DigestError::Malformed)
+        );
+    }
+
+    #[test]
+    fn test_picks_the_most_preferred_algorithm() {
+        assert_eq!(
+            preferred_algorithm("sha-512=3, sha-256=10"),
+            Some(DigestAlgorithm::Sha256)
+        );
+        assert_eq!(
+            preferred_algorithm("sha-256=0, sha-512=1, md5=9"),
+            Some(DigestAlgorithm::Sha512)
+        );
+        assert_eq!(preferred_algorithm("sha-256=0"), None);
+    }
+}
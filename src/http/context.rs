@@ -0,0 +1,105 @@
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+
+// What is known about the connection a request arrived on. Requests not read off
+// a socket, e.g. built from bytes in tests, report nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    pub peer: Option<SocketAddr>,
+    pub local: Option<SocketAddr>,
+    pub tls: bool,
+    // The protocol agreed through TLS ALPN, e.g. "http/1.1".
+    pub alpn: Option<String>,
+    // The load balancer's address, when a PROXY protocol header named the real
+    // client as the peer.
+    pub proxy: Option<SocketAddr>,
+}
+
+impl ConnectionInfo {
+    pub(crate) const UNKNOWN: ConnectionInfo = ConnectionInfo {
+        peer: None,
+        local: None,
+        tls: false,
+        alpn: None,
+        proxy: None,
+    };
+
+    pub fn from_tcp(stream: &TcpStream) -> Self {
+        ConnectionInfo {
+            peer: stream.peer_addr().ok(),
+            local: stream.local_addr().ok(),
+            tls: false,
+            alpn: None,
+            proxy: None,
+        }
+    }
+}
+
+// Scratch state owned by a connection and reused for every request parsed on it.
+// Buffers are cleared between requests but keep their capacity, so a long-lived
+// keep-alive connection stops allocating for request heads once warmed up.
+#[derive(Debug, Default)]
+pub struct ConnectionContext {
+    pub(crate) head: Vec<u8>,
+    requests: u64,
+    // Shared with every request parsed on the connection.
+    info: Arc<ConnectionInfo>,
+}
+
+impl ConnectionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(head_capacity: usize) -> Self {
+        ConnectionContext {
+            head: Vec::with_capacity(head_capacity),
+            requests: 0,
+            info: Arc::default(),
+        }
+    }
+
+    // The client's address, attached to every request parsed on the connection.
+    pub fn with_peer(mut self, peer: Option<SocketAddr>) -> Self {
+        Arc::make_mut(&mut self.info).peer = peer;
+        self
+    }
+
+    pub fn with_info(mut self, info: ConnectionInfo) -> Self {
+        self.set_info(info);
+        self
+    }
+
+    pub fn set_info(&mut self, info: ConnectionInfo) {
+        self.info = Arc::new(info);
+    }
+
+    pub fn info(&self) -> &ConnectionInfo {
+        &self.info
+    }
+
+    pub(crate) fn shared_info(&self) -> Arc<ConnectionInfo> {
+        self.info.clone()
+    }
+
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.info.peer
+    }
+
+    pub fn reset(&mut self) {
+        self.head.clear();
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn head_capacity(&self) -> usize {
+        self.head.capacity()
+    }
+
+    pub(crate) fn begin_request(&mut self) {
+        self.reset();
+        self.requests += 1;
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::path::{EncodedSlashPolicy, sanitize_path};
+
+// Typed forms of the WebDAV request headers (RFC 4918 section 10).
+
+#[derive(Debug, Error, PartialEq)]
+pub enum DavHeaderError {
+    #[error("Invalid Depth header: {0}")]
+    InvalidDepth(String),
+
+    #[error("Invalid Destination header: {0}")]
+    InvalidDestination(String),
+
+    #[error("Invalid Overwrite header: {0}")]
+    InvalidOverwrite(String),
+}
+
+// How far a PROPFIND, COPY, MOVE or LOCK reaches below the target collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    Zero,
+    One,
+    Infinity,
+}
+
+impl Depth {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity",
+        }
+    }
+}
+
+impl FromStr for Depth {
+    type Err = DavHeaderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "0" => Ok(Depth::Zero),
+            "1" => Ok(Depth::One),
+            value if value.eq_ignore_ascii_case("infinity") => Ok(Depth::Infinity),
+            value => Err(DavHeaderError::InvalidDepth(value.to_string())),
+        }
+    }
+}
+
+impl Display for Depth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// The target of a COPY or MOVE. Clients send an absolute URI; the authority is
+// kept so a handler can refuse copies to another server, and the path is
+// decoded and normalized like a request path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Destination {
+    pub authority: Option<String>,
+    pub path: String,
+}
+
+impl FromStr for Destination {
+    type Err = DavHeaderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DavHeaderError::InvalidDestination(s.to_string());
+        let value = s.trim();
+        let (authority, raw_path) = match value.split_once("://") {
+            Some((scheme, rest)) => {
+                if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+                    return Err(invalid());
+                }
+                let (authority, path) = match rest.find('/') {
+                    Some(at) => rest.split_at(at),
+                    None => (rest, "/"),
+                };
+                if authority.is_empty() {
+                    return Err(invalid());
+                }
+                (Some(authority.to_string()), path)
+            }
+            None if value.starts_with('/') => (None, value),
+            None => return Err(invalid()),
+        };
+        let raw_path = raw_path.split(['?', '#']).next().unwrap_or("/");
+        let path = sanitize_path(raw_path, EncodedSlashPolicy::Reject).map_err(|_| invalid())?;
+        Ok(Destination { authority, path })
+    }
+}
+
+// Whether COPY or MOVE may replace an existing resource; absent means true.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overwrite(pub bool);
+
+impl FromStr for Overwrite {
+    type Err = DavHeaderError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "T" => Ok(Overwrite(true)),
+            "F" => Ok(Overwrite(false)),
+            value => Err(DavHeaderError::InvalidOverwrite(value.to_string())),
+        }
+    }
+}
+
+impl Display for Overwrite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(if self.0 { "T" } else { "F" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_depth_and_overwrite() {
+        assert_eq!("0".parse(), Ok(Depth::Zero));
+        assert_eq!(" Infinity".parse(), Ok(Depth::Infinity));
+        assert!("2".parse::<Depth>().is_err());
+        assert_eq!(Depth::One.to_string(), "1");
+
+        assert_eq!("F".parse(), Ok(Overwrite(false)));
+        assert!("yes".parse::<Overwrite>().is_err());
+    }
+
+    #[test]
+    fn test_parses_destinations() {
+        let destination: Destination = "http://dav.example:8080/docs/a%20b.txt?x=1"
+            .parse()
+            .unwrap();
+        assert_eq!(destination.authority.as_deref(), Some("dav.example:8080"));
+        assert_eq!(destination.path, "/docs/a b.txt");
+
+        let relative: Destination = "/docs/../archive/".parse().unwrap();
+        assert_eq!(relative.authority, None);
+        assert_eq!(relative.path, "/archive/");
+
+        assert!("ftp://dav.example/x".parse::<Destination>().is_err());
+        assert!("docs/x".parse::<Destination>().is_err());
+        assert!("/a%2Fb".parse::<Destination>().is_err());
+    }
+
+    #[test]
+    fn test_parses_webdav_requests() {
+        use crate::http::{Method, Request};
+
+        let raw = b"MOVE /docs/a.txt HTTP/1.1\r\nHost: dav.example\r\n\
+            Destination: http://dav.example/docs/b.txt\r\nOverwrite: F\r\nDepth: oops\r\n\r\n";
+        let request = Request::try_from(&raw[..]).unwrap();
+        assert_eq!(request.method(), &Method::MOVE);
+        assert_eq!(request.destination().unwrap().path, "/docs/b.txt");
+        assert_eq!(request.overwrite(), Some(Overwrite(false)));
+        assert_eq!(request.depth(), None);
+    }
+}
@@ -0,0 +1,346 @@
+use std::fmt::Debug;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::sync::Mutex;
+
+use super::Body;
+
+const OUTPUT_BUFFER_SIZE: usize = 8 * 1024; // 8KB
+const MAX_CHUNK_LINE: u64 = 4096;
+const MAX_TRAILER_BYTES: u64 = 16 * 1024; // 16KB
+
+// How the part of a request body still on the wire is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFraming {
+    Length(u64),
+    Chunked,
+}
+
+// A connection the server reads requests from and writes responses to.
+pub(crate) trait Connection: BufRead {
+    fn output(&mut self) -> &mut dyn Write;
+}
+
+impl<T: Read + Write> Connection for BufReader<T> {
+    fn output(&mut self) -> &mut dyn Write {
+        self.get_mut()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Input {
+    Length(u64),
+    // Bytes left in the current chunk; 0 means a size line comes next.
+    Chunk(u64),
+    Done,
+}
+
+// Both directions of an exchange whose response is produced while the request
+// body is still arriving. Reads yield the decoded request body; writes form the
+// chunked response body. Writes collect until `flush`, which sends them as one
+// chunk, or until the buffer fills, so a handler decides when the client sees
+// each piece.
+pub struct DuplexStream<'a> {
+    conn: &'a mut dyn Connection,
+    // Body bytes the server had already read before the handler ran.
+    buffered: Body,
+    buffered_pos: usize,
+    input: Input,
+    received: u64,
+    limit: Option<u64>,
+    output: Vec<u8>,
+}
+
+impl<'a> DuplexStream<'a> {
+    pub(crate) fn new(
+        conn: &'a mut dyn Connection,
+        framing: BodyFraming,
+        limit: Option<usize>,
+    ) -> Self {
+        let input = match framing {
+            BodyFraming::Length(0) => Input::Done,
+            BodyFraming::Length(length) => Input::Length(length),
+            BodyFraming::Chunked => Input::Chunk(0),
+        };
+        DuplexStream {
+            conn,
+            buffered: Body::Empty,
+            buffered_pos: 0,
+            input,
+            received: 0,
+            limit: limit.map(|limit| limit as u64),
+            output: Vec::with_capacity(OUTPUT_BUFFER_SIZE),
+        }
+    }
+
+    // Serves `body` to reads before anything left on the wire.
+    pub(crate) fn with_buffered(mut self, body: Body) -> Self {
+        self.buffered = body;
+        self
+    }
+
+    // Request body bytes read from the wire so far.
+    pub fn body_received(&self) -> u64 {
+        self.received
+    }
+
+    // Whether the whole request body has been read.
+    pub fn body_finished(&self) -> bool {
+        self.input == Input::Done && self.buffered_pos == self.buffered.len()
+    }
+
+    // Response bytes written but not yet sent.
+    pub fn pending(&self) -> usize {
+        self.output.len()
+    }
+
+    // Reads and discards up to `limit` bytes of request body, so the connection
+    // can carry another request. Returns whether the body is now finished.
+    pub(crate) fn skip_body(&mut self, limit: u64) -> bool {
+        let skipped = io::copy(&mut Read::by_ref(self).take(limit), &mut io::sink());
+        skipped.is_ok() && self.body_finished()
+    }
+
+    // Sends what is still buffered and the last chunk, ending the response.
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.send_chunk()?;
+        let output = self.conn.output();
+        output.write_all(b"0\r\n\r\n")?;
+        output.flush()
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        if self.output.is_empty() {
+            return Ok(());
+        }
+        let output = self.conn.output();
+        output.write_all(format!("{:x}\r\n", self.output.len()).as_bytes())?;
+        output.write_all(&self.output)?;
+        output.write_all(b"\r\n")?;
+        self.output.clear();
+        Ok(())
+    }
+
+    // Copies at most `max` bytes from the wire into `buf`.
+    fn read_wire(&mut self, buf: &mut [u8], max: u64) -> io::Result<usize> {
+        let available = self.conn.fill_buf()?;
+        if available.is_empty() {
+            return Err(ErrorKind::UnexpectedEof.into());
+        }
+        let n = available.len().min(buf.len()).min(max as usize);
+        buf[..n].copy_from_slice(&available[..n]);
+        self.conn.consume(n);
+        self.received += n as u64;
+        if self.limit.is_some_and(|limit| self.received > limit) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "request body exceeds the size limit",
+            ));
+        }
+        Ok(n)
+    }
+
+    fn read_line(&mut self, max: u64) -> io::Result<Vec<u8>> {
+        let mut line = Vec::new();
+        (&mut *self.conn).take(max).read_until(b'\n', &mut line)?;
+        if !line.ends_with(b"\n") {
+            return Err(invalid_chunk());
+        }
+        Ok(line)
+    }
+
+    fn read_chunk_size(&mut self) -> io::Result<u64> {
+        let line = self.read_line(MAX_CHUNK_LINE)?;
+        let line = std::str::from_utf8(&line).map_err(|_| invalid_chunk())?;
+        let size = line.split(';').next().unwrap_or("").trim();
+        u64::from_str_radix(size, 16).map_err(|_| invalid_chunk())
+    }
+
+    // Trailer fields are not passed on; only their size is checked.
+    fn skip_trailers(&mut self) -> io::Result<()> {
+        let mut budget = MAX_TRAILER_BYTES;
+        loop {
+            let line = self.read_line(budget)?;
+            if line == b"\r\n" || line == b"\n" {
+                return Ok(());
+            }
+            budget -= line.len() as u64;
+        }
+    }
+}
+
+fn invalid_chunk() -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, "invalid chunk in request body")
+}
+
+impl Read for DuplexStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let buffered = &self.buffered.as_bytes()[self.buffered_pos..];
+        if !buffered.is_empty() {
+            let n = buffered.len().min(buf.len());
+            buf[..n].copy_from_slice(&buffered[..n]);
+            self.buffered_pos += n;
+            return Ok(n);
+        }
+
+        loop {
+            match self.input {
+                Input::Done => return Ok(0),
+                Input::Length(remaining) => {
+                    let n = self.read_wire(buf, remaining)?;
+                    self.input = match remaining - n as u64 {
+                        0 => Input::Done,
+                        left => Input::Length(left),
+                    };
+                    return Ok(n);
+                }
+                Input::Chunk(0) => {
+                    self.input = match self.read_chunk_size()? {
+                        0 => {
+                            self.skip_trailers()?;
+                            Input::Done
+                        }
+                        size => Input::Chunk(size),
+                    };
+                }
+                Input::Chunk(remaining) => {
+                    let n = self.read_wire(buf, remaining)?;
+                    let left = remaining - n as u64;
+                    if left == 0 {
+                        let line = self.read_line(2)?;
+                        if line != b"\r\n" && line != b"\n" {
+                            return Err(invalid_chunk());
+                        }
+                    }
+                    self.input = Input::Chunk(left);
+                    return Ok(n);
+                }
+            }
+        }
+    }
+}
+
+impl Write for DuplexStream<'_> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(data);
+        if self.output.len() >= OUTPUT_BUFFER_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_chunk()?;
+        self.conn.output().flush()
+    }
+}
+
+type DuplexFn = Box<dyn FnOnce(&mut DuplexStream<'_>) -> io::Result<()> + Send>;
+
+// Like Takeover, the mutex only makes responses Sync.
+pub struct Duplex(Mutex<DuplexFn>);
+
+impl Duplex {
+    pub fn new(f: impl FnOnce(&mut DuplexStream<'_>) -> io::Result<()> + Send + 'static) -> Self {
+        Duplex(Mutex::new(Box::new(f)))
+    }
+
+    pub fn run(self, stream: &mut DuplexStream<'_>) -> io::Result<()> {
+        let f = self.0.into_inner().unwrap_or_else(|e| e.into_inner());
+        f(stream)
+    }
+}
+
+impl Debug for Duplex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Duplex")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // Input comes from `input`; everything written lands in `output`.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn pipe(input: &[u8]) -> BufReader<Pipe> {
+        BufReader::new(Pipe {
+            input: Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        })
+    }
+
+    #[test]
+    fn test_reads_chunked_body_and_writes_chunks_on_flush() {
+        let mut conn = pipe(b"5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\nX-Sum: 1\r\n\r\nNEXT");
+        let mut stream = DuplexStream::new(&mut conn, BodyFraming::Chunked, None);
+
+        let mut buf = [0u8; 3];
+        let n = stream.read(&mut buf).unwrap();
+        stream.write_all(&buf[..n]).unwrap();
+        stream.write_all(b"!").unwrap();
+        assert_eq!(stream.pending(), 4);
+        stream.flush().unwrap();
+
+        let mut rest = String::new();
+        stream.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "lo world");
+        assert!(stream.body_finished());
+        assert_eq!(stream.body_received(), 11);
+        stream.write_all(rest.as_bytes()).unwrap();
+        stream.finish().unwrap();
+
+        assert_eq!(conn.buffer(), b"NEXT");
+        assert_eq!(
+            conn.get_ref().output,
+            b"4\r\nhel!\r\n8\r\nlo world\r\n0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn test_length_framing_limits_and_skipping() {
+        let mut conn = pipe(b"abcdefNEXT");
+        let mut stream = DuplexStream::new(&mut conn, BodyFraming::Length(6), None)
+            .with_buffered(Body::from("xy"));
+        let mut buf = [0u8; 3];
+        assert_eq!(stream.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], b"xy");
+        assert!(!stream.body_finished());
+        assert!(stream.skip_body(64));
+        assert_eq!(conn.buffer(), b"NEXT");
+
+        let mut conn = pipe(b"abcdef");
+        let mut stream = DuplexStream::new(&mut conn, BodyFraming::Length(6), Some(4));
+        let mut body = Vec::new();
+        let err = stream.read_to_end(&mut body).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(!stream.skip_body(64));
+
+        let mut conn = pipe(b"zz\r\n");
+        let mut stream = DuplexStream::new(&mut conn, BodyFraming::Chunked, None);
+        assert!(stream.read(&mut buf).is_err());
+    }
+}
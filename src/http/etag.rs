@@ -0,0 +1,217 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::digest::content_hash;
+
+#[derive(Debug, Error)]
+pub enum ETagError {
+    #[error("Invalid entity tag: {0}")]
+    InvalidTag(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag {
+    tag: String,
+    weak: bool,
+}
+
+impl ETag {
+    pub fn strong(tag: impl Into<String>) -> Result<Self, ETagError> {
+        Self::build(tag.into(), false)
+    }
+
+    pub fn weak(tag: impl Into<String>) -> Result<Self, ETagError> {
+        Self::build(tag.into(), true)
+    }
+
+    // A strong tag derived from the representation bytes, so identical content
+    // gets the same tag on every instance.
+    pub fn from_content(data: &[u8]) -> Self {
+        Self::strong(content_hash(data)).expect("hex digits are valid etagc")
+    }
+
+    fn build(tag: String, weak: bool) -> Result<Self, ETagError> {
+        if !tag.bytes().all(Self::is_etagc) {
+            return Err(ETagError::InvalidTag(tag));
+        }
+        Ok(ETag { tag, weak })
+    }
+
+    // etagc = %x21 / %x23-7E / obs-text
+    fn is_etagc(b: u8) -> bool {
+        b == 0x21 || (0x23..=0x7E).contains(&b) || b >= 0x80
+    }
+
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    // RFC 9110 8.8.3.2: both validators must be strong and the opaque tags identical.
+    pub fn strong_eq(&self, other: &ETag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    // RFC 9110 8.8.3.2: opaque tags match regardless of either being weak.
+    pub fn weak_eq(&self, other: &ETag) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl FromStr for ETag {
+    type Err = ETagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (weak, quoted) = match s.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+
+        let tag = quoted
+            .strip_prefix('"')
+            .and_then(|rest| rest.strip_suffix('"'))
+            .ok_or_else(|| ETagError::InvalidTag(s.to_string()))?;
+
+        Self::build(tag.to_string(), weak)
+    }
+}
+
+impl Display for ETag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            write!(f, "W/\"{}\"", self.tag)
+        } else {
+            write!(f, "\"{}\"", self.tag)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ETagList {
+    Any,
+    Tags(Vec<ETag>),
+}
+
+impl ETagList {
+    pub fn parse(value: &str) -> Result<Self, ETagError> {
+        if value.trim() == "*" {
+            return Ok(ETagList::Any);
+        }
+
+        let mut tags = Vec::new();
+        let mut rest = value;
+        loop {
+            rest = rest.trim_start_matches([' ', '\t', ',']);
+            if rest.is_empty() {
+                break;
+            }
+
+            // Tags may contain commas, so split on the closing quote rather than on ','.
+            let start = if rest.starts_with("W/") { 3 } else { 1 };
+            let end = rest
+                .get(start..)
+                .and_then(|s| s.find('"'))
+                .map(|pos| start + pos + 1)
+                .ok_or_else(|| ETagError::InvalidTag(rest.to_string()))?;
+
+            tags.push(rest[..end].parse()?);
+            rest = &rest[end..];
+        }
+
+        Ok(ETagList::Tags(tags))
+    }
+
+    // If-None-Match uses the weak comparison function.
+    pub fn matches_weak(&self, etag: &ETag) -> bool {
+        match self {
+            ETagList::Any => true,
+            ETagList::Tags(tags) => tags.iter().any(|t| t.weak_eq(etag)),
+        }
+    }
+
+    // If-Match and If-Range use the strong comparison function.
+    pub fn matches_strong(&self, etag: &ETag) -> bool {
+        match self {
+            ETagList::Any => true,
+            ETagList::Tags(tags) => tags.iter().any(|t| t.strong_eq(etag)),
+        }
+    }
+}
+
+impl FromStr for ETagList {
+    type Err = ETagError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_strong_and_weak() {
+        let strong: ETag = "\"xyzzy\"".parse().unwrap();
+        let weak: ETag = "W/\"xyzzy\"".parse().unwrap();
+
+        assert!(!strong.is_weak());
+        assert!(weak.is_weak());
+        assert_eq!(strong.tag(), "xyzzy");
+        assert_eq!(weak.to_string(), "W/\"xyzzy\"");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!("xyzzy".parse::<ETag>().is_err());
+        assert!("\"xy\"zzy\"".parse::<ETag>().is_err());
+        assert!(ETag::strong("a b\"").is_err());
+    }
+
+    #[test]
+    fn test_comparison_functions() {
+        // RFC 9110 8.8.3.2 example table
+        let w1 = ETag::weak("1").unwrap();
+        let w2 = ETag::weak("2").unwrap();
+        let s1 = ETag::strong("1").unwrap();
+
+        assert!(!w1.strong_eq(&w1.clone()));
+        assert!(w1.weak_eq(&w1.clone()));
+        assert!(!w1.strong_eq(&w2));
+        assert!(!w1.weak_eq(&w2));
+        assert!(!w1.strong_eq(&s1));
+        assert!(w1.weak_eq(&s1));
+        assert!(s1.strong_eq(&s1.clone()));
+    }
+
+    #[test]
+    fn test_list_parse() {
+        let list = ETagList::parse("\"a\", W/\"b,c\" ,\"d\"").unwrap();
+        assert_eq!(
+            list,
+            ETagList::Tags(vec![
+                ETag::strong("a").unwrap(),
+                ETag::weak("b,c").unwrap(),
+                ETag::strong("d").unwrap(),
+            ])
+        );
+        assert_eq!(ETagList::parse("*").unwrap(), ETagList::Any);
+        assert!(ETagList::parse("\"a\", b").is_err());
+    }
+
+    #[test]
+    fn test_list_matching() {
+        let list = ETagList::parse("W/\"a\", \"b\"").unwrap();
+
+        assert!(list.matches_weak(&ETag::strong("a").unwrap()));
+        assert!(!list.matches_strong(&ETag::strong("a").unwrap()));
+        assert!(list.matches_strong(&ETag::strong("b").unwrap()));
+        assert!(ETagList::Any.matches_strong(&ETag::weak("z").unwrap()));
+    }
+}
@@ -0,0 +1,216 @@
+use std::io::{self, Write};
+
+// Chunk-at-a-time body transformations: each call sees only the bytes written so
+// far, so the same filter works on a buffered body and on a stream written
+// through a takeover. A filter may hold back the tail of a chunk when a match
+// could continue into the next one.
+pub trait BodyFilter: Send {
+    fn filter(&mut self, chunk: &[u8], out: &mut Vec<u8>);
+
+    // End of the body: emit whatever was held back.
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        let _ = out;
+    }
+}
+
+// Runs a whole body through a filter in chunks of `chunk_size`.
+pub fn apply(filter: &mut dyn BodyFilter, body: &[u8], chunk_size: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    for chunk in body.chunks(chunk_size.max(1)) {
+        filter.filter(chunk, &mut out);
+    }
+    filter.finish(&mut out);
+    out
+}
+
+// Replaces a byte string wherever it occurs, also across chunk boundaries, e.g.
+// `http://internal:8080` with the public origin, or a token with a placeholder.
+pub struct Replace {
+    needle: Vec<u8>,
+    replacement: Vec<u8>,
+    // None replaces every occurrence.
+    remaining: Option<usize>,
+    held: Vec<u8>,
+}
+
+impl Replace {
+    pub fn new(needle: impl Into<Vec<u8>>, replacement: impl Into<Vec<u8>>) -> Self {
+        let needle = needle.into();
+        Replace {
+            remaining: needle.is_empty().then_some(0),
+            needle,
+            replacement: replacement.into(),
+            held: Vec::new(),
+        }
+    }
+
+    pub fn once(mut self) -> Self {
+        self.remaining = Some(self.remaining.unwrap_or(1).min(1));
+        self
+    }
+
+    // Inserts `content` right before the first `marker`, e.g. a banner before
+    // `</body>`.
+    pub fn insert_before(marker: &str, content: &str) -> Self {
+        Replace::new(marker, format!("{}{}", content, marker)).once()
+    }
+
+    pub fn insert_after(marker: &str, content: &str) -> Self {
+        Replace::new(marker, format!("{}{}", marker, content)).once()
+    }
+}
+
+impl BodyFilter for Replace {
+    fn filter(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        self.held.extend_from_slice(chunk);
+        let mut start = 0;
+        while self.remaining != Some(0) {
+            let Some(pos) = find(&self.held[start..], &self.needle) else {
+                break;
+            };
+            out.extend_from_slice(&self.held[start..start + pos]);
+            out.extend_from_slice(&self.replacement);
+            start += pos + self.needle.len();
+            self.remaining = self.remaining.map(|n| n - 1);
+        }
+        // A partial match may finish in the next chunk.
+        let keep = match self.remaining {
+            Some(0) => 0,
+            _ => (self.needle.len() - 1).min(self.held.len() - start),
+        };
+        let end = self.held.len() - keep;
+        out.extend_from_slice(&self.held[start..end]);
+        self.held.drain(..end);
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        out.append(&mut self.held);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// Filters applied in order, each seeing the previous one's output.
+pub struct Pipeline(Vec<Box<dyn BodyFilter>>);
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline(Vec::new())
+    }
+
+    pub fn then(mut self, filter: impl BodyFilter + 'static) -> Self {
+        self.0.push(Box::new(filter));
+        self
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BodyFilter for Pipeline {
+    fn filter(&mut self, chunk: &[u8], out: &mut Vec<u8>) {
+        let mut carry = chunk.to_vec();
+        for filter in &mut self.0 {
+            let mut next = Vec::with_capacity(carry.len());
+            filter.filter(&carry, &mut next);
+            carry = next;
+        }
+        out.append(&mut carry);
+    }
+
+    fn finish(&mut self, out: &mut Vec<u8>) {
+        // What one filter held back still has to pass through the later ones.
+        let mut carry = Vec::new();
+        for filter in &mut self.0 {
+            let mut next = Vec::new();
+            filter.filter(&carry, &mut next);
+            filter.finish(&mut next);
+            carry = next;
+        }
+        out.append(&mut carry);
+    }
+}
+
+// Filters everything written through it, for streamed responses. Call finish at
+// the end of the body, or bytes a filter held back are lost.
+pub struct FilterWriter<W: Write> {
+    inner: W,
+    filter: Box<dyn BodyFilter>,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> FilterWriter<W> {
+    pub fn new(inner: W, filter: impl BodyFilter + 'static) -> Self {
+        FilterWriter {
+            inner,
+            filter: Box::new(filter),
+            buf: Vec::new(),
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.buf.clear();
+        self.filter.finish(&mut self.buf);
+        self.inner.write_all(&self.buf)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for FilterWriter<W> {
+    fn write(&mut self, chunk: &[u8]) -> io::Result<usize> {
+        self.buf.clear();
+        self.filter.filter(chunk, &mut self.buf);
+        self.inner.write_all(&self.buf)?;
+        Ok(chunk.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replaces_across_chunk_boundaries() {
+        let body = b"see http://backend:8080/a and http://backend:8080/b";
+        for chunk_size in [1, 3, 7, body.len()] {
+            let mut filter = Replace::new("http://backend:8080", "https://example.com");
+            assert_eq!(
+                apply(&mut filter, body, chunk_size),
+                b"see https://example.com/a and https://example.com/b",
+                "chunk size {}",
+                chunk_size
+            );
+        }
+
+        let mut banner = Pipeline::new()
+            .then(Replace::insert_after("<body>", "<p>beta</p>"))
+            .then(Replace::new("secret-token", "[redacted]"));
+        let page = b"<html><body>secret-token<body></body></html>";
+        assert_eq!(
+            apply(&mut banner, page, 4),
+            b"<html><body><p>beta</p>[redacted]<body></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_filter_writer_flushes_held_bytes_on_finish() {
+        let mut writer = FilterWriter::new(Vec::new(), Replace::new("abc", "X"));
+        for byte in b"zzab" {
+            writer.write_all(&[*byte]).unwrap();
+        }
+        writer.write_all(b"cab").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"zzXab");
+    }
+}
@@ -0,0 +1,678 @@
+use std::io::{self, Write};
+
+use thiserror::Error;
+
+use crate::random::random_u64;
+
+use super::{
+    body::Body,
+    query::{Query, QueryError},
+    status_code::StatusCode,
+};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FormError {
+    #[error("Unsupported form content type: {0}")]
+    UnsupportedContentType(String),
+
+    #[error("Missing multipart boundary")]
+    MissingBoundary,
+
+    #[error("Malformed form part: {0}")]
+    MalformedPart(&'static str),
+
+    #[error("Invalid form encoding")]
+    InvalidEncoding,
+
+    #[error("Form has more than {limit} fields")]
+    TooManyFields { limit: usize },
+
+    #[error("Form field name exceeds {limit} bytes")]
+    NameTooLong { limit: usize },
+
+    #[error("Form field value exceeds {limit} bytes")]
+    ValueTooLong { limit: usize },
+
+    #[error("Form has more than {limit} files")]
+    TooManyFiles { limit: usize },
+
+    #[error("Uploaded file exceeds {limit} bytes")]
+    FileTooLarge { limit: usize },
+}
+
+impl FormError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            FormError::UnsupportedContentType(_) => StatusCode::UnsupportedMediaType,
+            FormError::TooManyFields { .. }
+            | FormError::NameTooLong { .. }
+            | FormError::ValueTooLong { .. }
+            | FormError::TooManyFiles { .. }
+            | FormError::FileTooLarge { .. } => StatusCode::ContentTooLarge,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+impl From<QueryError> for FormError {
+    fn from(_: QueryError) -> Self {
+        FormError::InvalidEncoding
+    }
+}
+
+// Caps applied while decoding a form body. The body as a whole is already bounded
+// by ParserLimits; these stop a small body from carrying thousands of fields, and
+// can be tightened or relaxed per route by passing different limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormLimits {
+    // Non-file fields.
+    pub max_fields: usize,
+    pub max_name_len: usize,
+    pub max_value_len: usize,
+    pub max_files: usize,
+    pub max_file_size: usize,
+}
+
+impl Default for FormLimits {
+    fn default() -> Self {
+        FormLimits {
+            max_fields: 100,
+            max_name_len: 256,
+            max_value_len: 64 * 1024,
+            max_files: 10,
+            max_file_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+// Decodes application/x-www-form-urlencoded. Lengths are checked on the encoded
+// text, which is never shorter than what it decodes to.
+pub fn parse_urlencoded(body: &[u8], limits: &FormLimits) -> Result<Query, FormError> {
+    let body = std::str::from_utf8(body).map_err(|_| FormError::InvalidEncoding)?;
+    let mut fields = 0;
+    for pair in body.split('&').filter(|pair| !pair.is_empty()) {
+        fields += 1;
+        if fields > limits.max_fields {
+            return Err(FormError::TooManyFields {
+                limit: limits.max_fields,
+            });
+        }
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if name.len() > limits.max_name_len {
+            return Err(FormError::NameTooLong {
+                limit: limits.max_name_len,
+            });
+        }
+        if value.len() > limits.max_value_len {
+            return Err(FormError::ValueTooLong {
+                limit: limits.max_value_len,
+            });
+        }
+    }
+    Ok(Query::parse(body)?)
+}
+
+// Encodes pairs as an application/x-www-form-urlencoded body, in the order given.
+// parse_urlencoded reads it back.
+pub fn encode_urlencoded<K: AsRef<str>, V: AsRef<str>>(
+    pairs: impl IntoIterator<Item = (K, V)>,
+) -> String {
+    pairs
+        .into_iter()
+        .map(|(name, value)| {
+            format!(
+                "{}={}",
+                Query::encode_url(name.as_ref()),
+                Query::encode_url(value.as_ref())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FormPart {
+    pub name: String,
+    // Sanitized; see sanitize_filename. Only file parts have one.
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub data: Vec<u8>,
+}
+
+impl FormPart {
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.data).ok()
+    }
+}
+
+// The parts of a multipart/form-data body, in the order they were sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Multipart {
+    parts: Vec<FormPart>,
+}
+
+impl Multipart {
+    pub fn parse(content_type: &str, body: &[u8], limits: &FormLimits) -> Result<Self, FormError> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        if !media_type.eq_ignore_ascii_case("multipart/form-data") {
+            return Err(FormError::UnsupportedContentType(media_type.to_string()));
+        }
+        let boundary = parameter(content_type, "boundary")
+            .filter(|b| !b.is_empty())
+            .ok_or(FormError::MissingBoundary)?;
+
+        let delimiter = format!("--{}", boundary);
+        let mut rest = match find(body, delimiter.as_bytes()) {
+            Some(i) => &body[i + delimiter.len()..],
+            None => return Err(FormError::MalformedPart("missing opening boundary")),
+        };
+        let closing = format!("\r\n--{}", boundary);
+        let (mut fields, mut files) = (0, 0);
+        let mut parts = Vec::new();
+
+        loop {
+            if rest.starts_with(b"--") {
+                return Ok(Multipart { parts });
+            }
+            rest = rest
+                .strip_prefix(b"\r\n")
+                .ok_or(FormError::MalformedPart("boundary is not followed by CRLF"))?;
+            let end = find(rest, closing.as_bytes())
+                .ok_or(FormError::MalformedPart("missing closing boundary"))?;
+            let part = decode_part(&rest[..end])?;
+            rest = &rest[end + closing.len()..];
+
+            if part.name.len() > limits.max_name_len {
+                return Err(FormError::NameTooLong {
+                    limit: limits.max_name_len,
+                });
+            }
+            if part.is_file() {
+                files += 1;
+                if files > limits.max_files {
+                    return Err(FormError::TooManyFiles {
+                        limit: limits.max_files,
+                    });
+                }
+                if part.data.len() > limits.max_file_size {
+                    return Err(FormError::FileTooLarge {
+                        limit: limits.max_file_size,
+                    });
+                }
+            } else {
+                fields += 1;
+                if fields > limits.max_fields {
+                    return Err(FormError::TooManyFields {
+                        limit: limits.max_fields,
+                    });
+                }
+                if part.data.len() > limits.max_value_len {
+                    return Err(FormError::ValueTooLong {
+                        limit: limits.max_value_len,
+                    });
+                }
+            }
+            parts.push(part);
+        }
+    }
+
+    pub fn parts(&self) -> &[FormPart] {
+        &self.parts
+    }
+
+    pub fn get(&self, name: &str) -> Option<&FormPart> {
+        self.parts.iter().find(|part| part.name == name)
+    }
+
+    // Value of the first non-file field called `name`.
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.parts
+            .iter()
+            .find(|part| part.name == name && !part.is_file())
+            .and_then(FormPart::text)
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = &FormPart> {
+        self.parts.iter().filter(|part| part.is_file())
+    }
+}
+
+fn decode_part(section: &[u8]) -> Result<FormPart, FormError> {
+    let (head, data) = match find(section, b"\r\n\r\n") {
+        Some(i) => (&section[..i], &section[i + 4..]),
+        None => return Err(FormError::MalformedPart("part has no header section")),
+    };
+    let head =
+        std::str::from_utf8(head).map_err(|_| FormError::MalformedPart("head is not UTF-8"))?;
+
+    let mut disposition = None;
+    let mut content_type = None;
+    for line in head.split("\r\n") {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(FormError::MalformedPart("invalid part header"))?;
+        if name.trim().eq_ignore_ascii_case("content-disposition") {
+            disposition = Some(value.trim());
+        } else if name.trim().eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim().to_string());
+        }
+    }
+
+    let disposition =
+        disposition.ok_or(FormError::MalformedPart("part has no Content-Disposition"))?;
+    let name =
+        parameter(disposition, "name").ok_or(FormError::MalformedPart("part has no name"))?;
+    // A file part keeps a filename even when nothing survives sanitizing.
+    let filename = parameter(disposition, "filename")
+        .map(|raw| sanitize_filename(&raw).unwrap_or_else(|| "upload".to_string()));
+
+    Ok(FormPart {
+        name,
+        filename,
+        content_type,
+        data: data.to_vec(),
+    })
+}
+
+// One part of an outgoing multipart/form-data body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartPart {
+    name: String,
+    filename: Option<String>,
+    headers: Vec<(String, String)>,
+    data: Vec<u8>,
+}
+
+impl MultipartPart {
+    pub fn new(name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        MultipartPart {
+            name: name.into(),
+            filename: None,
+            headers: Vec::new(),
+            data: data.into(),
+        }
+    }
+
+    pub fn filename(mut self, filename: impl Into<String>) -> Self {
+        self.filename = Some(filename.into());
+        self
+    }
+
+    pub fn content_type(self, content_type: impl Into<String>) -> Self {
+        self.with_header("Content-Type", content_type)
+    }
+
+    // Extra part headers are written after Content-Disposition, in order. Line
+    // breaks are stripped so a value cannot start a header of its own.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let clean = |s: String| s.replace(['\r', '\n'], "");
+        self.headers.push((clean(name.into()), clean(value.into())));
+        self
+    }
+
+    fn write_head(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(b"Content-Disposition: form-data; name=\"");
+        out.extend_from_slice(quote(&self.name).as_bytes());
+        out.push(b'"');
+        if let Some(filename) = &self.filename {
+            out.extend_from_slice(b"; filename=\"");
+            out.extend_from_slice(quote(filename).as_bytes());
+            out.push(b'"');
+        }
+        out.extend_from_slice(b"\r\n");
+        for (name, value) in &self.headers {
+            out.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+    }
+}
+
+// Builds a multipart/form-data body under a generated boundary, for uploads and
+// tests:
+//
+//     let form = MultipartBody::new()
+//         .text("title", "report")
+//         .file("doc", "report.pdf", "application/pdf", bytes);
+//     let request_body = form.to_body();
+//     let content_type = form.content_type();
+//
+// write_to streams the parts one at a time instead of assembling the whole body.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultipartBody {
+    boundary: String,
+    parts: Vec<MultipartPart>,
+}
+
+impl Default for MultipartBody {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MultipartBody {
+    pub fn new() -> Self {
+        // 128 random bits; a collision with the content is not a practical concern.
+        Self::with_boundary(format!(
+            "rawhttp-{:016x}{:016x}",
+            random_u64(),
+            random_u64()
+        ))
+    }
+
+    // A fixed boundary, for reproducible output. It must not occur in any part.
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        MultipartBody {
+            boundary: boundary.into(),
+            parts: Vec::new(),
+        }
+    }
+
+    pub fn text(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.part(MultipartPart::new(name, value.into()))
+    }
+
+    pub fn file(
+        self,
+        name: impl Into<String>,
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) -> Self {
+        self.part(
+            MultipartPart::new(name, data)
+                .filename(filename)
+                .content_type(content_type),
+        )
+    }
+
+    pub fn part(mut self, part: MultipartPart) -> Self {
+        self.parts.push(part);
+        self
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    // The Content-Type header value that goes with this body.
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary=\"{}\"", self.boundary)
+    }
+
+    pub fn content_length(&self) -> usize {
+        let mut head = Vec::new();
+        let parts: usize = self
+            .parts
+            .iter()
+            .map(|part| {
+                head.clear();
+                part.write_head(&mut head);
+                // "--" boundary CRLF, head, data, CRLF
+                self.boundary.len() + 4 + head.len() + part.data.len() + 2
+            })
+            .sum();
+        parts + self.boundary.len() + 6
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = Vec::new();
+        for part in &self.parts {
+            head.clear();
+            head.extend_from_slice(format!("--{}\r\n", self.boundary).as_bytes());
+            part.write_head(&mut head);
+            writer.write_all(&head)?;
+            writer.write_all(&part.data)?;
+            writer.write_all(b"\r\n")?;
+        }
+        writer.write_all(format!("--{}--\r\n", self.boundary).as_bytes())
+    }
+
+    pub fn to_body(&self) -> Body {
+        let mut buf = Vec::with_capacity(self.content_length());
+        self.write_to(&mut buf)
+            .expect("writing to a Vec cannot fail");
+        Body::Content(buf)
+    }
+}
+
+// Escapes a Content-Disposition parameter the way `parameter` reads it back.
+fn quote(value: &str) -> String {
+    value
+        .replace(['\r', '\n'], "")
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+}
+
+// Reduces a client-supplied filename to a bare name that is safe to join onto a
+// directory: any path (either separator) is dropped, control characters removed,
+// and names made only of dots rejected. None when nothing usable remains.
+pub fn sanitize_filename(raw: &str) -> Option<String> {
+    let base = raw.rsplit(['/', '\\']).next().unwrap_or(raw);
+    let cleaned: String = base.chars().filter(|c| !c.is_control()).collect();
+    let cleaned = cleaned.trim();
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '.') {
+        return None;
+    }
+    Some(cleaned.to_string())
+}
+
+// `name=value` or `name="value"` from a header's ;-separated parameters.
+fn parameter(header: &str, name: &str) -> Option<String> {
+    parameters(header)
+        .into_iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+}
+
+// The parameters after a header's leading value. Quoted strings are scanned
+// rather than split, since they may hold ';', and backslash escapes are undone.
+fn parameters(header: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut chars = header.chars().peekable();
+    while chars.next_if(|&c| c != ';').is_some() {}
+    while chars.next() == Some(';') {
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|&c| c != '=' && c != ';') {
+            key.push(c);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            continue;
+        }
+        while chars.next_if(|c| c.is_ascii_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            while chars.next_if(|&c| c != ';').is_some() {}
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ';') {
+                value.push(c);
+            }
+            value = value.trim_end().to_string();
+        }
+        params.push((key.trim().to_string(), value));
+    }
+    params
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT_TYPE: &str = "multipart/form-data; boundary=\"XyZ\"";
+
+    fn body(parts: &[(&str, &str)]) -> Vec<u8> {
+        let mut body = String::new();
+        for (disposition, data) in parts {
+            body.push_str(&format!(
+                "--XyZ\r\nContent-Disposition: form-data; {}\r\n\r\n{}\r\n",
+                disposition, data
+            ));
+        }
+        body.push_str("--XyZ--\r\n");
+        body.into_bytes()
+    }
+
+    #[test]
+    fn test_parses_fields_and_sanitizes_filenames() {
+        let body = body(&[
+            ("name=\"title\"", "hello"),
+            (
+                "name=\"doc\"; filename=\"..\\\\..\\\\windows\\\\evil\u{7}.txt\"",
+                "file body",
+            ),
+            ("name=\"other\"; filename=\"../../..\"", ""),
+        ]);
+        let form = Multipart::parse(CONTENT_TYPE, &body, &FormLimits::default()).unwrap();
+
+        assert_eq!(form.text("title"), Some("hello"));
+        let doc = form.get("doc").unwrap();
+        assert_eq!(doc.filename.as_deref(), Some("evil.txt"));
+        assert_eq!(doc.data, b"file body");
+        assert_eq!(
+            form.get("other").unwrap().filename.as_deref(),
+            Some("upload")
+        );
+        assert_eq!(form.files().count(), 2);
+    }
+
+    #[test]
+    fn test_urlencoded_bodies_round_trip() {
+        let body = encode_urlencoded([("q", "rust & c++"), ("lang", "fr-CA"), ("empty", "")]);
+        assert_eq!(body, "q=rust+%26+c%2B%2B&lang=fr-CA&empty=");
+        let form = parse_urlencoded(body.as_bytes(), &FormLimits::default()).unwrap();
+        assert_eq!(form.get("q"), Some("rust & c++"));
+        assert_eq!(form.get("empty"), Some(""));
+    }
+
+    #[test]
+    fn test_built_bodies_parse_back() {
+        let form = MultipartBody::new()
+            .text("title", "hello")
+            .file(
+                "doc",
+                "my \"notes\".txt",
+                "text/plain",
+                b"line 1\r\nline 2".to_vec(),
+            )
+            .part(
+                MultipartPart::new("meta", "{}")
+                    .content_type("application/json")
+                    .with_header("X-Checksum", "abc\r\nInjected: 1"),
+            );
+        assert!(form.boundary().starts_with("rawhttp-"));
+        assert_ne!(form.boundary(), MultipartBody::new().boundary());
+
+        let body = form.to_body();
+        assert_eq!(body.len(), form.content_length());
+        let text = body.as_str().unwrap();
+        assert!(text.contains("X-Checksum: abcInjected: 1\r\n"));
+
+        let parsed = Multipart::parse(
+            &form.content_type(),
+            body.as_bytes(),
+            &FormLimits::default(),
+        )
+        .unwrap();
+        assert_eq!(parsed.text("title"), Some("hello"));
+        let doc = parsed.get("doc").unwrap();
+        assert_eq!(doc.filename.as_deref(), Some("my \"notes\".txt"));
+        assert_eq!(doc.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(doc.data, b"line 1\r\nline 2");
+        assert_eq!(
+            parsed.get("meta").unwrap().content_type.as_deref(),
+            Some("application/json")
+        );
+    }
+
+    #[test]
+    fn test_quoted_parameters_may_hold_separators() {
+        assert_eq!(
+            parameter("form-data; filename=\"a;b.txt\"; name=f", "name").as_deref(),
+            Some("f")
+        );
+        assert_eq!(
+            parameter("form-data; filename=\"a;b.txt\"; name=f", "filename").as_deref(),
+            Some("a;b.txt")
+        );
+
+        let form = MultipartBody::with_boundary("XyZ").file(
+            "x;\"y\\z\"",
+            "a;b \"c\".txt",
+            "text/plain",
+            b"data".to_vec(),
+        );
+        let parsed = Multipart::parse(
+            &form.content_type(),
+            form.to_body().as_bytes(),
+            &FormLimits::default(),
+        )
+        .unwrap();
+        let part = parsed.get("x;\"y\\z\"").unwrap();
+        assert_eq!(part.filename.as_deref(), Some("a;b \"c\".txt"));
+    }
+
+    #[test]
+    fn test_limits_reject_abusive_forms() {
+        let limits = FormLimits {
+            max_fields: 2,
+            max_files: 1,
+            max_file_size: 4,
+            ..FormLimits::default()
+        };
+
+        let three_fields = body(&[
+            ("name=\"a\"", "1"),
+            ("name=\"b\"", "2"),
+            ("name=\"c\"", "3"),
+        ]);
+        let err = Multipart::parse(CONTENT_TYPE, &three_fields, &limits).unwrap_err();
+        assert_eq!(err, FormError::TooManyFields { limit: 2 });
+        assert_eq!(err.status(), StatusCode::ContentTooLarge);
+
+        let big_file = body(&[("name=\"f\"; filename=\"a.bin\"", "12345")]);
+        assert_eq!(
+            Multipart::parse(CONTENT_TYPE, &big_file, &limits),
+            Err(FormError::FileTooLarge { limit: 4 })
+        );
+
+        let two_files = body(&[
+            ("name=\"f\"; filename=\"a\"", "1"),
+            ("name=\"g\"; filename=\"b\"", "2"),
+        ]);
+        assert_eq!(
+            Multipart::parse(CONTENT_TYPE, &two_files, &limits),
+            Err(FormError::TooManyFiles { limit: 1 })
+        );
+
+        assert_eq!(
+            parse_urlencoded(b"a=1&b=2&c=3", &limits),
+            Err(FormError::TooManyFields { limit: 2 })
+        );
+        let long_name = FormLimits {
+            max_name_len: 3,
+            ..FormLimits::default()
+        };
+        assert_eq!(
+            parse_urlencoded(b"abcd=1", &long_name),
+            Err(FormError::NameTooLong { limit: 3 })
+        );
+        assert_eq!(
+            parse_urlencoded(b"a=x+y&b=%2F", &limits).unwrap().get("a"),
+            Some("x y")
+        );
+    }
+}
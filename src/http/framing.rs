@@ -0,0 +1,260 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use thiserror::Error;
+
+use super::body::Body;
+
+const PREFIX_LEN: usize = 5;
+const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024; // 4MB
+
+#[derive(Debug, Error)]
+pub enum FramingError {
+    #[error("Truncated message: expected {expected} bytes, got {actual}")]
+    Truncated { expected: usize, actual: usize },
+
+    #[error("Message of {0} bytes exceeds the size limit")]
+    TooLarge(usize),
+
+    #[error("IO error while framing messages")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub flags: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Message {
+    pub const COMPRESSED: u8 = 0x01;
+    pub const TRAILER: u8 = 0x80;
+
+    pub fn new(payload: impl Into<Vec<u8>>) -> Self {
+        Message {
+            flags: 0,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn trailer(payload: impl Into<Vec<u8>>) -> Self {
+        Message {
+            flags: Self::TRAILER,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & Self::COMPRESSED != 0
+    }
+
+    pub fn is_trailer(&self) -> bool {
+        self.flags & Self::TRAILER != 0
+    }
+
+    // Fails for payloads the 4-byte length prefix cannot describe.
+    pub fn encode(&self) -> Result<Vec<u8>, FramingError> {
+        let len = length_prefix(self.payload.len())?;
+        let mut buf = Vec::with_capacity(PREFIX_LEN + self.payload.len());
+        buf.push(self.flags);
+        buf.extend_from_slice(&len);
+        buf.extend_from_slice(&self.payload);
+        Ok(buf)
+    }
+}
+
+pub struct MessageReader<R: Read> {
+    inner: R,
+    max_message_size: usize,
+}
+
+impl<R: Read> MessageReader<R> {
+    pub fn new(inner: R) -> Self {
+        MessageReader {
+            inner,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+        }
+    }
+
+    pub fn with_max_message_size(mut self, max: usize) -> Self {
+        self.max_message_size = max;
+        self
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    pub fn read_message(&mut self) -> Result<Option<Message>, FramingError> {
+        let mut prefix = [0u8; PREFIX_LEN];
+        let read = read_full(&mut self.inner, &mut prefix)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        if read < PREFIX_LEN {
+            return Err(FramingError::Truncated {
+                expected: PREFIX_LEN,
+                actual: read,
+            });
+        }
+
+        let len = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+        if len > self.max_message_size {
+            return Err(FramingError::TooLarge(len));
+        }
+
+        let mut payload = vec![0u8; len];
+        let read = read_full(&mut self.inner, &mut payload)?;
+        if read < len {
+            return Err(FramingError::Truncated {
+                expected: len,
+                actual: read,
+            });
+        }
+
+        Ok(Some(Message {
+            flags: prefix[0],
+            payload,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for MessageReader<R> {
+    type Item = Result<Message, FramingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+pub struct MessageWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> MessageWriter<W> {
+    pub fn new(inner: W) -> Self {
+        MessageWriter { inner }
+    }
+
+    pub fn write_message(&mut self, message: &Message) -> Result<(), FramingError> {
+        self.inner.write_all(&message.encode()?)?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+pub fn decode_body(body: &Body, max_message_size: usize) -> Result<Vec<Message>, FramingError> {
+    MessageReader::new(body.as_bytes())
+        .with_max_message_size(max_message_size)
+        .collect()
+}
+
+pub fn encode_body(messages: &[Message]) -> Result<Body, FramingError> {
+    let mut data = Vec::new();
+    for message in messages {
+        data.extend(message.encode()?);
+    }
+    Ok(if data.is_empty() {
+        Body::Empty
+    } else {
+        Body::Content(data)
+    })
+}
+
+fn length_prefix(len: usize) -> Result<[u8; 4], FramingError> {
+    u32::try_from(len)
+        .map(u32::to_be_bytes)
+        .map_err(|_| FramingError::TooLarge(len))
+}
+
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        let messages = vec![
+            Message::new(b"hello".to_vec()),
+            Message::new(Vec::new()),
+            Message::trailer(b"grpc-status:0\r\n".to_vec()),
+        ];
+
+        let body = encode_body(&messages).unwrap();
+        assert_eq!(&body.as_bytes()[..5], &[0, 0, 0, 0, 5]);
+
+        let decoded = decode_body(&body, 1024).unwrap();
+        assert_eq!(decoded, messages);
+        assert!(decoded[2].is_trailer());
+        assert!(!decoded[0].is_compressed());
+    }
+
+    #[test]
+    fn test_truncated_prefix_and_payload() {
+        let result = decode_body(&Body::Content(vec![0, 0, 0]), 1024);
+        assert!(matches!(
+            result,
+            Err(FramingError::Truncated {
+                expected: 5,
+                actual: 3
+            })
+        ));
+
+        let result = decode_body(&Body::Content(vec![0, 0, 0, 0, 4, b'a']), 1024);
+        assert!(matches!(
+            result,
+            Err(FramingError::Truncated {
+                expected: 4,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_message_too_large() {
+        let body = encode_body(&[Message::new(vec![0; 64])]).unwrap();
+        assert!(matches!(
+            decode_body(&body, 32),
+            Err(FramingError::TooLarge(64))
+        ));
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn test_length_must_fit_the_prefix() {
+        assert_eq!(length_prefix(5).unwrap(), [0, 0, 0, 5]);
+        assert_eq!(length_prefix(u32::MAX as usize).unwrap(), [0xff; 4]);
+        let past = u32::MAX as usize + 1;
+        assert!(matches!(length_prefix(past), Err(FramingError::TooLarge(n)) if n == past));
+    }
+
+    #[test]
+    fn test_writer_streams_messages() {
+        let mut writer = MessageWriter::new(Vec::new());
+        writer.write_message(&Message::new(b"a".to_vec())).unwrap();
+        writer.write_message(&Message::new(b"bc".to_vec())).unwrap();
+
+        let mut reader = MessageReader::new(std::io::Cursor::new(writer.into_inner()));
+        assert_eq!(reader.read_message().unwrap().unwrap().payload, b"a");
+        assert_eq!(reader.read_message().unwrap().unwrap().payload, b"bc");
+        assert!(reader.read_message().unwrap().is_none());
+    }
+}
@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use thiserror::Error;
 
+use super::connection::{ConnectionHeader, HOP_BY_HOP};
+use super::scan;
+
 #[derive(Debug, Error)]
 pub enum HeaderError {
     #[error("Invalid head format: missing colon separator")]
@@ -20,82 +23,200 @@ pub enum HeaderError {
     InvalidHeaderValue,
 }
 
-#[derive(Debug, Clone)]
+// Requests rarely carry more than a handful of fields, so headers live in an
+// insertion-ordered vector searched linearly. Past INLINE_LIMIT entries a name
+// index takes over lookups.
+const INLINE_LIMIT: usize = 16;
+
+#[derive(Debug, Clone, Default)]
 pub struct Headers {
-    pub headers: HashMap<String, String>,
+    entries: Vec<(String, String)>,
+    index: Option<HashMap<String, usize>>,
+    // Where insert joined a repeated field onto an entry: the entry and the byte
+    // offset of the joining comma. Empty unless a field was repeated.
+    joins: Vec<(usize, usize)>,
 }
 
 impl Headers {
     pub fn new() -> Self {
-        Headers {
-            headers: HashMap::new(),
+        Self::default()
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        match &self.index {
+            Some(index) if name.bytes().any(|b| b.is_ascii_uppercase()) => {
+                index.get(&name.to_ascii_lowercase()).copied()
+            }
+            Some(index) => index.get(name).copied(),
+            None => self
+                .entries
+                .iter()
+                .position(|(n, _)| n.eq_ignore_ascii_case(name)),
         }
     }
 
+    fn push(&mut self, name: String, value: String) {
+        let name = if name.bytes().any(|b| b.is_ascii_uppercase()) {
+            name.to_ascii_lowercase()
+        } else {
+            name
+        };
+
+        if let Some(index) = &mut self.index {
+            index.insert(name.clone(), self.entries.len());
+        }
+        self.entries.push((name, value));
+
+        if self.index.is_none() && self.entries.len() > INLINE_LIMIT {
+            self.rebuild_index();
+        }
+    }
+
+    fn rebuild_index(&mut self) {
+        self.index = (self.entries.len() > INLINE_LIMIT).then(|| {
+            self.entries
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (name.clone(), i))
+                .collect()
+        });
+    }
+
     pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
         let name = name.into();
         let value = value.into();
-        let key = name.to_lowercase();
 
-        self.headers
-            .entry(key)
-            .and_modify(|existing| {
+        match self.position(&name) {
+            Some(i) => {
+                let existing = &mut self.entries[i].1;
+                self.joins.push((i, existing.len()));
                 existing.push(',');
                 existing.push_str(&value);
-            })
-            .or_insert(value);
+            }
+            None => self.push(name, value),
+        }
+    }
+
+    pub fn try_insert(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+        Self::validate(&name, &value)?;
+        self.insert(name, value);
+        Ok(())
+    }
+
+    pub fn try_set(
+        &mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<(), HeaderError> {
+        let (name, value) = (name.into(), value.into());
+        Self::validate(&name, &value)?;
+        self.set(name, value);
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        match self.position(&name) {
+            Some(i) => {
+                self.joins.retain(|&(entry, _)| entry != i);
+                self.entries[i].1 = value.into();
+            }
+            None => self.push(name, value.into()),
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let i = self.position(name)?;
+        let (_, value) = self.entries.remove(i);
+        self.joins.retain(|&(entry, _)| entry != i);
+        for (entry, _) in &mut self.joins {
+            if *entry > i {
+                *entry -= 1;
+            }
+        }
+        if self.index.is_some() {
+            self.rebuild_index();
+        }
+        Some(value)
     }
 
     pub fn get(&self, name: &str) -> Option<&str> {
-        self.headers.get(&name.to_lowercase()).map(|s| s.as_str())
+        self.position(name).map(|i| self.entries[i].1.as_str())
+    }
+
+    // The value of each line a field arrived on, where get() has them joined with
+    // ','. Commas inside a single line stay put.
+    pub fn field_lines(&self, name: &str) -> Vec<&str> {
+        let Some(i) = self.position(name) else {
+            return Vec::new();
+        };
+        let value = self.entries[i].1.as_str();
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for &(_, at) in self.joins.iter().filter(|&&(entry, _)| entry == i) {
+            lines.push(&value[start..at]);
+            start = at + 1;
+        }
+        lines.push(&value[start..]);
+        lines
     }
 
     pub fn contains(&self, name: &str) -> bool {
-        self.headers.contains_key(&name.to_lowercase())
+        self.position(name).is_some()
     }
 
     pub fn len(&self) -> usize {
-        self.headers.len()
+        self.entries.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.headers.is_empty()
+        self.entries.is_empty()
     }
 
+    // Yields fields in the order they were first inserted.
     pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> + '_ {
-        self.headers.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn strip_hop_by_hop(&mut self) {
+        let connection = ConnectionHeader::from_headers(self);
+
+        for token in connection.tokens() {
+            self.remove(token);
+        }
+        for name in HOP_BY_HOP {
+            self.remove(name);
+        }
+    }
+
+    pub fn validate(name: &str, value: &str) -> Result<(), HeaderError> {
+        if !Self::is_valid_token(name) {
+            return Err(HeaderError::InvalidHeaderName);
+        }
+        if !Self::is_valid_header_value(value) {
+            return Err(HeaderError::InvalidHeaderValue);
+        }
+        Ok(())
+    }
+
+    pub fn sanitize_value(value: &str) -> String {
+        value
+            .chars()
+            .filter(|&c| c == '\t' || !c.is_ascii_control())
+            .collect()
     }
 
     fn is_valid_token(s: &str) -> bool {
-        !s.is_empty()
-            && s.chars().all(|c| {
-                c.is_ascii_alphanumeric()
-                    || matches!(
-                        c,
-                        '!' | '#'
-                            | '$'
-                            | '%'
-                            | '&'
-                            | '\''
-                            | '*'
-                            | '+'
-                            | '-'
-                            | '.'
-                            | '^'
-                            | '_'
-                            | '`'
-                            | '|'
-                            | '~'
-                    )
-            })
+        scan::is_token(s.as_bytes())
     }
 
     fn is_valid_header_value(s: &str) -> bool {
-        if s.contains('\r') || s.contains('\n') {
-            return false;
-        }
-        s.chars()
-            .all(|c| matches!(c, ' ' | '\t') || c.is_ascii_graphic() || !c.is_ascii())
+        scan::is_field_value(s.as_bytes())
     }
 
     fn parse_header_line(line: &str) -> Result<(String, String), HeaderError> {
@@ -135,9 +256,83 @@ impl Headers {
     }
 }
 
-impl Default for Headers {
-    fn default() -> Self {
-        Self::new()
+const COMMON_LINES: &[(&str, &str, &[u8])] = &[
+    ("connection", "close", b"connection: close\r\n"),
+    ("connection", "keep-alive", b"connection: keep-alive\r\n"),
+    ("content-length", "0", b"content-length: 0\r\n"),
+    (
+        "transfer-encoding",
+        "chunked",
+        b"transfer-encoding: chunked\r\n",
+    ),
+    (
+        "content-type",
+        "application/json",
+        b"content-type: application/json\r\n",
+    ),
+    (
+        "content-type",
+        "text/plain; charset=utf-8",
+        b"content-type: text/plain; charset=utf-8\r\n",
+    ),
+    ("cache-control", "no-cache", b"cache-control: no-cache\r\n"),
+    ("cache-control", "no-store", b"cache-control: no-store\r\n"),
+];
+
+// Appends "name: value\r\n" without an intermediate String; frequent pairs come
+// from a table of preserialized lines.
+pub(crate) fn write_line(buf: &mut Vec<u8>, name: &str, value: &str) {
+    if let Some((_, _, line)) = COMMON_LINES
+        .iter()
+        .find(|(n, v, _)| *n == name && *v == value)
+    {
+        buf.extend_from_slice(line);
+        return;
+    }
+
+    buf.reserve(name.len() + value.len() + 4);
+    buf.extend_from_slice(name.as_bytes());
+    buf.extend_from_slice(b": ");
+    buf.extend_from_slice(value.as_bytes());
+    buf.extend_from_slice(b"\r\n");
+}
+
+// A map from field name to value, in insertion order. Repeated names are joined
+// as insert joins them; names and values are validated on the way in.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Headers {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Headers {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Headers;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of header fields")
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Headers, A::Error> {
+                let mut headers = Headers::new();
+                while let Some((name, value)) = map.next_entry::<String, String>()? {
+                    headers
+                        .try_insert(name, value)
+                        .map_err(serde::de::Error::custom)?;
+                }
+                Ok(headers)
+            }
+        }
+
+        deserializer.deserialize_map(Visitor)
     }
 }
 
@@ -145,6 +340,46 @@ impl Default for Headers {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_preserves_insertion_order() {
+        let mut headers = Headers::new();
+        headers.insert("Zeta", "1");
+        headers.insert("Alpha", "2");
+        headers.insert("zeta", "3");
+
+        let pairs: Vec<_> = headers.iter().collect();
+        assert_eq!(pairs, vec![("zeta", "1,3"), ("alpha", "2")]);
+    }
+
+    #[test]
+    fn test_overflow_index() {
+        let mut headers = Headers::new();
+        for i in 0..40 {
+            headers.insert(format!("X-Field-{}", i), i.to_string());
+        }
+        assert!(headers.index.is_some());
+        assert_eq!(headers.get("x-field-39"), Some("39"));
+        assert_eq!(headers.get("X-FIELD-7"), Some("7"));
+
+        for i in 0..30 {
+            assert_eq!(
+                headers.remove(&format!("x-field-{}", i)),
+                Some(i.to_string())
+            );
+        }
+        assert!(headers.index.is_none());
+        assert_eq!(headers.get("X-Field-35"), Some("35"));
+        assert_eq!(headers.iter().next(), Some(("x-field-30", "30")));
+    }
+
+    #[test]
+    fn test_write_line() {
+        let mut buf = Vec::new();
+        write_line(&mut buf, "connection", "close");
+        write_line(&mut buf, "x-id", "42");
+        assert_eq!(buf, b"connection: close\r\nx-id: 42\r\n");
+    }
+
     #[test]
     fn test_valid_headers() {
         let lines = "Host: localhost:42069\r\nFoFo:     barbar\r\n\r\n";
@@ -220,6 +455,35 @@ mod tests {
         assert_eq!(headers.get("Set-Cookie"), Some("session=abc,user=john"));
     }
 
+    #[test]
+    fn test_field_lines_survive_joining() {
+        let mut headers = Headers::new();
+        headers.insert("X-Old", "gone");
+        headers.insert("Accept", "text/html,text/plain");
+        headers.insert("Accept", "*/*");
+        assert_eq!(
+            headers.field_lines("accept"),
+            ["text/html,text/plain", "*/*"]
+        );
+
+        headers.remove("X-Old");
+        assert_eq!(headers.field_lines("Accept").len(), 2);
+        headers.set("Accept", "a,b");
+        assert_eq!(headers.field_lines("Accept"), ["a,b"]);
+        assert!(headers.field_lines("Missing").is_empty());
+    }
+
+    #[test]
+    fn test_set_replaces_existing_value() {
+        let mut headers = Headers::new();
+        headers.insert("Content-Length", "13");
+        headers.set("content-length", "5");
+
+        assert_eq!(headers.get("Content-Length"), Some("5"));
+        assert_eq!(headers.remove("CONTENT-LENGTH"), Some("5".to_string()));
+        assert!(!headers.contains("Content-Length"));
+    }
+
     #[test]
     fn test_crlf_injection_in_header_value() {
         let line = "X-Custom: value\r\nInjected-Header: malicious";
@@ -233,4 +497,45 @@ mod tests {
         let result = Headers::parse_header_line(line);
         assert!(matches!(result, Err(HeaderError::InvalidHeaderValue)));
     }
+
+    #[test]
+    fn test_strip_hop_by_hop() {
+        let mut headers = Headers::new();
+        headers.insert("Connection", "close, X-Secret");
+        headers.insert("X-Secret", "1");
+        headers.insert("Keep-Alive", "timeout=5");
+        headers.insert("Transfer-Encoding", "chunked");
+        headers.insert("Content-Type", "text/plain");
+
+        headers.strip_hop_by_hop();
+
+        assert_eq!(headers.len(), 1);
+        assert_eq!(headers.get("Content-Type"), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_try_insert_rejects_injection() {
+        let mut headers = Headers::new();
+
+        assert!(matches!(
+            headers.try_insert("X-Custom", "value\r\nSet-Cookie: evil=1"),
+            Err(HeaderError::InvalidHeaderValue)
+        ));
+        assert!(matches!(
+            headers.try_set("X-Custom\r\nEvil", "value"),
+            Err(HeaderError::InvalidHeaderName)
+        ));
+        assert!(headers.is_empty());
+
+        headers.try_insert("X-Custom", "fine\tvalue").unwrap();
+        assert_eq!(headers.get("X-Custom"), Some("fine\tvalue"));
+    }
+
+    #[test]
+    fn test_sanitize_value() {
+        assert_eq!(
+            Headers::sanitize_value("a\r\nSet-Cookie: x\0\tb"),
+            "aSet-Cookie: x\tb"
+        );
+    }
 }
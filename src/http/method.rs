@@ -13,6 +13,37 @@ pub enum Method {
     OPTIONS,
     TRACE,
     PATCH,
+    // WebDAV (RFC 4918).
+    PROPFIND,
+    PROPPATCH,
+    MKCOL,
+    COPY,
+    MOVE,
+    LOCK,
+    UNLOCK,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::GET => "GET",
+            Self::HEAD => "HEAD",
+            Self::POST => "POST",
+            Self::PUT => "PUT",
+            Self::DELETE => "DELETE",
+            Self::CONNECT => "CONNECT",
+            Self::OPTIONS => "OPTIONS",
+            Self::TRACE => "TRACE",
+            Self::PATCH => "PATCH",
+            Self::PROPFIND => "PROPFIND",
+            Self::PROPPATCH => "PROPPATCH",
+            Self::MKCOL => "MKCOL",
+            Self::COPY => "COPY",
+            Self::MOVE => "MOVE",
+            Self::LOCK => "LOCK",
+            Self::UNLOCK => "UNLOCK",
+        }
+    }
 }
 
 impl FromStr for Method {
@@ -29,7 +60,29 @@ impl FromStr for Method {
             "OPTIONS" => Ok(Self::OPTIONS),
             "TRACE" => Ok(Self::TRACE),
             "PATCH" => Ok(Self::PATCH),
+            "PROPFIND" => Ok(Self::PROPFIND),
+            "PROPPATCH" => Ok(Self::PROPPATCH),
+            "MKCOL" => Ok(Self::MKCOL),
+            "COPY" => Ok(Self::COPY),
+            "MOVE" => Ok(Self::MOVE),
+            "LOCK" => Ok(Self::LOCK),
+            "UNLOCK" => Ok(Self::UNLOCK),
             _ => Err(RequestLineError::InvalidMethod(s.to_string())),
         }
     }
 }
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Method {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Method {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let method = <String as serde::Deserialize>::deserialize(deserializer)?;
+        method.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -1,17 +1,73 @@
+pub mod accept_encoding;
+#[cfg(feature = "arena")]
+pub mod arena;
+pub mod batch;
 pub mod body;
+pub mod cache_control;
+pub mod charset;
+pub mod conditional;
+pub mod connection;
+pub mod content_digest;
+pub mod context;
+pub mod dav;
+pub mod duplex;
+pub mod etag;
+pub mod extensions;
+pub mod filter;
+pub mod form;
+pub mod framing;
 pub mod header;
 pub mod method;
+pub mod negotiate;
+pub mod pagination;
+pub mod path;
+pub mod problem;
 pub mod query;
+pub mod range;
+pub mod record;
 pub mod request;
 pub mod request_line;
 pub mod response;
+pub mod scan;
+pub mod server_timing;
+pub mod signature;
 pub mod status_code;
+pub mod takeover;
 
+pub use accept_encoding::AcceptEncoding;
+#[cfg(feature = "arena")]
+pub use arena::{ArenaRequest, PathParams, RequestArena, with_arena};
+pub use batch::{Batch, BatchError, BatchFormat};
 pub use body::Body;
+pub use cache_control::CacheControl;
+pub use charset::Charset;
+pub use connection::{ConnectionHeader, Persistence};
+pub use content_digest::{DigestAlgorithm, DigestError, DigestHeader};
+pub use context::{ConnectionContext, ConnectionInfo};
+pub use dav::{DavHeaderError, Depth, Destination, Overwrite};
+pub use duplex::{BodyFraming, Duplex, DuplexStream};
+pub use etag::{ETag, ETagList};
+pub use extensions::Extensions;
+pub use filter::{BodyFilter, FilterWriter, Pipeline, Replace};
+pub use form::{
+    FormError, FormLimits, FormPart, Multipart, MultipartBody, MultipartPart, encode_urlencoded,
+};
 pub use header::Headers;
 pub use method::Method;
+pub use negotiate::{Accept, AcceptError, MediaRange, NotAcceptable};
+pub use pagination::{Pagination, PaginationConfig};
+pub use path::EncodedSlashPolicy;
+pub use problem::{Problem, ProblemFormat};
 pub use query::{Query, QueryError};
-pub use request::{ParseError, Request};
-pub use request_line::RequestLine;
-pub use response::Response;
+pub use range::{ByteRange, RangeError, RangeHeader};
+pub use record::{RecordError, RequestRecord, ResponseRecord};
+pub use request::{ParseError, ParseOptions, ParserLimits, Request, RequestHead};
+pub use request_line::{RequestLine, TargetPolicy};
+pub use response::{Abort, LengthMismatchPolicy, Response, ResponseError};
+pub use server_timing::ServerTiming;
+pub use signature::{
+    HmacSha256, KeyStore, SignatureError, SignatureParams, Signer, SigningKey, StaticKeys,
+    VerifiedSignature, Verifier,
+};
 pub use status_code::StatusCode;
+pub use takeover::{TakenStream, Takeover};
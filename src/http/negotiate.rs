@@ -0,0 +1,239 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::{Request, Response, StatusCode, accept_encoding::parse_qvalue};
+
+#[derive(Debug, Error)]
+pub enum AcceptError {
+    #[error("Invalid media range: {0}")]
+    InvalidMediaRange(String),
+
+    #[error("Invalid quality value: {0}")]
+    InvalidQuality(String),
+}
+
+// No type a handler can produce is acceptable to the client.
+#[derive(Debug, Error, PartialEq)]
+#[error("None of the available media types is acceptable: {available}")]
+pub struct NotAcceptable {
+    pub available: String,
+}
+
+impl NotAcceptable {
+    pub fn status(&self) -> StatusCode {
+        StatusCode::NotAcceptable
+    }
+
+    pub fn into_response(self) -> Response {
+        Response::problem(
+            self.status(),
+            "Not Acceptable",
+            &format!("Available media types: {}", self.available),
+            "about:blank",
+        )
+        .with_header("Vary", "Accept")
+    }
+}
+
+// One entry of an Accept header: "text/*", "application/json;v=2;q=0.5".
+// Types are lowercased; parameters after q are accept-extensions and dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    pub media_type: String,
+    pub subtype: String,
+    pub params: Vec<(String, String)>,
+    pub quality: u16,
+}
+
+impl MediaRange {
+    // How closely this range names `candidate`, or None when it does not match.
+    // RFC 9110 12.5.1: more specific ranges override less specific ones.
+    fn specificity(&self, candidate: &MediaRange) -> Option<(u8, usize)> {
+        let level = match (self.media_type.as_str(), self.subtype.as_str()) {
+            ("*", "*") => 0,
+            (media_type, "*") if media_type == candidate.media_type => 1,
+            (media_type, subtype)
+                if media_type == candidate.media_type && subtype == candidate.subtype =>
+            {
+                2
+            }
+            _ => return None,
+        };
+        let params_match = self.params.iter().all(|(name, value)| {
+            candidate
+                .params
+                .iter()
+                .any(|(n, v)| n == name && v.eq_ignore_ascii_case(value))
+        });
+        params_match.then_some((level, self.params.len()))
+    }
+}
+
+impl FromStr for MediaRange {
+    type Err = AcceptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(';');
+        let essence = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let (media_type, subtype) = essence
+            .split_once('/')
+            .filter(|(t, s)| is_token(t) && is_token(s) && !(*t == "*" && *s != "*"))
+            .ok_or_else(|| AcceptError::InvalidMediaRange(s.trim().to_string()))?;
+
+        let mut params = Vec::new();
+        let mut quality = 1000;
+        for param in parts {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let name = name.trim().to_ascii_lowercase();
+            let value = value.trim().trim_matches('"');
+            if name == "q" {
+                quality = parse_qvalue(value)
+                    .map_err(|_| AcceptError::InvalidQuality(value.to_string()))?;
+                break;
+            }
+            params.push((name, value.to_string()));
+        }
+
+        Ok(MediaRange {
+            media_type: media_type.to_string(),
+            subtype: subtype.to_string(),
+            params,
+            quality,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Accept {
+    ranges: Vec<MediaRange>,
+}
+
+impl Accept {
+    pub fn parse(value: &str) -> Result<Self, AcceptError> {
+        let ranges = value
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Accept { ranges })
+    }
+
+    pub fn ranges(&self) -> &[MediaRange] {
+        &self.ranges
+    }
+
+    // The quality the client gives `media_type` (scaled to 0..=1000), taken from
+    // the most specific range that matches it; 0 when none does.
+    pub fn quality(&self, media_type: &str) -> u16 {
+        let Ok(candidate) = media_type.parse::<MediaRange>() else {
+            return 0;
+        };
+        self.ranges
+            .iter()
+            .filter_map(|range| Some((range.specificity(&candidate)?, range.quality)))
+            .max_by_key(|(specificity, _)| *specificity)
+            .map_or(0, |(_, quality)| quality)
+    }
+
+    // The acceptable type with the highest quality; ties go to the one listed
+    // first in `available`, so list the preferred serialization first.
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best: Option<(&'a str, u16)> = None;
+        for &media_type in available {
+            let q = self.quality(media_type);
+            if q > 0 && best.is_none_or(|(_, best_q)| q > best_q) {
+                best = Some((media_type, q));
+            }
+        }
+        best.map(|(media_type, _)| media_type)
+    }
+}
+
+impl FromStr for Accept {
+    type Err = AcceptError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+// Picks which of `available` to produce for this request; the error turns into
+// the 406 to send. Without a usable Accept header any type will do and the
+// first is chosen. Responses that depend on the choice should carry
+// `Vary: Accept`.
+pub fn negotiate<'a>(request: &Request, available: &[&'a str]) -> Result<&'a str, NotAcceptable> {
+    let chosen = match request.accept() {
+        Some(accept) => accept.negotiate(available),
+        None => available.first().copied(),
+    };
+    chosen.ok_or_else(|| NotAcceptable {
+        available: available.join(", "),
+    })
+}
+
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVAILABLE: [&str; 3] = ["application/json", "text/html", "text/csv"];
+
+    #[test]
+    fn test_more_specific_ranges_win() {
+        let accept =
+            Accept::parse("text/*;q=0.3, text/html;q=0.7, text/html;level=1, */*;q=0.5").unwrap();
+        assert_eq!(accept.quality("text/html;level=1"), 1000);
+        assert_eq!(accept.quality("text/html"), 700);
+        assert_eq!(accept.quality("text/plain"), 300);
+        assert_eq!(accept.quality("image/png"), 500);
+        assert_eq!(accept.negotiate(&AVAILABLE), Some("text/html"));
+    }
+
+    #[test]
+    fn test_negotiates_by_quality_then_server_order() {
+        let accept = Accept::parse("text/csv, application/json").unwrap();
+        assert_eq!(accept.negotiate(&AVAILABLE), Some("application/json"));
+
+        let accept = Accept::parse("text/csv;q=1, application/json;q=0.9").unwrap();
+        assert_eq!(accept.negotiate(&AVAILABLE), Some("text/csv"));
+
+        let accept = Accept::parse("*/*, application/json;q=0").unwrap();
+        assert_eq!(accept.negotiate(&AVAILABLE), Some("text/html"));
+        assert_eq!(
+            Accept::parse("image/*").unwrap().negotiate(&AVAILABLE),
+            None
+        );
+
+        assert!(Accept::parse("text/html;q=2").is_err());
+        assert!(Accept::parse("*/html").is_err());
+        assert!(Accept::parse("html").is_err());
+    }
+
+    #[test]
+    fn test_answers_406_when_nothing_fits() {
+        let request = |accept: &str| {
+            let raw = format!("GET / HTTP/1.1\r\nHost: x\r\n{}\r\n", accept);
+            Request::try_from(raw.as_bytes()).unwrap()
+        };
+
+        assert_eq!(negotiate(&request(""), &AVAILABLE), Ok("application/json"));
+        assert_eq!(
+            negotiate(&request("Accept: text/csv\r\n"), &AVAILABLE),
+            Ok("text/csv")
+        );
+        let Err(err) = negotiate(&request("Accept: image/png\r\n"), &AVAILABLE) else {
+            panic!("expected 406");
+        };
+        let response = err.into_response();
+        assert_eq!(response.status_code(), StatusCode::NotAcceptable);
+        assert_eq!(response.headers().get("vary"), Some("Accept"));
+    }
+}
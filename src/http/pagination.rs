@@ -0,0 +1,315 @@
+use thiserror::Error;
+
+use super::Query;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PaginationError {
+    #[error("Invalid value for '{0}': expected a non-negative integer")]
+    InvalidNumber(String),
+
+    #[error("Page numbers start at 1")]
+    PageOutOfRange,
+
+    #[error("Limit must be between 1 and {0}")]
+    LimitOutOfRange(usize),
+
+    #[error("Cannot sort by '{0}'")]
+    InvalidSortField(String),
+
+    #[error("Cannot filter by '{0}'")]
+    InvalidFilterField(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct PaginationConfig {
+    pub default_limit: usize,
+    pub max_limit: usize,
+    pub sortable: Vec<String>,
+    pub filterable: Vec<String>,
+}
+
+impl PaginationConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn limits(mut self, default_limit: usize, max_limit: usize) -> Self {
+        self.default_limit = default_limit;
+        self.max_limit = max_limit;
+        self
+    }
+
+    pub fn sortable(mut self, fields: &[&str]) -> Self {
+        self.sortable = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+
+    pub fn filterable(mut self, fields: &[&str]) -> Self {
+        self.filterable = fields.iter().map(|f| f.to_string()).collect();
+        self
+    }
+}
+
+impl Default for PaginationConfig {
+    fn default() -> Self {
+        PaginationConfig {
+            default_limit: 20,
+            max_limit: 100,
+            sortable: Vec::new(),
+            filterable: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortField {
+    pub field: String,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pagination {
+    pub limit: usize,
+    pub offset: usize,
+    pub sort: Vec<SortField>,
+    pub filters: Vec<(String, String)>,
+}
+
+impl Pagination {
+    // Accepts page/limit or offset/limit; "sort=-created,name" sorts descending by
+    // created then ascending by name; "filter[field]=value" adds an equality filter.
+    pub fn from_query(query: &Query, config: &PaginationConfig) -> Result<Self, PaginationError> {
+        let number = |key: &str| -> Result<Option<usize>, PaginationError> {
+            query
+                .get(key)
+                .map(|v| {
+                    v.parse::<usize>()
+                        .map_err(|_| PaginationError::InvalidNumber(key.to_string()))
+                })
+                .transpose()
+        };
+
+        let limit = number("limit")?.unwrap_or(config.default_limit);
+        if limit == 0 || limit > config.max_limit {
+            return Err(PaginationError::LimitOutOfRange(config.max_limit));
+        }
+
+        let offset = match (number("offset")?, number("page")?) {
+            (Some(offset), _) => offset,
+            (None, Some(0)) => return Err(PaginationError::PageOutOfRange),
+            (None, Some(page)) => (page - 1).saturating_mul(limit),
+            (None, None) => 0,
+        };
+
+        let mut sort = Vec::new();
+        for field in query.get("sort").unwrap_or("").split(',') {
+            let field = field.trim();
+            if field.is_empty() {
+                continue;
+            }
+            let (name, descending) = match field.strip_prefix('-') {
+                Some(name) => (name, true),
+                None => (field.strip_prefix('+').unwrap_or(field), false),
+            };
+            if !config.sortable.iter().any(|f| f == name) {
+                return Err(PaginationError::InvalidSortField(name.to_string()));
+            }
+            sort.push(SortField {
+                field: name.to_string(),
+                descending,
+            });
+        }
+
+        let mut filters = Vec::new();
+        for (key, value) in query.iter_all() {
+            let Some(name) = key
+                .strip_prefix("filter[")
+                .and_then(|rest| rest.strip_suffix(']'))
+            else {
+                continue;
+            };
+            if !config.filterable.iter().any(|f| f == name) {
+                return Err(PaginationError::InvalidFilterField(name.to_string()));
+            }
+            filters.push((name.to_string(), value.to_string()));
+        }
+        filters.sort();
+
+        Ok(Pagination {
+            limit,
+            offset,
+            sort,
+            filters,
+        })
+    }
+
+    pub fn page(&self) -> usize {
+        (self.offset / self.limit).saturating_add(1)
+    }
+
+    pub fn link_header(&self, path: &str, query: &Query, total: Option<usize>) -> String {
+        let mut links = Vec::new();
+        let mut link = |offset: usize, rel: &str| {
+            links.push(format!(
+                "<{}>; rel=\"{}\"",
+                self.page_url(path, query, offset),
+                rel
+            ));
+        };
+
+        // Offsets come from the client, so they may sit past the end or near
+        // usize::MAX; prev then leads back to the last page and next is left out.
+        let last = total.map(|total| total.saturating_sub(1) / self.limit * self.limit);
+        link(0, "first");
+        if self.offset > 0 {
+            let prev = self.offset.saturating_sub(self.limit);
+            link(last.map_or(prev, |last| prev.min(last)), "prev");
+        }
+        if let Some(next) = self
+            .offset
+            .checked_add(self.limit)
+            .filter(|&next| total.is_none_or(|total| next < total))
+        {
+            link(next, "next");
+        }
+        if let Some(last) = last {
+            link(last, "last");
+        }
+
+        links.join(", ")
+    }
+
+    fn page_url(&self, path: &str, query: &Query, offset: usize) -> String {
+        let mut params: Vec<(&str, &str)> = query
+            .iter_all()
+            .filter(|(k, _)| !matches!(*k, "page" | "offset" | "limit"))
+            .collect();
+        params.sort();
+
+        let mut url = format!("{}?limit={}&offset={}", path, self.limit, offset);
+        for (key, value) in params {
+            url.push('&');
+            url.push_str(&Query::encode_url(key));
+            url.push('=');
+            url.push_str(&Query::encode_url(value));
+        }
+        url
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PaginationConfig {
+        PaginationConfig::new()
+            .limits(10, 50)
+            .sortable(&["created", "name"])
+            .filterable(&["status"])
+    }
+
+    #[test]
+    fn test_defaults() {
+        let p = Pagination::from_query(&Query::new(), &config()).unwrap();
+        assert_eq!(p.limit, 10);
+        assert_eq!(p.offset, 0);
+        assert_eq!(p.page(), 1);
+    }
+
+    #[test]
+    fn test_page_and_limit() {
+        let q = Query::parse("page=3&limit=25").unwrap();
+        let p = Pagination::from_query(&q, &config()).unwrap();
+        assert_eq!(p.offset, 50);
+        assert_eq!(p.page(), 3);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let parse = |s: &str| Pagination::from_query(&Query::parse(s).unwrap(), &config());
+
+        assert_eq!(parse("limit=0"), Err(PaginationError::LimitOutOfRange(50)));
+        assert_eq!(parse("limit=51"), Err(PaginationError::LimitOutOfRange(50)));
+        assert_eq!(parse("page=0"), Err(PaginationError::PageOutOfRange));
+        assert_eq!(
+            parse("offset=-1"),
+            Err(PaginationError::InvalidNumber("offset".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let q = Query::parse("sort=-created,name&filter[status]=open").unwrap();
+        let p = Pagination::from_query(&q, &config()).unwrap();
+
+        assert_eq!(
+            p.sort,
+            vec![
+                SortField {
+                    field: "created".to_string(),
+                    descending: true
+                },
+                SortField {
+                    field: "name".to_string(),
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!(p.filters, vec![("status".to_string(), "open".to_string())]);
+
+        let q = Query::parse("sort=password").unwrap();
+        assert_eq!(
+            Pagination::from_query(&q, &config()),
+            Err(PaginationError::InvalidSortField("password".to_string()))
+        );
+
+        let q = Query::parse("filter[owner]=me").unwrap();
+        assert_eq!(
+            Pagination::from_query(&q, &config()),
+            Err(PaginationError::InvalidFilterField("owner".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_link_header() {
+        let q = Query::parse("page=2&sort=name").unwrap();
+        let p = Pagination::from_query(&q, &config()).unwrap();
+
+        assert_eq!(
+            p.link_header("/items", &q, Some(35)),
+            "</items?limit=10&offset=0&sort=name>; rel=\"first\", \
+             </items?limit=10&offset=0&sort=name>; rel=\"prev\", \
+             </items?limit=10&offset=20&sort=name>; rel=\"next\", \
+             </items?limit=10&offset=30&sort=name>; rel=\"last\""
+        );
+    }
+
+    #[test]
+    fn test_link_header_unknown_total() {
+        let p = Pagination::from_query(&Query::new(), &config()).unwrap();
+        assert_eq!(
+            p.link_header("/items", &Query::new(), None),
+            "</items?limit=10&offset=0>; rel=\"first\", </items?limit=10&offset=10>; rel=\"next\""
+        );
+    }
+
+    #[test]
+    fn test_link_header_for_offsets_past_the_end() {
+        let q = Query::parse(&format!("offset={}", usize::MAX)).unwrap();
+        let p = Pagination::from_query(&q, &config()).unwrap();
+        assert_eq!(
+            p.link_header("/items", &q, None),
+            format!(
+                "</items?limit=10&offset=0>; rel=\"first\", </items?limit=10&offset={}>; rel=\"prev\"",
+                usize::MAX - 10
+            )
+        );
+        assert_eq!(
+            p.link_header("/items", &q, Some(35)),
+            "</items?limit=10&offset=0>; rel=\"first\", \
+             </items?limit=10&offset=30>; rel=\"prev\", \
+             </items?limit=10&offset=30>; rel=\"last\""
+        );
+        assert_eq!(p.page(), usize::MAX / 10 + 1);
+    }
+}
@@ -0,0 +1,207 @@
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PathError {
+    #[error("Invalid percent-encoding in path")]
+    InvalidEncoding,
+
+    #[error("Encoded slash (%2F) is not allowed in path")]
+    EncodedSlash,
+
+    #[error("Control character in path")]
+    ControlCharacter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncodedSlashPolicy {
+    #[default]
+    Reject,
+    Decode,
+    // Keeps %2F, and with it %25, encoded; everything else is decoded.
+    Preserve,
+}
+
+pub fn sanitize_path(raw: &str, policy: EncodedSlashPolicy) -> Result<String, PathError> {
+    let mut segments: Vec<String> = Vec::new();
+    let trailing_slash = raw.len() > 1 && raw.ends_with('/');
+
+    for segment in raw.split('/') {
+        let decoded = decode_segment(segment, policy)?;
+
+        // With the Decode policy an encoded slash becomes a real separator, so the
+        // decoded segment must be re-split before dot segments are resolved.
+        for part in decoded.split('/') {
+            match part {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                _ => segments.push(part.to_string()),
+            }
+        }
+    }
+
+    let mut path = String::from("/");
+    path.push_str(&segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        path.push('/');
+    }
+    Ok(path)
+}
+
+fn decode_segment(segment: &str, policy: EncodedSlashPolicy) -> Result<String, PathError> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let byte = if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3).ok_or(PathError::InvalidEncoding)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| PathError::InvalidEncoding)?;
+            let byte = u8::from_str_radix(hex, 16).map_err(|_| PathError::InvalidEncoding)?;
+            i += 3;
+
+            if byte == b'/' {
+                match policy {
+                    EncodedSlashPolicy::Reject => return Err(PathError::EncodedSlash),
+                    EncodedSlashPolicy::Preserve => {
+                        decoded.extend_from_slice(b"%2F");
+                        continue;
+                    }
+                    EncodedSlashPolicy::Decode => {}
+                }
+            }
+            // With %2F left encoded, a decoded '%' must be too, or "/a%252Fb" would
+            // come out the same as "/a%2Fb".
+            if byte == b'%' && policy == EncodedSlashPolicy::Preserve {
+                decoded.extend_from_slice(b"%25");
+                continue;
+            }
+            byte
+        } else {
+            i += 1;
+            bytes[i - 1]
+        };
+
+        if byte.is_ascii_control() {
+            return Err(PathError::ControlCharacter);
+        }
+        decoded.push(byte);
+    }
+
+    String::from_utf8(decoded).map_err(|_| PathError::InvalidEncoding)
+}
+
+// Percent-encodes one path segment for use in a URL, the inverse of the decoding
+// sanitize_path does. Unreserved characters and sub-delims stay as they are.
+pub fn encode_segment(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'-'
+            | b'.'
+            | b'_'
+            | b'~'
+            | b'!'
+            | b'$'
+            | b'&'
+            | b'\''
+            | b'('
+            | b')'
+            | b'*'
+            | b'+'
+            | b','
+            | b';'
+            | b'='
+            | b':'
+            | b'@' => out.push(byte as char),
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(raw: &str) -> Result<String, PathError> {
+        sanitize_path(raw, EncodedSlashPolicy::Reject)
+    }
+
+    #[test]
+    fn test_plain_paths() {
+        assert_eq!(sanitize("/").unwrap(), "/");
+        assert_eq!(sanitize("/status").unwrap(), "/status");
+        assert_eq!(sanitize("/a/b/").unwrap(), "/a/b/");
+        assert_eq!(sanitize("//a///b").unwrap(), "/a/b");
+    }
+
+    #[test]
+    fn test_encoded_segments_round_trip() {
+        let name = "report #1 (final)/ü?.txt";
+        let encoded = encode_segment(name);
+        assert_eq!(encoded, "report%20%231%20(final)%2F%C3%BC%3F.txt");
+        assert_eq!(
+            sanitize_path(&format!("/{}", encoded), EncodedSlashPolicy::Decode).unwrap(),
+            "/report #1 (final)/ü?.txt"
+        );
+    }
+
+    #[test]
+    fn test_dot_segments_resolved() {
+        assert_eq!(sanitize("/a/./b/../c").unwrap(), "/a/c");
+        assert_eq!(sanitize("/../../etc/passwd").unwrap(), "/etc/passwd");
+        assert_eq!(sanitize("/a/..").unwrap(), "/");
+    }
+
+    #[test]
+    fn test_encoded_dot_segments_resolved() {
+        assert_eq!(sanitize("/static/%2e%2e/%2E%2E/secret").unwrap(), "/secret");
+    }
+
+    #[test]
+    fn test_percent_decoding() {
+        assert_eq!(sanitize("/caf%C3%A9/a+b").unwrap(), "/café/a+b");
+        assert_eq!(sanitize("/bad%zz"), Err(PathError::InvalidEncoding));
+        assert_eq!(sanitize("/bad%4"), Err(PathError::InvalidEncoding));
+        assert_eq!(sanitize("/%FF"), Err(PathError::InvalidEncoding));
+    }
+
+    #[test]
+    fn test_control_characters_rejected() {
+        assert_eq!(sanitize("/file%00.txt"), Err(PathError::ControlCharacter));
+        assert_eq!(sanitize("/a%0Db"), Err(PathError::ControlCharacter));
+        assert_eq!(sanitize("/a%7F"), Err(PathError::ControlCharacter));
+    }
+
+    #[test]
+    fn test_encoded_slash_policies() {
+        assert_eq!(sanitize("/a%2Fb"), Err(PathError::EncodedSlash));
+        assert_eq!(
+            sanitize_path("/a%2F..%2F..%2Fb", EncodedSlashPolicy::Decode).unwrap(),
+            "/b"
+        );
+        assert_eq!(
+            sanitize_path("/a%2fb/../c", EncodedSlashPolicy::Preserve).unwrap(),
+            "/c"
+        );
+        assert_eq!(
+            sanitize_path("/a%2fb", EncodedSlashPolicy::Preserve).unwrap(),
+            "/a%2Fb"
+        );
+    }
+
+    #[test]
+    fn test_preserve_keeps_percent_encoded() {
+        let preserve = |raw| sanitize_path(raw, EncodedSlashPolicy::Preserve).unwrap();
+        assert_eq!(preserve("/a%252Fb"), "/a%252Fb");
+        assert_ne!(preserve("/a%252Fb"), preserve("/a%2Fb"));
+        assert_eq!(preserve("/a%25b"), "/a%25b");
+        assert_eq!(preserve("/%e2%82%ac"), "/\u{20ac}");
+        assert_eq!(sanitize("/a%25b").unwrap(), "/a%b");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::json::{self, Value};
+
+use super::{Response, StatusCode, accept_encoding::parse_qvalue, body::Body};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProblemFormat {
+    Json,
+    Xml,
+}
+
+impl ProblemFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ProblemFormat::Json => "application/problem+json",
+            ProblemFormat::Xml => "application/problem+xml",
+        }
+    }
+
+    pub fn negotiate(accept: Option<&str>) -> Self {
+        let Some(accept) = accept else {
+            return ProblemFormat::Json;
+        };
+
+        let mut json_q = 0;
+        let mut xml_q = 0;
+        for item in accept.split(',') {
+            let mut params = item.split(';');
+            let media_type = params.next().unwrap_or("").trim().to_lowercase();
+            let q = params
+                .filter_map(|p| p.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, value)| parse_qvalue(value.trim()).ok())
+                .unwrap_or(1000);
+
+            match media_type.as_str() {
+                "application/problem+xml" | "application/xml" => xml_q = xml_q.max(q),
+                "application/problem+json" | "application/json" | "*/*" | "application/*" => {
+                    json_q = json_q.max(q)
+                }
+                _ => {}
+            }
+        }
+
+        if xml_q > json_q {
+            ProblemFormat::Xml
+        } else {
+            ProblemFormat::Json
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub status: StatusCode,
+    pub type_uri: String,
+    pub title: String,
+    pub detail: Option<String>,
+    pub instance: Option<String>,
+    pub extensions: Vec<(String, Value)>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            status,
+            type_uri: "about:blank".to_string(),
+            title: status.reason_parse().to_string(),
+            detail: None,
+            instance: None,
+            extensions: Vec::new(),
+        }
+    }
+
+    pub fn with_type(mut self, type_uri: impl Into<String>) -> Self {
+        self.type_uri = type_uri.into();
+        self
+    }
+
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_instance(mut self, instance: impl Into<String>) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    pub fn with_extension(mut self, name: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.extensions.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut members = vec![
+            format!("\"type\":{}", json::quote(&self.type_uri)),
+            format!("\"title\":{}", json::quote(&self.title)),
+            format!("\"status\":{}", self.status.as_u16()),
+        ];
+        if let Some(detail) = &self.detail {
+            members.push(format!("\"detail\":{}", json::quote(detail)));
+        }
+        if let Some(instance) = &self.instance {
+            members.push(format!("\"instance\":{}", json::quote(instance)));
+        }
+        for (name, value) in &self.extensions {
+            members.push(format!("{}:{}", json::quote(name), value));
+        }
+
+        format!("{{{}}}", members.join(","))
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <problem xmlns=\"urn:ietf:rfc:7807\">",
+        );
+        xml.push_str(&format!("<type>{}</type>", escape_xml(&self.type_uri)));
+        xml.push_str(&format!("<title>{}</title>", escape_xml(&self.title)));
+        xml.push_str(&format!("<status>{}</status>", self.status.as_u16()));
+        if let Some(detail) = &self.detail {
+            xml.push_str(&format!("<detail>{}</detail>", escape_xml(detail)));
+        }
+        if let Some(instance) = &self.instance {
+            xml.push_str(&format!("<instance>{}</instance>", escape_xml(instance)));
+        }
+        for (name, value) in &self.extensions {
+            if is_xml_name(name) {
+                let text = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                xml.push_str(&format!("<{0}>{1}</{0}>", name, escape_xml(&text)));
+            }
+        }
+        xml.push_str("</problem>");
+        xml
+    }
+
+    pub fn into_response(self, format: ProblemFormat) -> Response {
+        let body = match format {
+            ProblemFormat::Json => self.to_json(),
+            ProblemFormat::Xml => self.to_xml(),
+        };
+
+        Response::new(self.status)
+            .with_header("Content-Type", format.content_type())
+            .with_body(Body::from(body))
+    }
+}
+
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn is_xml_name(s: &str) -> bool {
+    s.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_json() {
+        let problem = Problem::new(StatusCode::Forbidden)
+            .with_type("https://example.com/probs/out-of-credit")
+            .with_title("You do not have enough credit.")
+            .with_detail("Your current balance is 30, but that costs 50.")
+            .with_extension("balance", 30u64);
+
+        assert_eq!(
+            problem.to_json(),
+            "{\"type\":\"https://example.com/probs/out-of-credit\",\
+             \"title\":\"You do not have enough credit.\",\
+             \"status\":403,\
+             \"detail\":\"Your current balance is 30, but that costs 50.\",\
+             \"balance\":30}"
+        );
+    }
+
+    #[test]
+    fn test_problem_xml_escapes() {
+        let problem = Problem::new(StatusCode::BadRequest).with_detail("<script>&");
+        let xml = problem.to_xml();
+
+        assert!(xml.contains("<title>Bad Request</title>"));
+        assert!(xml.contains("<detail>&lt;script&gt;&amp;</detail>"));
+    }
+
+    #[test]
+    fn test_negotiate_format() {
+        assert_eq!(ProblemFormat::negotiate(None), ProblemFormat::Json);
+        assert_eq!(
+            ProblemFormat::negotiate(Some("application/problem+xml")),
+            ProblemFormat::Xml
+        );
+        assert_eq!(
+            ProblemFormat::negotiate(Some("application/xml;q=0.5, application/json")),
+            ProblemFormat::Json
+        );
+        assert_eq!(
+            ProblemFormat::negotiate(Some("text/html")),
+            ProblemFormat::Json
+        );
+    }
+
+    #[test]
+    fn test_into_response() {
+        let response = Problem::new(StatusCode::NotFound).into_response(ProblemFormat::Json);
+
+        assert_eq!(response.status_code(), StatusCode::NotFound);
+        assert_eq!(
+            response.headers().get("Content-Type"),
+            Some("application/problem+json")
+        );
+        assert!(response.body().as_str().unwrap().contains("\"status\":404"));
+    }
+}
@@ -10,7 +10,13 @@ pub enum QueryError {
     InvalidEncoding,
 }
 
+// Under serde, a map from each key to all of its values.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
 pub struct Query {
     params: HashMap<String, Vec<String>>,
 }
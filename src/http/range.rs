@@ -0,0 +1,251 @@
+use thiserror::Error;
+
+use super::{body::Body, etag::ETag, response::Response, status_code::StatusCode};
+
+// Requests asking for more pieces than this are served in full instead.
+const MAX_RANGES: usize = 16;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RangeError {
+    #[error("Unsupported range unit: {0}")]
+    UnsupportedUnit(String),
+
+    #[error("Malformed range: {0}")]
+    Malformed(String),
+
+    #[error("Too many ranges requested")]
+    TooManyRanges,
+
+    #[error("No requested range is satisfiable")]
+    Unsatisfiable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    // bytes=first-last
+    FromTo(u64, u64),
+    // bytes=first-
+    From(u64),
+    // bytes=-suffix_length
+    Suffix(u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeHeader {
+    ranges: Vec<ByteRange>,
+}
+
+impl RangeHeader {
+    pub fn parse(value: &str) -> Result<Self, RangeError> {
+        let (unit, specs) = value
+            .split_once('=')
+            .ok_or_else(|| RangeError::Malformed(value.to_string()))?;
+        if !unit.trim().eq_ignore_ascii_case("bytes") {
+            return Err(RangeError::UnsupportedUnit(unit.trim().to_string()));
+        }
+
+        let mut ranges = Vec::new();
+        for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let malformed = || RangeError::Malformed(spec.to_string());
+            let (first, last) = spec.split_once('-').ok_or_else(malformed)?;
+            let number = |s: &str| {
+                if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+                    return Err(malformed());
+                }
+                s.parse::<u64>().map_err(|_| malformed())
+            };
+
+            let range = match (first.trim(), last.trim()) {
+                ("", suffix) => ByteRange::Suffix(number(suffix)?),
+                (first, "") => ByteRange::From(number(first)?),
+                (first, last) => {
+                    let (first, last) = (number(first)?, number(last)?);
+                    if last < first {
+                        return Err(malformed());
+                    }
+                    ByteRange::FromTo(first, last)
+                }
+            };
+            ranges.push(range);
+        }
+
+        if ranges.is_empty() {
+            return Err(RangeError::Malformed(value.to_string()));
+        }
+        if ranges.len() > MAX_RANGES {
+            return Err(RangeError::TooManyRanges);
+        }
+        Ok(RangeHeader { ranges })
+    }
+
+    pub fn ranges(&self) -> &[ByteRange] {
+        &self.ranges
+    }
+
+    // Resolves against a representation of `len` bytes into sorted, merged
+    // inclusive (first, last) pairs, dropping pieces that lie past the end.
+    pub fn resolve(&self, len: u64) -> Result<Vec<(u64, u64)>, RangeError> {
+        let mut resolved: Vec<(u64, u64)> = self
+            .ranges
+            .iter()
+            .filter_map(|range| match *range {
+                ByteRange::FromTo(first, _) | ByteRange::From(first) if first >= len => None,
+                ByteRange::FromTo(first, last) => Some((first, last.min(len - 1))),
+                ByteRange::From(first) => Some((first, len - 1)),
+                ByteRange::Suffix(0) => None,
+                ByteRange::Suffix(n) => Some((len.saturating_sub(n), len - 1)),
+            })
+            .collect();
+
+        if resolved.is_empty() {
+            return Err(RangeError::Unsatisfiable);
+        }
+
+        resolved.sort();
+        let mut merged: Vec<(u64, u64)> = Vec::with_capacity(resolved.len());
+        for (first, last) in resolved {
+            match merged.last_mut() {
+                Some(previous) if first <= previous.1 + 1 => previous.1 = previous.1.max(last),
+                _ => merged.push((first, last)),
+            }
+        }
+        Ok(merged)
+    }
+}
+
+// RFC 9110 13.1.5: the range applies only if the validator still matches.
+// Entity tags need a strong match; dates must equal Last-Modified exactly.
+fn if_range_matches(response: &Response, if_range: &str) -> bool {
+    let if_range = if_range.trim();
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        let current = response
+            .headers()
+            .get("ETag")
+            .and_then(|e| e.parse::<ETag>().ok());
+        match (if_range.parse::<ETag>(), current) {
+            (Ok(wanted), Some(current)) => wanted.strong_eq(&current),
+            _ => false,
+        }
+    } else {
+        response.headers().get("Last-Modified") == Some(if_range)
+    }
+}
+
+// Cuts a 206 (or 416) out of a complete 200 response. Returns None when the full
+// response should be sent instead: the header is unusable, If-Range no longer
+// matches, or there is no complete representation to slice.
+pub fn partial_response(full: &Response, range: &str, if_range: Option<&str>) -> Option<Response> {
+    if full.status_code() != StatusCode::OK || full.streams_body() {
+        return None;
+    }
+    if if_range.is_some_and(|v| !if_range_matches(full, v)) {
+        return None;
+    }
+
+    let header = RangeHeader::parse(range).ok()?;
+    let body = full.body().as_bytes();
+    let len = body.len() as u64;
+
+    let ranges = match header.resolve(len) {
+        Ok(ranges) => ranges,
+        Err(_) => {
+            return Some(
+                Response::new(StatusCode::RangeNotSatisfiable)
+                    .with_header("Content-Range", format!("bytes */{}", len)),
+            );
+        }
+    };
+
+    let mut response = Response::new(StatusCode::PartialContent);
+    for (name, value) in full.headers().iter() {
+        if !matches!(name, "content-length" | "content-range") {
+            response.headers.insert(name, value);
+        }
+    }
+
+    if let [(first, last)] = ranges[..] {
+        let slice = body[first as usize..=last as usize].to_vec();
+        response
+            .headers
+            .set("Content-Range", format!("bytes {}-{}/{}", first, last, len));
+        return Some(response.with_body(Body::Content(slice)));
+    }
+
+    let content_type = response.headers.remove("Content-Type");
+    let boundary = format!("byteranges_{:016x}", len ^ 0x5bd1_e995_7f4a_7c15);
+    let mut out = Vec::new();
+    for (first, last) in ranges {
+        out.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        if let Some(content_type) = &content_type {
+            out.extend_from_slice(format!("Content-Type: {}\r\n", content_type).as_bytes());
+        }
+        out.extend_from_slice(
+            format!("Content-Range: bytes {}-{}/{}\r\n\r\n", first, last, len).as_bytes(),
+        );
+        out.extend_from_slice(&body[first as usize..=last as usize]);
+        out.extend_from_slice(b"\r\n");
+    }
+    out.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+    response.headers.set(
+        "Content-Type",
+        format!("multipart/byteranges; boundary={}", boundary),
+    );
+    Some(response.with_body(Body::Content(out)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn full() -> Response {
+        Response::ok()
+            .with_header("Content-Type", "text/plain")
+            .with_header("ETag", "\"v1\"")
+            .with_body(Body::from("0123456789"))
+    }
+
+    #[test]
+    fn test_parse_and_resolve() {
+        let header = RangeHeader::parse("bytes=0-2, 7-, -2, 1-3").unwrap();
+        assert_eq!(header.resolve(10).unwrap(), vec![(0, 3), (7, 9)]);
+        assert_eq!(
+            RangeHeader::parse("bytes=20-").unwrap().resolve(10),
+            Err(RangeError::Unsatisfiable)
+        );
+        assert!(matches!(
+            RangeHeader::parse("items=0-1"),
+            Err(RangeError::UnsupportedUnit(_))
+        ));
+        assert!(RangeHeader::parse("bytes=5-1").is_err());
+        assert!(RangeHeader::parse("bytes=+1-2").is_err());
+    }
+
+    #[test]
+    fn test_single_and_multiple_ranges() {
+        let partial = partial_response(&full(), "bytes=2-4", None).unwrap();
+        assert_eq!(partial.status_code(), StatusCode::PartialContent);
+        assert_eq!(partial.body().as_bytes(), b"234");
+        assert_eq!(partial.headers().get("content-range"), Some("bytes 2-4/10"));
+        assert_eq!(partial.headers().get("content-length"), Some("3"));
+
+        let partial = partial_response(&full(), "bytes=0-0,-1", None).unwrap();
+        let content_type = partial.headers().get("content-type").unwrap();
+        assert!(content_type.starts_with("multipart/byteranges; boundary="));
+        let body = partial.body().as_str().unwrap();
+        assert!(body.contains("Content-Range: bytes 0-0/10\r\n\r\n0\r\n"));
+        assert!(body.contains("Content-Range: bytes 9-9/10\r\n\r\n9\r\n"));
+    }
+
+    #[test]
+    fn test_unsatisfiable_and_if_range() {
+        let response = partial_response(&full(), "bytes=50-", None).unwrap();
+        assert_eq!(response.status_code(), StatusCode::RangeNotSatisfiable);
+        assert_eq!(response.headers().get("content-range"), Some("bytes */10"));
+
+        assert!(partial_response(&full(), "bytes=0-1", Some("\"v1\"")).is_some());
+        assert!(partial_response(&full(), "bytes=0-1", Some("\"v0\"")).is_none());
+        assert!(partial_response(&full(), "bytes=0-1", Some("W/\"v1\"")).is_none());
+        assert!(partial_response(&full(), "lines=0-1", None).is_none());
+    }
+}
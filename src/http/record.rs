@@ -0,0 +1,326 @@
+use thiserror::Error;
+
+use crate::{
+    base64,
+    json::{self, JsonError, Value},
+};
+
+use super::status_code::StatusCode;
+use super::{Headers, body::Body, method::Method, request::Request, response::Response};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum RecordError {
+    #[error("Invalid JSON: {0}")]
+    Json(#[from] JsonError),
+
+    #[error("Missing or invalid field '{0}'")]
+    InvalidField(&'static str),
+}
+
+// Wire-agnostic snapshots of a request/response exchange, used for HAR-style
+// export, audit logs and canned mock responses loaded from config files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestRecord {
+    pub method: Method,
+    pub target: String,
+    pub version: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseRecord {
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RequestRecord {
+    pub fn to_json(&self) -> Value {
+        let mut members = vec![
+            ("method".to_string(), Value::from(self.method.as_str())),
+            ("target".to_string(), Value::from(self.target.as_str())),
+            ("version".to_string(), Value::from(self.version.as_str())),
+            ("headers".to_string(), headers_to_json(&self.headers)),
+        ];
+        push_body(&mut members, &self.body);
+        Value::Object(members)
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, RecordError> {
+        let method = value
+            .get("method")
+            .and_then(Value::as_str)
+            .and_then(|m| m.parse::<Method>().ok())
+            .ok_or(RecordError::InvalidField("method"))?;
+        let target = string_field(value, "target")?;
+        let version = match value.get("version") {
+            None => "HTTP/1.1".to_string(),
+            Some(_) => string_field(value, "version")?,
+        };
+
+        Ok(RequestRecord {
+            method,
+            target,
+            version,
+            headers: headers_from_json(value)?,
+            body: body_from_json(value)?,
+        })
+    }
+
+    pub fn parse(input: &str) -> Result<Self, RecordError> {
+        Self::from_json(&json::parse(input)?)
+    }
+}
+
+impl From<&Request> for RequestRecord {
+    fn from(request: &Request) -> Self {
+        RequestRecord {
+            method: request.method().clone(),
+            target: request.target().to_string(),
+            version: request.http_version().to_string(),
+            headers: sorted_headers(&request.headers),
+            body: request.body_as_bytes().to_vec(),
+        }
+    }
+}
+
+impl ResponseRecord {
+    pub fn to_json(&self) -> Value {
+        let mut members = vec![
+            (
+                "status".to_string(),
+                Value::from(self.status.as_u16() as u64),
+            ),
+            ("headers".to_string(), headers_to_json(&self.headers)),
+        ];
+        push_body(&mut members, &self.body);
+        Value::Object(members)
+    }
+
+    pub fn from_json(value: &Value) -> Result<Self, RecordError> {
+        let status = value
+            .get("status")
+            .and_then(Value::as_f64)
+            .filter(|n| n.fract() == 0.0 && (100.0..=599.0).contains(n))
+            .and_then(|n| StatusCode::from_u16(n as u16))
+            .ok_or(RecordError::InvalidField("status"))?;
+
+        Ok(ResponseRecord {
+            status,
+            headers: headers_from_json(value)?,
+            body: body_from_json(value)?,
+        })
+    }
+
+    pub fn parse(input: &str) -> Result<Self, RecordError> {
+        Self::from_json(&json::parse(input)?)
+    }
+
+    pub fn into_response(self) -> Response {
+        let mut response = Response::new(self.status);
+        for (name, value) in self.headers {
+            response = response.with_header(name, value);
+        }
+        if self.body.is_empty() {
+            response
+        } else {
+            response.with_body(Body::Content(self.body))
+        }
+    }
+}
+
+impl From<&Response> for ResponseRecord {
+    fn from(response: &Response) -> Self {
+        ResponseRecord {
+            status: response.status_code(),
+            headers: sorted_headers(response.headers()),
+            body: response.body().as_bytes().to_vec(),
+        }
+    }
+}
+
+fn sorted_headers(headers: &Headers) -> Vec<(String, String)> {
+    let mut pairs: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    pairs.sort();
+    pairs
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> Value {
+    Value::Array(
+        headers
+            .iter()
+            .map(|(name, value)| {
+                Value::Object(vec![
+                    ("name".to_string(), Value::from(name.as_str())),
+                    ("value".to_string(), Value::from(value.as_str())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn headers_from_json(value: &Value) -> Result<Vec<(String, String)>, RecordError> {
+    let Some(headers) = value.get("headers") else {
+        return Ok(Vec::new());
+    };
+
+    headers
+        .as_array()
+        .ok_or(RecordError::InvalidField("headers"))?
+        .iter()
+        .map(|header| {
+            let name = header.get("name").and_then(Value::as_str);
+            let value = header.get("value").and_then(Value::as_str);
+            match (name, value) {
+                (Some(name), Some(value)) if Headers::validate(name, value).is_ok() => {
+                    Ok((name.to_string(), value.to_string()))
+                }
+                _ => Err(RecordError::InvalidField("headers")),
+            }
+        })
+        .collect()
+}
+
+// Text bodies stay readable in the record; anything else is stored as base64.
+fn push_body(members: &mut Vec<(String, Value)>, body: &[u8]) {
+    match std::str::from_utf8(body) {
+        Ok(text) => members.push(("body".to_string(), Value::from(text))),
+        Err(_) => members.push(("body_base64".to_string(), Value::from(base64::encode(body)))),
+    }
+}
+
+fn body_from_json(value: &Value) -> Result<Vec<u8>, RecordError> {
+    if let Some(encoded) = value.get("body_base64") {
+        return encoded
+            .as_str()
+            .and_then(base64::decode)
+            .ok_or(RecordError::InvalidField("body_base64"));
+    }
+
+    match value.get("body") {
+        None | Some(Value::Null) => Ok(Vec::new()),
+        Some(body) => body
+            .as_str()
+            .map(|s| s.as_bytes().to_vec())
+            .ok_or(RecordError::InvalidField("body")),
+    }
+}
+
+fn string_field(value: &Value, field: &'static str) -> Result<String, RecordError> {
+    value
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or(RecordError::InvalidField(field))
+}
+
+// Under serde the records keep the shape of to_json, and deserializing applies
+// the same checks as from_json.
+#[cfg(feature = "serde")]
+impl serde::Serialize for RequestRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.to_json(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for RequestRecord {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::from_json(&<Value as serde::Deserialize>::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for ResponseRecord {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serde::Serialize::serialize(&self.to_json(), serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ResponseRecord {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::from_json(&<Value as serde::Deserialize>::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_record_round_trip() {
+        let head = "POST /upload HTTP/1.1\r\nHost: example.com\r\nContent-Length: 3";
+        let request = Request::from_parts(head, vec![0xff, 0xfe, 0x00]).unwrap();
+        let record = RequestRecord::from(&request);
+
+        let json = record.to_json().to_string();
+        assert!(json.contains("\"body_base64\":\"//4A\""));
+        assert_eq!(RequestRecord::parse(&json).unwrap(), record);
+    }
+
+    #[test]
+    fn test_mock_response_from_config() {
+        let record = ResponseRecord::parse(
+            r#"{"status": 201, "headers": [{"name": "Content-Type", "value": "text/plain"}], "body": "made"}"#,
+        )
+        .unwrap();
+        let response = record.clone().into_response();
+
+        assert_eq!(response.status_code(), StatusCode::Created);
+        assert_eq!(response.headers().get("content-type"), Some("text/plain"));
+        assert_eq!(response.body().as_bytes(), b"made");
+        assert_eq!(ResponseRecord::from(&response).status, record.status);
+    }
+
+    #[test]
+    fn test_invalid_records() {
+        assert_eq!(
+            ResponseRecord::parse(r#"{"status": 299}"#),
+            Err(RecordError::InvalidField("status"))
+        );
+        assert_eq!(
+            RequestRecord::parse(
+                r#"{"method": "GET", "target": "/", "headers": [{"name": "a b", "value": "x"}]}"#
+            ),
+            Err(RecordError::InvalidField("headers"))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_uses_the_record_format() {
+        let head = "PUT /a?x=1&x=2 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 2";
+        let request = Request::from_parts(head, b"hi".to_vec()).unwrap();
+        let record = RequestRecord::from(&request);
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(RequestRecord::parse(&json).unwrap(), record);
+        assert_eq!(
+            serde_json::from_str::<RequestRecord>(&json).unwrap(),
+            record
+        );
+        assert!(serde_json::from_str::<ResponseRecord>(r#"{"status": 299}"#).is_err());
+
+        assert_eq!(serde_json::to_string(&Method::PUT).unwrap(), "\"PUT\"");
+        assert_eq!(
+            serde_json::from_str::<StatusCode>("404").unwrap(),
+            StatusCode::NotFound
+        );
+        let headers = serde_json::to_value(&request.headers).unwrap();
+        assert_eq!(headers["host"], "example.com");
+        assert!(serde_json::from_str::<Headers>(r#"{"a b": "x"}"#).is_err());
+        let query: crate::http::Query =
+            serde_json::from_value(serde_json::to_value(request.query()).unwrap()).unwrap();
+        assert_eq!(
+            query.get_all("x"),
+            Some(&["1".to_string(), "2".to_string()][..])
+        );
+    }
+}
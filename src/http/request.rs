@@ -1,14 +1,34 @@
-use std::io::{BufRead, BufReader, Read};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
 use std::str;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use thiserror::Error;
 
+use crate::io::{LimitError, read_line_before};
+
 use super::{
     Query, QueryError,
+    accept_encoding::AcceptEncoding,
     body::{Body, BodyError},
+    connection::ConnectionHeader,
+    content_digest::{DigestError, DigestHeader},
+    context::{ConnectionContext, ConnectionInfo},
+    dav::{Depth, Destination, Overwrite},
+    duplex::BodyFraming,
+    etag::ETagList,
+    extensions::Extensions,
+    form::{FormError, FormLimits, Multipart, parse_urlencoded},
     header::{HeaderError, Headers},
     method::Method,
-    request_line::{RequestLine, RequestLineError},
+    negotiate::Accept,
+    pagination::{Pagination, PaginationConfig, PaginationError},
+    path::{EncodedSlashPolicy, PathError, sanitize_path},
+    problem::ProblemFormat,
+    request_line::{RequestLine, RequestLineError, TargetPolicy},
+    server_timing::ServerTiming,
+    status_code::StatusCode,
 };
 
 #[derive(Debug, Error)]
@@ -34,20 +54,132 @@ pub enum ParseError {
     #[error("Body error: {0}")]
     Body(#[from] BodyError),
 
+    #[error("Invalid path: {0}")]
+    Path(#[from] PathError),
+
     #[error("Header too large")]
     HeaderTooLarge,
 
+    #[error("Request line exceeds {limit} bytes")]
+    RequestLineTooLong { limit: usize },
+
     #[error("Invalid chunk size")]
     InvalidChunkFormat,
+
+    #[error("Timed out reading request headers")]
+    HeaderTimeout,
+
+    #[error("Timed out reading request body")]
+    BodyTimeout,
+
+    #[error("Request body exceeds {limit} bytes")]
+    BodyTooLarge { limit: usize },
 }
 
-const MAX_HEADER_SIZE: usize = 8 * 1024; // 8KB
+impl ParseError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ParseError::HeaderTimeout | ParseError::BodyTimeout => StatusCode::RequestTimeout,
+            ParseError::BodyTooLarge { .. } => StatusCode::ContentTooLarge,
+            ParseError::RequestLineTooLong { .. } => StatusCode::UriTooLong,
+            ParseError::RequestLine(e) => e.status(),
+            ParseError::HeaderTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+pub(crate) const MAX_HEADER_SIZE: usize = 8 * 1024; // 8KB
+pub(crate) const MAX_REQUEST_LINE: usize = 4 * 1024;
+// Longer than any registered method and any version we could speak.
+pub(crate) const MAX_METHOD: usize = 32;
+pub(crate) const MAX_VERSION: usize = 16;
+pub(crate) const HEADER_DEADLINE: Duration = Duration::from_secs(20);
+pub(crate) const MAX_BODY_SIZE: usize = 10 * 1024 * 1024; // 10MB
+
+// Resource limits applied while reading a request off the wire. Heads past their
+// limits are answered with 414 or 431, bodies with 413.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParserLimits {
+    // Request line plus header fields.
+    pub max_header_bytes: usize,
+    // Longest request line, counted within max_header_bytes.
+    pub max_request_line: usize,
+    // Parts of the request line, each answered with its own status: 501 for the
+    // method, 414 for the target and 400 for the version.
+    pub max_method: usize,
+    pub max_target: usize,
+    pub max_version: usize,
+    // Total time allowed for the whole head, so a client trickling a byte at a
+    // time cannot hold the connection. None leaves it to the socket timeouts.
+    pub header_deadline: Option<Duration>,
+    // Decoded payload for either framing. None leaves the body unbounded.
+    pub max_body_bytes: Option<usize>,
+}
+
+impl Default for ParserLimits {
+    fn default() -> Self {
+        ParserLimits {
+            max_header_bytes: MAX_HEADER_SIZE,
+            max_request_line: MAX_REQUEST_LINE,
+            max_method: MAX_METHOD,
+            max_target: MAX_REQUEST_LINE,
+            max_version: MAX_VERSION,
+            header_deadline: Some(HEADER_DEADLINE),
+            max_body_bytes: Some(MAX_BODY_SIZE),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub target_policy: TargetPolicy,
+    pub encoded_slash: EncodedSlashPolicy,
+    pub limits: ParserLimits,
+}
 
 pub struct Request {
     pub requestline: RequestLine,
     pub headers: Headers,
     pub body: Body,
     pub query: Query,
+    pub path: String,
+    pub extensions: Extensions,
+}
+
+#[derive(Debug)]
+pub struct RequestHead {
+    pub requestline: RequestLine,
+    pub headers: Headers,
+    pub query: Query,
+    pub path: String,
+    pub extensions: Extensions,
+}
+
+// Extensions hold arbitrary non-Clone values, so clones start with an empty map.
+impl Clone for RequestHead {
+    fn clone(&self) -> Self {
+        RequestHead {
+            requestline: self.requestline.clone(),
+            headers: self.headers.clone(),
+            query: self.query.clone(),
+            path: self.path.clone(),
+            extensions: Extensions::new(),
+        }
+    }
+}
+
+impl Clone for Request {
+    fn clone(&self) -> Self {
+        Request::from_head(self.head(), self.body.clone())
+    }
 }
 
 impl Request {
@@ -68,7 +200,22 @@ impl Request {
     }
 
     pub fn path(&self) -> &str {
-        self.target().split('?').next().unwrap_or("")
+        &self.path
+    }
+
+    pub fn raw_path(&self) -> &str {
+        let path = self.target().split('?').next().unwrap_or("");
+
+        match path.find("://") {
+            Some(pos) => {
+                let after_scheme = &path[pos + 3..];
+                after_scheme
+                    .find('/')
+                    .map(|slash| &after_scheme[slash..])
+                    .unwrap_or("/")
+            }
+            None => path,
+        }
     }
 
     pub fn header(&self, name: &str) -> Option<&str> {
@@ -87,6 +234,158 @@ impl Request {
         self.body.as_str()
     }
 
+    // Trailer fields sent after a chunked body and declared in its Trailer header,
+    // such as a Content-Digest computed while streaming.
+    pub fn trailers(&self) -> Option<&Headers> {
+        self.extensions
+            .get::<Trailers>()
+            .map(|trailers| &trailers.0)
+    }
+
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    pub fn connection(&self) -> &ConnectionInfo {
+        self.extensions
+            .get::<Arc<ConnectionInfo>>()
+            .map_or(&ConnectionInfo::UNKNOWN, |info| info)
+    }
+
+    // The address of the connected client, when read off a socket. Behind a proxy
+    // this is the proxy's address, unless the server reads the PROXY protocol.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.connection().peer
+    }
+
+    pub fn server_timing(&self) -> Option<&ServerTiming> {
+        self.extensions.get::<ServerTiming>()
+    }
+
+    // The server span's context, attached by the server before the handler runs.
+    #[cfg(feature = "otel")]
+    pub fn trace_context(&self) -> Option<&crate::otel::TraceContext> {
+        self.extensions.get::<crate::otel::TraceContext>()
+    }
+
+    pub fn if_none_match(&self) -> Option<ETagList> {
+        self.header("If-None-Match")?.parse().ok()
+    }
+
+    pub fn if_match(&self) -> Option<ETagList> {
+        self.header("If-Match")?.parse().ok()
+    }
+
+    pub fn accept(&self) -> Option<Accept> {
+        self.header("Accept")?.parse().ok()
+    }
+
+    pub fn accept_encoding(&self) -> Option<AcceptEncoding> {
+        self.header("Accept-Encoding")?.parse().ok()
+    }
+
+    // WebDAV headers; each is None when absent or malformed, so check header()
+    // to tell a bad value from a missing one.
+    pub fn depth(&self) -> Option<Depth> {
+        self.header("Depth")?.parse().ok()
+    }
+
+    pub fn destination(&self) -> Option<Destination> {
+        self.header("Destination")?.parse().ok()
+    }
+
+    pub fn overwrite(&self) -> Option<Overwrite> {
+        self.header("Overwrite")?.parse().ok()
+    }
+
+    // Checks the body against its Content-Digest header.
+    // Streaming clients often only know the digest at the end, so a declared
+    // Content-Digest trailer counts too.
+    pub fn verify_content_digest(&self) -> Result<(), DigestError> {
+        let header: DigestHeader = self
+            .header("Content-Digest")
+            .or_else(|| self.trailers()?.get("Content-Digest"))
+            .ok_or(DigestError::Missing)?
+            .parse()?;
+        header.verify(self.body.as_bytes())
+    }
+
+    // A value from the Cookie header; the first of repeated names wins.
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.header("Cookie")?
+            .split([';', ','])
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    }
+
+    pub fn connection_header(&self) -> ConnectionHeader {
+        ConnectionHeader::from_headers(&self.headers)
+    }
+
+    // The protocols the client offers to switch to, in its order of preference.
+    // Upgrade only counts when Connection lists it as well (RFC 9110 7.8).
+    pub fn upgrade_protocols(&self) -> Vec<&str> {
+        if !self.connection_header().upgrade() {
+            return Vec::new();
+        }
+        self.header("Upgrade")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|p| !p.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Whether `protocol` is on offer, by full token ("HTTP/2.0") or by name alone
+    // ("websocket" matches "websocket/13").
+    pub fn wants_upgrade(&self, protocol: &str) -> bool {
+        self.upgrade_protocols().iter().any(|offered| {
+            offered.eq_ignore_ascii_case(protocol)
+                || offered
+                    .split('/')
+                    .next()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(protocol))
+        })
+    }
+
+    // Decodes a multipart/form-data body. Limits are passed per call so each route
+    // can allow as much as its uploads need.
+    pub fn multipart(&self, limits: &FormLimits) -> Result<Multipart, FormError> {
+        let content_type = self.header("Content-Type").unwrap_or("");
+        Multipart::parse(content_type, self.body.as_bytes(), limits)
+    }
+
+    // Decodes an application/x-www-form-urlencoded body into the same Query that
+    // query() gives for the target, under the default FormLimits.
+    pub fn form(&self) -> Result<Query, FormError> {
+        self.form_with(&FormLimits::default())
+    }
+
+    pub fn form_with(&self, limits: &FormLimits) -> Result<Query, FormError> {
+        let content_type = self.header("Content-Type").unwrap_or("");
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        if !media_type.eq_ignore_ascii_case("application/x-www-form-urlencoded") {
+            return Err(FormError::UnsupportedContentType(media_type.to_string()));
+        }
+        parse_urlencoded(self.body.as_bytes(), limits)
+    }
+
+    pub fn pagination(&self, config: &PaginationConfig) -> Result<Pagination, PaginationError> {
+        Pagination::from_query(&self.query, config)
+    }
+
+    pub fn problem_format(&self) -> ProblemFormat {
+        ProblemFormat::negotiate(self.header("Accept"))
+    }
+
     pub fn validated_host(&self, allowed_hosts: &[&str]) -> Option<&str> {
         let host = self.header("Host")?;
         if allowed_hosts.contains(&host) {
@@ -96,13 +395,68 @@ impl Request {
         }
     }
 
+    pub fn head(&self) -> RequestHead {
+        RequestHead {
+            requestline: self.requestline.clone(),
+            headers: self.headers.clone(),
+            query: self.query.clone(),
+            path: self.path.clone(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    pub fn into_parts(self) -> (RequestHead, Body) {
+        let head = RequestHead {
+            requestline: self.requestline,
+            headers: self.headers,
+            query: self.query,
+            path: self.path,
+            extensions: self.extensions,
+        };
+        (head, self.body)
+    }
+
+    pub fn from_head(head: RequestHead, body: Body) -> Self {
+        Request {
+            requestline: head.requestline,
+            headers: head.headers,
+            body,
+            query: head.query,
+            path: head.path,
+            extensions: head.extensions,
+        }
+    }
+
+    // Cheap copy for sub-dispatch and retries: the head is cloned but both requests
+    // point at the same body bytes.
+    pub fn fork(&mut self) -> Request {
+        let body = self.body.share();
+        Request::from_head(self.head(), body)
+    }
+
     pub fn from_parts(header_section: &str, body: Vec<u8>) -> Result<Self, ParseError> {
+        Self::from_parts_with(header_section, body, &ParseOptions::default())
+    }
+
+    pub fn from_parts_with(
+        header_section: &str,
+        body: Vec<u8>,
+        options: &ParseOptions,
+    ) -> Result<Self, ParseError> {
+        let mut request = Self::from_head_section(header_section, options)?;
+        request.body = request.body_from(body)?;
+        Ok(request)
+    }
+
+    // Parses everything but the body, which is left empty.
+    fn from_head_section(header_section: &str, options: &ParseOptions) -> Result<Self, ParseError> {
         let lines: Vec<&str> = header_section.lines().collect();
         if lines.is_empty() {
             return Err(ParseError::IncompleteRequest);
         }
 
-        let requestline = RequestLine::parse(lines[0])?;
+        let requestline =
+            RequestLine::parse_limited(lines[0], options.target_policy, &options.limits)?;
 
         let query = Query::from_url(&requestline.target)?;
 
@@ -113,7 +467,27 @@ impl Request {
             headers.parse_headers(&header_text)?;
         }
 
-        let body = if let Some(content_length_str) = headers.get("Content-Length") {
+        let mut request = Request {
+            requestline,
+            headers,
+            body: Body::Empty,
+            query,
+            path: String::new(),
+            extensions: Extensions::new(),
+        };
+
+        let raw_path = request.raw_path();
+        request.path = if raw_path.starts_with('/') {
+            sanitize_path(raw_path, options.encoded_slash)?
+        } else {
+            raw_path.to_string()
+        };
+
+        Ok(request)
+    }
+
+    fn body_from(&self, body: Vec<u8>) -> Result<Body, ParseError> {
+        let body = if let Some(content_length_str) = self.headers.get("Content-Length") {
             let content_length = content_length_str
                 .parse::<usize>()
                 .map_err(|_| BodyError::InvalidContentLength(content_length_str.to_string()))?;
@@ -124,13 +498,7 @@ impl Request {
         } else {
             Body::Content(body)
         };
-
-        Ok(Request {
-            requestline,
-            headers,
-            body,
-            query,
-        })
+        Ok(body)
     }
 }
 
@@ -151,73 +519,248 @@ impl TryFrom<&[u8]> for Request {
     }
 }
 
-fn read_chunked_body<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ParseError> {
+// Trailer fields that would change how the message is framed, routed,
+// authenticated or interpreted once the handler has the head (RFC 9110 section
+// 6.5.1). They are dropped even when declared.
+const FORBIDDEN_TRAILERS: &[&str] = &[
+    "authorization",
+    "cache-control",
+    "connection",
+    "content-encoding",
+    "content-length",
+    "content-range",
+    "content-type",
+    "expect",
+    "host",
+    "keep-alive",
+    "max-forwards",
+    "proxy-authorization",
+    "range",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// Trailer fields of a chunked request, kept apart from the head so a client cannot
+// override a header after the fact.
+struct Trailers(Headers);
+
+fn read_chunked_body<R: BufRead>(
+    reader: &mut R,
+    limits: &ParserLimits,
+) -> Result<(Vec<u8>, Headers), ParseError> {
     let mut body = Vec::new();
 
     loop {
-        let mut size_line = String::new();
-        reader.read_line(&mut size_line)?;
-
-        let size_str = size_line.trim();
-        if size_str.is_empty() {
-            continue;
+        let size_line = read_chunk_line(reader)?;
+        let size_part = size_line.split(';').next().unwrap_or("").trim();
+        if size_part.is_empty() {
+            return Err(ParseError::InvalidChunkFormat);
         }
 
-        let size_part = size_str.split(';').next().unwrap_or("");
-
+        // from_str_radix would also take a sign, which no chunk size may carry.
+        if !size_part.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidChunkFormat);
+        }
         let chunk_size =
             usize::from_str_radix(size_part, 16).map_err(|_| ParseError::InvalidChunkFormat)?;
 
         if chunk_size == 0 {
-            loop {
-                let mut line = String::new();
-                reader.read_line(&mut line)?;
-                if line == "\r\n" || line == "\n" || line.is_empty() {
-                    break;
-                }
-            }
-            break;
+            let trailers = read_trailers(reader, limits.max_header_bytes)?;
+            return Ok((body, trailers));
+        }
+
+        if let Some(limit) = limits.max_body_bytes
+            && body.len().saturating_add(chunk_size) > limit
+        {
+            return Err(ParseError::BodyTooLarge { limit });
         }
 
         let mut chunk = vec![0; chunk_size];
         reader.read_exact(&mut chunk)?;
         body.extend_from_slice(&chunk);
 
-        let mut crlf = String::new();
-        reader.read_line(&mut crlf)?;
-        if crlf != "\r\n" && crlf != "\n" {
+        if !read_chunk_line(reader)?.is_empty() {
             return Err(ParseError::InvalidChunkFormat);
         }
     }
+}
+
+// A chunk-size line, extensions included, or the CRLF after a chunk's data. Bounded
+// so a client cannot stream an endless size line into memory.
+const MAX_CHUNK_LINE_BYTES: usize = 1024;
+
+// Reads one such line without its line ending. Running out of input before the
+// line ends is an error rather than an empty line.
+fn read_chunk_line<R: BufRead>(reader: &mut R) -> Result<String, ParseError> {
+    let mut line = Vec::new();
+    match read_line_before(reader, &mut line, MAX_CHUNK_LINE_BYTES, None) {
+        Ok(_) => {}
+        Err(LimitError::Io(e)) => return Err(ParseError::IoError(e)),
+        Err(_) => return Err(ParseError::InvalidChunkFormat),
+    }
+    let Some(line) = line.strip_suffix(b"\n") else {
+        return Err(ParseError::IoError(
+            std::io::ErrorKind::UnexpectedEof.into(),
+        ));
+    };
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+    Ok(str::from_utf8(line)?.to_string())
+}
+
+// The trailer section counts against the same budget as the head.
+fn read_trailers<R: BufRead>(reader: &mut R, max_bytes: usize) -> Result<Headers, ParseError> {
+    let mut section = Vec::new();
+    let mut trailers = Headers::new();
+    loop {
+        let line_start = section.len();
+        let remaining = max_bytes.saturating_sub(line_start);
+        match read_line_before(reader, &mut section, remaining, None) {
+            Ok(0) => return Ok(trailers),
+            Ok(_) => {}
+            Err(LimitError::Io(e)) => return Err(ParseError::IoError(e)),
+            Err(_) => return Err(ParseError::HeaderTooLarge),
+        }
+        let line = str::from_utf8(&section[line_start..])?;
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if line.is_empty() {
+            return Ok(trailers);
+        }
+        trailers.parse_headers(line)?;
+    }
+}
 
-    Ok(body)
+// Keeps the trailers the head announced in its Trailer field, minus the forbidden
+// ones; anything undeclared is discarded.
+fn declared_trailers(head: &Headers, trailers: Headers) -> Headers {
+    let declared: Vec<String> = head
+        .get("Trailer")
+        .unwrap_or("")
+        .split(',')
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty() && !FORBIDDEN_TRAILERS.contains(&name.as_str()))
+        .collect();
+    let mut kept = Headers::new();
+    for (name, value) in trailers.iter() {
+        if declared.contains(&name.to_ascii_lowercase()) {
+            kept.insert(name, value);
+        }
+    }
+    kept
 }
 
 pub fn request_from_reader<R: std::io::Read>(reader: &mut R) -> Result<Request, ParseError> {
-    let mut reader = BufReader::new(reader);
-    let mut headers_buf = Vec::new();
+    request_from_reader_with(reader, &ParseOptions::default())
+}
 
-    loop {
-        let mut line = String::new();
-        let bytes_read = reader.read_line(&mut line)?;
+pub fn request_from_reader_with<R: std::io::Read>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<Request, ParseError> {
+    request_from_buf_reader(&mut BufReader::new(reader), options)
+}
+
+pub fn request_from_buf_reader<R: BufRead>(
+    reader: &mut R,
+    options: &ParseOptions,
+) -> Result<Request, ParseError> {
+    request_from_buf_reader_in(reader, options, &mut ConnectionContext::new())
+}
+
+pub fn request_from_buf_reader_in<R: BufRead>(
+    reader: &mut R,
+    options: &ParseOptions,
+    context: &mut ConnectionContext,
+) -> Result<Request, ParseError> {
+    request_from_buf_reader_phased(reader, options, context, |_| Ok(()))
+}
+
+// The error for a request line cut off at max_request_line, given what was read
+// of it and what is still buffered: usually the target is what ran long, unless
+// the method never ended.
+pub(crate) fn overlong_request_line(
+    read: &[u8],
+    pending: &[u8],
+    limits: &ParserLimits,
+) -> ParseError {
+    let method = read
+        .iter()
+        .chain(pending)
+        .take(limits.max_method + 1)
+        .take_while(|&&b| b != b' ')
+        .count();
+    if method > limits.max_method {
+        return RequestLineError::MethodTooLong {
+            limit: limits.max_method,
+        }
+        .into();
+    }
+    ParseError::RequestLineTooLong {
+        limit: limits.max_request_line,
+    }
+}
 
-        if headers_buf.len() + bytes_read > MAX_HEADER_SIZE {
-            return Err(ParseError::HeaderTooLarge);
+// Like request_from_buf_reader_in, with a hook that runs once the head is read and
+// before any body bytes are, so a caller can switch the socket to its body timeout.
+pub fn request_from_buf_reader_phased<R: BufRead>(
+    reader: &mut R,
+    options: &ParseOptions,
+    context: &mut ConnectionContext,
+    before_body: impl FnOnce(&mut R) -> std::io::Result<()>,
+) -> Result<Request, ParseError> {
+    request_from_buf_reader_deferred(reader, options, context, |_| false, before_body)
+        .map(|(request, _)| request)
+}
+
+// Like request_from_buf_reader_phased, except that a body `defer_body` claims is
+// left unread, to be streamed by the handler; its framing is returned with the
+// request. The claim is made on the head alone.
+pub(crate) fn request_from_buf_reader_deferred<R: BufRead>(
+    reader: &mut R,
+    options: &ParseOptions,
+    context: &mut ConnectionContext,
+    defer_body: impl FnOnce(&Request) -> bool,
+    before_body: impl FnOnce(&mut R) -> std::io::Result<()>,
+) -> Result<(Request, Option<BodyFraming>), ParseError> {
+    context.begin_request();
+    let headers_buf = &mut context.head;
+    let deadline = options
+        .limits
+        .header_deadline
+        .map(|limit| Instant::now() + limit);
+
+    loop {
+        let line_start = headers_buf.len();
+        let mut remaining = options.limits.max_header_bytes.saturating_sub(line_start);
+        let request_line = line_start == 0;
+        if request_line {
+            remaining = remaining.min(options.limits.max_request_line);
         }
+        let bytes_read = match read_line_before(reader, headers_buf, remaining, deadline) {
+            Ok(n) => n,
+            Err(LimitError::Io(e)) if is_timeout(&e) => return Err(ParseError::HeaderTimeout),
+            Err(LimitError::Io(e)) => return Err(ParseError::IoError(e)),
+            Err(_) if request_line && remaining == options.limits.max_request_line => {
+                let pending = reader.fill_buf().unwrap_or_default();
+                return Err(overlong_request_line(headers_buf, pending, &options.limits));
+            }
+            Err(_) => return Err(ParseError::HeaderTooLarge),
+        };
 
         if bytes_read == 0 {
             break; // EOF
         }
 
-        if line == "\r\n" || line == "\n" {
+        let line = &headers_buf[line_start..];
+        if line == b"\r\n" || line == b"\n" {
+            headers_buf.truncate(line_start);
             break; // End of headers
         }
-
-        headers_buf.extend_from_slice(line.as_bytes());
     }
 
-    let headers_str =
-        String::from_utf8(headers_buf).map_err(|e| ParseError::InvalidEncoding(e.utf8_error()))?;
+    let headers_str = str::from_utf8(headers_buf)?;
 
     let te_headers: Vec<&str> = headers_str
         .lines()
@@ -233,28 +776,352 @@ pub fn request_from_reader<R: std::io::Read>(reader: &mut R) -> Result<Request,
         .map(|line| line.to_lowercase().contains("chunked"))
         .unwrap_or(false);
 
+    let content_length = headers_str
+        .lines()
+        .find(|line| line.to_lowercase().starts_with("content-length:"))
+        .and_then(|line| line.split(':').nth(1))
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut request = Request::from_head_section(headers_str, options)?;
+
+    if defer_body(&request) {
+        if let Some(limit) = options.limits.max_body_bytes
+            && !chunk_encoding
+            && content_length > limit
+        {
+            return Err(ParseError::BodyTooLarge { limit });
+        }
+        before_body(reader)?;
+        request.extensions_mut().insert(context.shared_info());
+        let framing = match chunk_encoding {
+            true => BodyFraming::Chunked,
+            false => BodyFraming::Length(content_length as u64),
+        };
+        return Ok((request, Some(framing)));
+    }
+
+    before_body(reader)?;
+    let mut trailers = Headers::new();
     let body_buf = if chunk_encoding {
-        read_chunked_body(&mut reader)?
+        read_chunked_body(reader, &options.limits).map(|(body, section)| {
+            trailers = section;
+            body
+        })
     } else {
-        let content_length = headers_str
-            .lines()
-            .find(|line| line.to_lowercase().starts_with("content-length:"))
-            .and_then(|line| line.split(':').nth(1))
-            .and_then(|value| value.trim().parse::<usize>().ok())
-            .unwrap_or(0);
+        if let Some(limit) = options.limits.max_body_bytes
+            && content_length > limit
+        {
+            return Err(ParseError::BodyTooLarge { limit });
+        }
 
         let mut body_buf = vec![0; content_length];
-        reader.read_exact(&mut body_buf)?;
-        body_buf
+        reader
+            .read_exact(&mut body_buf)
+            .map(|_| body_buf)
+            .map_err(ParseError::from)
+    };
+    let body_buf = match body_buf {
+        Err(ParseError::IoError(e)) if is_timeout(&e) => return Err(ParseError::BodyTimeout),
+        result => result?,
     };
 
-    Request::from_parts(&headers_str, body_buf)
+    request.body = request.body_from(body_buf)?;
+    request.extensions_mut().insert(context.shared_info());
+    let trailers = declared_trailers(&request.headers, trailers);
+    if !trailers.is_empty() {
+        request.extensions_mut().insert(Trailers(trailers));
+    }
+    Ok((request, None))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_upgrade_offers_need_the_connection_token() {
+        let request = |extra: &str| {
+            Request::try_from(format!("GET / HTTP/1.1\r\nHost: x\r\n{}\r\n", extra).as_bytes())
+                .unwrap()
+        };
+        let offered = request("Connection: keep-alive, Upgrade\r\nUpgrade: websocket/13, h2c\r\n");
+        assert_eq!(offered.upgrade_protocols(), ["websocket/13", "h2c"]);
+        assert!(offered.wants_upgrade("WebSocket"));
+        assert!(offered.wants_upgrade("h2c"));
+        assert!(!offered.wants_upgrade("irc"));
+
+        let bare = request("Upgrade: websocket\r\n");
+        assert!(bare.upgrade_protocols().is_empty());
+        assert!(!bare.wants_upgrade("websocket"));
+    }
+
+    // Serves `data`, then fails every read the way a socket read timeout does.
+    struct Stalling<'a> {
+        data: &'a [u8],
+    }
+
+    impl std::io::Read for Stalling<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            let n = buf.len().min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_body_size_limit_covers_both_framings() {
+        let options = ParseOptions {
+            limits: ParserLimits {
+                max_body_bytes: Some(4),
+                ..ParserLimits::default()
+            },
+            ..ParseOptions::default()
+        };
+
+        let mut cursor = std::io::Cursor::new(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello");
+        let Err(err) = request_from_reader_with(&mut cursor, &options) else {
+            panic!("expected the body to be rejected");
+        };
+        assert!(matches!(err, ParseError::BodyTooLarge { limit: 4 }));
+        assert_eq!(err.status(), StatusCode::ContentTooLarge);
+
+        let chunked = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nhe\r\n3\r\nllo\r\n0\r\n\r\n";
+        let mut cursor = std::io::Cursor::new(chunked);
+        let result = request_from_reader_with(&mut cursor, &options);
+        assert!(matches!(result, Err(ParseError::BodyTooLarge { .. })));
+
+        let mut cursor = std::io::Cursor::new(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nhell");
+        assert!(request_from_reader_with(&mut cursor, &options).is_ok());
+    }
+
+    #[test]
+    fn test_default_limits_cap_declared_sizes_before_reading() {
+        // Neither body is sent; the declared sizes alone are refused.
+        let mut cursor =
+            std::io::Cursor::new(b"POST / HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n");
+        let result = request_from_reader(&mut cursor);
+        assert!(matches!(
+            result,
+            Err(ParseError::BodyTooLarge {
+                limit: MAX_BODY_SIZE
+            })
+        ));
+
+        let mut cursor = std::io::Cursor::new(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nfffffffff\r\n",
+        );
+        let result = request_from_reader(&mut cursor);
+        assert!(matches!(result, Err(ParseError::BodyTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_signed_chunk_sizes_are_rejected() {
+        let mut cursor = std::io::Cursor::new(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n+2\r\nhe\r\n0\r\n\r\n",
+        );
+        let result = request_from_reader(&mut cursor);
+        assert!(matches!(result, Err(ParseError::InvalidChunkFormat)));
+    }
+
+    #[test]
+    fn test_chunked_body_cut_short_is_an_error() {
+        for raw in [
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n"[..],
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello",
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5",
+        ] {
+            let result = request_from_reader(&mut std::io::Cursor::new(raw));
+            assert!(
+                matches!(&result, Err(ParseError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof),
+                "{:?}",
+                result.err()
+            );
+        }
+
+        let blank =
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+        let result = request_from_reader(&mut std::io::Cursor::new(blank));
+        assert!(matches!(result, Err(ParseError::InvalidChunkFormat)));
+    }
+
+    #[test]
+    fn test_chunk_lines_are_bounded() {
+        let mut raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+        raw.extend(std::iter::repeat_n(b'0', 4096));
+        raw.extend_from_slice(b"1\r\nx\r\n0\r\n\r\n");
+        let result = request_from_reader(&mut std::io::Cursor::new(raw));
+        assert!(matches!(result, Err(ParseError::InvalidChunkFormat)));
+
+        let mut raw = b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n1\r\nx".to_vec();
+        raw.extend(std::iter::repeat_n(b' ', 4096));
+        raw.extend_from_slice(b"\r\n0\r\n\r\n");
+        let result = request_from_reader(&mut std::io::Cursor::new(raw));
+        assert!(matches!(result, Err(ParseError::InvalidChunkFormat)));
+    }
+
+    // Hands out one byte per read, each after a short pause, like a slowloris client.
+    struct Trickle<'a> {
+        data: &'a [u8],
+    }
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(5));
+            let Some((first, rest)) = self.data.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.data = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_slow_heads_hit_the_deadline() {
+        let options = ParseOptions {
+            limits: ParserLimits {
+                header_deadline: Some(Duration::from_millis(50)),
+                ..ParserLimits::default()
+            },
+            ..ParseOptions::default()
+        };
+        let mut reader = Trickle {
+            data: b"GET / HTTP/1.1\r\nHost: x\r\nX-Padding: aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\n\r\n",
+        };
+        let result = request_from_reader_with(&mut reader, &options);
+        assert!(matches!(result, Err(ParseError::HeaderTimeout)));
+
+        let mut reader = Trickle {
+            data: b"GET / HTTP/1.1\r\n\r\n",
+        };
+        let options = ParseOptions {
+            limits: ParserLimits {
+                header_deadline: Some(Duration::from_secs(5)),
+                ..ParserLimits::default()
+            },
+            ..ParseOptions::default()
+        };
+        assert!(request_from_reader_with(&mut reader, &options).is_ok());
+    }
+
+    #[test]
+    fn test_request_line_limit_answers_414() {
+        let options = ParseOptions {
+            limits: ParserLimits {
+                max_request_line: 32,
+                ..ParserLimits::default()
+            },
+            ..ParseOptions::default()
+        };
+        let raw = format!("GET /{} HTTP/1.1\r\nHost: x\r\n\r\n", "a".repeat(32));
+        let Err(err) = request_from_reader_with(&mut raw.as_bytes(), &options) else {
+            panic!("expected the request line to be rejected");
+        };
+        assert!(matches!(err, ParseError::RequestLineTooLong { limit: 32 }));
+        assert_eq!(err.status(), StatusCode::UriTooLong);
+
+        // A method that never ends is reported as such, with 501.
+        let raw = format!("{} / HTTP/1.1\r\n\r\n", "A".repeat(64));
+        let Err(err) = request_from_reader_with(&mut raw.as_bytes(), &options) else {
+            panic!("expected the method to be rejected");
+        };
+        assert_eq!(err.status(), StatusCode::NotImplemented);
+
+        // Header lines are only bounded by the head as a whole.
+        let raw = format!("GET / HTTP/1.1\r\nX-Long: {}\r\n\r\n", "a".repeat(64));
+        assert!(request_from_reader_with(&mut raw.as_bytes(), &options).is_ok());
+    }
+
+    #[test]
+    fn test_timeouts_are_reported_per_phase() {
+        let options = ParseOptions::default();
+
+        let mut reader = BufReader::new(Stalling {
+            data: b"GET / HTTP/1.1\r\nHost: x\r\n",
+        });
+        let Err(err) = request_from_buf_reader(&mut reader, &options) else {
+            panic!("expected a timeout");
+        };
+        assert!(matches!(err, ParseError::HeaderTimeout));
+        assert_eq!(err.status(), StatusCode::RequestTimeout);
+
+        let mut switched = false;
+        let mut reader = BufReader::new(Stalling {
+            data: b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhalf",
+        });
+        let result = request_from_buf_reader_phased(
+            &mut reader,
+            &options,
+            &mut ConnectionContext::new(),
+            |_| {
+                switched = true;
+                Ok(())
+            },
+        );
+        let Err(err) = result else {
+            panic!("expected a timeout");
+        };
+        assert!(matches!(err, ParseError::BodyTimeout));
+        assert!(switched);
+    }
+
+    #[test]
+    fn test_connection_context_reuses_head_buffer() {
+        let raw = "GET /a HTTP/1.1\r\nHost: x\r\n\r\nGET /b HTTP/1.1\r\nHost: y\r\n\r\n";
+        let mut reader = std::io::Cursor::new(raw.as_bytes());
+        let mut context = ConnectionContext::with_capacity(256);
+        let options = ParseOptions::default();
+
+        let first = request_from_buf_reader_in(&mut reader, &options, &mut context).unwrap();
+        let capacity = context.head_capacity();
+        let second = request_from_buf_reader_in(&mut reader, &options, &mut context).unwrap();
+
+        assert_eq!(first.path(), "/a");
+        assert_eq!(second.path(), "/b");
+        assert_eq!(second.header("Host"), Some("y"));
+        assert_eq!(context.requests(), 2);
+        assert_eq!(context.head_capacity(), capacity);
+    }
+
+    #[test]
+    fn test_into_parts_round_trip() {
+        let raw = "POST /items?x=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 5\r\n\r\nhello";
+        let mut request = Request::try_from(raw.as_bytes()).unwrap();
+        request.extensions_mut().insert(7u32);
+
+        let (head, body) = request.into_parts();
+        assert_eq!(head.path, "/items");
+        assert_eq!(body.as_bytes(), b"hello");
+
+        let request = Request::from_head(head, body);
+        assert_eq!(request.header("Host"), Some("example.com"));
+        assert_eq!(request.query().get("x"), Some("1"));
+        assert_eq!(request.extensions().get::<u32>(), Some(&7));
+    }
+
+    #[test]
+    fn test_clone_and_fork() {
+        let raw = "POST /items HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+        let mut request = Request::try_from(raw.as_bytes()).unwrap();
+        request.extensions_mut().insert(7u32);
+
+        let cloned = request.clone();
+        assert_eq!(cloned.target(), "/items");
+        assert_eq!(cloned.body(), request.body());
+        assert!(cloned.extensions().is_empty());
+
+        let forked = request.fork();
+        match (&request.body, &forked.body) {
+            (Body::Shared(a), Body::Shared(b)) => assert!(std::sync::Arc::ptr_eq(a, b)),
+            other => panic!("expected shared bodies, got {:?}", other),
+        }
+        assert_eq!(forked.body_as_bytes(), b"hello");
+    }
+
     #[test]
     fn test_parse_get_request() {
         let raw = "GET /index.html HTTP/1.1";
@@ -389,6 +1256,29 @@ mod tests {
         assert!(request.body().is_empty());
     }
 
+    #[test]
+    fn test_chunked_trailers_are_kept_when_declared() {
+        let raw = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\
+                   Trailer: Content-Digest, Host\r\n\r\n\
+                   5\r\nHello\r\n\
+                   0\r\nContent-Digest: sha-256=:abc=:\r\nHost: evil\r\nX-Extra: 1\r\n\r\n";
+        let mut cursor = std::io::Cursor::new(raw.as_bytes());
+        let request = request_from_reader(&mut cursor).unwrap();
+
+        assert_eq!(request.body_as_str().unwrap(), "Hello");
+        let trailers = request.trailers().unwrap();
+        assert_eq!(trailers.get("content-digest"), Some("sha-256=:abc=:"));
+        assert_eq!(trailers.len(), 1);
+        assert_eq!(request.header("Host"), None);
+
+        let oversized = format!(
+            "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nX-Big: {}\r\n\r\n",
+            "a".repeat(MAX_HEADER_SIZE)
+        );
+        let result = request_from_reader(&mut std::io::Cursor::new(oversized.as_bytes()));
+        assert!(matches!(result, Err(ParseError::HeaderTooLarge)));
+    }
+
     #[test]
     fn test_chunked_encoding_invalid_size() {
         let raw = "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
@@ -419,6 +1309,18 @@ mod tests {
         assert!(matches!(result, Err(ParseError::HeaderTooLarge)));
     }
 
+    #[test]
+    fn test_header_too_large_is_431() {
+        let large_header = "X-Large: ".to_string() + &"a".repeat(8192);
+        let raw = format!("GET / HTTP/1.1\r\n{}\r\n\r\n", large_header);
+        let mut cursor = std::io::Cursor::new(raw.as_bytes());
+
+        let Err(err) = request_from_reader(&mut cursor) else {
+            panic!("expected the head to be rejected");
+        };
+        assert_eq!(err.status(), StatusCode::RequestHeaderFieldsTooLarge);
+    }
+
     #[test]
     fn test_validated_host_allowed() {
         let raw = "GET / HTTP/1.1\r\nHost: localhost:8080\r\n\r\n";
@@ -448,4 +1350,140 @@ mod tests {
         let allowed_hosts = &["localhost:8080", "grishmadhakal.com.np"];
         assert_eq!(request.validated_host(allowed_hosts), None);
     }
+
+    #[test]
+    fn test_conditional_etag_headers() {
+        let raw = "GET / HTTP/1.1\r\nIf-None-Match: W/\"v1\"\r\nIf-Match: \"v1\"\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        let current = crate::http::ETag::strong("v1").unwrap();
+
+        assert!(request.if_none_match().unwrap().matches_weak(&current));
+        assert!(request.if_match().unwrap().matches_strong(&current));
+    }
+
+    #[test]
+    fn test_conditional_etag_headers_missing_or_invalid() {
+        let raw = "GET / HTTP/1.1\r\nIf-Match: v1\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+
+        assert_eq!(request.if_none_match(), None);
+        assert_eq!(request.if_match(), None);
+    }
+
+    #[test]
+    fn test_accept_encoding_header() {
+        let raw = "GET / HTTP/1.1\r\nAccept-Encoding: gzip;q=0.5, identity;q=0\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        let accept = request.accept_encoding().unwrap();
+
+        assert_eq!(accept.negotiate(&["gzip"]), Some("gzip"));
+        assert_eq!(accept.negotiate(&["br"]), None);
+    }
+
+    #[test]
+    fn test_connection_header() {
+        let raw = "GET / HTTP/1.1\r\nConnection: Keep-Alive\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+
+        assert!(request.connection_header().keep_alive());
+        assert!(!request.connection_header().close());
+    }
+
+    #[test]
+    fn test_fragment_does_not_reach_path_or_query() {
+        let raw = "GET /search?q=1#results HTTP/1.1\r\n\r\n";
+        let result = Request::try_from(raw.as_bytes());
+        assert!(matches!(
+            result,
+            Err(ParseError::RequestLine(RequestLineError::InvalidTarget(_)))
+        ));
+
+        let options = ParseOptions {
+            target_policy: TargetPolicy::Strip,
+            ..Default::default()
+        };
+        let mut cursor = std::io::Cursor::new(raw.as_bytes());
+        let request = request_from_reader_with(&mut cursor, &options).unwrap();
+        assert_eq!(request.path(), "/search");
+        assert_eq!(request.query().get("q"), Some("1"));
+    }
+
+    #[test]
+    fn test_path_is_sanitized() {
+        let raw = "GET /static/../%2e%2e/etc/passwd?x=1 HTTP/1.1\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+
+        assert_eq!(request.path(), "/etc/passwd");
+        assert_eq!(request.raw_path(), "/static/../%2e%2e/etc/passwd");
+    }
+
+    #[test]
+    fn test_path_from_absolute_form() {
+        let raw = "GET http://example.com/a/./b?x=1 HTTP/1.1\r\n\r\n";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+
+        assert_eq!(request.path(), "/a/b");
+    }
+
+    #[test]
+    fn test_path_with_nul_rejected() {
+        let raw = "GET /file%00.txt HTTP/1.1\r\n\r\n";
+        let result = Request::try_from(raw.as_bytes());
+
+        assert!(matches!(
+            result,
+            Err(ParseError::Path(PathError::ControlCharacter))
+        ));
+    }
+
+    #[test]
+    fn test_form_body() {
+        let post = |content_type: &str, body: &str| {
+            let raw = format!(
+                "POST /submit?page=2 HTTP/1.1\r\nHost: x\r\n{}Content-Length: {}\r\n\r\n{}",
+                content_type,
+                body.len(),
+                body
+            );
+            Request::try_from(raw.as_bytes()).unwrap()
+        };
+
+        let request = post(
+            "Content-Type: Application/X-WWW-Form-Urlencoded; charset=UTF-8\r\n",
+            "name=Ana+Lima&tag=a%26b&tag=c&empty",
+        );
+        let form = request.form().unwrap();
+        assert_eq!(form.get("name"), Some("Ana Lima"));
+        assert_eq!(
+            form.get_all("tag"),
+            Some(&["a&b".to_string(), "c".to_string()][..])
+        );
+        assert_eq!(form.get("empty"), Some(""));
+        assert_eq!(request.query().get("page"), Some("2"));
+
+        let limits = FormLimits {
+            max_fields: 2,
+            ..FormLimits::default()
+        };
+        assert_eq!(
+            request.form_with(&limits),
+            Err(FormError::TooManyFields { limit: 2 })
+        );
+        let err = post("Content-Type: text/plain\r\n", "a=1")
+            .form()
+            .unwrap_err();
+        assert_eq!(err.status(), StatusCode::UnsupportedMediaType);
+        assert_eq!(
+            post("", "a=1").form(),
+            Err(FormError::UnsupportedContentType(String::new()))
+        );
+        assert_eq!(
+            post(
+                "Content-Type: application/x-www-form-urlencoded\r\n",
+                "a=%zz"
+            )
+            .form(),
+            Err(FormError::InvalidEncoding)
+        );
+    }
 }
@@ -1,20 +1,78 @@
-use super::{Headers, body::Body, status_code::StatusCode};
+use std::io::{self, Write};
+use std::time::SystemTime;
+
+use thiserror::Error;
+
+use crate::date::DateTime;
+use crate::io::{FlushPolicy, FlushingWriter};
+
+use super::{
+    Headers,
+    body::Body,
+    connection::{ConnectionHeader, Persistence},
+    content_digest::{DigestAlgorithm, DigestHeader},
+    duplex::{Duplex, DuplexStream},
+    etag::ETag,
+    header::{self, HeaderError},
+    problem::{Problem, ProblemFormat},
+    status_code::StatusCode,
+    takeover::{TakenStream, Takeover},
+};
+use crate::logging::log_warn;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum ResponseError {
+    #[error("Content-Length {declared} does not match body length {actual}")]
+    LengthMismatch { declared: String, actual: usize },
+
+    #[error("Body of {actual} bytes exceeds the snapshot limit of {limit}")]
+    TooLargeToFreeze { limit: usize, actual: usize },
+
+    #[error("Responses that take over the connection or stream their body cannot be snapshotted")]
+    HasTakeover,
+}
+
+// Bodies up to this size are copied behind the head so the whole response is
+// handed to the kernel in one write.
+const COALESCE_LIMIT: usize = 16 * 1024; // 16KB
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LengthMismatchPolicy {
+    #[default]
+    FixHeader,
+    TruncateOrPad,
+    Fail,
+}
+
+// Deliberately broken delivery, for fault injection. The response is still
+// validated as usual and only sabotaged while being written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Abort {
+    // Close the connection without writing anything.
+    Drop,
+    // Write the head and only this many body bytes, then close.
+    Truncate(usize),
+}
 
 #[derive(Debug)]
 pub struct Response {
     pub status_code: StatusCode,
     pub headers: Headers,
     pub body: Body,
+    pub takeover: Option<Takeover>,
+    pub duplex: Option<Duplex>,
+    pub abort: Option<Abort>,
 }
 
 impl Response {
     pub fn new(status_code: StatusCode) -> Self {
-        let mut headers = Headers::new();
-        headers.insert("Connection".to_string(), "close".to_string());
         Response {
             status_code,
-            headers,
+            headers: Headers::new(),
             body: Body::Empty,
+            takeover: None,
+            duplex: None,
+            abort: None,
         }
     }
 
@@ -50,6 +108,10 @@ impl Response {
         Self::new(StatusCode::MethodNotAllowed)
     }
 
+    pub fn not_acceptable() -> Self {
+        Self::new(StatusCode::NotAcceptable)
+    }
+
     pub fn conflict() -> Self {
         Self::new(StatusCode::Conflict)
     }
@@ -62,19 +124,143 @@ impl Response {
         Self::new(StatusCode::ServiceUnavailable)
     }
 
+    pub fn problem(status: StatusCode, title: &str, detail: &str, type_uri: &str) -> Self {
+        Problem::new(status)
+            .with_title(title)
+            .with_detail(detail)
+            .with_type(type_uri)
+            .into_response(ProblemFormat::Json)
+    }
+
     pub fn with_body(mut self, body: Body) -> Self {
         self.body = body;
+        self.sync_content_length();
+        self
+    }
 
-        if !self.body.is_empty() {
+    pub fn map_body(mut self, f: impl FnOnce(Body) -> Body) -> Self {
+        let body = std::mem::take(&mut self.body);
+        self.body = f(body);
+        self.sync_content_length();
+        self
+    }
+
+    pub fn take_body(&mut self) -> Body {
+        let body = std::mem::take(&mut self.body);
+        self.sync_content_length();
+        body
+    }
+
+    fn sync_content_length(&mut self) {
+        if self.body.is_empty() {
+            self.headers.remove("Content-Length");
+        } else {
             self.headers
-                .insert("Content-Length".to_string(), self.body.len().to_string());
+                .set("Content-Length", self.body.len().to_string());
+        }
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        let name = name.into();
+        let value = Headers::sanitize_value(&value.into());
+
+        if name.eq_ignore_ascii_case("Connection") {
+            let mut connection = self.connection_header();
+            connection.merge(&ConnectionHeader::parse(&value));
+            self.set_connection_header(&connection);
+            return self;
         }
 
+        if let Err(e) = self.headers.try_insert(name.as_str(), value) {
+            log_warn!("Dropping response header {:?}: {}", name, e);
+        }
         self
     }
 
-    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
-        self.headers.insert(name.into(), value.into());
+    pub fn try_with_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self, HeaderError> {
+        self.headers.try_insert(name, value)?;
+        Ok(self)
+    }
+
+    pub fn keep_alive(mut self) -> Self {
+        self.set_persistence(Persistence::KeepAlive);
+        self
+    }
+
+    pub fn close(mut self) -> Self {
+        self.set_persistence(Persistence::Close);
+        self
+    }
+
+    pub fn connection_header(&self) -> ConnectionHeader {
+        ConnectionHeader::from_headers(&self.headers)
+    }
+
+    // What the handler asked for, if anything. The server has the final say.
+    pub fn persistence(&self) -> Option<Persistence> {
+        self.connection_header().persistence()
+    }
+
+    fn set_persistence(&mut self, persistence: Persistence) {
+        let mut connection = self.connection_header();
+        connection.set_persistence(persistence);
+        self.set_connection_header(&connection);
+    }
+
+    fn set_connection_header(&mut self, connection: &ConnectionHeader) {
+        if connection.is_empty() {
+            self.headers.remove("Connection");
+        } else {
+            self.headers.set("Connection", connection.to_value());
+        }
+    }
+
+    // Settles the Connection header against what the server is willing to do and
+    // returns whether the connection stays open. A handler may opt out of a
+    // persistent connection but never force one the server did not offer.
+    pub fn reconcile_connection(&mut self, server_keeps_alive: bool) -> bool {
+        let mut connection = self.connection_header();
+        let keep_alive = server_keeps_alive && connection.persistence() != Some(Persistence::Close);
+
+        if keep_alive {
+            connection.clear_persistence();
+            if self.persistence() == Some(Persistence::KeepAlive) {
+                connection.set_persistence(Persistence::KeepAlive);
+            }
+        } else {
+            connection.set_persistence(Persistence::Close);
+        }
+        self.set_connection_header(&connection);
+        keep_alive
+    }
+
+    pub fn with_etag(mut self, etag: &ETag) -> Self {
+        self.headers.set("ETag", etag.to_string());
+        self
+    }
+
+    pub fn with_last_modified(mut self, modified: SystemTime) -> Self {
+        let date = DateTime::from_system_time(modified).to_http_date();
+        self.headers.set("Last-Modified", date);
+        self
+    }
+
+    // Set once the body is final; later body changes leave the digest stale.
+    pub fn with_content_digest(mut self, algorithm: DigestAlgorithm) -> Self {
+        let digest = DigestHeader::compute(algorithm, self.body.as_bytes());
+        self.headers.set("Content-Digest", digest.to_string());
+        self
+    }
+
+    // Bodies here carry the representation as-is, so unless a Content-Encoding was
+    // applied by hand this equals the Content-Digest.
+    pub fn with_repr_digest(mut self, algorithm: DigestAlgorithm) -> Self {
+        let digest = DigestHeader::compute(algorithm, self.body.as_bytes());
+        self.headers.set("Repr-Digest", digest.to_string());
         self
     }
 
@@ -85,6 +271,115 @@ impl Response {
         self
     }
 
+    pub fn enforce_content_length(
+        &mut self,
+        policy: LengthMismatchPolicy,
+    ) -> Result<(), ResponseError> {
+        // 304 may carry the Content-Length of the selected representation.
+        if self.status_code == StatusCode::NotModified {
+            return Ok(());
+        }
+
+        let Some(declared) = self.headers.get("Content-Length") else {
+            return Ok(());
+        };
+        let actual = self.body.len();
+        let declared_len = declared.trim().parse::<usize>().ok();
+        if declared_len == Some(actual) {
+            return Ok(());
+        }
+
+        match (policy, declared_len) {
+            (LengthMismatchPolicy::TruncateOrPad, Some(len)) => {
+                let mut data = std::mem::take(&mut self.body).as_bytes().to_vec();
+                data.resize(len, b' ');
+                self.body = Body::Content(data);
+                self.sync_content_length();
+                Ok(())
+            }
+            (LengthMismatchPolicy::Fail, _) => Err(ResponseError::LengthMismatch {
+                declared: declared.to_string(),
+                actual,
+            }),
+            _ => {
+                self.sync_content_length();
+                Ok(())
+            }
+        }
+    }
+
+    // Switches the connection to `protocol`: the server writes this 101 and hands
+    // the stream to `f`, which speaks the new protocol from then on. Check
+    // Request::wants_upgrade first; clients that did not ask cannot follow.
+    pub fn upgrade(protocol: &str, f: impl FnOnce(TakenStream) + Send + 'static) -> Self {
+        Self::new(StatusCode::SwitchingProtocols)
+            .with_header("Connection", "upgrade")
+            .with_header("Upgrade", protocol)
+            .with_takeover(f)
+    }
+
+    pub fn with_takeover(mut self, f: impl FnOnce(TakenStream) + Send + 'static) -> Self {
+        self.takeover = Some(Takeover::new(f));
+        self
+    }
+
+    pub fn take_takeover(&mut self) -> Option<Takeover> {
+        self.takeover.take()
+    }
+
+    // Streams the body from `f`, which may read the request body through the same
+    // DuplexStream while writing. The head goes out before `f` runs, so the
+    // status and headers are final here. See ServerBuilder::stream_request_bodies
+    // for having the server leave the request body unread until then.
+    pub fn with_duplex(
+        mut self,
+        f: impl FnOnce(&mut DuplexStream<'_>) -> io::Result<()> + Send + 'static,
+    ) -> Self {
+        self.body = Body::Empty;
+        self.headers.remove("Content-Length");
+        self.headers.set("Transfer-Encoding", "chunked");
+        self.duplex = Some(Duplex::new(f));
+        self
+    }
+
+    pub fn take_duplex(&mut self) -> Option<Duplex> {
+        self.duplex.take()
+    }
+
+    // Whether something other than `body` produces what goes on the wire, which
+    // middleware that rewrite bodies must leave alone.
+    pub fn streams_body(&self) -> bool {
+        self.takeover.is_some() || self.duplex.is_some()
+    }
+
+    pub fn with_abort(mut self, abort: Abort) -> Self {
+        self.abort = Some(abort);
+        self
+    }
+
+    // Returns a snapshot suitable for caching or retries. The body is moved behind an
+    // Arc, so the snapshot and any later clones of either share the same bytes.
+    pub fn freeze(&mut self, max_body_bytes: usize) -> Result<Response, ResponseError> {
+        if self.streams_body() {
+            return Err(ResponseError::HasTakeover);
+        }
+        if self.body.len() > max_body_bytes {
+            return Err(ResponseError::TooLargeToFreeze {
+                limit: max_body_bytes,
+                actual: self.body.len(),
+            });
+        }
+
+        Ok(Response {
+            status_code: self.status_code,
+            headers: self.headers.clone(),
+            body: self.body.share(),
+            takeover: None,
+            duplex: None,
+            abort: None,
+        })
+    }
+
     pub fn status_code(&self) -> StatusCode {
         self.status_code
     }
@@ -97,29 +392,112 @@ impl Response {
         &self.body
     }
 
-    pub fn to_bytes(&self) -> Vec<u8> {
-        let mut response = Vec::new();
+    fn head_bytes(&self, extra: usize) -> Vec<u8> {
+        let head_len: usize = self
+            .headers
+            .iter()
+            .map(|(name, value)| name.len() + value.len() + 4)
+            .sum();
+        let mut head = Vec::with_capacity(64 + head_len + extra);
 
-        let status_line = format!("HTTP/1.1 {}\r\n", self.status_code());
-        response.extend_from_slice(status_line.as_bytes());
+        head.extend_from_slice(self.status_code.status_line());
 
         for (name, value) in self.headers.iter() {
-            let header_line = format!("{}: {}\r\n", name, value);
-            response.extend_from_slice(header_line.as_bytes());
+            if Headers::validate(name, value).is_err() {
+                continue;
+            }
+            header::write_line(&mut head, name, value);
         }
 
-        response.extend_from_slice(b"\r\n");
+        head.extend_from_slice(b"\r\n");
+        head
+    }
 
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut response = self.head_bytes(self.body.len());
         response.extend_from_slice(self.body.as_bytes());
-
         response
     }
 
-    pub fn send(&self, stream: &mut impl std::io::Write) -> std::io::Result<()> {
-        stream.write_all(&self.to_bytes())?;
+    // Small responses go out as one buffer so head and body share a single write;
+    // larger bodies are written in place rather than copied behind the head.
+    pub fn send(&self, stream: &mut impl Write) -> io::Result<()> {
+        match self.abort {
+            Some(Abort::Drop) => return Ok(()),
+            Some(Abort::Truncate(len)) => {
+                let body = self.body.as_bytes();
+                stream.write_all(&self.head_bytes(0))?;
+                stream.write_all(&body[..len.min(body.len())])?;
+                return stream.flush();
+            }
+            None => {}
+        }
+
+        if self.body.len() <= COALESCE_LIMIT {
+            stream.write_all(&self.to_bytes())?;
+        } else {
+            stream.write_all(&self.head_bytes(0))?;
+            stream.write_all(self.body.as_bytes())?;
+        }
         stream.flush()?;
         Ok(())
     }
+
+    // Async counterpart of send with the same abort and coalescing behavior.
+    #[cfg(feature = "async")]
+    pub async fn send_async<W: tokio::io::AsyncWrite + Unpin>(
+        &self,
+        stream: &mut W,
+    ) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        match self.abort {
+            Some(Abort::Drop) => return Ok(()),
+            Some(Abort::Truncate(len)) => {
+                let body = self.body.as_bytes();
+                stream.write_all(&self.head_bytes(0)).await?;
+                stream.write_all(&body[..len.min(body.len())]).await?;
+                return stream.flush().await;
+            }
+            None => {}
+        }
+
+        if self.body.len() <= COALESCE_LIMIT {
+            stream.write_all(&self.to_bytes()).await?;
+        } else {
+            stream.write_all(&self.head_bytes(0)).await?;
+            stream.write_all(self.body.as_bytes()).await?;
+        }
+        stream.flush().await
+    }
+
+    // Writes only the status line and headers and hands back a writer for the body.
+    // The caller is responsible for framing, e.g. a Content-Length or chunked
+    // Transfer-Encoding header set beforehand. Unless the policy is Always, the
+    // head stays buffered and leaves together with the first body bytes.
+    pub fn send_head<W: Write>(
+        &self,
+        stream: W,
+        policy: FlushPolicy,
+    ) -> io::Result<FlushingWriter<W>> {
+        let mut writer = FlushingWriter::new(stream, policy);
+        writer.write_all(&self.head_bytes(0))?;
+        Ok(writer)
+    }
+}
+
+// Takeover and duplex callbacks run at most once, so clones never carry them.
+impl Clone for Response {
+    fn clone(&self) -> Self {
+        Response {
+            status_code: self.status_code,
+            headers: self.headers.clone(),
+            body: self.body.clone(),
+            takeover: None,
+            duplex: None,
+            abort: self.abort,
+        }
+    }
 }
 
 impl Default for Response {
@@ -132,6 +510,39 @@ impl Default for Response {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_freeze_shares_body() {
+        let mut response = Response::ok().with_body(Body::from("cached"));
+        let snapshot = response.freeze(64).unwrap();
+
+        match (&response.body, &snapshot.clone().body) {
+            (Body::Shared(a), Body::Shared(b)) => assert!(std::sync::Arc::ptr_eq(a, b)),
+            other => panic!("expected shared bodies, got {:?}", other),
+        }
+        assert_eq!(snapshot.to_bytes(), response.to_bytes());
+
+        assert_eq!(
+            response.freeze(3).unwrap_err(),
+            ResponseError::TooLargeToFreeze {
+                limit: 3,
+                actual: 6
+            }
+        );
+
+        let mut upgraded = Response::ok().with_takeover(|_| {});
+        assert_eq!(upgraded.freeze(64).unwrap_err(), ResponseError::HasTakeover);
+        assert!(upgraded.clone().takeover.is_none());
+    }
+
+    #[test]
+    fn test_preserialized_status_lines() {
+        assert_eq!(StatusCode::OK.status_line(), b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(
+            StatusCode::HttpVersionNotSupported.status_line(),
+            format!("HTTP/1.1 {}\r\n", StatusCode::HttpVersionNotSupported).as_bytes()
+        );
+    }
+
     #[test]
     fn test_status_code_display() {
         assert_eq!(StatusCode::OK.to_string(), "200 OK");
@@ -143,6 +554,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::bool_assert_comparison)]
     fn test_basic_response() {
         let response = Response::ok();
         assert_eq!(response.status_code(), StatusCode::OK);
@@ -159,4 +571,214 @@ mod tests {
         assert_eq!(response.body().as_str().unwrap(), "Hello, World!");
         assert_eq!(response.headers().get("Content-Length"), Some("13"));
     }
+
+    #[test]
+    fn test_map_body_updates_content_length() {
+        let response = Response::ok()
+            .with_header("Content-Type", "text/plain")
+            .with_body(Body::from("Hello"))
+            .map_body(|body| {
+                let mut data = body.as_bytes().to_vec();
+                data.extend_from_slice(b", World!");
+                Body::Content(data)
+            });
+
+        assert_eq!(response.body().as_str().unwrap(), "Hello, World!");
+        assert_eq!(response.headers().get("Content-Length"), Some("13"));
+        assert_eq!(response.headers().get("Content-Type"), Some("text/plain"));
+    }
+
+    #[test]
+    fn test_map_body_to_empty_removes_content_length() {
+        let response = Response::ok()
+            .with_body(Body::from("Hello"))
+            .map_body(|_| Body::Empty);
+
+        assert!(response.body().is_empty());
+        assert_eq!(response.headers().get("Content-Length"), None);
+    }
+
+    #[test]
+    fn test_take_body() {
+        let mut response = Response::ok().with_body(Body::from("Hello"));
+        let body = response.take_body();
+
+        assert_eq!(body.as_str().unwrap(), "Hello");
+        assert!(response.body().is_empty());
+        assert_eq!(response.headers().get("Content-Length"), None);
+    }
+
+    #[test]
+    fn test_with_body_twice_replaces_content_length() {
+        let response = Response::ok()
+            .with_body(Body::from("Hello"))
+            .with_body(Body::from("Hi"));
+
+        assert_eq!(response.headers().get("Content-Length"), Some("2"));
+    }
+
+    #[test]
+    fn test_enforce_content_length_fix_header() {
+        let mut response = Response::ok()
+            .with_body(Body::from("Hello"))
+            .with_header("X-Test", "1");
+        response.headers.set("Content-Length", "10");
+
+        response
+            .enforce_content_length(LengthMismatchPolicy::FixHeader)
+            .unwrap();
+        assert_eq!(response.headers().get("Content-Length"), Some("5"));
+    }
+
+    #[test]
+    fn test_enforce_content_length_truncate_or_pad() {
+        let mut response = Response::ok().with_body(Body::from("Hello, World!"));
+        response.headers.set("Content-Length", "5");
+        response
+            .enforce_content_length(LengthMismatchPolicy::TruncateOrPad)
+            .unwrap();
+        assert_eq!(response.body().as_str().unwrap(), "Hello");
+
+        response.headers.set("Content-Length", "7");
+        response
+            .enforce_content_length(LengthMismatchPolicy::TruncateOrPad)
+            .unwrap();
+        assert_eq!(response.body().as_str().unwrap(), "Hello  ");
+        assert_eq!(response.headers().get("Content-Length"), Some("7"));
+    }
+
+    #[test]
+    fn test_enforce_content_length_fail() {
+        let mut response = Response::ok().with_body(Body::from("Hello"));
+        response.headers.set("Content-Length", "abc");
+
+        let result = response.enforce_content_length(LengthMismatchPolicy::Fail);
+        assert_eq!(
+            result,
+            Err(ResponseError::LengthMismatch {
+                declared: "abc".to_string(),
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_enforce_content_length_matching() {
+        let mut response = Response::ok().with_body(Body::from("Hello"));
+        assert!(
+            response
+                .enforce_content_length(LengthMismatchPolicy::Fail)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_with_header_sanitizes_crlf() {
+        let response = Response::ok().with_header("X-Name", "alice\r\nSet-Cookie: admin=1");
+
+        assert_eq!(
+            response.headers().get("X-Name"),
+            Some("aliceSet-Cookie: admin=1")
+        );
+        assert!(!response.headers().contains("Set-Cookie"));
+    }
+
+    #[test]
+    fn test_with_header_drops_invalid_name() {
+        let response = Response::ok().with_header("Bad Name", "value");
+        assert!(!response.headers().contains("Bad Name"));
+    }
+
+    #[test]
+    fn test_try_with_header_rejects_injection() {
+        let result = Response::ok().try_with_header("X-Name", "a\nb");
+        assert!(matches!(result, Err(HeaderError::InvalidHeaderValue)));
+    }
+
+    #[test]
+    fn test_to_bytes_skips_unvalidated_headers() {
+        let mut response = Response::ok();
+        response.headers.insert("X-Raw", "a\r\nInjected: 1");
+
+        let bytes = String::from_utf8(response.to_bytes()).unwrap();
+        assert!(!bytes.contains("Injected"));
+    }
+
+    #[derive(Default)]
+    struct CountingWriter {
+        writes: usize,
+        data: Vec<u8>,
+    }
+
+    impl Write for CountingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_send_coalesces_small_response() {
+        let response = Response::ok().with_body(Body::from("Hello"));
+        let mut stream = CountingWriter::default();
+        response.send(&mut stream).unwrap();
+
+        assert_eq!(stream.writes, 1);
+        assert_eq!(stream.data, response.to_bytes());
+
+        let large = Response::ok().with_body(Body::Content(vec![b'a'; COALESCE_LIMIT + 1]));
+        let mut stream = CountingWriter::default();
+        large.send(&mut stream).unwrap();
+        assert_eq!(stream.writes, 2);
+        assert_eq!(stream.data, large.to_bytes());
+    }
+
+    #[test]
+    fn test_send_head_coalesces_with_first_chunk() {
+        let response = Response::ok().with_header("Transfer-Encoding", "chunked");
+        let mut writer = response
+            .send_head(CountingWriter::default(), FlushPolicy::Manual)
+            .unwrap();
+        writer.write_all(b"5\r\nhello\r\n0\r\n\r\n").unwrap();
+
+        let stream = writer.into_inner().unwrap();
+        assert_eq!(stream.writes, 1);
+        assert!(stream.data.ends_with(b"\r\n\r\n5\r\nhello\r\n0\r\n\r\n"));
+    }
+
+    #[test]
+    fn test_connection_header_control() {
+        let response = Response::ok().with_header("Connection", "keep-alive");
+        assert_eq!(response.headers().get("connection"), Some("keep-alive"));
+
+        let response = response.close().with_header("Connection", "Upgrade");
+        assert_eq!(response.headers().get("connection"), Some("close, upgrade"));
+        assert_eq!(response.persistence(), Some(Persistence::Close));
+
+        assert!(Response::ok().headers().get("connection").is_none());
+    }
+
+    #[test]
+    fn test_reconcile_connection() {
+        let mut response = Response::ok();
+        assert!(!response.reconcile_connection(false));
+        assert_eq!(response.headers().get("connection"), Some("close"));
+
+        let mut response = Response::ok().keep_alive();
+        assert!(response.reconcile_connection(true));
+        assert_eq!(response.headers().get("connection"), Some("keep-alive"));
+
+        let mut response = Response::ok().keep_alive();
+        assert!(!response.reconcile_connection(false));
+        assert_eq!(response.headers().get("connection"), Some("close"));
+
+        let mut response = Response::ok().close();
+        assert!(!response.reconcile_connection(true));
+        assert_eq!(response.headers().get("connection"), Some("close"));
+    }
 }
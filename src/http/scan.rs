@@ -0,0 +1,137 @@
+// Byte scanning for the parser hot paths. Searches process eight bytes per step
+// using word-at-a-time (SWAR) arithmetic, and byte-class checks are table lookups,
+// so neither needs platform intrinsics or extra dependencies. With the memchr
+// feature the searches use its SIMD routines instead; the portable versions stay
+// available for comparison (see benches/scan.rs).
+
+const LO: u64 = 0x0101_0101_0101_0101;
+const HI: u64 = 0x8080_8080_8080_8080;
+
+const fn build_table(token: bool) -> [bool; 256] {
+    let mut table = [false; 256];
+    let mut b = 0;
+    while b < 256 {
+        let c = b as u8;
+        table[b] = if token {
+            c.is_ascii_alphanumeric()
+                || matches!(
+                    c,
+                    b'!' | b'#'
+                        | b'$'
+                        | b'%'
+                        | b'&'
+                        | b'\''
+                        | b'*'
+                        | b'+'
+                        | b'-'
+                        | b'.'
+                        | b'^'
+                        | b'_'
+                        | b'`'
+                        | b'|'
+                        | b'~'
+                )
+        } else {
+            // field-vchar, SP, HTAB, and obs-text
+            c == b' ' || c == b'\t' || (c >= 0x21 && c != 0x7f)
+        };
+        b += 1;
+    }
+    table
+}
+
+static TOKEN: [bool; 256] = build_table(true);
+static FIELD_VALUE: [bool; 256] = build_table(false);
+
+pub fn is_token(bytes: &[u8]) -> bool {
+    !bytes.is_empty() && bytes.iter().all(|&b| TOKEN[b as usize])
+}
+
+pub fn is_field_value(bytes: &[u8]) -> bool {
+    bytes.iter().all(|&b| FIELD_VALUE[b as usize])
+}
+
+pub fn find_byte(needle: u8, haystack: &[u8]) -> Option<usize> {
+    #[cfg(feature = "memchr")]
+    return memchr::memchr(needle, haystack);
+    #[cfg(not(feature = "memchr"))]
+    find_byte_portable(needle, haystack)
+}
+
+pub fn find_crlf(haystack: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while let Some(i) = find_byte(b'\r', &haystack[start..]) {
+        let at = start + i;
+        if haystack.get(at + 1) == Some(&b'\n') {
+            return Some(at);
+        }
+        start = at + 1;
+    }
+    None
+}
+
+pub fn find_byte_portable(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let pattern = LO.wrapping_mul(needle as u64);
+    let mut chunks = haystack.chunks_exact(8);
+    let mut offset = 0;
+
+    for chunk in &mut chunks {
+        let word = u64::from_le_bytes(chunk.try_into().expect("chunk is 8 bytes")) ^ pattern;
+        // Sets the high bit of every zero byte; the lowest set bit marks the first match.
+        let found = word.wrapping_sub(LO) & !word & HI;
+        if found != 0 {
+            return Some(offset + (found.trailing_zeros() / 8) as usize);
+        }
+        offset += 8;
+    }
+
+    chunks
+        .remainder()
+        .iter()
+        .position(|&b| b == needle)
+        .map(|i| offset + i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_byte_matches_naive_search() {
+        let haystack: Vec<u8> = (0..64u8).map(|i| i.wrapping_mul(37) | 0x80).collect();
+        for len in 0..haystack.len() {
+            for needle in [b'\n', b':', 0x80, 0xa5, 0xff] {
+                let mut data = haystack[..len].to_vec();
+                if len > 0 {
+                    data[len / 2] = needle;
+                }
+                let expected = data.iter().position(|&b| b == needle);
+                assert_eq!(
+                    find_byte_portable(needle, &data),
+                    expected,
+                    "len {} needle {:#x}",
+                    len,
+                    needle
+                );
+                assert_eq!(find_byte(needle, &data), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_crlf() {
+        assert_eq!(find_crlf(b"Host: a\r\n"), Some(7));
+        assert_eq!(find_crlf(b"a\rb\r\r\n"), Some(4));
+        assert_eq!(find_crlf(b"no line end\r"), None);
+    }
+
+    #[test]
+    fn test_byte_classes() {
+        assert!(is_token(b"X-Request-Id"));
+        assert!(!is_token(b"Bad Name"));
+        assert!(!is_token(b""));
+        assert!(is_field_value("text/html; q=0.9\tcafé".as_bytes()));
+        assert!(!is_field_value(b"a\r\nb"));
+        assert!(!is_field_value(b"a\x7fb"));
+    }
+}
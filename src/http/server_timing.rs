@@ -0,0 +1,133 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingMetric {
+    pub name: String,
+    pub duration: Option<Duration>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct ServerTiming {
+    metrics: Mutex<Vec<TimingMetric>>,
+}
+
+impl ServerTiming {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, name: &str, duration: Duration) {
+        self.push(name, Some(duration), None);
+    }
+
+    pub fn record_with_description(&self, name: &str, duration: Duration, description: &str) {
+        self.push(name, Some(duration), Some(description));
+    }
+
+    pub fn mark(&self, name: &str, description: &str) {
+        self.push(name, None, Some(description));
+    }
+
+    pub fn time<T>(&self, name: &str, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.record(name, started.elapsed());
+        result
+    }
+
+    fn push(&self, name: &str, duration: Option<Duration>, description: Option<&str>) {
+        let valid_name = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+        if !valid_name {
+            return;
+        }
+
+        self.metrics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(TimingMetric {
+                name: name.to_string(),
+                duration,
+                description: description.map(|d| d.to_string()),
+            });
+    }
+
+    pub fn metrics(&self) -> Vec<TimingMetric> {
+        self.metrics
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn header_value(&self) -> Option<String> {
+        let metrics = self.metrics.lock().unwrap_or_else(|e| e.into_inner());
+        if metrics.is_empty() {
+            return None;
+        }
+
+        let entries: Vec<String> = metrics
+            .iter()
+            .map(|metric| {
+                let mut entry = metric.name.clone();
+                if let Some(description) = &metric.description {
+                    let escaped = description
+                        .chars()
+                        .filter(|c| !c.is_control())
+                        .flat_map(|c| match c {
+                            '"' | '\\' => vec!['\\', c],
+                            c => vec![c],
+                        })
+                        .collect::<String>();
+                    entry.push_str(&format!(";desc=\"{}\"", escaped));
+                }
+                if let Some(duration) = metric.duration {
+                    entry.push_str(&format!(";dur={:.1}", duration.as_secs_f64() * 1000.0));
+                }
+                entry
+            })
+            .collect();
+
+        Some(entries.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_value() {
+        let timing = ServerTiming::new();
+        assert_eq!(timing.header_value(), None);
+
+        timing.record("db", Duration::from_micros(53_250));
+        timing.record_with_description("cache", Duration::from_millis(2), "Cache \"hit\"");
+        timing.mark("miss", "edge");
+
+        assert_eq!(
+            timing.header_value().unwrap(),
+            "db;dur=53.2, cache;desc=\"Cache \\\"hit\\\"\";dur=2.0, miss;desc=\"edge\""
+        );
+    }
+
+    #[test]
+    fn test_invalid_names_ignored() {
+        let timing = ServerTiming::new();
+        timing.record("bad name", Duration::from_millis(1));
+        timing.record("", Duration::from_millis(1));
+        assert!(timing.metrics().is_empty());
+    }
+
+    #[test]
+    fn test_time_closure() {
+        let timing = ServerTiming::new();
+        let value = timing.time("work", || 42);
+
+        assert_eq!(value, 42);
+        assert_eq!(timing.metrics()[0].name, "work");
+    }
+}
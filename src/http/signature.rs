@@ -0,0 +1,592 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::{base64, digest};
+
+use super::{request::Request, status_code::StatusCode};
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SignatureError {
+    #[error("Missing Signature or Signature-Input header")]
+    Missing,
+
+    #[error("Malformed signature header")]
+    Malformed,
+
+    #[error("Unknown signing key {0}")]
+    UnknownKey(String),
+
+    #[error("Signature algorithm {0} does not match the key")]
+    Algorithm(String),
+
+    #[error("Unsupported signature component {0}")]
+    Unsupported(String),
+
+    #[error("Signed component {0} is not present")]
+    MissingComponent(String),
+
+    #[error("Signature does not cover {0}")]
+    NotCovered(String),
+
+    #[error("Signature has expired or is not yet valid")]
+    Expired,
+
+    #[error("Signature does not match")]
+    Invalid,
+}
+
+impl SignatureError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SignatureError::Malformed => StatusCode::BadRequest,
+            _ => StatusCode::Unauthorized,
+        }
+    }
+}
+
+// A key that produces and checks signatures for one algorithm from the RFC 9421
+// registry. Only HMAC-SHA256 ships in-tree; asymmetric algorithms plug in by
+// implementing this over a crypto library.
+pub trait SigningKey: Send + Sync {
+    fn algorithm(&self) -> &str;
+    fn sign(&self, base: &[u8]) -> Vec<u8>;
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool;
+}
+
+pub struct HmacSha256 {
+    secret: Vec<u8>,
+}
+
+impl HmacSha256 {
+    pub fn new(secret: impl Into<Vec<u8>>) -> Self {
+        HmacSha256 {
+            secret: secret.into(),
+        }
+    }
+}
+
+impl SigningKey for HmacSha256 {
+    fn algorithm(&self) -> &str {
+        "hmac-sha256"
+    }
+
+    fn sign(&self, base: &[u8]) -> Vec<u8> {
+        digest::hmac_sha256(&self.secret, base).to_vec()
+    }
+
+    fn verify(&self, base: &[u8], signature: &[u8]) -> bool {
+        digest::constant_time_eq(&digest::hmac_sha256(&self.secret, base), signature)
+    }
+}
+
+// Looks keys up by the keyid a signature names, e.g. from a database or a
+// federation peer's published keys.
+pub trait KeyStore: Send + Sync {
+    fn key(&self, key_id: &str) -> Option<Arc<dyn SigningKey>>;
+}
+
+#[derive(Default)]
+pub struct StaticKeys {
+    keys: HashMap<String, Arc<dyn SigningKey>>,
+}
+
+impl StaticKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_key(mut self, key_id: impl Into<String>, key: impl SigningKey + 'static) -> Self {
+        self.keys.insert(key_id.into(), Arc::new(key));
+        self
+    }
+}
+
+impl KeyStore for StaticKeys {
+    fn key(&self, key_id: &str) -> Option<Arc<dyn SigningKey>> {
+        self.keys.get(key_id).cloned()
+    }
+}
+
+// The covered components and parameters of one Signature-Input member, e.g.
+// `("@method" "@path" "content-digest");created=1618884473;keyid="k1"`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SignatureParams {
+    pub components: Vec<String>,
+    pub created: Option<u64>,
+    pub expires: Option<u64>,
+    pub key_id: Option<String>,
+    pub alg: Option<String>,
+    pub nonce: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl Display for SignatureParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components: Vec<String> = self.components.iter().map(|c| quote(c)).collect();
+        write!(f, "({})", components.join(" "))?;
+        if let Some(created) = self.created {
+            write!(f, ";created={}", created)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, ";expires={}", expires)?;
+        }
+        for (name, value) in [
+            ("keyid", &self.key_id),
+            ("alg", &self.alg),
+            ("nonce", &self.nonce),
+            ("tag", &self.tag),
+        ] {
+            if let Some(value) = value {
+                write!(f, ";{}={}", name, quote(value))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SignatureParams {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s
+            .trim()
+            .strip_prefix('(')
+            .ok_or(SignatureError::Malformed)?;
+        let (list, mut rest) = rest.split_once(')').ok_or(SignatureError::Malformed)?;
+        let mut params = SignatureParams::default();
+        for item in list.split_whitespace() {
+            // Component parameters such as ;sf or ;key are not supported.
+            params.components.push(unquote(item)?.to_ascii_lowercase());
+        }
+
+        while let Some(param) = rest.strip_prefix(';') {
+            let end = param.find(';').unwrap_or(param.len());
+            let (name, value) = param[..end]
+                .split_once('=')
+                .ok_or(SignatureError::Malformed)?;
+            let number = || value.parse::<u64>().map_err(|_| SignatureError::Malformed);
+            match name {
+                "created" => params.created = Some(number()?),
+                "expires" => params.expires = Some(number()?),
+                "keyid" => params.key_id = Some(unquote(value)?),
+                "alg" => params.alg = Some(unquote(value)?),
+                "nonce" => params.nonce = Some(unquote(value)?),
+                "tag" => params.tag = Some(unquote(value)?),
+                _ => {}
+            }
+            rest = &param[end..];
+        }
+        match rest.trim().is_empty() {
+            true => Ok(params),
+            false => Err(SignatureError::Malformed),
+        }
+    }
+}
+
+// Quoted strings here never contain ';', ',' or whitespace worth preserving, which
+// keeps the parsing above to plain splits.
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn unquote(value: &str) -> Result<String, SignatureError> {
+    let inner = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .ok_or(SignatureError::Malformed)?;
+    Ok(inner.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
+
+// Splits a structured-field dictionary into label and raw member value, honouring
+// commas inside quoted strings.
+fn members(value: &str) -> Result<Vec<(&str, &str)>, SignatureError> {
+    let mut out = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+    for (i, c) in value.char_indices().chain([(value.len(), ',')]) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                let member = value[start..i].trim();
+                if !member.is_empty() {
+                    let (label, rest) = member.split_once('=').ok_or(SignatureError::Malformed)?;
+                    out.push((label.trim(), rest.trim()));
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+fn component_value(request: &Request, name: &str) -> Result<String, SignatureError> {
+    let scheme = match request.connection().tls {
+        true => "https",
+        false => "http",
+    };
+    let authority = || {
+        request
+            .header("Host")
+            .map(|host| host.trim().to_ascii_lowercase())
+            .ok_or_else(|| SignatureError::MissingComponent(name.to_string()))
+    };
+    let target = request.target();
+    let value = match name {
+        "@method" => request.method().as_str().to_string(),
+        "@scheme" => scheme.to_string(),
+        "@authority" => authority()?,
+        "@request-target" => target.to_string(),
+        "@path" => request.raw_path().to_string(),
+        "@query" => format!("?{}", target.split_once('?').map_or("", |(_, q)| q)),
+        "@target-uri" if target.contains("://") => target.to_string(),
+        "@target-uri" => format!("{}://{}{}", scheme, authority()?, target),
+        _ if name.starts_with('@') => return Err(SignatureError::Unsupported(name.to_string())),
+        // Every line of a repeated field, trimmed and joined as in section 2.1.
+        _ => {
+            let lines = request.headers.field_lines(name);
+            if lines.is_empty() {
+                return Err(SignatureError::MissingComponent(name.to_string()));
+            }
+            lines
+                .iter()
+                .map(|line| line.trim())
+                .collect::<Vec<_>>()
+                .join(", ")
+        }
+    };
+    Ok(value)
+}
+
+// The signature base of RFC 9421 section 2.5: one line per covered component,
+// then the serialized parameters exactly as they appear in Signature-Input.
+pub fn signature_base(
+    request: &Request,
+    components: &[String],
+    serialized_params: &str,
+) -> Result<String, SignatureError> {
+    let mut base = String::new();
+    for name in components {
+        base.push_str(&format!(
+            "{}: {}\n",
+            quote(name),
+            component_value(request, name)?
+        ));
+    }
+    base.push_str(&format!("\"@signature-params\": {}", serialized_params));
+    Ok(base)
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// Signs outgoing requests, adding Signature-Input and Signature members under its
+// label next to any signatures already present.
+pub struct Signer {
+    label: String,
+    key_id: String,
+    key: Arc<dyn SigningKey>,
+    components: Vec<String>,
+    expires_in: Option<Duration>,
+}
+
+impl Signer {
+    pub fn new(key_id: impl Into<String>, key: impl SigningKey + 'static) -> Self {
+        Signer {
+            label: "sig1".to_string(),
+            key_id: key_id.into(),
+            key: Arc::new(key),
+            components: ["@method", "@authority", "@path", "@query"]
+                .map(String::from)
+                .to_vec(),
+            expires_in: None,
+        }
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    // Derived components start with '@'; anything else names a header field.
+    pub fn components(mut self, components: &[&str]) -> Self {
+        self.components = components.iter().map(|c| c.to_ascii_lowercase()).collect();
+        self
+    }
+
+    pub fn expires_in(mut self, lifetime: Duration) -> Self {
+        self.expires_in = Some(lifetime);
+        self
+    }
+
+    pub fn sign(&self, request: &mut Request) -> Result<(), SignatureError> {
+        self.sign_at(request, unix_now())
+    }
+
+    pub fn sign_at(&self, request: &mut Request, created: u64) -> Result<(), SignatureError> {
+        let params = SignatureParams {
+            components: self.components.clone(),
+            created: Some(created),
+            expires: self
+                .expires_in
+                .map(|lifetime| created.saturating_add(lifetime.as_secs())),
+            key_id: Some(self.key_id.clone()),
+            ..SignatureParams::default()
+        };
+        let serialized = params.to_string();
+        let base = signature_base(request, &params.components, &serialized)?;
+        let signature = self.key.sign(base.as_bytes());
+
+        request
+            .headers
+            .insert("Signature-Input", format!("{}={}", self.label, serialized));
+        request.headers.insert(
+            "Signature",
+            format!("{}=:{}:", self.label, base64::encode(&signature)),
+        );
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedSignature {
+    pub label: String,
+    pub key_id: String,
+    pub params: SignatureParams,
+}
+
+// Checks inbound signatures against a key store. Without a label the first
+// signature is checked; `require` lists components it must cover, so a signer
+// cannot leave out the parts that matter. By default that is @method, @authority
+// and @path, without which a signature could be replayed against another route.
+pub struct Verifier {
+    keys: Arc<dyn KeyStore>,
+    label: Option<String>,
+    required: Vec<String>,
+    max_age: Option<Duration>,
+    skew: Duration,
+}
+
+impl Verifier {
+    pub fn new(keys: impl KeyStore + 'static) -> Self {
+        Verifier {
+            keys: Arc::new(keys),
+            label: None,
+            required: ["@method", "@authority", "@path"]
+                .map(String::from)
+                .to_vec(),
+            max_age: None,
+            skew: Duration::from_secs(60),
+        }
+    }
+
+    pub fn label(mut self, label: impl Into<String>) -> Self {
+        self.label = Some(label.into());
+        self
+    }
+
+    pub fn require(mut self, components: &[&str]) -> Self {
+        self.required = components.iter().map(|c| c.to_ascii_lowercase()).collect();
+        self
+    }
+
+    // Refuse signatures created longer ago than this, even without an expires.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn verify(&self, request: &Request) -> Result<VerifiedSignature, SignatureError> {
+        self.verify_at(request, unix_now())
+    }
+
+    pub fn verify_at(
+        &self,
+        request: &Request,
+        now: u64,
+    ) -> Result<VerifiedSignature, SignatureError> {
+        let (Some(input), Some(signature)) = (
+            request.header("Signature-Input"),
+            request.header("Signature"),
+        ) else {
+            return Err(SignatureError::Missing);
+        };
+        let inputs = members(input)?;
+        let (label, serialized) = match &self.label {
+            Some(label) => inputs.into_iter().find(|(l, _)| l == label),
+            None => inputs.into_iter().next(),
+        }
+        .ok_or(SignatureError::Missing)?;
+        let encoded = members(signature)?
+            .into_iter()
+            .find(|(l, _)| *l == label)
+            .map(|(_, value)| value)
+            .ok_or(SignatureError::Missing)?;
+        let signature = encoded
+            .strip_prefix(':')
+            .and_then(|v| v.strip_suffix(':'))
+            .and_then(base64::decode)
+            .ok_or(SignatureError::Malformed)?;
+
+        let params: SignatureParams = serialized.parse()?;
+        if let Some(missing) = self
+            .required
+            .iter()
+            .find(|c| !params.components.contains(c))
+        {
+            return Err(SignatureError::NotCovered(missing.clone()));
+        }
+        let skew = self.skew.as_secs();
+        let created = params.created.unwrap_or(now);
+        let stale = self.max_age.is_some_and(|max_age| {
+            created
+                .saturating_add(max_age.as_secs())
+                .saturating_add(skew)
+                < now
+        });
+        if created > now.saturating_add(skew)
+            || params.expires.is_some_and(|e| e.saturating_add(skew) < now)
+            || stale
+        {
+            return Err(SignatureError::Expired);
+        }
+
+        let key_id = params.key_id.clone().ok_or(SignatureError::Malformed)?;
+        let key = self
+            .keys
+            .key(&key_id)
+            .ok_or_else(|| SignatureError::UnknownKey(key_id.clone()))?;
+        if let Some(alg) = params.alg.as_deref().filter(|alg| *alg != key.algorithm()) {
+            return Err(SignatureError::Algorithm(alg.to_string()));
+        }
+        let base = signature_base(request, &params.components, serialized)?;
+        if !key.verify(base.as_bytes(), &signature) {
+            return Err(SignatureError::Invalid);
+        }
+        Ok(VerifiedSignature {
+            label: label.to_string(),
+            key_id,
+            params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 9421 appendix B.2 test request and B.1.5 shared secret.
+    const SECRET: &str =
+        "uzvJfB4u3N0Jy4T7NZ75MDVcr8zSTInedJtkgcu46YW4XByzNJjxBdtjUkdJPBtbmHhIDi6pcl8jekqS1RDvFw==";
+
+    fn request() -> Request {
+        Request::from_parts(
+            "POST /foo?param=Value&Pet=dog HTTP/1.1\r\nHost: example.com\r\n\
+             Date: Tue, 20 Apr 2021 02:07:55 GMT\r\nContent-Type: application/json\r\n\
+             Content-Length: 18\r\n\r\n",
+            b"{\"hello\": \"world\"}".to_vec(),
+        )
+        .unwrap()
+    }
+
+    fn key() -> HmacSha256 {
+        HmacSha256::new(base64::decode(SECRET).unwrap())
+    }
+
+    #[test]
+    fn test_signs_and_verifies_covered_components() {
+        let mut request = request();
+        Signer::new("test-shared-secret", key())
+            .components(&["date", "@authority", "content-type"])
+            .sign_at(&mut request, 1618884473)
+            .unwrap();
+        assert_eq!(
+            request.header("Signature-Input"),
+            Some(
+                "sig1=(\"date\" \"@authority\" \"content-type\");created=1618884473;keyid=\"test-shared-secret\""
+            )
+        );
+        assert_eq!(
+            request.header("Signature"),
+            Some("sig1=:38moGOb0PERixkEQrHxi7Z7frcHEN0IhxZI0xUU1PEs=:")
+        );
+
+        // The RFC example covers no route, so only @authority can be required.
+        let verifier = Verifier::new(StaticKeys::new().with_key("test-shared-secret", key()))
+            .require(&["@authority"]);
+        let verified = verifier.verify_at(&request, 1618884480).unwrap();
+        assert_eq!(verified.label, "sig1");
+        assert_eq!(verified.key_id, "test-shared-secret");
+
+        assert_eq!(
+            Verifier::new(StaticKeys::new().with_key("test-shared-secret", key()))
+                .verify_at(&request, 1618884480),
+            Err(SignatureError::NotCovered("@method".to_string()))
+        );
+        request.headers.set("Content-Type", "text/plain");
+        assert_eq!(
+            verifier.verify_at(&request, 1618884480),
+            Err(SignatureError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_rejects_expired_and_unknown_signatures() {
+        let mut request = request();
+        Signer::new("k1", key())
+            .expires_in(Duration::from_secs(300))
+            .sign_at(&mut request, 1_000_000)
+            .unwrap();
+        let verifier = Verifier::new(StaticKeys::new().with_key("k1", key()));
+        assert!(verifier.verify_at(&request, 1_000_100).is_ok());
+        assert_eq!(
+            verifier.verify_at(&request, 1_001_000),
+            Err(SignatureError::Expired)
+        );
+
+        let other = Verifier::new(StaticKeys::new().with_key("k2", key()));
+        assert_eq!(
+            other.verify_at(&request, 1_000_100),
+            Err(SignatureError::UnknownKey("k1".to_string()))
+        );
+        assert_eq!(
+            other.verify_at(&self::request(), 1_000_100),
+            Err(SignatureError::Missing)
+        );
+    }
+
+    #[test]
+    fn test_far_future_timestamps_do_not_overflow() {
+        let mut request = request();
+        Signer::new("k1", key())
+            .expires_in(Duration::from_secs(u64::MAX))
+            .sign_at(&mut request, u64::MAX - 10)
+            .unwrap();
+        let verifier = Verifier::new(StaticKeys::new().with_key("k1", key()))
+            .max_age(Duration::from_secs(u64::MAX));
+        assert!(verifier.verify_at(&request, u64::MAX - 5).is_ok());
+        assert_eq!(
+            verifier.verify_at(&request, 1_000_000),
+            Err(SignatureError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_repeated_fields_are_signed_whole() {
+        let mut request = request();
+        request.headers.insert("X-Tag", "a,b ");
+        request.headers.insert("X-Tag", " c");
+        let base = signature_base(&request, &["x-tag".to_string()], "()").unwrap();
+        assert_eq!(base, "\"x-tag\": a,b, c\n\"@signature-params\": ()");
+    }
+}
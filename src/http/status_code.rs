@@ -2,10 +2,14 @@ use std::fmt::Display;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StatusCode {
+    SwitchingProtocols = 101,
+
     OK = 200,
     Created = 201,
     Accepted = 202,
     NoContent = 204,
+    PartialContent = 206,
+    MultiStatus = 207,
 
     MovedPermanently = 301,
     Found = 302,
@@ -20,6 +24,7 @@ pub enum StatusCode {
     NotFound = 404,
     MethodNotAllowed = 405,
     NotAcceptable = 406,
+    RequestTimeout = 408,
     Conflict = 409,
     Gone = 410,
     PreconditionFailed = 412,
@@ -27,7 +32,10 @@ pub enum StatusCode {
     UriTooLong = 414,
     UnsupportedMediaType = 415,
     RangeNotSatisfiable = 416,
+    UnprocessableContent = 422,
+    Locked = 423,
     UpgradeRequired = 426,
+    RequestHeaderFieldsTooLarge = 431,
 
     InternalServerError = 500,
     NotImplemented = 501,
@@ -35,15 +43,20 @@ pub enum StatusCode {
     ServiceUnavailable = 503,
     GatewayTimeout = 504,
     HttpVersionNotSupported = 505,
+    InsufficientStorage = 507,
 }
 
 impl StatusCode {
     pub fn reason_parse(&self) -> &'static str {
         match self {
+            StatusCode::SwitchingProtocols => "Switching Protocols",
+
             StatusCode::OK => "OK",
             StatusCode::Created => "Created",
             StatusCode::Accepted => "Accepted",
             StatusCode::NoContent => "No Content",
+            StatusCode::PartialContent => "Partial Content",
+            StatusCode::MultiStatus => "Multi-Status",
 
             StatusCode::MovedPermanently => "Moved Permanently",
             StatusCode::Found => "Found",
@@ -58,6 +71,7 @@ impl StatusCode {
             StatusCode::NotFound => "Not Found",
             StatusCode::MethodNotAllowed => "Method Not Allowed",
             StatusCode::NotAcceptable => "Not Acceptable",
+            StatusCode::RequestTimeout => "Request Timeout",
             StatusCode::Conflict => "Conflict",
             StatusCode::Gone => "Gone",
             StatusCode::PreconditionFailed => "Precondition Failed",
@@ -65,7 +79,10 @@ impl StatusCode {
             StatusCode::UriTooLong => "URI Too Long",
             StatusCode::UnsupportedMediaType => "Unsupported Media Type",
             StatusCode::RangeNotSatisfiable => "Range Not Satisfiable",
+            StatusCode::UnprocessableContent => "Unprocessable Content",
+            StatusCode::Locked => "Locked",
             StatusCode::UpgradeRequired => "Upgrade Required",
+            StatusCode::RequestHeaderFieldsTooLarge => "Request Header Fields Too Large",
 
             StatusCode::InternalServerError => "Internal Server Error",
             StatusCode::NotImplemented => "Not Implemented",
@@ -73,6 +90,97 @@ impl StatusCode {
             StatusCode::ServiceUnavailable => "Service Unavailable",
             StatusCode::GatewayTimeout => "Gateway Timeout",
             StatusCode::HttpVersionNotSupported => "HTTP Version Not Supported",
+            StatusCode::InsufficientStorage => "Insufficient Storage",
+        }
+    }
+
+    pub fn from_u16(code: u16) -> Option<Self> {
+        match code {
+            101 => Some(StatusCode::SwitchingProtocols),
+            200 => Some(StatusCode::OK),
+            201 => Some(StatusCode::Created),
+            202 => Some(StatusCode::Accepted),
+            204 => Some(StatusCode::NoContent),
+            206 => Some(StatusCode::PartialContent),
+            207 => Some(StatusCode::MultiStatus),
+            301 => Some(StatusCode::MovedPermanently),
+            302 => Some(StatusCode::Found),
+            303 => Some(StatusCode::SeeOther),
+            304 => Some(StatusCode::NotModified),
+            307 => Some(StatusCode::TemporaryRedirect),
+            308 => Some(StatusCode::PermanentRedirect),
+            400 => Some(StatusCode::BadRequest),
+            401 => Some(StatusCode::Unauthorized),
+            403 => Some(StatusCode::Forbidden),
+            404 => Some(StatusCode::NotFound),
+            405 => Some(StatusCode::MethodNotAllowed),
+            406 => Some(StatusCode::NotAcceptable),
+            408 => Some(StatusCode::RequestTimeout),
+            409 => Some(StatusCode::Conflict),
+            410 => Some(StatusCode::Gone),
+            412 => Some(StatusCode::PreconditionFailed),
+            413 => Some(StatusCode::ContentTooLarge),
+            414 => Some(StatusCode::UriTooLong),
+            415 => Some(StatusCode::UnsupportedMediaType),
+            416 => Some(StatusCode::RangeNotSatisfiable),
+            422 => Some(StatusCode::UnprocessableContent),
+            423 => Some(StatusCode::Locked),
+            426 => Some(StatusCode::UpgradeRequired),
+            431 => Some(StatusCode::RequestHeaderFieldsTooLarge),
+            500 => Some(StatusCode::InternalServerError),
+            501 => Some(StatusCode::NotImplemented),
+            502 => Some(StatusCode::BadGateway),
+            503 => Some(StatusCode::ServiceUnavailable),
+            504 => Some(StatusCode::GatewayTimeout),
+            505 => Some(StatusCode::HttpVersionNotSupported),
+            507 => Some(StatusCode::InsufficientStorage),
+            _ => None,
+        }
+    }
+
+    // Preserialized so the response writer never formats the status line.
+    pub const fn status_line(&self) -> &'static [u8] {
+        match self {
+            StatusCode::SwitchingProtocols => b"HTTP/1.1 101 Switching Protocols\r\n",
+            StatusCode::OK => b"HTTP/1.1 200 OK\r\n",
+            StatusCode::Created => b"HTTP/1.1 201 Created\r\n",
+            StatusCode::Accepted => b"HTTP/1.1 202 Accepted\r\n",
+            StatusCode::NoContent => b"HTTP/1.1 204 No Content\r\n",
+            StatusCode::PartialContent => b"HTTP/1.1 206 Partial Content\r\n",
+            StatusCode::MultiStatus => b"HTTP/1.1 207 Multi-Status\r\n",
+            StatusCode::MovedPermanently => b"HTTP/1.1 301 Moved Permanently\r\n",
+            StatusCode::Found => b"HTTP/1.1 302 Found\r\n",
+            StatusCode::SeeOther => b"HTTP/1.1 303 See Other\r\n",
+            StatusCode::NotModified => b"HTTP/1.1 304 Not Modified\r\n",
+            StatusCode::TemporaryRedirect => b"HTTP/1.1 307 Temporary Redirect\r\n",
+            StatusCode::PermanentRedirect => b"HTTP/1.1 308 Permanent Redirect\r\n",
+            StatusCode::BadRequest => b"HTTP/1.1 400 Bad Request\r\n",
+            StatusCode::Unauthorized => b"HTTP/1.1 401 Unauthorized\r\n",
+            StatusCode::Forbidden => b"HTTP/1.1 403 Forbidden\r\n",
+            StatusCode::NotFound => b"HTTP/1.1 404 Not Found\r\n",
+            StatusCode::MethodNotAllowed => b"HTTP/1.1 405 Method Not Allowed\r\n",
+            StatusCode::NotAcceptable => b"HTTP/1.1 406 Not Acceptable\r\n",
+            StatusCode::RequestTimeout => b"HTTP/1.1 408 Request Timeout\r\n",
+            StatusCode::Conflict => b"HTTP/1.1 409 Conflict\r\n",
+            StatusCode::Gone => b"HTTP/1.1 410 Gone\r\n",
+            StatusCode::PreconditionFailed => b"HTTP/1.1 412 Precondition Failed\r\n",
+            StatusCode::ContentTooLarge => b"HTTP/1.1 413 Content Too Large\r\n",
+            StatusCode::UriTooLong => b"HTTP/1.1 414 URI Too Long\r\n",
+            StatusCode::UnsupportedMediaType => b"HTTP/1.1 415 Unsupported Media Type\r\n",
+            StatusCode::RangeNotSatisfiable => b"HTTP/1.1 416 Range Not Satisfiable\r\n",
+            StatusCode::UnprocessableContent => b"HTTP/1.1 422 Unprocessable Content\r\n",
+            StatusCode::Locked => b"HTTP/1.1 423 Locked\r\n",
+            StatusCode::UpgradeRequired => b"HTTP/1.1 426 Upgrade Required\r\n",
+            StatusCode::RequestHeaderFieldsTooLarge => {
+                b"HTTP/1.1 431 Request Header Fields Too Large\r\n"
+            }
+            StatusCode::InternalServerError => b"HTTP/1.1 500 Internal Server Error\r\n",
+            StatusCode::NotImplemented => b"HTTP/1.1 501 Not Implemented\r\n",
+            StatusCode::BadGateway => b"HTTP/1.1 502 Bad Gateway\r\n",
+            StatusCode::ServiceUnavailable => b"HTTP/1.1 503 Service Unavailable\r\n",
+            StatusCode::GatewayTimeout => b"HTTP/1.1 504 Gateway Timeout\r\n",
+            StatusCode::HttpVersionNotSupported => b"HTTP/1.1 505 HTTP Version Not Supported\r\n",
+            StatusCode::InsufficientStorage => b"HTTP/1.1 507 Insufficient Storage\r\n",
         }
     }
 
@@ -80,6 +188,10 @@ impl StatusCode {
         *self as u16
     }
 
+    pub fn is_informational(&self) -> bool {
+        matches!(self.as_u16(), 100..=199)
+    }
+
     pub fn is_success(&self) -> bool {
         matches!(self.as_u16(), 200..=299)
     }
@@ -102,3 +214,20 @@ impl Display for StatusCode {
         write!(f, "{} {}", self.as_u16(), self.reason_parse())
     }
 }
+
+// Serialized as the bare number, e.g. 404.
+#[cfg(feature = "serde")]
+impl serde::Serialize for StatusCode {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.as_u16())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for StatusCode {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = <u16 as serde::Deserialize>::deserialize(deserializer)?;
+        StatusCode::from_u16(code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown status code {}", code)))
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Debug;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+
+use crate::io::{CopyOptions, CopyStats, copy_bidirectional};
+
+pub struct TakenStream {
+    reader: BufReader<TcpStream>,
+}
+
+impl TakenStream {
+    pub fn new(reader: BufReader<TcpStream>) -> Self {
+        TakenStream { reader }
+    }
+
+    pub fn buffered(&self) -> &[u8] {
+        self.reader.buffer()
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        self.reader.get_ref()
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.reader.get_ref().peer_addr()
+    }
+
+    // Returns bytes the server had already read past the request head, followed by
+    // the raw socket; callers that split the stream must replay the buffer first.
+    pub fn into_parts(self) -> (Vec<u8>, TcpStream) {
+        let buffered = self.reader.buffer().to_vec();
+        (buffered, self.reader.into_inner())
+    }
+
+    // Tunnels the connection to `upstream`, replaying read-ahead bytes first.
+    pub fn splice(self, mut upstream: TcpStream, options: &CopyOptions) -> io::Result<CopyStats> {
+        let (buffered, client) = self.into_parts();
+        upstream.write_all(&buffered)?;
+
+        let mut stats = copy_bidirectional(&client, &upstream, options)?;
+        stats.a_to_b += buffered.len() as u64;
+        Ok(stats)
+    }
+}
+
+impl Read for TakenStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl BufRead for TakenStream {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.reader.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.reader.consume(amt)
+    }
+}
+
+impl Write for TakenStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.reader.get_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.reader.get_mut().flush()
+    }
+}
+
+// The mutex only exists to make responses Sync, so async servers can hold one
+// across an await; the closure is never shared.
+pub struct Takeover(Mutex<Box<dyn FnOnce(TakenStream) + Send>>);
+
+impl Takeover {
+    pub fn new(f: impl FnOnce(TakenStream) + Send + 'static) -> Self {
+        Takeover(Mutex::new(Box::new(f)))
+    }
+
+    pub fn run(self, stream: TakenStream) {
+        let f = self.0.into_inner().unwrap_or_else(|e| e.into_inner());
+        f(stream)
+    }
+}
+
+impl Debug for Takeover {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Takeover")
+    }
+}
@@ -0,0 +1,778 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, BufReader, ErrorKind},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    base64,
+    http::{ConnectionContext, Headers, Method, ParseError, Request, Response},
+    logging::log_error,
+    server::{Handler, ServerConfig, Transport, answer, finalize_response},
+};
+
+use super::{
+    frame::{self, ErrorCode, Frame},
+    hpack::{self, Decoder, HpackError},
+};
+
+pub const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+const MAX_CONCURRENT_STREAMS: u32 = 32;
+// Handlers run side by side for requests that arrive together.
+const MAX_PARALLEL: usize = 8;
+const SWITCHING_PROTOCOLS: &[u8] =
+    b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+
+// Connection-specific fields, which HTTP/2 requests must not carry (RFC 9113 8.2.2).
+const CONNECTION_SPECIFIC: [&str; 5] = [
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+// RFC 9113 8.2.1. Fields are written into a text head for the parser, so a CR or
+// LF that got through would start a header line of its own.
+fn valid_field(name: &str, value: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_graphic() && b != b':' && !b.is_ascii_uppercase())
+        && !value.bytes().any(|b| matches!(b, b'\0' | b'\r' | b'\n'))
+        && !value.starts_with([' ', '\t'])
+        && !value.ends_with([' ', '\t'])
+}
+
+// True when the first bytes read could be the client preface. "PRI" is no method
+// an HTTP/1.1 request would use.
+pub(crate) fn is_preface_start(buf: &[u8]) -> bool {
+    buf.len() >= 3 && PREFACE.starts_with(&buf[..buf.len().min(PREFACE.len())])
+}
+
+// An HTTP/1.1 request asking to continue over cleartext HTTP/2 (RFC 7540 3.2).
+pub(crate) fn is_h2c_upgrade(request: &Request) -> bool {
+    request.wants_upgrade("h2c") && request.header("HTTP2-Settings").is_some()
+}
+
+// How a connection ends, short of an I/O error.
+enum Stop {
+    // The peer closed the connection or went quiet.
+    Closed,
+    Io(io::Error),
+    // Connection error: send GOAWAY with this code, then close.
+    Error(ErrorCode),
+}
+
+impl From<io::Error> for Stop {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::UnexpectedEof => Stop::Closed,
+            _ => Stop::Io(e),
+        }
+    }
+}
+
+fn protocol_error<T>() -> Result<T, Stop> {
+    Err(Stop::Error(ErrorCode::Protocol))
+}
+
+// A stream whose request is still arriving.
+struct Incoming {
+    fields: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+struct Connection<'a, T: Transport> {
+    reader: &'a mut BufReader<T>,
+    handler: &'a dyn Handler,
+    config: &'a ServerConfig,
+    context: &'a mut ConnectionContext,
+    decoder: Decoder,
+    open: HashMap<u32, Incoming>,
+    // Complete requests, dispatched in the order they finished arriving.
+    ready: VecDeque<(u32, Result<Request, ParseError>)>,
+    // Answered before the client finished sending, so they end with RST_STREAM.
+    cut_short: HashSet<u32>,
+    // Send windows of streams that have not been answered yet.
+    windows: HashMap<u32, i64>,
+    // A header block awaiting CONTINUATION: stream, fragments, END_STREAM.
+    continuing: Option<(u32, Vec<u8>, bool)>,
+    last_stream: u32,
+    send_window: i64,
+    peer_initial_window: i64,
+    peer_max_frame: usize,
+    goaway: bool,
+}
+
+// Serves HTTP/2 on a connection whose first bytes are the client preface, either
+// negotiated through ALPN or sent with prior knowledge.
+pub(crate) fn serve<T: Transport>(
+    reader: &mut BufReader<T>,
+    handler: &dyn Handler,
+    config: &ServerConfig,
+    context: &mut ConnectionContext,
+    closed: &AtomicBool,
+) -> io::Result<()> {
+    Connection::new(reader, handler, config, context).run(None, closed)
+}
+
+// Answers an h2c upgrade with 101, then serves HTTP/2; the upgrading request
+// becomes stream 1.
+pub(crate) fn upgrade<T: Transport>(
+    reader: &mut BufReader<T>,
+    handler: &dyn Handler,
+    config: &ServerConfig,
+    context: &mut ConnectionContext,
+    closed: &AtomicBool,
+    request: Request,
+) -> io::Result<()> {
+    reader.get_mut().write_all(SWITCHING_PROTOCOLS)?;
+    let mut connection = Connection::new(reader, handler, config, context);
+    // The header carries the client's SETTINGS payload in base64url.
+    let settings = request
+        .header("HTTP2-Settings")
+        .map(|value| value.trim().replace('-', "+").replace('_', "/"))
+        .and_then(|value| base64::decode(&value))
+        .and_then(|payload| Frame::setting_pairs(&payload));
+    let Some(settings) = settings else {
+        return connection.finish(Stop::Error(ErrorCode::Protocol));
+    };
+    if let Err(stop) = connection.apply_settings(&settings) {
+        return connection.finish(stop);
+    }
+    connection.run(Some(request), closed)
+}
+
+impl<'a, T: Transport> Connection<'a, T> {
+    fn new(
+        reader: &'a mut BufReader<T>,
+        handler: &'a dyn Handler,
+        config: &'a ServerConfig,
+        context: &'a mut ConnectionContext,
+    ) -> Self {
+        Connection {
+            reader,
+            handler,
+            config,
+            context,
+            decoder: Decoder::default(),
+            open: HashMap::new(),
+            ready: VecDeque::new(),
+            cut_short: HashSet::new(),
+            windows: HashMap::new(),
+            continuing: None,
+            last_stream: 0,
+            send_window: frame::DEFAULT_WINDOW,
+            peer_initial_window: frame::DEFAULT_WINDOW,
+            peer_max_frame: frame::DEFAULT_MAX_FRAME_SIZE,
+            goaway: false,
+        }
+    }
+
+    fn run(mut self, upgraded: Option<Request>, closed: &AtomicBool) -> io::Result<()> {
+        let result = self.start(upgraded).and_then(|()| {
+            loop {
+                // Frames that have already arrived are taken in first, so requests
+                // sent together are handled together.
+                while self.frame_buffered() {
+                    let frame = self.read_frame()?;
+                    self.process(frame)?;
+                }
+                self.respond_ready()?;
+                if closed.load(Ordering::SeqCst) {
+                    break Err(Stop::Error(ErrorCode::NoError));
+                }
+                if self.goaway && self.open.is_empty() {
+                    break Ok(());
+                }
+                let frame = self.read_frame()?;
+                self.process(frame)?;
+            }
+        });
+        match result {
+            Ok(()) => Ok(()),
+            Err(stop) => self.finish(stop),
+        }
+    }
+
+    fn start(&mut self, upgraded: Option<Request>) -> Result<(), Stop> {
+        let max_header_list = self.config.limits.max_header_bytes as u32;
+        self.write(Frame::settings(&[
+            (
+                frame::SETTINGS_MAX_CONCURRENT_STREAMS,
+                MAX_CONCURRENT_STREAMS,
+            ),
+            (frame::SETTINGS_MAX_HEADER_LIST_SIZE, max_header_list),
+        ]))?;
+
+        let mut preface = [0; 24];
+        io::Read::read_exact(self.reader, &mut preface)?;
+        if preface != PREFACE {
+            return protocol_error();
+        }
+        self.reader
+            .get_ref()
+            .tcp()
+            .set_read_timeout(Some(self.config.keep_alive.idle_timeout))?;
+
+        if let Some(request) = upgraded {
+            self.last_stream = 1;
+            self.windows.insert(1, self.peer_initial_window);
+            self.ready.push_back((1, Ok(request)));
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, stop: Stop) -> io::Result<()> {
+        match stop {
+            Stop::Closed => Ok(()),
+            Stop::Io(e) => Err(e),
+            Stop::Error(code) => {
+                // Best effort: the peer may already be gone.
+                let _ = self.write(Frame::goaway(self.last_stream, code));
+                self.reader.get_mut().finish();
+                Ok(())
+            }
+        }
+    }
+
+    fn write(&mut self, frame: Frame) -> io::Result<()> {
+        frame.write(self.reader.get_mut())
+    }
+
+    // Whether a whole frame is buffered, so reading it will not block.
+    fn frame_buffered(&self) -> bool {
+        let buf = self.reader.buffer();
+        buf.len() >= 9 && buf.len() >= 9 + u32::from_be_bytes([0, buf[0], buf[1], buf[2]]) as usize
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, Stop> {
+        match Frame::read(self.reader, frame::DEFAULT_MAX_FRAME_SIZE)? {
+            None => Err(Stop::Closed),
+            Some(Ok(frame)) => Ok(frame),
+            Some(Err(_)) => Err(Stop::Error(ErrorCode::FrameSize)),
+        }
+    }
+
+    fn process(&mut self, frame: Frame) -> Result<(), Stop> {
+        if let Some((id, ..)) = &self.continuing
+            && (frame.kind != frame::CONTINUATION || frame.stream != *id)
+        {
+            return protocol_error();
+        }
+        match frame.kind {
+            frame::DATA => self.on_data(frame),
+            frame::HEADERS => self.on_headers(frame),
+            frame::CONTINUATION => {
+                let Some((id, mut block, end_stream)) = self.continuing.take() else {
+                    return protocol_error();
+                };
+                block.extend_from_slice(&frame.payload);
+                if frame.has(frame::END_HEADERS) {
+                    return self.on_header_block(id, block, end_stream);
+                }
+                // Header blocks are bounded like HTTP/1.1 heads, with room for the
+                // encoding overhead.
+                if block.len() > self.config.limits.max_header_bytes * 2 {
+                    return Err(Stop::Error(ErrorCode::EnhanceYourCalm));
+                }
+                self.continuing = Some((id, block, end_stream));
+                Ok(())
+            }
+            frame::PRIORITY => match (frame.stream, frame.payload.len()) {
+                (0, _) => protocol_error(),
+                (_, 5) => Ok(()),
+                _ => Err(Stop::Error(ErrorCode::FrameSize)),
+            },
+            frame::RST_STREAM => {
+                if frame.stream == 0 || frame.stream > self.last_stream {
+                    return protocol_error();
+                }
+                if frame.payload.len() != 4 {
+                    return Err(Stop::Error(ErrorCode::FrameSize));
+                }
+                self.close_stream(frame.stream);
+                Ok(())
+            }
+            frame::SETTINGS => {
+                if frame.stream != 0 {
+                    return protocol_error();
+                }
+                if frame.has(frame::ACK) {
+                    return match frame.payload.is_empty() {
+                        true => Ok(()),
+                        false => Err(Stop::Error(ErrorCode::FrameSize)),
+                    };
+                }
+                let settings = Frame::setting_pairs(&frame.payload)
+                    .ok_or(Stop::Error(ErrorCode::FrameSize))?;
+                self.apply_settings(&settings)?;
+                Ok(self.write(Frame::new(frame::SETTINGS, frame::ACK, 0, Vec::new()))?)
+            }
+            frame::PING => {
+                if frame.stream != 0 {
+                    return protocol_error();
+                }
+                if frame.payload.len() != 8 {
+                    return Err(Stop::Error(ErrorCode::FrameSize));
+                }
+                if !frame.has(frame::ACK) {
+                    self.write(Frame::new(frame::PING, frame::ACK, 0, frame.payload))?;
+                }
+                Ok(())
+            }
+            frame::GOAWAY => {
+                self.goaway = true;
+                Ok(())
+            }
+            frame::WINDOW_UPDATE => self.on_window_update(frame),
+            // Clients cannot push.
+            frame::PUSH_PROMISE => protocol_error(),
+            // Unknown frame types are ignored.
+            _ => Ok(()),
+        }
+    }
+
+    fn apply_settings(&mut self, settings: &[(u16, u32)]) -> Result<(), Stop> {
+        for &(id, value) in settings {
+            match id {
+                frame::SETTINGS_ENABLE_PUSH if value > 1 => return protocol_error(),
+                frame::SETTINGS_INITIAL_WINDOW_SIZE => {
+                    let value = value as i64;
+                    if value > frame::MAX_WINDOW {
+                        return Err(Stop::Error(ErrorCode::FlowControl));
+                    }
+                    let delta = value - self.peer_initial_window;
+                    for window in self.windows.values_mut() {
+                        *window += delta;
+                        if *window > frame::MAX_WINDOW {
+                            return Err(Stop::Error(ErrorCode::FlowControl));
+                        }
+                    }
+                    self.peer_initial_window = value;
+                }
+                frame::SETTINGS_MAX_FRAME_SIZE => {
+                    if !(frame::DEFAULT_MAX_FRAME_SIZE as u32..1 << 24).contains(&value) {
+                        return protocol_error();
+                    }
+                    self.peer_max_frame = value as usize;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn on_window_update(&mut self, frame: Frame) -> Result<(), Stop> {
+        let Ok(bytes) = <[u8; 4]>::try_from(&frame.payload[..]) else {
+            return Err(Stop::Error(ErrorCode::FrameSize));
+        };
+        let increment = (u32::from_be_bytes(bytes) & 0x7fff_ffff) as i64;
+        if frame.stream == 0 {
+            if increment == 0 {
+                return protocol_error();
+            }
+            self.send_window += increment;
+            if self.send_window > frame::MAX_WINDOW {
+                return Err(Stop::Error(ErrorCode::FlowControl));
+            }
+            return Ok(());
+        }
+        let Some(window) = self.windows.get_mut(&frame.stream) else {
+            return Ok(());
+        };
+        *window += increment;
+        if increment == 0 || *window > frame::MAX_WINDOW {
+            let code = match increment {
+                0 => ErrorCode::Protocol,
+                _ => ErrorCode::FlowControl,
+            };
+            self.close_stream(frame.stream);
+            self.write(Frame::rst_stream(frame.stream, code))?;
+        }
+        Ok(())
+    }
+
+    fn close_stream(&mut self, id: u32) {
+        self.open.remove(&id);
+        self.windows.remove(&id);
+        self.cut_short.remove(&id);
+        self.ready.retain(|(ready, _)| *ready != id);
+    }
+
+    fn on_headers(&mut self, frame: Frame) -> Result<(), Stop> {
+        if frame.stream == 0 || frame.stream.is_multiple_of(2) {
+            return protocol_error();
+        }
+        let Some(block) = frame::unpad(&frame) else {
+            return protocol_error();
+        };
+        let (id, block, end_stream) = (frame.stream, block.to_vec(), frame.has(frame::END_STREAM));
+        if frame.has(frame::END_HEADERS) {
+            self.on_header_block(id, block, end_stream)
+        } else {
+            self.continuing = Some((id, block, end_stream));
+            Ok(())
+        }
+    }
+
+    fn on_header_block(&mut self, id: u32, block: Vec<u8>, end_stream: bool) -> Result<(), Stop> {
+        let max_list = self.config.limits.max_header_bytes;
+        let fields = match self.decoder.decode(&block, max_list) {
+            Ok(fields) => Ok(fields),
+            Err(HpackError::HeaderListTooLarge { .. }) => Err(ParseError::HeaderTooLarge),
+            Err(_) => return Err(Stop::Error(ErrorCode::Compression)),
+        };
+
+        if id <= self.last_stream {
+            // Trailers end a request still in progress; their fields are dropped.
+            // Anything else on a used stream id is an error.
+            return match self.open.remove(&id) {
+                Some(incoming) if end_stream => self.complete(id, incoming),
+                Some(_) => protocol_error(),
+                None if self.windows.contains_key(&id) => protocol_error(),
+                None => Ok(()),
+            };
+        }
+        self.last_stream = id;
+        if self.open.len() + self.ready.len() >= MAX_CONCURRENT_STREAMS as usize {
+            return Ok(self.write(Frame::rst_stream(id, ErrorCode::RefusedStream))?);
+        }
+        self.windows.insert(id, self.peer_initial_window);
+
+        match fields {
+            Err(e) => {
+                if !end_stream {
+                    self.cut_short.insert(id);
+                }
+                self.ready.push_back((id, Err(e)));
+                Ok(())
+            }
+            Ok(fields) => {
+                let incoming = Incoming {
+                    fields,
+                    body: Vec::new(),
+                };
+                if end_stream {
+                    return self.complete(id, incoming);
+                }
+                self.open.insert(id, incoming);
+                Ok(())
+            }
+        }
+    }
+
+    fn on_data(&mut self, frame: Frame) -> Result<(), Stop> {
+        if frame.stream == 0 {
+            return protocol_error();
+        }
+        let Some(data) = frame::unpad(&frame) else {
+            return protocol_error();
+        };
+        let id = frame.stream;
+        let end_stream = frame.has(frame::END_STREAM);
+        // Padding counts against flow control too. Windows are topped up as soon
+        // as data arrives; the body size limit bounds a request, and the buffer
+        // budget all requests on the connection together.
+        let consumed = frame.payload.len() as u32;
+        if consumed > 0 {
+            self.write(Frame::window_update(0, consumed))?;
+        }
+
+        let Some(incoming) = self.open.get_mut(&id) else {
+            if id > self.last_stream {
+                return protocol_error();
+            }
+            return Ok(());
+        };
+        incoming.body.extend_from_slice(data);
+        let limit = self.config.limits.max_body_bytes;
+        if let Some(limit) = limit.filter(|&limit| incoming.body.len() > limit) {
+            self.open.remove(&id);
+            if !end_stream {
+                self.cut_short.insert(id);
+            }
+            self.ready
+                .push_back((id, Err(ParseError::BodyTooLarge { limit })));
+            return Ok(());
+        }
+        // Nothing was dispatched, so the client may retry the stream later.
+        if let Some(budget) = limit.map(|limit| limit.saturating_mul(2))
+            && self.buffered() > budget
+        {
+            self.close_stream(id);
+            return Ok(self.write(Frame::rst_stream(id, ErrorCode::RefusedStream))?);
+        }
+        if end_stream {
+            let incoming = self.open.remove(&id).expect("stream is open");
+            return self.complete(id, incoming);
+        }
+        if consumed > 0 {
+            self.write(Frame::window_update(id, consumed))?;
+        }
+        Ok(())
+    }
+
+    // Request body bytes held for streams not yet dispatched.
+    fn buffered(&self) -> usize {
+        let arriving: usize = self.open.values().map(|incoming| incoming.body.len()).sum();
+        let waiting: usize = self
+            .ready
+            .iter()
+            .filter_map(|(_, request)| request.as_ref().ok())
+            .map(|request| request.body().len())
+            .sum();
+        arriving + waiting
+    }
+
+    // A request has fully arrived; queue it, or reset the stream if it is malformed.
+    fn complete(&mut self, id: u32, incoming: Incoming) -> Result<(), Stop> {
+        match self.build_request(incoming) {
+            Some(request) => {
+                self.context.begin_request();
+                self.ready.push_back((id, request));
+            }
+            None => {
+                self.close_stream(id);
+                self.write(Frame::rst_stream(id, ErrorCode::Protocol))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Rebuilds an HTTP/1.1 style head from the decoded fields so requests go
+    // through the same parser and checks as on any other connection. None for
+    // requests RFC 9113 8.1.1 calls malformed.
+    fn build_request(&self, incoming: Incoming) -> Option<Result<Request, ParseError>> {
+        let (mut method, mut scheme, mut path, mut authority) = (None, None, None, None);
+        let mut headers = Vec::new();
+        let mut cookies = Vec::new();
+        for (name, value) in incoming.fields {
+            if let Some(pseudo) = name.strip_prefix(':') {
+                let slot = match pseudo {
+                    "method" => &mut method,
+                    "scheme" => &mut scheme,
+                    "path" => &mut path,
+                    "authority" => &mut authority,
+                    _ => return None,
+                };
+                // Pseudo-headers come first, once each. Their values end up in
+                // the request line, where whitespace would split them.
+                if !headers.is_empty()
+                    || !cookies.is_empty()
+                    || slot.is_some()
+                    || !value.bytes().all(|b| b.is_ascii_graphic())
+                {
+                    return None;
+                }
+                *slot = Some(value);
+                continue;
+            }
+            if !valid_field(&name, &value)
+                || CONNECTION_SPECIFIC.contains(&name.as_str())
+                || (name == "te" && value != "trailers")
+            {
+                return None;
+            }
+            // Cookies may arrive split into several fields (RFC 9113 8.2.3).
+            match name.as_str() {
+                "cookie" => cookies.push(value),
+                _ => headers.push((name, value)),
+            }
+        }
+
+        let (method, path) = (method?, path.filter(|p| !p.is_empty())?);
+        if scheme.is_none() || method == "CONNECT" {
+            return None;
+        }
+        let mut head = format!("{} {} HTTP/1.1\r\n", method, path);
+        if let Some(authority) = authority
+            && !headers.iter().any(|(name, _)| name == "host")
+        {
+            head.push_str(&format!("host: {}\r\n", authority));
+        }
+        if !cookies.is_empty() {
+            head.push_str(&format!("cookie: {}\r\n", cookies.join("; ")));
+        }
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+
+        let parsed = Request::from_parts_with(&head, incoming.body, &self.config.parse_options());
+        Some(parsed.map(|mut request| {
+            request.requestline.httpversion = "HTTP/2".to_string();
+            request
+        }))
+    }
+
+    // Answers the ready requests, up to MAX_PARALLEL at a time on threads of their
+    // own; each response is sent as soon as its handler returns, so a slow one
+    // holds up only itself.
+    fn respond_ready(&mut self) -> Result<(), Stop> {
+        while !self.ready.is_empty() {
+            let count = self.ready.len().min(MAX_PARALLEL);
+            let mut batch: Vec<_> = self
+                .ready
+                .drain(..count)
+                .map(|(id, request)| {
+                    let request = request.map(|mut request| {
+                        request.extensions_mut().insert(self.context.shared_info());
+                        request
+                    });
+                    (id, request)
+                })
+                .collect();
+            let (handler, config) = (self.handler, self.config);
+            if let [(id, _)] = batch[..] {
+                let (_, request) = batch.pop().expect("batch of one");
+                self.deliver(id, prepare(handler, config, request))?;
+                continue;
+            }
+            thread::scope(|scope| {
+                let (done, finished) = mpsc::channel();
+                for (id, request) in batch {
+                    let done = done.clone();
+                    scope.spawn(move || {
+                        let _ = done.send((id, prepare(handler, config, request)));
+                    });
+                }
+                drop(done);
+                finished
+                    .into_iter()
+                    .try_for_each(|(id, prepared)| self.deliver(id, prepared))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn deliver(&mut self, id: u32, prepared: Prepared) -> Result<(), Stop> {
+        let Prepared {
+            mut response,
+            is_head,
+        } = prepared;
+        // The client may have reset the stream while its handler ran.
+        if !self.windows.contains_key(&id) {
+            return Ok(());
+        }
+        if response.abort.is_some() {
+            self.close_stream(id);
+            return Ok(self.write(Frame::rst_stream(id, ErrorCode::Internal))?);
+        }
+
+        response.headers.strip_hop_by_hop();
+        let status = response.status_code().as_u16().to_string();
+        let mut fields = vec![(":status".to_string(), status)];
+        for (name, value) in response.headers.iter() {
+            if Headers::validate(name, value).is_ok() {
+                fields.push((name.to_ascii_lowercase(), value.to_string()));
+            }
+        }
+        let block = hpack::encode(fields.iter().map(|(n, v)| (n.as_str(), v.as_str())));
+        let body = match is_head {
+            true => &[][..],
+            false => response.body.as_bytes(),
+        };
+        self.send_headers(id, block, body.is_empty())?;
+        self.send_data(id, body)?;
+        if self.cut_short.remove(&id) && self.windows.contains_key(&id) {
+            self.write(Frame::rst_stream(id, ErrorCode::NoError))?;
+        }
+        self.windows.remove(&id);
+        Ok(self.reader.get_mut().flush()?)
+    }
+
+    fn send_headers(&mut self, id: u32, block: Vec<u8>, end_stream: bool) -> io::Result<()> {
+        let mut chunks = block.chunks(self.peer_max_frame).peekable();
+        let mut kind = frame::HEADERS;
+        let mut flags = if end_stream { frame::END_STREAM } else { 0 };
+        // An empty block still needs its HEADERS frame.
+        let first: &[u8] = chunks.next().unwrap_or(&[]);
+        let mut chunk = Some(first);
+        while let Some(payload) = chunk {
+            if chunks.peek().is_none() {
+                flags |= frame::END_HEADERS;
+            }
+            self.write(Frame::new(kind, flags, id, payload.to_vec()))?;
+            (kind, flags) = (frame::CONTINUATION, 0);
+            chunk = chunks.next();
+        }
+        Ok(())
+    }
+
+    // Sends the body as the peer's windows allow, reading frames while it waits
+    // for WINDOW_UPDATE. Stops early if the stream is reset meanwhile.
+    fn send_data(&mut self, id: u32, body: &[u8]) -> Result<(), Stop> {
+        let mut offset = 0;
+        while offset < body.len() {
+            let Some(&stream_window) = self.windows.get(&id) else {
+                return Ok(());
+            };
+            let available = self
+                .send_window
+                .min(stream_window)
+                .min(self.peer_max_frame as i64);
+            if available <= 0 {
+                self.reader.get_mut().flush()?;
+                let frame = self.read_frame()?;
+                self.process(frame)?;
+                continue;
+            }
+            let len = (available as usize).min(body.len() - offset);
+            let end = offset + len == body.len();
+            let flags = if end { frame::END_STREAM } else { 0 };
+            let payload = body[offset..offset + len].to_vec();
+            self.write(Frame::new(frame::DATA, flags, id, payload))?;
+            self.send_window -= len as i64;
+            if let Some(window) = self.windows.get_mut(&id) {
+                *window -= len as i64;
+            }
+            offset += len;
+        }
+        Ok(())
+    }
+}
+
+// A response ready to go out on its stream.
+struct Prepared {
+    response: Response,
+    is_head: bool,
+}
+
+// Everything about answering a stream that needs no access to the connection.
+fn prepare(
+    handler: &dyn Handler,
+    config: &ServerConfig,
+    request: Result<Request, ParseError>,
+) -> Prepared {
+    let started = Instant::now();
+    let (response, request) = match request {
+        Ok(mut request) => {
+            let response = answer(&mut request, handler, config);
+            (response, Some(request))
+        }
+        Err(e) => (handler.handle_bad_request(&e), None),
+    };
+    let is_head = request
+        .as_ref()
+        .is_some_and(|r| r.method() == &Method::HEAD);
+    let (mut response, _) =
+        finalize_response(response, request.as_ref(), config.length_mismatch, false);
+    if response.take_takeover().is_some() {
+        log_error!("Connection takeover is not supported over HTTP/2");
+        response = Response::internal_server_error();
+    }
+    if response.take_duplex().is_some() {
+        log_error!("Duplex bodies are not supported over HTTP/2");
+        response = Response::internal_server_error();
+    }
+    if let Some(request) = &request {
+        config.observe(request, &response, started.elapsed());
+    }
+    Prepared { response, is_head }
+}
@@ -0,0 +1,197 @@
+use std::io::{self, Read, Write};
+
+pub const DATA: u8 = 0x0;
+pub const HEADERS: u8 = 0x1;
+pub const PRIORITY: u8 = 0x2;
+pub const RST_STREAM: u8 = 0x3;
+pub const SETTINGS: u8 = 0x4;
+pub const PUSH_PROMISE: u8 = 0x5;
+pub const PING: u8 = 0x6;
+pub const GOAWAY: u8 = 0x7;
+pub const WINDOW_UPDATE: u8 = 0x8;
+pub const CONTINUATION: u8 = 0x9;
+
+pub const END_STREAM: u8 = 0x1;
+pub const ACK: u8 = 0x1;
+pub const END_HEADERS: u8 = 0x4;
+pub const PADDED: u8 = 0x8;
+pub const PRIORITY_FLAG: u8 = 0x20;
+
+pub const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+pub const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+pub const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+pub const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+pub const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+pub const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+pub const DEFAULT_WINDOW: i64 = 65_535;
+pub const MAX_WINDOW: i64 = (1 << 31) - 1;
+
+// RFC 9113 section 7.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    NoError = 0x0,
+    Protocol = 0x1,
+    Internal = 0x2,
+    FlowControl = 0x3,
+    StreamClosed = 0x5,
+    FrameSize = 0x6,
+    RefusedStream = 0x7,
+    Cancel = 0x8,
+    Compression = 0x9,
+    EnhanceYourCalm = 0xb,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub kind: u8,
+    pub flags: u8,
+    pub stream: u32,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(kind: u8, flags: u8, stream: u32, payload: Vec<u8>) -> Self {
+        Frame {
+            kind,
+            flags,
+            stream,
+            payload,
+        }
+    }
+
+    pub fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    // Ok(None) when the peer closed the connection between frames. A frame over
+    // `max_size` is read no further; the caller answers with FRAME_SIZE_ERROR.
+    pub fn read(
+        reader: &mut impl Read,
+        max_size: usize,
+    ) -> io::Result<Option<Result<Frame, usize>>> {
+        let mut head = [0; 9];
+        match reader.read(&mut head[..1])? {
+            0 => return Ok(None),
+            _ => reader.read_exact(&mut head[1..])?,
+        }
+        let len = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+        if len > max_size {
+            return Ok(Some(Err(len)));
+        }
+        let mut payload = vec![0; len];
+        reader.read_exact(&mut payload)?;
+        let stream = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff;
+        Ok(Some(Ok(Frame::new(head[3], head[4], stream, payload))))
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        // One write per frame, so small frames are not split across packets.
+        let mut buf = Vec::with_capacity(9 + self.payload.len());
+        buf.extend_from_slice(&(self.payload.len() as u32).to_be_bytes()[1..]);
+        buf.push(self.kind);
+        buf.push(self.flags);
+        buf.extend_from_slice(&self.stream.to_be_bytes());
+        buf.extend_from_slice(&self.payload);
+        writer.write_all(&buf)
+    }
+
+    pub fn settings(settings: &[(u16, u32)]) -> Self {
+        let mut payload = Vec::with_capacity(settings.len() * 6);
+        for (id, value) in settings {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        Frame::new(SETTINGS, 0, 0, payload)
+    }
+
+    pub fn window_update(stream: u32, increment: u32) -> Self {
+        Frame::new(WINDOW_UPDATE, 0, stream, increment.to_be_bytes().to_vec())
+    }
+
+    pub fn rst_stream(stream: u32, code: ErrorCode) -> Self {
+        Frame::new(RST_STREAM, 0, stream, (code as u32).to_be_bytes().to_vec())
+    }
+
+    pub fn goaway(last_stream: u32, code: ErrorCode) -> Self {
+        let mut payload = last_stream.to_be_bytes().to_vec();
+        payload.extend_from_slice(&(code as u32).to_be_bytes());
+        Frame::new(GOAWAY, 0, 0, payload)
+    }
+
+    // The (id, value) pairs of a SETTINGS payload; None if it is not a whole
+    // number of entries.
+    pub fn setting_pairs(payload: &[u8]) -> Option<Vec<(u16, u32)>> {
+        if !payload.len().is_multiple_of(6) {
+            return None;
+        }
+        Some(
+            payload
+                .chunks(6)
+                .map(|c| {
+                    (
+                        u16::from_be_bytes([c[0], c[1]]),
+                        u32::from_be_bytes([c[2], c[3], c[4], c[5]]),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+// Strips padding and priority fields from a DATA or HEADERS payload.
+pub fn unpad(frame: &Frame) -> Option<&[u8]> {
+    let mut payload = &frame.payload[..];
+    let mut pad = 0;
+    if frame.has(PADDED) {
+        let (&len, rest) = payload.split_first()?;
+        pad = len as usize;
+        payload = rest;
+    }
+    if frame.kind == HEADERS && frame.has(PRIORITY_FLAG) {
+        payload = payload.get(5..)?;
+    }
+    payload.get(..payload.len().checked_sub(pad)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames_round_trip() {
+        let mut wire = Vec::new();
+        Frame::settings(&[(SETTINGS_MAX_CONCURRENT_STREAMS, 100)])
+            .write(&mut wire)
+            .unwrap();
+        Frame::window_update(3, 1024).write(&mut wire).unwrap();
+
+        let mut reader = &wire[..];
+        let settings = Frame::read(&mut reader, 16_384).unwrap().unwrap().unwrap();
+        assert_eq!(settings.kind, SETTINGS);
+        assert_eq!(
+            Frame::setting_pairs(&settings.payload),
+            Some(vec![(SETTINGS_MAX_CONCURRENT_STREAMS, 100)])
+        );
+        let update = Frame::read(&mut reader, 16_384).unwrap().unwrap().unwrap();
+        assert_eq!((update.kind, update.stream), (WINDOW_UPDATE, 3));
+        assert!(Frame::read(&mut reader, 16_384).unwrap().is_none());
+
+        let mut big = Vec::new();
+        Frame::new(DATA, 0, 1, vec![0; 20]).write(&mut big).unwrap();
+        assert_eq!(Frame::read(&mut &big[..], 16).unwrap(), Some(Err(20)));
+    }
+
+    #[test]
+    fn test_unpads_payloads() {
+        let frame = Frame::new(HEADERS, PADDED | PRIORITY_FLAG, 1, {
+            let mut p = vec![2, 0, 0, 0, 0, 16];
+            p.extend_from_slice(b"block");
+            p.extend_from_slice(&[0, 0]);
+            p
+        });
+        assert_eq!(unpad(&frame), Some(&b"block"[..]));
+        assert_eq!(unpad(&Frame::new(DATA, PADDED, 1, vec![9, 1])), None);
+    }
+}
@@ -0,0 +1,401 @@
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use super::huffman;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum HpackError {
+    #[error("Header block ends mid-field")]
+    Truncated,
+
+    #[error("Header index {0} is out of range")]
+    InvalidIndex(usize),
+
+    #[error("Integer does not fit")]
+    IntegerOverflow,
+
+    #[error("Invalid Huffman-coded string")]
+    Huffman,
+
+    #[error("Header field is not valid UTF-8")]
+    InvalidUtf8,
+
+    #[error("Invalid dynamic table size update")]
+    TableSizeUpdate,
+
+    #[error("Header list exceeds {limit} bytes")]
+    HeaderListTooLarge { limit: usize },
+}
+
+// RFC 7541 Appendix A.
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+pub const DEFAULT_TABLE_SIZE: usize = 4096;
+
+// Per RFC 7541 4.1, each entry costs its name and value plus 32 bytes.
+fn entry_size(name: &str, value: &str) -> usize {
+    name.len() + value.len() + 32
+}
+
+// Decodes header blocks for one direction of a connection; the dynamic table
+// carries over from block to block, so blocks must be decoded in order.
+#[derive(Debug)]
+pub struct Decoder {
+    // Newest first, as indexed.
+    dynamic: VecDeque<(String, String)>,
+    size: usize,
+    max_size: usize,
+    // The SETTINGS_HEADER_TABLE_SIZE we advertised; the encoder may shrink the
+    // table below it but never grow past it.
+    limit: usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new(DEFAULT_TABLE_SIZE)
+    }
+}
+
+impl Decoder {
+    pub fn new(limit: usize) -> Self {
+        Decoder {
+            dynamic: VecDeque::new(),
+            size: 0,
+            max_size: limit,
+            limit,
+        }
+    }
+
+    // After any error but HeaderListTooLarge the table is out of step with the
+    // peer's, and the connection has to be dropped.
+    pub fn decode(
+        &mut self,
+        mut block: &[u8],
+        max_list_size: usize,
+    ) -> Result<Vec<(String, String)>, HpackError> {
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+        while let Some(&first) = block.first() {
+            let (name, value) = if first & 0x80 != 0 {
+                let index = decode_int(&mut block, 7)?;
+                self.get(index)?
+            } else if first & 0x40 != 0 {
+                let (name, value) = self.literal(&mut block, 6)?;
+                self.insert(name.clone(), value.clone());
+                (name, value)
+            } else if first & 0x20 != 0 {
+                // Size updates may only open a block.
+                let size = decode_int(&mut block, 5)?;
+                if list_size > 0 || size > self.limit {
+                    return Err(HpackError::TableSizeUpdate);
+                }
+                self.max_size = size;
+                self.evict(0);
+                continue;
+            } else {
+                // Literal without indexing or never indexed; both 4-bit prefixes.
+                self.literal(&mut block, 4)?
+            };
+            // An oversized list is still decoded to the end, since the dynamic
+            // table must stay in step with the encoder's.
+            list_size += entry_size(&name, &value);
+            if list_size <= max_list_size {
+                fields.push((name, value));
+            }
+        }
+        if list_size > max_list_size {
+            return Err(HpackError::HeaderListTooLarge {
+                limit: max_list_size,
+            });
+        }
+        Ok(fields)
+    }
+
+    fn get(&self, index: usize) -> Result<(String, String), HpackError> {
+        let entry = match index {
+            0 => None,
+            1..=61 => STATIC_TABLE
+                .get(index - 1)
+                .map(|(n, v)| (n.to_string(), v.to_string())),
+            _ => self.dynamic.get(index - 62).cloned(),
+        };
+        entry.ok_or(HpackError::InvalidIndex(index))
+    }
+
+    fn literal(&self, block: &mut &[u8], prefix: u8) -> Result<(String, String), HpackError> {
+        let index = decode_int(block, prefix)?;
+        let name = match index {
+            0 => decode_string(block)?,
+            index => self.get(index)?.0,
+        };
+        Ok((name, decode_string(block)?))
+    }
+
+    fn insert(&mut self, name: String, value: String) {
+        let size = entry_size(&name, &value);
+        self.evict(size);
+        // An entry larger than the whole table just empties it.
+        if size <= self.max_size {
+            self.size += size;
+            self.dynamic.push_front((name, value));
+        }
+    }
+
+    // Drops the oldest entries until `incoming` more bytes fit.
+    fn evict(&mut self, incoming: usize) {
+        while self.size + incoming > self.max_size {
+            let Some((name, value)) = self.dynamic.pop_back() else {
+                break;
+            };
+            self.size -= entry_size(&name, &value);
+        }
+    }
+}
+
+// Encodes a header block without touching the dynamic table, so no state has to
+// be kept in step with the peer: fields in the static table are indexed, the rest
+// sent as literals, Huffman-coded when that is shorter.
+pub fn encode<'a>(fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in fields {
+        if let Some(i) = STATIC_TABLE.iter().position(|&e| e == (name, value)) {
+            encode_int(&mut out, 0x80, 7, i + 1);
+            continue;
+        }
+        match STATIC_TABLE.iter().position(|&(n, _)| n == name) {
+            Some(i) => encode_int(&mut out, 0x00, 4, i + 1),
+            None => {
+                out.push(0x00);
+                encode_string(&mut out, name);
+            }
+        }
+        encode_string(&mut out, value);
+    }
+    out
+}
+
+fn decode_int(block: &mut &[u8], prefix: u8) -> Result<usize, HpackError> {
+    let (&first, rest) = block.split_first().ok_or(HpackError::Truncated)?;
+    *block = rest;
+    let mask = (1u16 << prefix) as usize - 1;
+    let mut value = first as usize & mask;
+    if value < mask {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        let (&byte, rest) = block.split_first().ok_or(HpackError::Truncated)?;
+        *block = rest;
+        if shift > 28 {
+            return Err(HpackError::IntegerOverflow);
+        }
+        value += ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+fn encode_int(out: &mut Vec<u8>, flags: u8, prefix: u8, mut value: usize) {
+    let mask = (1u16 << prefix) as usize - 1;
+    if value < mask {
+        out.push(flags | value as u8);
+        return;
+    }
+    out.push(flags | mask as u8);
+    value -= mask;
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn decode_string(block: &mut &[u8]) -> Result<String, HpackError> {
+    let huffman = block.first().ok_or(HpackError::Truncated)? & 0x80 != 0;
+    let len = decode_int(block, 7)?;
+    if block.len() < len {
+        return Err(HpackError::Truncated);
+    }
+    let (raw, rest) = block.split_at(len);
+    *block = rest;
+    let bytes = match huffman {
+        true => huffman::decode(raw).ok_or(HpackError::Huffman)?,
+        false => raw.to_vec(),
+    };
+    String::from_utf8(bytes).map_err(|_| HpackError::InvalidUtf8)
+}
+
+fn encode_string(out: &mut Vec<u8>, s: &str) {
+    let coded = huffman::encode(s.as_bytes());
+    if coded.len() < s.len() {
+        encode_int(out, 0x80, 7, coded.len());
+        out.extend_from_slice(&coded);
+    } else {
+        encode_int(out, 0x00, 7, s.len());
+        out.extend_from_slice(s.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_decodes_rfc_request_sequence_with_huffman() {
+        // RFC 7541 C.4: three requests sharing one dynamic table.
+        let mut decoder = Decoder::default();
+        let first = decoder
+            .decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"), 8192)
+            .unwrap();
+        assert_eq!(
+            first,
+            [
+                (":method".to_string(), "GET".to_string()),
+                (":scheme".to_string(), "http".to_string()),
+                (":path".to_string(), "/".to_string()),
+                (":authority".to_string(), "www.example.com".to_string()),
+            ]
+        );
+
+        let second = decoder
+            .decode(&hex("8286 84be 5886 a8eb 1064 9cbf"), 8192)
+            .unwrap();
+        assert_eq!(second[3].1, "www.example.com");
+        assert_eq!(
+            second[4],
+            ("cache-control".to_string(), "no-cache".to_string())
+        );
+
+        let third = decoder
+            .decode(
+                &hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf"),
+                8192,
+            )
+            .unwrap();
+        assert_eq!(third[1].1, "https");
+        assert_eq!(third[2].1, "/index.html");
+        assert_eq!(
+            third[4],
+            ("custom-key".to_string(), "custom-value".to_string())
+        );
+        assert_eq!(decoder.dynamic.len(), 3);
+        assert_eq!(decoder.size, 164);
+    }
+
+    #[test]
+    fn test_encoded_blocks_decode_back() {
+        let fields = [
+            (":status", "200"),
+            (":status", "201"),
+            ("content-type", "text/plain"),
+            ("x-request-id", "abc-123"),
+        ];
+        let block = encode(fields);
+        let decoded = Decoder::default().decode(&block, 8192).unwrap();
+        let decoded: Vec<(&str, &str)> = decoded
+            .iter()
+            .map(|(n, v)| (n.as_str(), v.as_str()))
+            .collect();
+        assert_eq!(decoded, fields);
+        assert_eq!(block[0], 0x88);
+    }
+
+    #[test]
+    fn test_rejects_bad_blocks() {
+        let mut decoder = Decoder::default();
+        assert_eq!(
+            decoder.decode(&[0x80], 8192),
+            Err(HpackError::InvalidIndex(0))
+        );
+        assert_eq!(
+            decoder.decode(&[0xbe], 8192),
+            Err(HpackError::InvalidIndex(62))
+        );
+        assert_eq!(
+            decoder.decode(&[0x04, 0x05], 8192),
+            Err(HpackError::Truncated)
+        );
+        // A size update past the advertised limit.
+        assert_eq!(
+            decoder.decode(&[0x3f, 0xe1, 0x3f], 8192),
+            Err(HpackError::TableSizeUpdate)
+        );
+        assert_eq!(
+            decoder.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff"), 64),
+            Err(HpackError::HeaderListTooLarge { limit: 64 })
+        );
+    }
+}
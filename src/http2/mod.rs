@@ -0,0 +1,11 @@
+// HTTP/2 (RFC 9113) for the threaded server: frames, HPACK header compression and
+// a connection loop that multiplexes streams onto the same handler HTTP/1.1 uses.
+// Enabled with Server::with_http2, over TLS through ALPN, or in cleartext through
+// prior knowledge or an h2c upgrade.
+mod connection;
+pub mod frame;
+pub mod hpack;
+mod huffman;
+
+pub use connection::PREFACE;
+pub(crate) use connection::{is_h2c_upgrade, is_preface_start, serve, upgrade};
@@ -0,0 +1,630 @@
+use std::io::{self, BufRead, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::http::scan;
+
+#[derive(Debug, Error)]
+pub enum LimitError {
+    #[error("Line exceeds {limit} bytes")]
+    LineTooLong { limit: usize },
+
+    #[error("Input exceeds {limit} bytes")]
+    LimitExceeded { limit: u64 },
+
+    #[error("Line is not terminated by CRLF")]
+    BareLineFeed,
+
+    #[error("IO error while reading")]
+    Io(#[from] io::Error),
+}
+
+impl From<LimitError> for io::Error {
+    fn from(e: LimitError) -> Self {
+        match e {
+            LimitError::Io(e) => e,
+            e => io::Error::new(ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+// Reads up to and including the next '\n' into `buf`, never buffering more than
+// `max` bytes of a single line. Returns 0 at EOF like BufRead::read_until.
+pub fn read_line_limited<R: BufRead + ?Sized>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> Result<usize, LimitError> {
+    read_line_before(reader, buf, max, None)
+}
+
+// Like read_line_limited, but fails with TimedOut once `deadline` passes, however
+// steadily bytes keep trickling in. The deadline is checked between reads, so a
+// single blocked read can overrun it by up to the socket's read timeout.
+pub fn read_line_before<R: BufRead + ?Sized>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+    deadline: Option<Instant>,
+) -> Result<usize, LimitError> {
+    let mut read = 0;
+
+    loop {
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(LimitError::Io(ErrorKind::TimedOut.into()));
+        }
+        let available = match reader.fill_buf() {
+            Ok(available) => available,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        if available.is_empty() {
+            return Ok(read);
+        }
+
+        let (done, used) = match scan::find_byte(b'\n', available) {
+            Some(i) => (true, i + 1),
+            None => (false, available.len()),
+        };
+        if read + used > max {
+            return Err(LimitError::LineTooLong { limit: max });
+        }
+
+        buf.extend_from_slice(&available[..used]);
+        reader.consume(used);
+        read += used;
+
+        if done {
+            return Ok(read);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LineReader<R: BufRead> {
+    inner: R,
+    max_line_length: usize,
+    require_crlf: bool,
+}
+
+impl<R: BufRead> LineReader<R> {
+    pub fn new(inner: R, max_line_length: usize) -> Self {
+        LineReader {
+            inner,
+            max_line_length,
+            require_crlf: false,
+        }
+    }
+
+    pub fn require_crlf(mut self, require: bool) -> Self {
+        self.require_crlf = require;
+        self
+    }
+
+    // Returns the next line without its terminator, or None at EOF. A final line
+    // without any terminator is returned as-is unless CRLF is required.
+    pub fn read_line(&mut self) -> Result<Option<Vec<u8>>, LimitError> {
+        let mut line = Vec::new();
+        if read_line_limited(&mut self.inner, &mut line, self.max_line_length)? == 0 {
+            return Ok(None);
+        }
+
+        match line.last() {
+            Some(b'\n') => {
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                } else if self.require_crlf {
+                    return Err(LimitError::BareLineFeed);
+                }
+            }
+            _ if self.require_crlf => return Err(LimitError::BareLineFeed),
+            _ => {}
+        }
+
+        Ok(Some(line))
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: BufRead> Iterator for LineReader<R> {
+    type Item = Result<Vec<u8>, LimitError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_line().transpose()
+    }
+}
+
+// Unlike Read::take, running past the limit is an error rather than a silent EOF,
+// so a peer sending too much is distinguishable from one that stopped in time.
+#[derive(Debug)]
+pub struct LimitedReader<R: BufRead> {
+    inner: R,
+    limit: u64,
+    remaining: u64,
+}
+
+impl<R: BufRead> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        LimitedReader {
+            inner,
+            limit,
+            remaining: limit,
+        }
+    }
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn check_exhausted(&mut self) -> io::Result<()> {
+        if self.remaining == 0 && !self.inner.fill_buf()?.is_empty() {
+            return Err(LimitError::LimitExceeded { limit: self.limit }.into());
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for LimitedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check_exhausted()?;
+        let available = self.inner.fill_buf()?;
+        let n = available.len().min(self.remaining as usize);
+        Ok(&available[..n])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.remaining as usize);
+        self.inner.consume(amt);
+        self.remaining -= amt as u64;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    // Every write reaches the underlying stream and is flushed immediately.
+    Always,
+    // Writes collect in a buffer that is handed on whenever it fills up.
+    #[default]
+    OnBufferFull,
+    // Nothing leaves the buffer until the caller flushes explicitly.
+    Manual,
+}
+
+const FLUSH_BUFFER_SIZE: usize = 8 * 1024; // 8KB
+
+// Write side counterpart of the readers above, used for streamed response bodies.
+// Dropping the writer discards anything still buffered, so finish with flush or
+// into_inner.
+#[derive(Debug)]
+pub struct FlushingWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,
+    capacity: usize,
+    policy: FlushPolicy,
+}
+
+impl<W: Write> FlushingWriter<W> {
+    pub fn new(inner: W, policy: FlushPolicy) -> Self {
+        Self::with_capacity(FLUSH_BUFFER_SIZE, inner, policy)
+    }
+
+    pub fn with_capacity(capacity: usize, inner: W, policy: FlushPolicy) -> Self {
+        FlushingWriter {
+            inner,
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            policy,
+        }
+    }
+
+    pub fn policy(&self) -> FlushPolicy {
+        self.policy
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn write_buffered(&mut self) -> io::Result<()> {
+        if !self.buf.is_empty() {
+            self.inner.write_all(&self.buf)?;
+            self.buf.clear();
+        }
+        Ok(())
+    }
+
+    pub fn into_inner(mut self) -> io::Result<W> {
+        self.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for FlushingWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        match self.policy {
+            FlushPolicy::Always => {
+                self.write_buffered()?;
+                self.inner.write_all(data)?;
+                self.inner.flush()?;
+            }
+            FlushPolicy::OnBufferFull => {
+                if self.buf.len() + data.len() > self.capacity {
+                    self.buf.extend_from_slice(data);
+                    self.write_buffered()?;
+                    self.inner.flush()?;
+                } else {
+                    self.buf.extend_from_slice(data);
+                }
+            }
+            FlushPolicy::Manual => self.buf.extend_from_slice(data),
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffered()?;
+        self.inner.flush()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyOptions {
+    pub buffer_size: usize,
+    // Ends the copy once no bytes have moved in either direction for this long.
+    pub idle_timeout: Option<Duration>,
+    // Caps throughput per direction; reads are paced rather than dropped.
+    pub bytes_per_second: Option<u64>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            buffer_size: 16 * 1024,
+            idle_timeout: None,
+            bytes_per_second: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CopyStats {
+    pub a_to_b: u64,
+    pub b_to_a: u64,
+    pub idle_timed_out: bool,
+}
+
+const IDLE_POLL: Duration = Duration::from_millis(250);
+
+// Milliseconds since `started` of the last byte moved, shared by both directions
+// so a busy download keeps an otherwise quiet upload alive.
+struct Activity {
+    started: Instant,
+    last: AtomicU64,
+}
+
+impl Activity {
+    fn touch(&self) {
+        let now = self.started.elapsed().as_millis() as u64;
+        self.last.fetch_max(now, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let last = Duration::from_millis(self.last.load(Ordering::Relaxed));
+        self.started.elapsed().saturating_sub(last)
+    }
+}
+
+// Copies from `reader` to `writer` until EOF, honouring the rate limit. A reader
+// that reports TimedOut or WouldBlock is treated as idle and given until the idle
+// timeout before the copy gives up with ErrorKind::TimedOut.
+pub fn copy_limited<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &CopyOptions,
+) -> io::Result<u64> {
+    let activity = Activity {
+        started: Instant::now(),
+        last: AtomicU64::new(0),
+    };
+    copy_tracked(reader, writer, options, &activity)
+}
+
+fn copy_tracked<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &CopyOptions,
+    activity: &Activity,
+) -> io::Result<u64> {
+    let mut buf = vec![0u8; options.buffer_size.max(1)];
+    let chunk = match options.bytes_per_second {
+        Some(rate) => buf.len().min(rate.max(1) as usize),
+        None => buf.len(),
+    };
+    let started = Instant::now();
+    let mut total = 0u64;
+
+    loop {
+        let n = match reader.read(&mut buf[..chunk]) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                match options.idle_timeout {
+                    Some(idle) if activity.idle_for() >= idle => {
+                        return Err(io::Error::new(ErrorKind::TimedOut, "copy went idle"));
+                    }
+                    Some(_) => continue,
+                    None => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buf[..n])?;
+        writer.flush()?;
+        total += n as u64;
+        activity.touch();
+
+        if let Some(rate) = options.bytes_per_second {
+            let due = Duration::from_secs_f64(total as f64 / rate.max(1) as f64);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+// Splices two sockets together until both directions reach EOF, forwarding each
+// half-close to the other side. Used for CONNECT tunnels, WebSocket pass-through
+// and proxying so none of them needs its own copy loop.
+pub fn copy_bidirectional(
+    a: &TcpStream,
+    b: &TcpStream,
+    options: &CopyOptions,
+) -> io::Result<CopyStats> {
+    if let Some(idle) = options.idle_timeout {
+        let poll = Some(idle.min(IDLE_POLL));
+        a.set_read_timeout(poll)?;
+        b.set_read_timeout(poll)?;
+    }
+
+    let activity = Activity {
+        started: Instant::now(),
+        last: AtomicU64::new(0),
+    };
+
+    let (a_to_b, b_to_a) = thread::scope(|scope| {
+        let forward = scope.spawn(|| copy_half(a, b, options, &activity));
+        let backward = copy_half(b, a, options, &activity);
+        let forward = forward
+            .join()
+            .unwrap_or_else(|_| Err((0, io::Error::other("copy thread panicked"))));
+        (forward, backward)
+    });
+
+    let mut stats = CopyStats::default();
+    for (result, count) in [(a_to_b, &mut stats.a_to_b), (b_to_a, &mut stats.b_to_a)] {
+        match result {
+            Ok(n) => *count = n,
+            Err((n, e)) if e.kind() == ErrorKind::TimedOut => {
+                *count = n;
+                stats.idle_timed_out = true;
+            }
+            Err((_, e)) => return Err(e),
+        }
+    }
+    Ok(stats)
+}
+
+fn copy_half(
+    mut from: &TcpStream,
+    mut to: &TcpStream,
+    options: &CopyOptions,
+    activity: &Activity,
+) -> Result<u64, (u64, io::Error)> {
+    let mut counted = Counted {
+        inner: &mut to,
+        count: 0,
+    };
+    let result = copy_tracked(&mut from, &mut counted, options, activity);
+    let count = counted.count;
+
+    match result {
+        Ok(n) => {
+            let _ = to.shutdown(Shutdown::Write);
+            Ok(n)
+        }
+        Err(e) => {
+            // Unblock the opposite direction, which may be parked in a read.
+            let _ = from.shutdown(Shutdown::Both);
+            let _ = to.shutdown(Shutdown::Both);
+            Err((count, e))
+        }
+    }
+}
+
+struct Counted<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for Counted<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Cursor};
+
+    #[test]
+    fn test_read_line_limited() {
+        let mut reader = Cursor::new(b"short\nmuch too long\n".to_vec());
+        let mut buf = Vec::new();
+
+        assert_eq!(read_line_limited(&mut reader, &mut buf, 8).unwrap(), 6);
+        assert_eq!(buf, b"short\n");
+
+        buf.clear();
+        assert!(matches!(
+            read_line_limited(&mut reader, &mut buf, 8),
+            Err(LimitError::LineTooLong { limit: 8 })
+        ));
+    }
+
+    #[test]
+    fn test_line_reader_crlf() {
+        let lines: Vec<Vec<u8>> = LineReader::new(Cursor::new(b"a\r\nb\nc".to_vec()), 16)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(lines, vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]);
+
+        let mut strict = LineReader::new(Cursor::new(b"a\r\nb\n".to_vec()), 16).require_crlf(true);
+        assert_eq!(strict.read_line().unwrap(), Some(b"a".to_vec()));
+        assert!(matches!(strict.read_line(), Err(LimitError::BareLineFeed)));
+    }
+
+    #[test]
+    fn test_line_reader_across_small_buffers() {
+        let inner = BufReader::with_capacity(2, Cursor::new(b"hello\r\nworld\r\n".to_vec()));
+        let mut reader = LineReader::new(inner, 7).require_crlf(true);
+
+        assert_eq!(reader.read_line().unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(reader.read_line().unwrap(), Some(b"world".to_vec()));
+        assert_eq!(reader.read_line().unwrap(), None);
+    }
+
+    #[test]
+    fn test_limited_reader() {
+        let mut exact = LimitedReader::new(Cursor::new(b"abcd".to_vec()), 4);
+        let mut out = Vec::new();
+        exact.read_to_end(&mut out).unwrap();
+        assert_eq!(out, b"abcd");
+
+        let mut over = LimitedReader::new(Cursor::new(b"abcdef".to_vec()), 4);
+        let mut out = Vec::new();
+        let err = over.read_to_end(&mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert_eq!(out, b"abcd");
+    }
+
+    #[test]
+    fn test_flushing_writer_policies() {
+        let mut manual = FlushingWriter::new(Vec::new(), FlushPolicy::Manual);
+        manual.write_all(b"head").unwrap();
+        manual.write_all(b"body").unwrap();
+        assert!(manual.get_ref().is_empty());
+        assert_eq!(manual.into_inner().unwrap(), b"headbody");
+
+        let mut small = FlushingWriter::with_capacity(4, Vec::new(), FlushPolicy::OnBufferFull);
+        small.write_all(b"abc").unwrap();
+        assert!(small.get_ref().is_empty());
+        small.write_all(b"de").unwrap();
+        assert_eq!(small.get_ref(), b"abcde");
+        assert_eq!(small.buffered(), 0);
+
+        let mut always = FlushingWriter::new(Vec::new(), FlushPolicy::Always);
+        always.write_all(b"x").unwrap();
+        assert_eq!(always.get_ref(), b"x");
+    }
+
+    #[test]
+    fn test_copy_limited_paces_reads() {
+        let options = CopyOptions {
+            buffer_size: 4,
+            bytes_per_second: Some(40),
+            ..CopyOptions::default()
+        };
+        let started = Instant::now();
+        let mut out = Vec::new();
+        let copied = copy_limited(&mut Cursor::new(vec![b'a'; 8]), &mut out, &options).unwrap();
+
+        assert_eq!(copied, 8);
+        assert_eq!(out, vec![b'a'; 8]);
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[test]
+    fn test_copy_bidirectional_forwards_half_close() {
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        let (server_side, _) = listener.accept().unwrap();
+        let mut upstream = TcpStream::connect(addr).unwrap();
+        let (proxy_side, _) = listener.accept().unwrap();
+
+        let tunnel = thread::spawn(move || {
+            copy_bidirectional(&server_side, &proxy_side, &CopyOptions::default())
+        });
+
+        client.write_all(b"ping").unwrap();
+        client.shutdown(Shutdown::Write).unwrap();
+        let mut received = Vec::new();
+        upstream.read_to_end(&mut received).unwrap();
+        assert_eq!(received, b"ping");
+
+        upstream.write_all(b"pong!").unwrap();
+        upstream.shutdown(Shutdown::Write).unwrap();
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).unwrap();
+        assert_eq!(reply, b"pong!");
+
+        let stats = tunnel.join().unwrap().unwrap();
+        assert_eq!(
+            stats,
+            CopyStats {
+                a_to_b: 4,
+                b_to_a: 5,
+                idle_timed_out: false
+            }
+        );
+    }
+}
@@ -1,2 +1,29 @@
+#[cfg(feature = "async")]
+pub mod async_server;
+pub mod base64;
+pub mod date;
+pub mod digest;
+pub mod handlers;
+#[cfg(unix)]
+pub mod handoff;
 pub mod http;
+pub mod http2;
+pub mod io;
+pub mod json;
+mod logging;
+pub mod metrics;
+pub mod middleware;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod pool;
+pub mod proxy_protocol;
+mod random;
 pub mod server;
+#[cfg(all(feature = "ctrl-c", unix))]
+pub mod signal;
+pub mod sse;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod websocket;
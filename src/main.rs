@@ -57,9 +57,21 @@ impl Handler for WebsiteHandler {
 }
 
 fn main() -> Result<()> {
+    // No other thread exists yet and nothing has opened a descriptor the
+    // predecessor's number could name.
+    #[cfg(unix)]
+    let inherited = unsafe { rawhttp::handoff::inherit() };
+
     println!("rawhttp Server");
 
     let server = Server::new("127.0.0.1:8080".to_string(), WebsiteHandler);
+    #[cfg(unix)]
+    let server = match inherited {
+        Some(inherited) => server.with_listener(inherited.listener),
+        None => server,
+    };
+    #[cfg(all(feature = "ctrl-c", unix))]
+    rawhttp::signal::shutdown_on_ctrl_c(server.shutdown_handle());
     server.run()?;
 
     Ok(())
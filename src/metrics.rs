@@ -0,0 +1,232 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use crate::{
+    http::{Body, Method, Request, Response},
+    server::Handler,
+};
+
+// Upper bounds in seconds, the Prometheus client defaults.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    // Per bucket, not cumulative; render() accumulates.
+    counts: [u64; BUCKETS.len()],
+    overflow: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        match BUCKETS.iter().position(|&bound| seconds <= bound) {
+            Some(i) => self.counts[i] += 1,
+            None => self.overflow += 1,
+        }
+        self.sum += seconds;
+    }
+
+    fn count(&self) -> u64 {
+        self.counts.iter().sum::<u64>() + self.overflow
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    requests: Mutex<BTreeMap<(&'static str, u16), u64>>,
+    latency: Mutex<Histogram>,
+    connections: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+// Request counters and a latency histogram in Prometheus text format. Cloning is
+// cheap and every clone records into the same metrics, so one can be handed to the
+// server builder and another mounted at /metrics.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Inner>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, request: &Request, response: &Response, elapsed: Duration) {
+        let key = (request.method().as_str(), response.status_code().as_u16());
+        *self
+            .inner
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(key)
+            .or_default() += 1;
+        self.inner
+            .latency
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .observe(elapsed.as_secs_f64());
+        self.inner
+            .bytes_in
+            .fetch_add(request.body().len() as u64, Ordering::Relaxed);
+        self.inner
+            .bytes_out
+            .fetch_add(response.body().len() as u64, Ordering::Relaxed);
+    }
+
+    pub fn connection_opened(&self) {
+        self.inner.connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.inner.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total Requests answered, by method and status.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, status), count) in self
+            .inner
+            .requests
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{}\",status=\"{}\"}} {}",
+                method, status, count
+            );
+        }
+
+        let latency = self.inner.latency.lock().unwrap_or_else(|e| e.into_inner());
+        out.push_str("# HELP http_request_duration_seconds Time to produce a response.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        let mut cumulative = 0;
+        for (bound, count) in BUCKETS.iter().zip(latency.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            latency.count()
+        );
+        let _ = writeln!(out, "http_request_duration_seconds_sum {}", latency.sum);
+        let _ = writeln!(
+            out,
+            "http_request_duration_seconds_count {}",
+            latency.count()
+        );
+        drop(latency);
+
+        let scalars = [
+            (
+                "http_connections_active",
+                "gauge",
+                "Open client connections.",
+                self.inner.connections.load(Ordering::Relaxed),
+            ),
+            (
+                "http_request_body_bytes_total",
+                "counter",
+                "Request body bytes received.",
+                self.inner.bytes_in.load(Ordering::Relaxed),
+            ),
+            (
+                "http_response_body_bytes_total",
+                "counter",
+                "Response body bytes sent.",
+                self.inner.bytes_out.load(Ordering::Relaxed),
+            ),
+        ];
+        for (name, kind, help, value) in scalars {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+
+    // Answers GET /metrics; None for any other path.
+    pub fn endpoint(&self, request: &Request) -> Option<Response> {
+        if request.path() != "/metrics" {
+            return None;
+        }
+        if !matches!(request.method(), Method::GET | Method::HEAD) {
+            return Some(Response::method_not_allowed().with_header("Allow", "GET, HEAD"));
+        }
+        Some(
+            Response::ok()
+                .with_header("Content-Type", "text/plain; version=0.0.4; charset=utf-8")
+                .with_header("Cache-Control", "no-store")
+                .with_body(Body::from(self.render())),
+        )
+    }
+}
+
+// Serves /metrics alone, e.g. on a separate admin listener. In front of an
+// application, chain it: metrics.clone().or(app).
+impl Handler for Metrics {
+    fn handle(&self, request: &Request) -> Response {
+        self.endpoint(request).unwrap_or_else(Response::not_found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(raw: &str) -> Request {
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_renders_counters_and_a_cumulative_histogram() {
+        let metrics = Metrics::new();
+        let post = request("POST /a HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc");
+        let get = request("GET /b HTTP/1.1\r\nHost: x\r\n\r\n");
+        let created = Response::new(crate::http::StatusCode::Created);
+        metrics.record(&post, &created, Duration::from_millis(3));
+        metrics.record(&get, &Response::not_found(), Duration::from_millis(30));
+        metrics.record(&get, &Response::not_found(), Duration::from_secs(20));
+        metrics.connection_opened();
+
+        let text = metrics.render();
+        let has = |line: &str| text.lines().any(|l| l == line);
+        assert!(has("http_requests_total{method=\"POST\",status=\"201\"} 1"));
+        assert!(has("http_requests_total{method=\"GET\",status=\"404\"} 2"));
+        assert!(has("http_request_duration_seconds_bucket{le=\"0.005\"} 1"));
+        assert!(has("http_request_duration_seconds_bucket{le=\"0.05\"} 2"));
+        assert!(has("http_request_duration_seconds_bucket{le=\"10\"} 2"));
+        assert!(has("http_request_duration_seconds_bucket{le=\"+Inf\"} 3"));
+        assert!(has("http_request_duration_seconds_count 3"));
+        assert!(has("http_connections_active 1"));
+        assert!(has("http_request_body_bytes_total 3"));
+
+        let response = metrics.handle(&request("GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert!(
+            response
+                .body()
+                .as_str()
+                .unwrap()
+                .contains("# TYPE http_requests_total counter")
+        );
+    }
+}
@@ -0,0 +1,394 @@
+use std::{fs, io, path::Path, str::FromStr, sync::Arc};
+
+use thiserror::Error;
+
+use crate::{
+    http::{Method, ParseError, Request, Response, StatusCode},
+    logging::log_warn,
+    server::Handler,
+};
+
+#[derive(Debug, Error)]
+pub enum AclError {
+    #[error("Failed to read ACL file: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("ACL line {line}: {reason}")]
+    Syntax { line: usize, reason: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclAction {
+    Allow,
+    Deny,
+    RequireAuth,
+    RequireTls,
+}
+
+impl FromStr for AclAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "allow" => Ok(AclAction::Allow),
+            "deny" => Ok(AclAction::Deny),
+            "require-auth" => Ok(AclAction::RequireAuth),
+            "require-tls" => Ok(AclAction::RequireTls),
+            _ => Err(format!("unknown action {:?}", s)),
+        }
+    }
+}
+
+// One rule; empty methods and a missing host match anything. Hosts match exactly
+// or, written as `*.example.com`, any subdomain. The port is ignored unless the
+// pattern names one. Path prefixes match whole segments, so `/admin` covers
+// `/admin/users` but not `/administrator`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AclRule {
+    pub action: AclAction,
+    pub methods: Vec<Method>,
+    pub host: Option<String>,
+    pub path_prefix: String,
+}
+
+impl AclRule {
+    pub fn new(action: AclAction) -> Self {
+        AclRule {
+            action,
+            methods: Vec::new(),
+            host: None,
+            path_prefix: "/".to_string(),
+        }
+    }
+
+    pub fn methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    pub fn host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into().to_ascii_lowercase());
+        self
+    }
+
+    pub fn path_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.path_prefix = prefix.into();
+        self
+    }
+
+    pub fn matches(&self, request: &Request) -> bool {
+        (self.methods.is_empty() || self.methods.contains(request.method()))
+            && self
+                .host
+                .as_deref()
+                .is_none_or(|host| host_matches(host, request))
+            && path_matches(&self.path_prefix, request.path())
+    }
+}
+
+fn host_matches(pattern: &str, request: &Request) -> bool {
+    let Some(host) = request.header("Host") else {
+        return false;
+    };
+    let host = host.trim().to_ascii_lowercase();
+    let host = match pattern.contains(':') {
+        true => host.as_str(),
+        false => host
+            .rsplit_once(':')
+            .map_or(host.as_str(), |(name, _)| name),
+    };
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+        None => host == pattern,
+    }
+}
+
+fn path_matches(prefix: &str, path: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path.strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+pub type Authenticator = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+pub struct AclConfig {
+    pub rules: Vec<AclRule>,
+    // Applied when no rule matches.
+    pub default: AclAction,
+    // Decides require-auth rules. Without one, nothing satisfies them: those
+    // requests are refused rather than let through on any credentials at all.
+    pub authenticate: Option<Authenticator>,
+    // Sent as WWW-Authenticate with 401 responses, e.g. `Basic realm="admin"`.
+    pub challenge: Option<String>,
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        AclConfig {
+            rules: Vec::new(),
+            default: AclAction::Allow,
+            authenticate: None,
+            challenge: None,
+        }
+    }
+}
+
+impl AclConfig {
+    // Parses one rule per line as `<action> <methods> <host> <path-prefix>`, with
+    // `*` for any method or host and '#' starting a comment:
+    //
+    //     deny         *         *                 /internal
+    //     require-tls  *         admin.example.com /
+    //     require-auth POST,PUT  *.example.com     /api
+    //     default      deny
+    pub fn parse(text: &str) -> Result<Self, AclError> {
+        let mut config = AclConfig::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let syntax = |reason: String| AclError::Syntax {
+                line: i + 1,
+                reason,
+            };
+            match fields.as_slice() {
+                [] => {}
+                ["default", action] => config.default = action.parse().map_err(syntax)?,
+                [action, methods, host, path] => {
+                    let mut rule = AclRule::new(action.parse().map_err(syntax)?);
+                    if *methods != "*" {
+                        let methods = methods
+                            .split(',')
+                            .map(|m| m.to_ascii_uppercase().parse::<Method>())
+                            .collect::<Result<Vec<_>, _>>()
+                            .map_err(|e| syntax(e.to_string()))?;
+                        rule = rule.methods(&methods);
+                    }
+                    if *host != "*" {
+                        rule = rule.host(*host);
+                    }
+                    if !path.starts_with('/') {
+                        return Err(syntax(format!(
+                            "path prefix {:?} must start with '/'",
+                            path
+                        )));
+                    }
+                    config.rules.push(rule.path_prefix(*path));
+                }
+                _ => {
+                    return Err(syntax(
+                        "expected <action> <methods> <host> <path>".to_string(),
+                    ));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AclError> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn rule(mut self, rule: AclRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn default_action(mut self, action: AclAction) -> Self {
+        self.default = action;
+        self
+    }
+
+    pub fn authenticate(mut self, f: impl Fn(&Request) -> bool + Send + Sync + 'static) -> Self {
+        self.authenticate = Some(Arc::new(f));
+        self
+    }
+
+    pub fn challenge(mut self, challenge: impl Into<String>) -> Self {
+        self.challenge = Some(challenge.into());
+        self
+    }
+
+    // The action of the first matching rule, in file order.
+    pub fn action_for(&self, request: &Request) -> AclAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(request))
+            .map_or(self.default, |rule| rule.action)
+    }
+}
+
+// Host, path and method access rules checked before the wrapped handler, so a
+// router behind it never sees refused requests.
+pub struct Acl<H: Handler> {
+    inner: H,
+    config: AclConfig,
+}
+
+impl<H: Handler> Acl<H> {
+    pub fn new(inner: H, config: AclConfig) -> Self {
+        if config.authenticate.is_none()
+            && config
+                .rules
+                .iter()
+                .any(|rule| rule.action == AclAction::RequireAuth)
+        {
+            log_warn!("ACL has require-auth rules but no authenticator; they refuse everyone");
+        }
+        Acl { inner, config }
+    }
+}
+
+impl<H: Handler> Handler for Acl<H> {
+    fn handle(&self, request: &Request) -> Response {
+        match self.config.action_for(request) {
+            AclAction::Allow => self.inner.handle(request),
+            AclAction::Deny => Response::problem(
+                StatusCode::Forbidden,
+                "Access denied",
+                "Access to this resource is not allowed",
+                "about:blank",
+            ),
+            AclAction::RequireTls if !request.connection().tls => Response::problem(
+                StatusCode::Forbidden,
+                "TLS required",
+                "This resource is only served over HTTPS",
+                "about:blank",
+            ),
+            AclAction::RequireAuth
+                if !self
+                    .config
+                    .authenticate
+                    .as_ref()
+                    .is_some_and(|authenticate| authenticate(request)) =>
+            {
+                let response = Response::problem(
+                    StatusCode::Unauthorized,
+                    "Authentication required",
+                    "This resource requires credentials",
+                    "about:blank",
+                );
+                match &self.config.challenge {
+                    Some(challenge) => response.with_header("WWW-Authenticate", challenge),
+                    None => response,
+                }
+            }
+            AclAction::RequireTls | AclAction::RequireAuth => self.inner.handle(request),
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+
+    fn request(method: &str, host: &str, path: &str, extra: &str) -> Request {
+        let raw = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\n{}\r\n",
+            method, path, host, extra
+        );
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_parses_rules_and_applies_the_first_match() {
+        let config = AclConfig::parse(
+            "# internal tools\n\
+             allow        GET       *                 /internal/health\n\
+             deny         *         *                 /internal\n\
+             require-tls  *         admin.example.com /\n\
+             require-auth post,PUT  *.example.com     /api  # writes\n\
+             default      allow\n",
+        )
+        .unwrap()
+        .challenge("Bearer")
+        .authenticate(|request| request.header("Authorization") == Some("Bearer t"));
+        let acl = Acl::new(handler_fn(|_| Response::ok()), config);
+        let status = |method, host, path, extra| {
+            acl.handle(&request(method, host, path, extra))
+                .status_code()
+        };
+
+        assert_eq!(status("GET", "x", "/internal/health", ""), StatusCode::OK);
+        assert_eq!(status("GET", "x", "/internal/x", ""), StatusCode::Forbidden);
+        assert_eq!(status("GET", "x", "/internals", ""), StatusCode::OK);
+        assert_eq!(
+            status("GET", "admin.example.com:8443", "/", ""),
+            StatusCode::Forbidden
+        );
+        assert_eq!(
+            status("POST", "api.example.com", "/api/items", ""),
+            StatusCode::Unauthorized
+        );
+        assert_eq!(
+            status(
+                "POST",
+                "api.example.com",
+                "/api/items",
+                "Authorization: Bearer t\r\n"
+            ),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("POST", "example.com", "/api/items", ""),
+            StatusCode::OK
+        );
+        assert_eq!(
+            status("GET", "api.example.com", "/api/items", ""),
+            StatusCode::OK
+        );
+
+        assert_eq!(
+            status(
+                "POST",
+                "api.example.com",
+                "/api/items",
+                "Authorization: Bearer forged\r\n"
+            ),
+            StatusCode::Unauthorized
+        );
+
+        let challenged = acl.handle(&request("PUT", "a.example.com", "/api", ""));
+        assert_eq!(challenged.headers().get("www-authenticate"), Some("Bearer"));
+    }
+
+    #[test]
+    fn test_require_auth_without_an_authenticator_refuses_everyone() {
+        let config = AclConfig::parse(
+            "require-auth * * /api
+",
+        )
+        .unwrap();
+        let acl = Acl::new(handler_fn(|_| Response::ok()), config);
+        let response = acl.handle(&request(
+            "GET",
+            "x",
+            "/api/items",
+            "Authorization: Bearer t\r\n",
+        ));
+        assert_eq!(response.status_code(), StatusCode::Unauthorized);
+        assert_eq!(
+            acl.handle(&request("GET", "x", "/public", ""))
+                .status_code(),
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn test_reports_syntax_errors_with_line_numbers() {
+        let Err(err) = AclConfig::parse("allow * * /\nblock * * /\n") else {
+            panic!("unknown action accepted");
+        };
+        assert_eq!(err.to_string(), "ACL line 2: unknown action \"block\"");
+        assert!(AclConfig::parse("deny * * admin").is_err());
+        assert!(AclConfig::parse("deny FETCH * /").is_err());
+        assert!(AclConfig::parse("deny *").is_err());
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    io,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Instant, SystemTime},
+};
+
+use crate::{
+    base64,
+    date::DateTime,
+    http::{ParseError, Request, Response},
+    json::Value,
+    logging::log_error,
+    server::Handler,
+};
+
+use super::rotation::{RotatingFile, Rotation};
+
+const REDACTED: &str = "[REDACTED]";
+
+#[derive(Debug, Clone)]
+pub struct AuditConfig {
+    pub path: PathBuf,
+    pub rotation: Rotation,
+    pub max_body_bytes: Option<usize>,
+    pub redact_headers: Vec<String>,
+}
+
+impl AuditConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditConfig {
+            path: path.into(),
+            rotation: Rotation::never(),
+            max_body_bytes: None,
+            redact_headers: [
+                "authorization",
+                "proxy-authorization",
+                "cookie",
+                "set-cookie",
+                "x-api-key",
+            ]
+            .iter()
+            .map(|h| h.to_string())
+            .collect(),
+        }
+    }
+
+    pub fn rotation(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn record_bodies(mut self, max_bytes: usize) -> Self {
+        self.max_body_bytes = Some(max_bytes);
+        self
+    }
+
+    pub fn redact(mut self, header: &str) -> Self {
+        self.redact_headers.push(header.to_lowercase());
+        self
+    }
+}
+
+pub struct Audit<H: Handler> {
+    inner: H,
+    config: AuditConfig,
+    file: Mutex<RotatingFile>,
+}
+
+impl<H: Handler> Audit<H> {
+    pub fn new(inner: H, config: AuditConfig) -> io::Result<Self> {
+        let file = RotatingFile::open(&config.path, config.rotation)?;
+        Ok(Audit {
+            inner,
+            config,
+            file: Mutex::new(file),
+        })
+    }
+
+    fn record(
+        &self,
+        request: &Request,
+        response: &Response,
+        started: SystemTime,
+        elapsed_ms: u128,
+    ) {
+        let mut headers: Vec<(&str, &str)> = request.headers.iter().collect();
+        headers.sort();
+        let headers = headers
+            .into_iter()
+            .map(|(name, value)| {
+                let value = if self.config.redact_headers.iter().any(|h| h == name) {
+                    REDACTED
+                } else {
+                    value
+                };
+                (name.to_string(), Value::from(value))
+            })
+            .collect();
+
+        let body = request.body_as_bytes();
+        let mut members = vec![
+            (
+                "ts".to_string(),
+                Value::from(DateTime::from_system_time(started).to_rfc3339()),
+            ),
+            ("method".to_string(), Value::from(request.method().as_str())),
+            ("target".to_string(), Value::from(request.target())),
+            ("version".to_string(), Value::from(request.http_version())),
+            ("headers".to_string(), Value::Object(headers)),
+            ("body_length".to_string(), Value::from(body.len() as u64)),
+        ];
+
+        if let Some(max) = self.config.max_body_bytes {
+            let captured = &body[..body.len().min(max)];
+            members.push(("body".to_string(), Value::from(base64::encode(captured))));
+            members.push((
+                "body_truncated".to_string(),
+                Value::Bool(captured.len() < body.len()),
+            ));
+        }
+
+        members.push((
+            "status".to_string(),
+            Value::from(response.status_code().as_u16() as u64),
+        ));
+        members.push((
+            "response_bytes".to_string(),
+            Value::from(response.body().len() as u64),
+        ));
+        members.push(("duration_ms".to_string(), Value::from(elapsed_ms as u64)));
+
+        let mut line = Value::Object(members).to_string();
+        line.push('\n');
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = file.write_record(line.as_bytes()) {
+            log_error!("Failed to write audit record: {}", e);
+        }
+    }
+}
+
+impl<H: Handler> Handler for Audit<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let started = SystemTime::now();
+        let timer = Instant::now();
+        let response = self.inner.handle(request);
+        self.record(request, &response, started, timer.elapsed().as_millis());
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{http::Body, json, testing::TempDir};
+
+    struct Echo;
+
+    impl Handler for Echo {
+        fn handle(&self, request: &Request) -> Response {
+            Response::ok().with_body(Body::Content(request.body_as_bytes().to_vec()))
+        }
+    }
+
+    #[test]
+    fn test_writes_redacted_jsonl_records() {
+        let dir = TempDir::new("audit");
+        let path = dir.join("audit.jsonl");
+
+        let audit = Audit::new(
+            Echo,
+            AuditConfig::new(&path).record_bodies(4).redact("X-Session"),
+        )
+        .unwrap();
+
+        let raw = "POST /login HTTP/1.1\r\nAuthorization: Bearer secret\r\n\
+                   X-Session: abc\r\nHost: example.com\r\nContent-Length: 6\r\n\r\n\x00\x01pass";
+        let request = Request::try_from(raw.as_bytes()).unwrap();
+        audit.handle(&request);
+        audit.handle(&request);
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let record = json::parse(lines[0]).unwrap();
+        let headers = record.get("headers").unwrap();
+        assert_eq!(record.get("method").and_then(Value::as_str), Some("POST"));
+        assert_eq!(record.get("target").and_then(Value::as_str), Some("/login"));
+        assert_eq!(
+            headers.get("authorization").and_then(Value::as_str),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            headers.get("x-session").and_then(Value::as_str),
+            Some(REDACTED)
+        );
+        assert_eq!(
+            headers.get("host").and_then(Value::as_str),
+            Some("example.com")
+        );
+        assert_eq!(record.get("body_length").and_then(Value::as_f64), Some(6.0));
+        assert_eq!(
+            record
+                .get("body")
+                .and_then(Value::as_str)
+                .and_then(base64::decode),
+            Some(b"\x00\x01pa".to_vec())
+        );
+        assert_eq!(record.get("body_truncated"), Some(&Value::Bool(true)));
+        assert_eq!(record.get("status").and_then(Value::as_f64), Some(200.0));
+    }
+}
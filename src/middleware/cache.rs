@@ -0,0 +1,781 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    http::{
+        CacheControl, Method, ParseError, Request, Response, ResponseRecord, StatusCode,
+        range::partial_response,
+    },
+    json::{self, Value},
+    server::Handler,
+};
+
+const DEFAULT_MAX_ENTRIES: usize = 1024;
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024; // 1MB
+
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    // Capacity of the default in-memory store; other stores bring their own.
+    pub max_entries: usize,
+    pub max_body_bytes: usize,
+    // Lifetime for responses that carry no max-age of their own; None leaves
+    // them uncached.
+    pub default_ttl: Option<Duration>,
+}
+
+impl CacheConfig {
+    pub fn new() -> Self {
+        CacheConfig {
+            max_entries: DEFAULT_MAX_ENTRIES,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            default_ttl: None,
+        }
+    }
+
+    pub fn max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    pub fn max_body_bytes(mut self, bytes: usize) -> Self {
+        self.max_body_bytes = bytes;
+        self
+    }
+
+    pub fn default_ttl(mut self, ttl: Duration) -> Self {
+        self.default_ttl = Some(ttl);
+        self
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CacheStats {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale_hits: AtomicU64,
+    stale_if_error_hits: AtomicU64,
+    revalidations: AtomicU64,
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    // Served past freshness under stale-while-revalidate.
+    pub fn stale_hits(&self) -> u64 {
+        self.stale_hits.load(Ordering::Relaxed)
+    }
+
+    // Served past freshness because the origin failed, under stale-if-error.
+    pub fn stale_if_error_hits(&self) -> u64 {
+        self.stale_if_error_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn revalidations(&self) -> u64 {
+        self.revalidations.load(Ordering::Relaxed)
+    }
+}
+
+// A stored response with the freshness parameters it was admitted with. Times are
+// wall-clock so entries keep their age across restarts in persistent stores.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub response: Response,
+    pub stored: SystemTime,
+    pub ttl: Duration,
+    pub stale_while_revalidate: Duration,
+    pub stale_if_error: Duration,
+    // The request headers the response's Vary names, lowercased, with the values
+    // they had (empty when absent). Only requests that agree on all of them get
+    // the entry.
+    pub vary: Vec<(String, String)>,
+}
+
+impl CacheEntry {
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.stored)
+            .unwrap_or_default()
+    }
+
+    // How long a store must keep the entry for it to remain servable at all.
+    pub fn retention(&self) -> Duration {
+        self.ttl + self.stale_while_revalidate.max(self.stale_if_error)
+    }
+
+    pub fn matches(&self, request: &Request) -> bool {
+        self.vary
+            .iter()
+            .all(|(name, value)| request.header(name).unwrap_or_default() == value)
+    }
+
+    // Self-describing JSON form shared by the stores that persist entries outside
+    // the process. The key travels with the entry so hashed file names or
+    // external keys can be checked against it.
+    pub(crate) fn encode(&self, key: &str) -> String {
+        let stored = self.stored.duration_since(UNIX_EPOCH).unwrap_or_default();
+        Value::Object(vec![
+            ("key".to_string(), Value::from(key)),
+            ("stored_ms".to_string(), millis(stored)),
+            ("ttl_ms".to_string(), millis(self.ttl)),
+            ("swr_ms".to_string(), millis(self.stale_while_revalidate)),
+            ("sie_ms".to_string(), millis(self.stale_if_error)),
+            (
+                "vary".to_string(),
+                Value::Object(
+                    self.vary
+                        .iter()
+                        .map(|(name, value)| (name.clone(), Value::from(value.as_str())))
+                        .collect(),
+                ),
+            ),
+            (
+                "response".to_string(),
+                ResponseRecord::from(&self.response).to_json(),
+            ),
+        ])
+        .to_string()
+    }
+
+    pub(crate) fn decode(input: &str) -> Option<(String, CacheEntry)> {
+        let value = json::parse(input).ok()?;
+        let duration = |field: &str| {
+            value
+                .get(field)
+                .and_then(Value::as_f64)
+                .filter(|n| *n >= 0.0)
+                .map(|n| Duration::from_millis(n as u64))
+        };
+
+        let key = value.get("key")?.as_str()?.to_string();
+        let response = ResponseRecord::from_json(value.get("response")?).ok()?;
+        let Value::Object(vary) = value.get("vary")? else {
+            return None;
+        };
+        let vary = vary
+            .iter()
+            .map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+            .collect::<Option<_>>()?;
+        let entry = CacheEntry {
+            response: response.into_response(),
+            stored: UNIX_EPOCH + duration("stored_ms")?,
+            ttl: duration("ttl_ms")?,
+            stale_while_revalidate: duration("swr_ms")?,
+            stale_if_error: duration("sie_ms")?,
+            vary,
+        };
+        Some((key, entry))
+    }
+}
+
+fn millis(duration: Duration) -> Value {
+    Value::from(duration.as_millis() as u64)
+}
+
+// Backing storage for Cache. Stores apply their own capacity limits on put.
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    fn remove(&self, key: &str);
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// Entry-count-capped LRU kept in process memory.
+pub struct MemoryStore {
+    max_entries: usize,
+    entries: Mutex<HashMap<String, (CacheEntry, u64)>>,
+    clock: AtomicU64,
+}
+
+impl MemoryStore {
+    pub fn new(max_entries: usize) -> Self {
+        MemoryStore {
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+            clock: AtomicU64::new(0),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl CacheStore for MemoryStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let (entry, last_used) = entries.get_mut(key)?;
+        *last_used = self.tick();
+        Some(entry.clone())
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if !entries.contains_key(key)
+            && entries.len() >= self.max_entries
+            && let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(k, _)| k.clone())
+        {
+            entries.remove(&oldest);
+        }
+        if self.max_entries > 0 {
+            entries.insert(key.to_string(), (entry, self.tick()));
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+
+    fn len(&self) -> usize {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+enum Lookup {
+    Fresh(Response, Duration),
+    Stale(Response, Duration, bool),
+    Expired(Option<(Response, Duration)>),
+    Miss,
+}
+
+struct Shared {
+    config: CacheConfig,
+    store: Box<dyn CacheStore>,
+    refreshing: Mutex<HashSet<String>>,
+    stats: CacheStats,
+}
+
+impl Shared {
+    fn lookup(&self, key: &str, request: &Request) -> Lookup {
+        // One variant is kept per key; a request that selects another misses,
+        // and its response replaces the stored one.
+        let Some(entry) = self.store.get(key).filter(|entry| entry.matches(request)) else {
+            return Lookup::Miss;
+        };
+
+        let age = entry.age();
+        if age < entry.ttl {
+            return Lookup::Fresh(entry.response, age);
+        }
+
+        let stale_for = age - entry.ttl;
+        if stale_for < entry.stale_while_revalidate {
+            let start_refresh = self
+                .refreshing
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .insert(key.to_string());
+            return Lookup::Stale(entry.response, age, start_refresh);
+        }
+
+        if stale_for < entry.stale_if_error {
+            Lookup::Expired(Some((entry.response, age)))
+        } else {
+            self.store.remove(key);
+            Lookup::Expired(None)
+        }
+    }
+
+    fn end_refresh(&self, key: &str) {
+        self.refreshing
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+
+    // Stores a cacheable response and, either way, ends a pending refresh.
+    fn store(&self, key: &str, request: &Request, response: &mut Response) {
+        self.end_refresh(key);
+
+        let cc = CacheControl::from_headers(response.headers());
+        // A response to an authenticated request is the user's own unless the
+        // origin says it may be shared (RFC 9111 3.5).
+        let shareable =
+            request.header("Authorization").is_none() || cc.public || cc.s_maxage.is_some();
+        let cacheable = response.status_code() == StatusCode::OK
+            && shareable
+            && !cc.no_store
+            && !cc.no_cache
+            && !cc.private
+            && !response.streams_body();
+        let Some(ttl) = cc.shared_max_age().or(self.config.default_ttl) else {
+            return;
+        };
+        if !cacheable {
+            return;
+        }
+        let Some(vary) = selected(response, request) else {
+            return;
+        };
+        let Ok(snapshot) = response.freeze(self.config.max_body_bytes) else {
+            return;
+        };
+
+        let (swr, sie) = if cc.must_revalidate {
+            (Duration::ZERO, Duration::ZERO)
+        } else {
+            (
+                cc.stale_while_revalidate.unwrap_or_default(),
+                cc.stale_if_error.unwrap_or_default(),
+            )
+        };
+        self.store.put(
+            key,
+            CacheEntry {
+                response: snapshot,
+                stored: SystemTime::now(),
+                ttl,
+                stale_while_revalidate: swr,
+                stale_if_error: sie,
+                vary,
+            },
+        );
+    }
+
+    fn invalidate(&self, key: &str) {
+        self.store.remove(key);
+    }
+}
+
+// Shared cache for GET and HEAD responses governed by Cache-Control, held in
+// memory unless another CacheStore is supplied.
+// Stale entries are served immediately while a background refresh runs when
+// stale-while-revalidate allows it, and kept as a fallback for origin errors
+// when stale-if-error does. Unsafe methods invalidate the target's entries.
+// Entries are keyed by method, Host and target, and only serve requests that
+// agree on the headers named in Vary. Responses to requests with Authorization
+// are stored only when marked public or given an s-maxage.
+pub struct Cache<H: Handler> {
+    inner: Arc<H>,
+    shared: Arc<Shared>,
+}
+
+impl<H: Handler + 'static> Cache<H> {
+    pub fn new(inner: H, config: CacheConfig) -> Self {
+        let store = MemoryStore::new(config.max_entries);
+        Self::with_store(inner, config, store)
+    }
+
+    pub fn with_store(inner: H, config: CacheConfig, store: impl CacheStore + 'static) -> Self {
+        Cache {
+            inner: Arc::new(inner),
+            shared: Arc::new(Shared {
+                config,
+                store: Box::new(store),
+                refreshing: Mutex::new(HashSet::new()),
+                stats: CacheStats::default(),
+            }),
+        }
+    }
+
+    pub fn stats(&self) -> &CacheStats {
+        &self.shared.stats
+    }
+
+    pub fn len(&self) -> usize {
+        self.shared.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn refresh(&self, key: String, request: &Request) {
+        let inner = self.inner.clone();
+        let shared = self.shared.clone();
+        let request = request.clone();
+
+        thread::spawn(move || {
+            let refreshing = Refreshing { shared, key };
+            let mut response = inner.handle(&request);
+            let shared = &refreshing.shared;
+            shared.stats.revalidations.fetch_add(1, Ordering::Relaxed);
+            shared.store(&refreshing.key, &request, &mut response);
+        });
+    }
+}
+
+// Ends a background refresh however it finishes, so a handler that panics does
+// not leave its key marked as refreshing for good.
+struct Refreshing {
+    shared: Arc<Shared>,
+    key: String,
+}
+
+impl Drop for Refreshing {
+    fn drop(&mut self) {
+        self.shared.end_refresh(&self.key);
+    }
+}
+
+// Virtual hosts share a target but not their responses.
+fn key(method: &Method, request: &Request) -> String {
+    let host = request.header("Host").unwrap_or_default();
+    format!(
+        "{} {} {}",
+        method.as_str(),
+        host.to_ascii_lowercase(),
+        request.target()
+    )
+}
+
+// The request header values the response's Vary names; None for "Vary: *", which
+// no later request can be shown to match.
+fn selected(response: &Response, request: &Request) -> Option<Vec<(String, String)>> {
+    let vary = response.headers().get("Vary").unwrap_or_default();
+    let mut selected = Vec::new();
+    for name in vary
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        if name == "*" {
+            return None;
+        }
+        let value = request.header(name).unwrap_or_default();
+        selected.push((name.to_ascii_lowercase(), value.to_string()));
+    }
+    Some(selected)
+}
+
+fn served(mut response: Response, age: Duration, status: &str) -> Response {
+    response.headers.set("Age", age.as_secs().to_string());
+    response.headers.set("X-Cache", status);
+    response
+}
+
+impl<H: Handler + 'static> Handler for Cache<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let stats = &self.shared.stats;
+
+        if !matches!(request.method(), Method::GET | Method::HEAD) {
+            self.shared.invalidate(&key(&Method::GET, request));
+            self.shared.invalidate(&key(&Method::HEAD, request));
+            return self.inner.handle(request);
+        }
+
+        let key = key(request.method(), request);
+        let request_cc = request
+            .header("Cache-Control")
+            .map(CacheControl::parse)
+            .unwrap_or_default();
+
+        // Ranges are cut from a stored full entity. Without one the Range goes to
+        // the origin as sent, rather than fetching the whole representation for a
+        // slice of it; 206s never enter the cache.
+        if request.method() == &Method::GET
+            && let Some(range) = request.header("Range")
+        {
+            let stored = match request_cc.no_store || request_cc.no_cache {
+                true => Lookup::Miss,
+                false => self.shared.lookup(&key, request),
+            };
+            let full = match stored {
+                Lookup::Fresh(response, age) => {
+                    stats.hits.fetch_add(1, Ordering::Relaxed);
+                    served(response, age, "HIT")
+                }
+                Lookup::Stale(response, age, start_refresh) => {
+                    stats.stale_hits.fetch_add(1, Ordering::Relaxed);
+                    if start_refresh {
+                        let mut full_request = request.clone();
+                        full_request.headers.remove("Range");
+                        full_request.headers.remove("If-Range");
+                        self.refresh(key, &full_request);
+                    }
+                    served(response, age, "STALE")
+                }
+                Lookup::Expired(_) | Lookup::Miss => {
+                    stats.misses.fetch_add(1, Ordering::Relaxed);
+                    let mut response = self.inner.handle(request);
+                    response.headers.set("X-Cache", "MISS");
+                    return response;
+                }
+            };
+            return partial_response(&full, range, request.header("If-Range")).unwrap_or(full);
+        }
+
+        let fallback = if request_cc.no_store || request_cc.no_cache {
+            None
+        } else {
+            match self.shared.lookup(&key, request) {
+                Lookup::Fresh(response, age) => {
+                    stats.hits.fetch_add(1, Ordering::Relaxed);
+                    return served(response, age, "HIT");
+                }
+                Lookup::Stale(response, age, start_refresh) => {
+                    stats.stale_hits.fetch_add(1, Ordering::Relaxed);
+                    if start_refresh {
+                        self.refresh(key, request);
+                    }
+                    return served(response, age, "STALE");
+                }
+                Lookup::Expired(fallback) => fallback,
+                Lookup::Miss => None,
+            }
+        };
+
+        stats.misses.fetch_add(1, Ordering::Relaxed);
+        let mut response = self.inner.handle(request);
+
+        if response.status_code().is_server_error()
+            && let Some((stale, age)) = fallback
+        {
+            stats.stale_if_error_hits.fetch_add(1, Ordering::Relaxed);
+            return served(stale, age, "STALE");
+        }
+
+        if !request_cc.no_store {
+            self.shared.store(&key, request, &mut response);
+        }
+        response.headers.set("X-Cache", "MISS");
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use crate::http::Body;
+    use std::{sync::atomic::AtomicUsize, time::Instant};
+
+    struct Counter {
+        calls: AtomicUsize,
+        cache_control: &'static str,
+        fail_after: usize,
+    }
+
+    impl Handler for Counter {
+        fn handle(&self, _request: &Request) -> Response {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            if n >= self.fail_after {
+                return Response::service_unavailable();
+            }
+            Response::ok()
+                .with_header("Cache-Control", self.cache_control)
+                .with_body(Body::from(format!("v{}", n)))
+        }
+    }
+
+    fn cache(cache_control: &'static str, fail_after: usize) -> Cache<Counter> {
+        Cache::new(
+            Counter {
+                calls: AtomicUsize::new(0),
+                cache_control,
+                fail_after,
+            },
+            CacheConfig::new(),
+        )
+    }
+
+    fn get(target: &str) -> Request {
+        let raw = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    fn body(response: &Response) -> &str {
+        response.body().as_str().unwrap()
+    }
+
+    #[test]
+    fn test_fresh_hits_and_invalidation() {
+        let cache = cache("max-age=60", usize::MAX);
+        assert_eq!(body(&cache.handle(&get("/a"))), "v0");
+
+        let hit = cache.handle(&get("/a"));
+        assert_eq!(body(&hit), "v0");
+        assert_eq!(hit.headers().get("x-cache"), Some("HIT"));
+        assert_eq!(cache.stats().hits(), 1);
+
+        let post =
+            Request::try_from(b"POST /a HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice()).unwrap();
+        cache.handle(&post);
+        assert_eq!(body(&cache.handle(&get("/a"))), "v2");
+    }
+
+    #[test]
+    fn test_stale_while_revalidate_refreshes_in_background() {
+        let cache = cache("max-age=0, stale-while-revalidate=60", usize::MAX);
+        assert_eq!(body(&cache.handle(&get("/a"))), "v0");
+
+        let stale = cache.handle(&get("/a"));
+        assert_eq!(body(&stale), "v0");
+        assert_eq!(stale.headers().get("x-cache"), Some("STALE"));
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while cache.stats().revalidations() == 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(cache.stats().revalidations(), 1);
+        assert_eq!(body(&cache.handle(&get("/a"))), "v1");
+        assert_eq!(cache.stats().stale_hits(), 2);
+    }
+
+    #[test]
+    fn test_stale_if_error() {
+        let cache = cache("max-age=0, stale-if-error=60", 1);
+        assert_eq!(body(&cache.handle(&get("/a"))), "v0");
+
+        let fallback = cache.handle(&get("/a"));
+        assert_eq!(fallback.status_code(), StatusCode::OK);
+        assert_eq!(body(&fallback), "v0");
+        assert_eq!(cache.stats().stale_if_error_hits(), 1);
+
+        let cache = self::cache("max-age=0, must-revalidate, stale-if-error=60", 1);
+        cache.handle(&get("/a"));
+        assert_eq!(
+            cache.handle(&get("/a")).status_code(),
+            StatusCode::ServiceUnavailable
+        );
+    }
+
+    #[test]
+    fn test_ranges_served_from_cached_entity() {
+        let cache = cache("max-age=60", usize::MAX);
+        let ranged = |range: &str| {
+            let raw = format!(
+                "GET /a HTTP/1.1\r\nHost: localhost\r\nRange: {}\r\n\r\n",
+                range
+            );
+            cache.handle(&Request::try_from(raw.as_bytes()).unwrap())
+        };
+
+        // A miss forwards the Range to the origin, which here ignores it, and
+        // keeps nothing.
+        let forwarded = ranged("bytes=1-");
+        assert_eq!(forwarded.status_code(), StatusCode::OK);
+        assert_eq!(body(&forwarded), "v0");
+        assert_eq!(forwarded.headers().get("x-cache"), Some("MISS"));
+        assert!(cache.is_empty());
+
+        let full = cache.handle(&get("/a"));
+        assert_eq!(body(&full), "v1");
+
+        let partial = ranged("bytes=1-");
+        assert_eq!(partial.status_code(), StatusCode::PartialContent);
+        assert_eq!(body(&partial), "1");
+        assert_eq!(partial.headers().get("x-cache"), Some("HIT"));
+
+        let partial = ranged("bytes=0-0");
+        assert_eq!(body(&partial), "v");
+        assert_eq!(cache.inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    fn request(headers: &str) -> Request {
+        let raw = format!("GET /a HTTP/1.1\r\n{}\r\n", headers);
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_keys_separate_hosts_variants_and_users() {
+        let cache = Cache::new(
+            handler_fn(|request: &Request| {
+                let language = request.header("Accept-Language").unwrap_or("en");
+                let who = request.header("Authorization").unwrap_or("anyone");
+                Response::ok()
+                    .with_header("Cache-Control", "max-age=60")
+                    .with_header("Vary", "Accept-Language")
+                    .with_body(Body::from(format!(
+                        "{} {} {}",
+                        request.header("Host").unwrap_or(""),
+                        language,
+                        who
+                    )))
+            }),
+            CacheConfig::new(),
+        );
+        let get = |headers: &str| body(&cache.handle(&request(headers))).to_string();
+
+        assert_eq!(get("Host: a.test\r\n"), "a.test en anyone");
+        assert_eq!(get("Host: b.test\r\n"), "b.test en anyone");
+        assert_eq!(get("Host: A.test\r\n"), "a.test en anyone");
+
+        let german = "Host: a.test\r\nAccept-Language: de\r\n";
+        assert_eq!(get(german), "a.test de anyone");
+        assert_eq!(get(german), "a.test de anyone");
+        assert_eq!(cache.stats().hits(), 2);
+
+        // Only public responses to authenticated requests are shared.
+        let alice = "Host: c.test\r\nAuthorization: Bearer alice\r\n";
+        let bob = "Host: c.test\r\nAuthorization: Bearer bob\r\n";
+        assert_eq!(get(alice), "c.test en Bearer alice");
+        assert_eq!(get(bob), "c.test en Bearer bob");
+    }
+
+    #[test]
+    fn test_public_responses_to_authenticated_requests_are_stored() {
+        let cache = cache("public, max-age=60", usize::MAX);
+        let authorized = "Host: localhost\r\nAuthorization: Bearer alice\r\n";
+        cache.handle(&request(authorized));
+        assert_eq!(body(&cache.handle(&get("/a"))), "v0");
+
+        let cache = self::cache("max-age=60, private", usize::MAX);
+        cache.handle(&request(authorized));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_panicking_refresh_can_be_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counted = calls.clone();
+        let cache = Cache::new(
+            handler_fn(move |_: &Request| {
+                if counted.fetch_add(1, Ordering::SeqCst) == 1 {
+                    panic!("refresh failed");
+                }
+                Response::ok()
+                    .with_header("Cache-Control", "max-age=0, stale-while-revalidate=60")
+                    .with_body(Body::from("v"))
+            }),
+            CacheConfig::new(),
+        );
+        cache.handle(&get("/a"));
+        cache.handle(&get("/a"));
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !cache.shared.refreshing.lock().unwrap().is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        cache.handle(&get("/a"));
+        while calls.load(Ordering::SeqCst) < 3 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
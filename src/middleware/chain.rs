@@ -0,0 +1,123 @@
+use std::sync::Arc;
+
+use crate::{
+    http::{ParseError, Request, Response},
+    server::Handler,
+};
+
+// Cross-cutting logic that runs around a handler. A layer can answer by itself
+// (auth failures, cached responses), or call next.run(request) and inspect or
+// rewrite what comes back.
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&Request, Next<'_>) -> Response + Send + Sync,
+{
+    fn handle(&self, request: &Request, next: Next<'_>) -> Response {
+        self(request, next)
+    }
+}
+
+// The rest of the chain: the remaining layers, then the handler.
+pub struct Next<'a> {
+    layers: &'a [Arc<dyn Middleware>],
+    handler: &'a dyn Handler,
+}
+
+impl Next<'_> {
+    pub fn run(self, request: &Request) -> Response {
+        match self.layers.split_first() {
+            Some((layer, rest)) => layer.handle(
+                request,
+                Next {
+                    layers: rest,
+                    handler: self.handler,
+                },
+            ),
+            None => self.handler.handle(request),
+        }
+    }
+}
+
+// A handler wrapped in layers. The first layer added is the outermost: it sees
+// the request first and the response last.
+pub struct Chain<H: Handler> {
+    layers: Vec<Arc<dyn Middleware>>,
+    handler: H,
+}
+
+impl<H: Handler> Chain<H> {
+    pub fn new(handler: H) -> Self {
+        Chain {
+            layers: Vec::new(),
+            handler,
+        }
+    }
+
+    pub fn with(mut self, layer: impl Middleware + 'static) -> Self {
+        self.layers.push(Arc::new(layer));
+        self
+    }
+
+    pub(crate) fn with_layers(handler: H, layers: Vec<Arc<dyn Middleware>>) -> Self {
+        Chain { layers, handler }
+    }
+}
+
+impl<H: Handler> Handler for Chain<H> {
+    fn handle(&self, request: &Request) -> Response {
+        Next {
+            layers: &self.layers,
+            handler: &self.handler,
+        }
+        .run(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.handler.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use crate::http::{Body, StatusCode};
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_layers_run_outermost_first_and_can_short_circuit() {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let record = |name: &'static str| {
+            let order = order.clone();
+            move |request: &Request, next: Next<'_>| {
+                order.lock().unwrap().push(name);
+                let response = next.run(request);
+                order.lock().unwrap().push(name);
+                response.with_header("X-Layer", name)
+            }
+        };
+        let auth = |request: &Request, next: Next<'_>| match request.header("Authorization") {
+            Some(_) => next.run(request),
+            None => Response::new(StatusCode::Unauthorized),
+        };
+        let chain = Chain::new(handler_fn(|_| Response::ok().with_body(Body::from("app"))))
+            .with(record("outer"))
+            .with(record("inner"))
+            .with(auth);
+
+        let request = |raw: &str| Request::try_from(raw.as_bytes()).unwrap();
+        let response = chain.handle(&request(
+            "GET / HTTP/1.1\r\nHost: x\r\nAuthorization: Bearer t\r\n\r\n",
+        ));
+        assert_eq!(response.body().as_str(), Ok("app"));
+        assert_eq!(response.headers().get("x-layer"), Some("inner,outer"));
+        assert_eq!(*order.lock().unwrap(), ["outer", "inner", "inner", "outer"]);
+
+        let denied = chain.handle(&request("GET / HTTP/1.1\r\nHost: x\r\n\r\n"));
+        assert_eq!(denied.status_code(), StatusCode::Unauthorized);
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    http::{Abort, Body, ParseError, Request, Response, StatusCode},
+    server::Handler,
+};
+
+#[derive(Debug, Clone)]
+pub struct ChaosConfig {
+    pub seed: u64,
+    pub latency_rate: f64,
+    pub latency: (Duration, Duration),
+    pub error_rate: f64,
+    pub error_statuses: Vec<StatusCode>,
+    pub truncate_rate: f64,
+    pub drop_rate: f64,
+}
+
+impl ChaosConfig {
+    pub fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+
+        ChaosConfig {
+            seed,
+            latency_rate: 0.0,
+            latency: (Duration::ZERO, Duration::ZERO),
+            error_rate: 0.0,
+            error_statuses: vec![
+                StatusCode::InternalServerError,
+                StatusCode::BadGateway,
+                StatusCode::ServiceUnavailable,
+                StatusCode::GatewayTimeout,
+            ],
+            truncate_rate: 0.0,
+            drop_rate: 0.0,
+        }
+    }
+
+    // A fixed seed makes a run reproducible for a given request sequence.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn latency(mut self, rate: f64, min: Duration, max: Duration) -> Self {
+        self.latency_rate = rate;
+        self.latency = (min, max.max(min));
+        self
+    }
+
+    pub fn errors(mut self, rate: f64) -> Self {
+        self.error_rate = rate;
+        self
+    }
+
+    pub fn error_statuses(mut self, statuses: Vec<StatusCode>) -> Self {
+        self.error_statuses = statuses;
+        self
+    }
+
+    pub fn truncate_bodies(mut self, rate: f64) -> Self {
+        self.truncate_rate = rate;
+        self
+    }
+
+    pub fn drop_connections(mut self, rate: f64) -> Self {
+        self.drop_rate = rate;
+        self
+    }
+}
+
+impl Default for ChaosConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Injects latency, 5xx responses, truncated bodies and dropped connections so
+// clients can be exercised against a misbehaving upstream. Each fault is rolled
+// independently per request; errors and drops skip the inner handler.
+pub struct Chaos<H: Handler> {
+    inner: H,
+    config: ChaosConfig,
+    state: Mutex<u64>,
+}
+
+impl<H: Handler> Chaos<H> {
+    pub fn new(inner: H, config: ChaosConfig) -> Self {
+        // xorshift gets stuck on zero.
+        let state = Mutex::new(config.seed.max(1));
+        Chaos {
+            inner,
+            config,
+            state,
+        }
+    }
+
+    fn next_u64(&self) -> u64 {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let mut x = *state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        *state = x;
+        x
+    }
+
+    fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn roll(&self, rate: f64) -> bool {
+        rate > 0.0 && self.next_f64() < rate
+    }
+
+    fn pick(&self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+impl<H: Handler> Handler for Chaos<H> {
+    fn handle(&self, request: &Request) -> Response {
+        if self.roll(self.config.latency_rate) {
+            let (min, max) = self.config.latency;
+            let spread = (max - min).as_secs_f64() * self.next_f64();
+            thread::sleep(min + Duration::from_secs_f64(spread));
+        }
+
+        if self.roll(self.config.drop_rate) {
+            return Response::internal_server_error().with_abort(Abort::Drop);
+        }
+
+        if self.roll(self.config.error_rate) && !self.config.error_statuses.is_empty() {
+            let status = self.config.error_statuses[self.pick(self.config.error_statuses.len())];
+            return Response::new(status)
+                .with_header("X-Chaos", "error")
+                .with_body(Body::from(format!("Injected fault: {}", status)));
+        }
+
+        let response = self.inner.handle(request);
+        if !response.body().is_empty() && self.roll(self.config.truncate_rate) {
+            let len = self.pick(response.body().len());
+            return response.with_abort(Abort::Truncate(len));
+        }
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Hello;
+
+    impl Handler for Hello {
+        fn handle(&self, _request: &Request) -> Response {
+            Response::ok().with_body(Body::from("hello world"))
+        }
+    }
+
+    fn request() -> Request {
+        Request::try_from(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_slice()).unwrap()
+    }
+
+    #[test]
+    fn test_seeded_faults_are_reproducible() {
+        let config = ChaosConfig::new().seed(42).errors(0.5);
+        let statuses = |chaos: &Chaos<Hello>| -> Vec<StatusCode> {
+            (0..32)
+                .map(|_| chaos.handle(&request()).status_code())
+                .collect()
+        };
+
+        let first = statuses(&Chaos::new(Hello, config.clone()));
+        let second = statuses(&Chaos::new(Hello, config));
+        assert_eq!(first, second);
+        assert!(first.contains(&StatusCode::OK));
+        assert!(first.iter().any(|s| s.as_u16() >= 500));
+    }
+
+    #[test]
+    fn test_truncate_and_drop() {
+        let chaos = Chaos::new(Hello, ChaosConfig::new().truncate_bodies(1.0));
+        let response = chaos.handle(&request());
+        let Some(Abort::Truncate(len)) = response.abort else {
+            panic!("expected truncation, got {:?}", response.abort);
+        };
+
+        let mut out = Vec::new();
+        response.send(&mut out).unwrap();
+        assert!(len < 11);
+        assert!(
+            String::from_utf8(out)
+                .unwrap()
+                .contains("content-length: 11")
+        );
+
+        let chaos = Chaos::new(Hello, ChaosConfig::new().drop_connections(1.0));
+        let mut out = Vec::new();
+        chaos.handle(&request()).send(&mut out).unwrap();
+        assert!(out.is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Condvar, Mutex},
+};
+
+use crate::{
+    http::{Method, ParseError, Request, Response},
+    server::Handler,
+};
+
+const DEFAULT_MAX_SHARED_BYTES: usize = 1024 * 1024; // 1MB
+
+#[derive(Debug, Clone)]
+pub struct CoalesceConfig {
+    // Request headers that select between representations and so belong in the key.
+    pub vary: Vec<String>,
+    pub max_shared_bytes: usize,
+}
+
+impl CoalesceConfig {
+    pub fn new() -> Self {
+        CoalesceConfig {
+            vary: vec!["accept".to_string(), "accept-encoding".to_string()],
+            max_shared_bytes: DEFAULT_MAX_SHARED_BYTES,
+        }
+    }
+
+    pub fn vary(mut self, header: &str) -> Self {
+        self.vary.push(header.to_lowercase());
+        self
+    }
+
+    pub fn max_shared_bytes(mut self, bytes: usize) -> Self {
+        self.max_shared_bytes = bytes;
+        self
+    }
+}
+
+impl Default for CoalesceConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Default)]
+struct Flight {
+    // None while the leader runs; Some(None) if it produced nothing shareable.
+    result: Mutex<Option<Option<Response>>>,
+    done: Condvar,
+}
+
+// Publishes the leader's outcome even if the handler panics, so followers never
+// wait forever; they fall back to running the handler themselves.
+struct Landing<'a> {
+    flights: &'a Mutex<HashMap<String, Arc<Flight>>>,
+    key: &'a str,
+    flight: &'a Flight,
+    response: Option<Response>,
+}
+
+impl Drop for Landing<'_> {
+    fn drop(&mut self) {
+        self.flights
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(self.key);
+        *self.flight.result.lock().unwrap_or_else(|e| e.into_inner()) = Some(self.response.take());
+        self.flight.done.notify_all();
+    }
+}
+
+// Single-flight for idempotent reads: while a GET or HEAD is in progress, identical
+// requests wait for it and receive a copy of its response instead of hitting the
+// handler again. Responses that cannot be snapshotted are not shared, and neither
+// are requests carrying credentials, whose responses may belong to that caller alone.
+pub struct Coalesce<H: Handler> {
+    inner: H,
+    config: CoalesceConfig,
+    flights: Mutex<HashMap<String, Arc<Flight>>>,
+}
+
+impl<H: Handler> Coalesce<H> {
+    pub fn new(inner: H, config: CoalesceConfig) -> Self {
+        Coalesce {
+            inner,
+            config,
+            flights: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn key(&self, request: &Request) -> String {
+        let mut key = format!(
+            "{} {} {}",
+            request.method().as_str(),
+            request.header("host").unwrap_or("").to_ascii_lowercase(),
+            request.target()
+        );
+        for name in &self.config.vary {
+            key.push('\n');
+            key.push_str(name);
+            key.push(':');
+            key.push_str(request.header(name).unwrap_or(""));
+        }
+        key
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.flights.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+impl<H: Handler> Handler for Coalesce<H> {
+    fn handle(&self, request: &Request) -> Response {
+        if !matches!(request.method(), Method::GET | Method::HEAD)
+            || request.header("authorization").is_some()
+            || request.header("cookie").is_some()
+        {
+            return self.inner.handle(request);
+        }
+
+        let key = self.key(request);
+        let (flight, leader) = {
+            let mut flights = self.flights.lock().unwrap_or_else(|e| e.into_inner());
+            match flights.get(&key) {
+                Some(flight) => (flight.clone(), false),
+                None => {
+                    let flight = Arc::new(Flight::default());
+                    flights.insert(key.clone(), flight.clone());
+                    (flight, true)
+                }
+            }
+        };
+
+        if leader {
+            let mut landing = Landing {
+                flights: &self.flights,
+                key: &key,
+                flight: &flight,
+                response: None,
+            };
+            let mut response = self.inner.handle(request);
+            landing.response = response.freeze(self.config.max_shared_bytes).ok();
+            return response;
+        }
+
+        let mut result = flight.result.lock().unwrap_or_else(|e| e.into_inner());
+        while result.is_none() {
+            result = flight.done.wait(result).unwrap_or_else(|e| e.into_inner());
+        }
+        match result.as_ref() {
+            Some(Some(response)) => response.clone(),
+            _ => {
+                drop(result);
+                self.inner.handle(request)
+            }
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Body;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    struct Slow {
+        calls: AtomicUsize,
+    }
+
+    impl Handler for Slow {
+        fn handle(&self, request: &Request) -> Response {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            Response::ok().with_body(Body::from(format!("{} #{}", request.path(), n)))
+        }
+    }
+
+    fn request(raw: &str) -> Request {
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_identical_requests_share_one_call() {
+        let coalesce = Coalesce::new(
+            Slow {
+                calls: AtomicUsize::new(0),
+            },
+            CoalesceConfig::new(),
+        );
+
+        let bodies: Vec<Vec<u8>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let req = request("GET /report HTTP/1.1\r\nHost: a\r\n\r\n");
+                        coalesce.handle(&req).body().as_bytes().to_vec()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(coalesce.inner.calls.load(Ordering::SeqCst), 1);
+        assert!(bodies.iter().all(|b| b == b"/report #0"));
+        assert_eq!(coalesce.in_flight(), 0);
+    }
+
+    #[test]
+    fn test_vary_and_unsafe_methods_are_not_shared() {
+        let coalesce = Coalesce::new(
+            Slow {
+                calls: AtomicUsize::new(0),
+            },
+            CoalesceConfig::new(),
+        );
+
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                coalesce.handle(&request("GET /r HTTP/1.1\r\nAccept: text/html\r\n\r\n"))
+            });
+            scope.spawn(|| {
+                coalesce.handle(&request(
+                    "GET /r HTTP/1.1\r\nAccept: application/json\r\n\r\n",
+                ))
+            });
+            scope.spawn(|| coalesce.handle(&request("POST /r HTTP/1.1\r\n\r\n")));
+        });
+
+        assert_eq!(coalesce.inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_other_hosts_and_credentials_are_not_shared() {
+        let coalesce = Coalesce::new(
+            Slow {
+                calls: AtomicUsize::new(0),
+            },
+            CoalesceConfig::new(),
+        );
+
+        thread::scope(|scope| {
+            for raw in [
+                "GET /me HTTP/1.1\r\nHost: a\r\n\r\n",
+                "GET /me HTTP/1.1\r\nHost: b\r\n\r\n",
+                "GET /me HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer x\r\n\r\n",
+                "GET /me HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer x\r\n\r\n",
+                "GET /me HTTP/1.1\r\nHost: a\r\nCookie: session=1\r\n\r\n",
+            ] {
+                let coalesce = &coalesce;
+                scope.spawn(move || coalesce.handle(&request(raw)));
+            }
+        });
+
+        assert_eq!(coalesce.inner.calls.load(Ordering::SeqCst), 5);
+    }
+}
@@ -0,0 +1,362 @@
+use std::io::{self, Write};
+
+use crate::{
+    http::{AcceptEncoding, Body, CacheControl, ETag, ParseError, Request, Response, StatusCode},
+    server::Handler,
+};
+
+#[cfg(feature = "brotli")]
+const BROTLI_QUALITY: u32 = 5;
+#[cfg(feature = "brotli")]
+const BROTLI_WINDOW: u32 = 22;
+
+// The content codings compiled in; each sits behind the feature of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coding {
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "deflate")]
+    Deflate,
+}
+
+impl Coding {
+    // Every coding compiled in, in the order servers usually prefer them.
+    pub fn all() -> Vec<Coding> {
+        vec![
+            #[cfg(feature = "brotli")]
+            Coding::Brotli,
+            #[cfg(feature = "gzip")]
+            Coding::Gzip,
+            #[cfg(feature = "deflate")]
+            Coding::Deflate,
+        ]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => "br",
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Coding::Deflate => "deflate",
+        }
+    }
+
+    pub fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match *self {
+            #[cfg(feature = "brotli")]
+            Coding::Brotli => {
+                let mut out = Vec::new();
+                let mut writer =
+                    brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                writer.write_all(data)?;
+                drop(writer);
+                Ok(out)
+            }
+            #[cfg(feature = "gzip")]
+            Coding::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            // HTTP's "deflate" is the zlib format, not a raw deflate stream.
+            #[cfg(feature = "deflate")]
+            Coding::Deflate => {
+                let mut encoder =
+                    flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressConfig {
+    // Codings on offer, most preferred first. The client's q-values decide and
+    // ties go to the earlier coding.
+    pub codings: Vec<Coding>,
+    // Bodies shorter than this are not worth the CPU and go out as they are.
+    pub min_size: usize,
+    // Media types that are compressed already; "type/*" covers a whole type.
+    pub skip_types: Vec<String>,
+}
+
+impl Default for CompressConfig {
+    fn default() -> Self {
+        CompressConfig {
+            codings: Coding::all(),
+            min_size: 1024,
+            skip_types: [
+                "image/png",
+                "image/jpeg",
+                "image/gif",
+                "image/webp",
+                "image/avif",
+                "audio/*",
+                "video/*",
+                "font/woff",
+                "font/woff2",
+                "application/zip",
+                "application/gzip",
+                "application/x-gzip",
+                "application/x-bzip2",
+                "application/x-7z-compressed",
+                "application/zstd",
+                "application/pdf",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl CompressConfig {
+    pub fn codings(mut self, codings: &[Coding]) -> Self {
+        self.codings = codings.to_vec();
+        self
+    }
+
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    pub fn skip_types(mut self, types: &[&str]) -> Self {
+        self.skip_types = types.iter().map(|t| t.to_ascii_lowercase()).collect();
+        self
+    }
+
+    fn skips(&self, media_type: &str) -> bool {
+        self.skip_types
+            .iter()
+            .any(|skipped| match skipped.strip_suffix("/*") {
+                Some(prefix) => media_type
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/')),
+                None => skipped == media_type,
+            })
+    }
+}
+
+// Compresses response bodies with the best coding the client accepts. Every
+// response that could have been compressed gets Vary: Accept-Encoding, whether it
+// was or not, so caches keep the variants apart. Streamed, already encoded,
+// partial and no-transform responses pass through. A strong ETag is weakened,
+// since the encoded bytes differ from the ones it names, and digests are dropped.
+pub struct Compress<H: Handler> {
+    inner: H,
+    config: CompressConfig,
+}
+
+impl<H: Handler> Compress<H> {
+    pub fn new(inner: H, config: CompressConfig) -> Self {
+        Compress { inner, config }
+    }
+
+    fn eligible(&self, response: &Response) -> bool {
+        let headers = response.headers();
+        if response.streams_body()
+            || response.body().len() < self.config.min_size.max(1)
+            || matches!(
+                response.status_code(),
+                StatusCode::PartialContent | StatusCode::NoContent | StatusCode::NotModified
+            )
+            || headers.contains("Content-Range")
+            || headers
+                .get("Content-Encoding")
+                .is_some_and(|e| !e.trim().eq_ignore_ascii_case("identity"))
+            || CacheControl::from_headers(headers).no_transform
+        {
+            return false;
+        }
+        let media_type = headers
+            .get("Content-Type")
+            .and_then(|value| value.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase());
+        !media_type.is_some_and(|media_type| self.config.skips(&media_type))
+    }
+
+    fn choose(&self, accept: &AcceptEncoding) -> Option<Coding> {
+        let mut candidates: Vec<&str> = self.config.codings.iter().map(Coding::as_str).collect();
+        candidates.push("identity");
+        let chosen = accept.negotiate(&candidates)?;
+        self.config
+            .codings
+            .iter()
+            .copied()
+            .find(|coding| coding.as_str() == chosen)
+    }
+}
+
+fn add_vary(response: &mut Response) {
+    let vary = response.headers().get("Vary").unwrap_or_default();
+    let listed = vary
+        .split(',')
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("Accept-Encoding"));
+    if listed {
+        return;
+    }
+    let value = match vary.trim() {
+        "" => "Accept-Encoding".to_string(),
+        vary => format!("{}, Accept-Encoding", vary),
+    };
+    response.headers.set("Vary", value);
+}
+
+impl<H: Handler> Handler for Compress<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let mut response = self.inner.handle(request);
+        if !self.eligible(&response) {
+            return response;
+        }
+        add_vary(&mut response);
+        let Some(accept) = request.accept_encoding() else {
+            return response;
+        };
+        // With identity ruled out, the body must go out encoded or not at all.
+        let identity = accept.is_acceptable("identity");
+        let not_acceptable = || {
+            let mut refused = Response::not_acceptable();
+            add_vary(&mut refused);
+            refused
+        };
+        let Some(coding) = self.choose(&accept) else {
+            return if identity { response } else { not_acceptable() };
+        };
+        let encoded = match coding.encode(response.body().as_bytes()) {
+            Ok(encoded) if !identity || encoded.len() < response.body().len() => encoded,
+            Ok(_) => return response,
+            Err(_) if identity => return response,
+            Err(_) => return not_acceptable(),
+        };
+
+        for name in ["Content-Digest", "Repr-Digest"] {
+            response.headers.remove(name);
+        }
+        let etag = response.headers().get("ETag").map(str::parse::<ETag>);
+        if let Some(Ok(etag)) = etag
+            && !etag.is_weak()
+            && let Ok(weak) = ETag::weak(etag.tag())
+        {
+            response.headers.set("ETag", weak.to_string());
+        }
+        response
+            .with_header("Content-Encoding", coding.as_str())
+            .with_body(Body::from(encoded))
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+
+    fn get(handler: &impl Handler, path: &str, accept: &str) -> Response {
+        let raw = format!(
+            "GET {} HTTP/1.1\r\nHost: x\r\nAccept-Encoding: {}\r\n\r\n",
+            path, accept
+        );
+        handler.handle(&Request::try_from(raw.as_bytes()).unwrap())
+    }
+
+    fn text_server() -> Compress<impl Handler> {
+        Compress::new(
+            handler_fn(|request| {
+                let (content_type, size) = match request.path() {
+                    "/small" => ("text/plain", 10),
+                    "/photo" => ("image/png", 4096),
+                    _ => ("text/plain; charset=utf-8", 4096),
+                };
+                Response::ok()
+                    .with_header("Content-Type", content_type)
+                    .with_header("Vary", "Origin")
+                    .with_header("ETag", "\"v1\"")
+                    .with_body(Body::from("a".repeat(size)))
+            }),
+            CompressConfig::default().min_size(100),
+        )
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzips_eligible_bodies() {
+        use std::io::Read;
+
+        let server = text_server();
+        let response = get(&server, "/", "deflate;q=0.5, gzip;q=0.8, br;q=0");
+        assert_eq!(response.headers().get("content-encoding"), Some("gzip"));
+        assert_eq!(
+            response.headers().get("vary"),
+            Some("Origin, Accept-Encoding")
+        );
+        assert_eq!(response.headers().get("etag"), Some("W/\"v1\""));
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(response.body().as_bytes())
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "a".repeat(4096));
+        assert_eq!(
+            response.headers().get("content-length"),
+            Some(response.body().len().to_string().as_str())
+        );
+
+        let identity = get(&server, "/", "gzip;q=0.1, identity");
+        assert_eq!(identity.headers().get("content-encoding"), None);
+        assert_eq!(
+            identity.headers().get("vary"),
+            Some("Origin, Accept-Encoding")
+        );
+        for path in ["/small", "/photo"] {
+            let response = get(&server, path, "gzip");
+            assert_eq!(response.headers().get("content-encoding"), None);
+            assert_eq!(response.headers().get("vary"), Some("Origin"));
+        }
+    }
+
+    #[test]
+    fn test_refuses_when_no_coding_is_acceptable() {
+        let response = get(&text_server(), "/", "identity;q=0, *;q=0");
+        assert_eq!(response.status_code(), StatusCode::NotAcceptable);
+        assert!(response.body().is_empty());
+        assert_eq!(response.headers().get("vary"), Some("Accept-Encoding"));
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_prefers_brotli_on_ties() {
+        use std::io::Read;
+
+        let response = get(&text_server(), "/", "gzip, deflate, br");
+        assert_eq!(response.headers().get("content-encoding"), Some("br"));
+        let mut decoded = Vec::new();
+        brotli::Decompressor::new(response.body().as_bytes(), 4096)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.len(), 4096);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn test_deflate_is_zlib_wrapped() {
+        use std::io::Read;
+
+        let response = get(&text_server(), "/", "deflate");
+        assert_eq!(response.headers().get("content-encoding"), Some("deflate"));
+        let mut decoded = Vec::new();
+        flate2::read::ZlibDecoder::new(response.body().as_bytes())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.len(), 4096);
+    }
+}
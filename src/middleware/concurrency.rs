@@ -0,0 +1,301 @@
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    http::{ParseError, Request, Response, StatusCode},
+    logging::log_warn,
+    server::Handler,
+};
+
+const DEFAULT_MAX_WAIT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct ConcurrencyConfig {
+    pub max_concurrent: usize,
+    // Requests allowed to wait for a free slot; beyond that they are refused.
+    pub max_queued: usize,
+    pub max_wait: Duration,
+    // Size of the worker pool the server runs handlers on, if it has one. A waiting
+    // request keeps its worker blocked, so the queue is capped to leave at least one
+    // worker free for other routes.
+    pub workers: Option<usize>,
+}
+
+impl ConcurrencyConfig {
+    pub fn new(max_concurrent: usize) -> Self {
+        ConcurrencyConfig {
+            max_concurrent: max_concurrent.max(1),
+            max_queued: 0,
+            max_wait: DEFAULT_MAX_WAIT,
+            workers: None,
+        }
+    }
+
+    pub fn queue(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    pub fn max_wait(mut self, max_wait: Duration) -> Self {
+        self.max_wait = max_wait;
+        self
+    }
+
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = Some(workers);
+        self
+    }
+
+    // The longest queue that still leaves a worker idle when this route is full.
+    fn queue_bound(&self) -> usize {
+        match self.workers {
+            Some(workers) => self
+                .max_queued
+                .min(workers.saturating_sub(self.max_concurrent + 1)),
+            None => self.max_queued,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Slots {
+    running: usize,
+    // Tickets of waiting requests in arrival order; the front goes next.
+    waiting: VecDeque<u64>,
+    next_ticket: u64,
+}
+
+// Returns the slot when the handler finishes, panics included.
+struct Permit<'a> {
+    slots: &'a Mutex<Slots>,
+    freed: &'a Condvar,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner()).running -= 1;
+        self.freed.notify_all();
+    }
+}
+
+// Caps how many requests run the wrapped handler at once, so one expensive route
+// cannot tie up every worker. Wrap the route's handler, e.g.
+//
+//     Router::new().get("/report", ConcurrencyLimit::new(report, ConcurrencyConfig::new(4).queue(16).workers(32)))
+//
+// Requests over the limit wait their turn, first come first served, while the
+// queue has room and for at most max_wait; the rest get 503 with Retry-After.
+// Waiting blocks a worker thread, so behind with_workers pass the pool size too.
+pub struct ConcurrencyLimit<H: Handler> {
+    inner: H,
+    config: ConcurrencyConfig,
+    slots: Mutex<Slots>,
+    freed: Condvar,
+}
+
+impl<H: Handler> ConcurrencyLimit<H> {
+    pub fn new(inner: H, mut config: ConcurrencyConfig) -> Self {
+        let bound = config.queue_bound();
+        if bound < config.max_queued {
+            log_warn!(
+                "concurrency queue of {} would block the whole pool of {} workers; capped at {}",
+                config.max_queued,
+                config.workers.unwrap_or(0),
+                bound
+            );
+            config.max_queued = bound;
+        }
+        ConcurrencyLimit {
+            inner,
+            config,
+            slots: Mutex::new(Slots::default()),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub fn running(&self) -> usize {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner()).running
+    }
+
+    pub fn queued(&self) -> usize {
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .waiting
+            .len()
+    }
+
+    fn acquire(&self) -> Option<Permit<'_>> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let permit = || Permit {
+            slots: &self.slots,
+            freed: &self.freed,
+        };
+        if slots.waiting.is_empty() && slots.running < self.config.max_concurrent {
+            slots.running += 1;
+            return Some(permit());
+        }
+        if slots.waiting.len() >= self.config.max_queued {
+            return None;
+        }
+
+        let ticket = slots.next_ticket;
+        slots.next_ticket += 1;
+        slots.waiting.push_back(ticket);
+        let deadline = Instant::now() + self.config.max_wait;
+        loop {
+            if slots.waiting.front() == Some(&ticket) && slots.running < self.config.max_concurrent
+            {
+                slots.waiting.pop_front();
+                slots.running += 1;
+                drop(slots);
+                // The next in line may fit as well.
+                self.freed.notify_all();
+                return Some(permit());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                slots.waiting.retain(|&t| t != ticket);
+                drop(slots);
+                self.freed.notify_all();
+                return None;
+            }
+            slots = self
+                .freed
+                .wait_timeout(slots, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+}
+
+impl<H: Handler> Handler for ConcurrencyLimit<H> {
+    fn handle(&self, request: &Request) -> Response {
+        match self.acquire() {
+            Some(_permit) => self.inner.handle(request),
+            None => Response::problem(
+                StatusCode::ServiceUnavailable,
+                "Too busy",
+                "Too many concurrent requests for this resource",
+                "about:blank",
+            )
+            .with_header(
+                "Retry-After",
+                self.config.max_wait.as_secs().max(1).to_string(),
+            ),
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    #[test]
+    fn test_excess_requests_queue_then_get_503() {
+        let peak = AtomicUsize::new(0);
+        let active = AtomicUsize::new(0);
+        let limit = ConcurrencyLimit::new(
+            handler_fn(|_| {
+                let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(50));
+                active.fetch_sub(1, Ordering::SeqCst);
+                Response::ok()
+            }),
+            ConcurrencyConfig::new(2)
+                .queue(2)
+                .max_wait(Duration::from_secs(2)),
+        );
+        let request = Request::try_from(&b"GET /report HTTP/1.1\r\nHost: x\r\n\r\n"[..]).unwrap();
+
+        let statuses: Vec<StatusCode> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..6)
+                .map(|_| {
+                    let handle = scope.spawn(|| limit.handle(&request).status_code());
+                    thread::sleep(Duration::from_millis(5));
+                    handle
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let ok = statuses.iter().filter(|s| **s == StatusCode::OK).count();
+        assert_eq!(ok, 4, "{:?}", statuses);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!((limit.running(), limit.queued()), (0, 0));
+    }
+
+    #[test]
+    fn test_queue_leaves_a_worker_free() {
+        let limit = |workers| {
+            ConcurrencyLimit::new(
+                handler_fn(|_| Response::ok()),
+                ConcurrencyConfig::new(4).queue(16).workers(workers),
+            )
+        };
+        assert_eq!(limit(32).config.max_queued, 16);
+        assert_eq!(limit(8).config.max_queued, 3);
+        assert_eq!(limit(4).config.max_queued, 0);
+    }
+
+    #[test]
+    fn test_full_pool_refuses_without_waiting() {
+        let limit = ConcurrencyLimit::new(
+            handler_fn(|_| {
+                thread::sleep(Duration::from_millis(100));
+                Response::ok()
+            }),
+            ConcurrencyConfig::new(1)
+                .queue(4)
+                .workers(2)
+                .max_wait(Duration::from_secs(5)),
+        );
+        let request = Request::try_from(&b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..]).unwrap();
+
+        thread::scope(|scope| {
+            let first = scope.spawn(|| limit.handle(&request).status_code());
+            thread::sleep(Duration::from_millis(10));
+            let started = Instant::now();
+            let refused = limit.handle(&request);
+            assert_eq!(refused.status_code(), StatusCode::ServiceUnavailable);
+            assert!(started.elapsed() < Duration::from_millis(50));
+            assert_eq!(first.join().unwrap(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_queued_requests_give_up_after_max_wait() {
+        let limit = ConcurrencyLimit::new(
+            handler_fn(|_| {
+                thread::sleep(Duration::from_millis(100));
+                Response::ok()
+            }),
+            ConcurrencyConfig::new(1)
+                .queue(4)
+                .max_wait(Duration::from_millis(20)),
+        );
+        let request = Request::try_from(&b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..]).unwrap();
+
+        thread::scope(|scope| {
+            let first = scope.spawn(|| limit.handle(&request).status_code());
+            thread::sleep(Duration::from_millis(10));
+            let waited = limit.handle(&request);
+            assert_eq!(waited.status_code(), StatusCode::ServiceUnavailable);
+            assert_eq!(waited.headers().get("retry-after"), Some("1"));
+            assert_eq!(first.join().unwrap(), StatusCode::OK);
+        });
+    }
+}
@@ -0,0 +1,264 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::digest::content_hash;
+use crate::logging::log_warn;
+
+use super::cache::{CacheEntry, CacheStore};
+
+const ENTRY_EXT: &str = "entry";
+
+#[derive(Debug)]
+struct IndexEntry {
+    file: PathBuf,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    entries: HashMap<String, IndexEntry>,
+    total_bytes: u64,
+    clock: u64,
+}
+
+impl Index {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn forget(&mut self, key: &str) -> Option<IndexEntry> {
+        let entry = self.entries.remove(key)?;
+        self.total_bytes -= entry.size;
+        Some(entry)
+    }
+}
+
+// Persistent CacheStore keeping one JSON file per entry under a directory, capped
+// by total size with least-recently-used eviction. The index lives in memory and
+// is rebuilt from the entry files on open, so the cache survives restarts.
+#[derive(Debug)]
+pub struct DiskStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+impl DiskStore {
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+
+        let mut found = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != ENTRY_EXT) {
+                continue;
+            }
+            match fs::read_to_string(&path)
+                .ok()
+                .and_then(|s| CacheEntry::decode(&s))
+            {
+                Some((key, entry)) => {
+                    let size = fs::metadata(&path)?.len();
+                    found.push((entry.stored, key, path, size));
+                }
+                None => fs::remove_file(&path)?,
+            }
+        }
+
+        // Without access times from a previous run, older entries go first.
+        found.sort_by_key(|(stored, ..)| *stored);
+        let mut index = Index::default();
+        for (_, key, file, size) in found {
+            let last_used = index.tick();
+            index.total_bytes += size;
+            index.entries.insert(
+                key,
+                IndexEntry {
+                    file,
+                    size,
+                    last_used,
+                },
+            );
+        }
+
+        let store = DiskStore {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        };
+        store.evict(&mut store.lock(), 0);
+        Ok(store)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.lock().total_bytes
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn evict(&self, index: &mut Index, incoming: u64) {
+        while index.total_bytes + incoming > self.max_bytes {
+            let Some(oldest) = index
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = index.forget(&oldest) {
+                let _ = fs::remove_file(entry.file);
+            }
+        }
+    }
+}
+
+impl CacheStore for DiskStore {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let mut index = self.lock();
+        let file = index.entries.get(key)?.file.clone();
+
+        match fs::read_to_string(&file)
+            .ok()
+            .and_then(|s| CacheEntry::decode(&s))
+        {
+            Some((stored_key, entry)) if stored_key == key => {
+                let tick = index.tick();
+                if let Some(indexed) = index.entries.get_mut(key) {
+                    indexed.last_used = tick;
+                }
+                Some(entry)
+            }
+            _ => {
+                index.forget(key);
+                let _ = fs::remove_file(file);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let data = entry.encode(key);
+        let size = data.len() as u64;
+        if size > self.max_bytes {
+            return;
+        }
+
+        let file = self
+            .dir
+            .join(format!("{}.{}", content_hash(key.as_bytes()), ENTRY_EXT));
+        let tmp = file.with_extension("tmp");
+
+        let mut index = self.lock();
+        if let Some(previous) = index.forget(key) {
+            let _ = fs::remove_file(previous.file);
+        }
+        self.evict(&mut index, size);
+
+        // Write-then-rename so a crash never leaves a half-written entry behind.
+        if let Err(e) = fs::write(&tmp, &data).and_then(|_| fs::rename(&tmp, &file)) {
+            log_warn!("Failed to write cache entry {}: {}", file.display(), e);
+            return;
+        }
+
+        let last_used = index.tick();
+        index.total_bytes += size;
+        index.entries.insert(
+            key.to_string(),
+            IndexEntry {
+                file,
+                size,
+                last_used,
+            },
+        );
+    }
+
+    fn remove(&self, key: &str) {
+        if let Some(entry) = self.lock().forget(key) {
+            let _ = fs::remove_file(entry.file);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{Body, Response},
+        testing::TempDir,
+    };
+    use std::time::{Duration, SystemTime};
+
+    fn entry(body: &str) -> CacheEntry {
+        CacheEntry {
+            response: Response::ok()
+                .with_header("Content-Type", "text/plain")
+                .with_body(Body::from(body)),
+            stored: SystemTime::now(),
+            ttl: Duration::from_secs(60),
+            stale_while_revalidate: Duration::from_secs(5),
+            stale_if_error: Duration::ZERO,
+            vary: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_entries_survive_reopen() {
+        let dir = TempDir::new("disk-cache-reopen");
+        let store = DiskStore::open(dir.path(), 1024 * 1024).unwrap();
+        store.put("GET /a", entry("alpha"));
+        assert_eq!(store.len(), 1);
+        drop(store);
+
+        fs::write(dir.join("garbage.entry"), "not json").unwrap();
+        let store = DiskStore::open(dir.path(), 1024 * 1024).unwrap();
+        assert_eq!(store.len(), 1);
+        assert!(!dir.join("garbage.entry").exists());
+
+        let cached = store.get("GET /a").unwrap();
+        assert_eq!(cached.response.body().as_bytes(), b"alpha");
+        assert_eq!(
+            cached.response.headers().get("content-type"),
+            Some("text/plain")
+        );
+        assert_eq!(cached.stale_while_revalidate, Duration::from_secs(5));
+
+        store.remove("GET /a");
+        assert!(store.get("GET /a").is_none());
+        assert_eq!(store.total_bytes(), 0);
+    }
+
+    #[test]
+    fn test_size_cap_evicts_least_recently_used() {
+        let dir = TempDir::new("disk-cache-lru");
+        let size = entry("aaaa").encode("GET /a").len() as u64;
+        let store = DiskStore::open(dir.path(), size * 2).unwrap();
+
+        store.put("GET /a", entry("aaaa"));
+        store.put("GET /b", entry("bbbb"));
+        assert!(store.get("GET /a").is_some());
+        store.put("GET /c", entry("cccc"));
+
+        assert!(store.get("GET /b").is_none());
+        assert!(store.get("GET /a").is_some());
+        assert!(store.get("GET /c").is_some());
+        assert!(store.total_bytes() <= size * 2);
+    }
+}
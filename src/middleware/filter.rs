@@ -0,0 +1,148 @@
+use crate::{
+    http::{
+        Body, ParseError, Request, Response,
+        filter::{self, BodyFilter},
+    },
+    server::Handler,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterConfig {
+    // Media types the filter runs on; empty means every response.
+    pub content_types: Vec<String>,
+    // Bytes fed to the filter per call.
+    pub chunk_size: usize,
+}
+
+impl Default for FilterConfig {
+    fn default() -> Self {
+        FilterConfig {
+            content_types: vec!["text/html".to_string()],
+            chunk_size: 16 * 1024,
+        }
+    }
+}
+
+impl FilterConfig {
+    pub fn content_types(mut self, types: &[&str]) -> Self {
+        self.content_types = types.iter().map(|t| t.to_ascii_lowercase()).collect();
+        self
+    }
+}
+
+type FilterFactory = Box<dyn Fn(&Request) -> Option<Box<dyn BodyFilter>> + Send + Sync>;
+
+// Runs response bodies through a BodyFilter made per request; the factory returns
+// None to leave a response alone. Encoded bodies are skipped, since the filter
+// would see compressed bytes, and so are takeovers, which can wrap their stream
+// in a FilterWriter themselves. Validators of a changed body (ETag and digests)
+// are dropped, so this belongs inside layers that add them.
+pub struct Filter<H: Handler> {
+    inner: H,
+    config: FilterConfig,
+    factory: FilterFactory,
+}
+
+impl<H: Handler> Filter<H> {
+    pub fn new<F>(inner: H, config: FilterConfig, factory: F) -> Self
+    where
+        F: Fn(&Request) -> Option<Box<dyn BodyFilter>> + Send + Sync + 'static,
+    {
+        Filter {
+            inner,
+            config,
+            factory: Box::new(factory),
+        }
+    }
+
+    fn applies_to(&self, response: &Response) -> bool {
+        let headers = response.headers();
+        if response.streams_body()
+            || response.body().is_empty()
+            || headers
+                .get("Content-Encoding")
+                .is_some_and(|e| !e.trim().eq_ignore_ascii_case("identity"))
+        {
+            return false;
+        }
+        let media_type = headers
+            .get("Content-Type")
+            .and_then(|value| value.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase());
+        self.config.content_types.is_empty()
+            || media_type.is_some_and(|media_type| self.config.content_types.contains(&media_type))
+    }
+}
+
+impl<H: Handler> Handler for Filter<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let mut response = self.inner.handle(request);
+        if !self.applies_to(&response) {
+            return response;
+        }
+        let Some(mut filter) = (self.factory)(request) else {
+            return response;
+        };
+        let body = response.take_body();
+        let filtered = filter::apply(filter.as_mut(), body.as_bytes(), self.config.chunk_size);
+        if filtered.as_slice() != body.as_bytes() {
+            for name in ["ETag", "Content-Digest", "Repr-Digest"] {
+                response.headers.remove(name);
+            }
+        }
+        response.with_body(Body::from(filtered))
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use crate::http::filter::Replace;
+
+    #[test]
+    fn test_filters_matching_bodies_and_drops_stale_validators() {
+        let filter = Filter::new(
+            handler_fn(|request| {
+                let content_type = match request.path() {
+                    "/page" => "text/html; charset=utf-8",
+                    _ => "application/json",
+                };
+                Response::ok()
+                    .with_header("Content-Type", content_type)
+                    .with_header("ETag", "\"v1\"")
+                    .with_body(Body::from("<body>hello</body>"))
+            }),
+            FilterConfig {
+                chunk_size: 3,
+                ..FilterConfig::default()
+            },
+            |_| {
+                Some(Box::new(Replace::insert_before(
+                    "</body>",
+                    "<footer>beta</footer>",
+                )))
+            },
+        );
+        let get = |path: &str| {
+            let raw = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+            filter.handle(&Request::try_from(raw.as_bytes()).unwrap())
+        };
+
+        let page = get("/page");
+        assert_eq!(
+            page.body().as_bytes(),
+            b"<body>hello<footer>beta</footer></body>"
+        );
+        assert_eq!(page.headers().get("content-length"), Some("39"));
+        assert_eq!(page.headers().get("etag"), None);
+
+        let api = get("/api");
+        assert_eq!(api.body().as_bytes(), b"<body>hello</body>");
+        assert_eq!(api.headers().get("etag"), Some("\"v1\""));
+    }
+}
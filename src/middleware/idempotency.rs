@@ -0,0 +1,376 @@
+use std::{fmt, sync::Arc, sync::Mutex, time::Duration};
+
+use crate::{
+    digest::sha256,
+    http::{Method, ParseError, Request, Response, ResponseRecord, StatusCode},
+    json::{self, Value},
+    server::Handler,
+};
+
+use super::store::KeyValueStore;
+
+const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_LOCK_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024; // 1MB
+const MAX_KEY_LEN: usize = 255;
+
+// Who a request comes from, so keys chosen by different callers never collide.
+// None for anonymous requests, which share one namespace.
+pub type Identity = Arc<dyn Fn(&Request) -> Option<String> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct IdempotencyConfig {
+    pub header: String,
+    // How long a completed response is replayed for.
+    pub ttl: Duration,
+    // How long a request in progress holds its key; bounds the damage if the
+    // process dies before the response is stored.
+    pub lock_ttl: Duration,
+    pub prefix: String,
+    pub max_body_bytes: usize,
+    // Scopes keys by caller. The default uses the Authorization header; only a
+    // hash of it reaches the store.
+    pub identity: Identity,
+}
+
+impl fmt::Debug for IdempotencyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdempotencyConfig")
+            .field("header", &self.header)
+            .field("ttl", &self.ttl)
+            .field("lock_ttl", &self.lock_ttl)
+            .field("prefix", &self.prefix)
+            .field("max_body_bytes", &self.max_body_bytes)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdempotencyConfig {
+    pub fn new() -> Self {
+        IdempotencyConfig {
+            header: "Idempotency-Key".to_string(),
+            ttl: DEFAULT_TTL,
+            lock_ttl: DEFAULT_LOCK_TTL,
+            prefix: "idempotency:".to_string(),
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            identity: Arc::new(|request| request.header("authorization").map(str::to_string)),
+        }
+    }
+
+    pub fn header(mut self, name: &str) -> Self {
+        self.header = name.to_string();
+        self
+    }
+
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn lock_ttl(mut self, ttl: Duration) -> Self {
+        self.lock_ttl = ttl;
+        self
+    }
+
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn max_body_bytes(mut self, bytes: usize) -> Self {
+        self.max_body_bytes = bytes;
+        self
+    }
+
+    pub fn identity(
+        mut self,
+        f: impl Fn(&Request) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        self.identity = Arc::new(f);
+        self
+    }
+}
+
+impl Default for IdempotencyConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// What the store holds under a key: the request it was claimed for and, once the
+// handler has answered, the response to replay.
+struct Stored {
+    fingerprint: String,
+    response: Option<Response>,
+}
+
+impl Stored {
+    fn encode(fingerprint: &str, response: Option<&Response>) -> Vec<u8> {
+        let mut members = vec![("fingerprint".to_string(), Value::from(fingerprint))];
+        if let Some(response) = response {
+            members.push((
+                "response".to_string(),
+                ResponseRecord::from(response).to_json(),
+            ));
+        }
+        Value::Object(members).to_string().into_bytes()
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Stored> {
+        let value = json::parse(std::str::from_utf8(bytes).ok()?).ok()?;
+        let response = match value.get("response") {
+            Some(record) => Some(ResponseRecord::from_json(record).ok()?.into_response()),
+            None => None,
+        };
+        Some(Stored {
+            fingerprint: value.get("fingerprint")?.as_str()?.to_string(),
+            response,
+        })
+    }
+}
+
+// Releases the key if the handler panics, so a retry is not locked out until
+// lock_ttl runs down.
+struct Claim<'a, S: KeyValueStore> {
+    store: &'a S,
+    key: &'a str,
+    settled: bool,
+}
+
+impl<S: KeyValueStore> Drop for Claim<'_, S> {
+    fn drop(&mut self) {
+        if !self.settled {
+            self.store.delete(self.key);
+        }
+    }
+}
+
+// The Idempotency-Key pattern for unsafe methods: the first request with a given
+// key runs and its response is stored; retries with the same key and request get
+// that response replayed, marked Idempotent-Replayed, without running the handler
+// again. A retry while the first is still running gets 409, and reusing a key for a
+// different request gets 422. Server errors are not stored, so they can be retried.
+//
+// The claim on a key is atomic within this process. Across instances sharing a
+// store it is best effort, since KeyValueStore has no compare-and-set.
+pub struct Idempotency<H: Handler, S: KeyValueStore> {
+    inner: H,
+    store: S,
+    config: IdempotencyConfig,
+    claims: Mutex<()>,
+}
+
+impl<H: Handler, S: KeyValueStore> Idempotency<H, S> {
+    pub fn new(inner: H, store: S, config: IdempotencyConfig) -> Self {
+        Idempotency {
+            inner,
+            store,
+            config,
+            claims: Mutex::new(()),
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    // The store key: the caller's identity hash, then the key they chose.
+    fn store_key(&self, request: &Request, key: &str) -> String {
+        let caller = (self.config.identity)(request)
+            .map(|identity| hex_sha256(identity.as_bytes()))
+            .unwrap_or_default();
+        format!("{}{}:{}", self.config.prefix, caller, key)
+    }
+
+    fn fingerprint(request: &Request) -> String {
+        let mut data = format!("{} {}\n", request.method().as_str(), request.target()).into_bytes();
+        data.extend_from_slice(request.body_as_bytes());
+        hex_sha256(&data)
+    }
+}
+
+fn hex_sha256(data: &[u8]) -> String {
+    sha256(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl<H: Handler, S: KeyValueStore> Handler for Idempotency<H, S> {
+    fn handle(&self, request: &Request) -> Response {
+        if matches!(
+            request.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE | Method::PROPFIND
+        ) {
+            return self.inner.handle(request);
+        }
+        let Some(key) = request.header(&self.config.header).map(str::trim) else {
+            return self.inner.handle(request);
+        };
+        if key.is_empty() || key.len() > MAX_KEY_LEN {
+            return Response::problem(
+                StatusCode::BadRequest,
+                "Invalid idempotency key",
+                &format!("{} must be 1 to {} bytes", self.config.header, MAX_KEY_LEN),
+                "about:blank",
+            );
+        }
+
+        let key = self.store_key(request, key);
+        let fingerprint = Self::fingerprint(request);
+        {
+            let _claims = self.claims.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(stored) = self.store.get(&key).and_then(|b| Stored::decode(&b)) {
+                if stored.fingerprint != fingerprint {
+                    return Response::problem(
+                        StatusCode::UnprocessableContent,
+                        "Idempotency key reused",
+                        "The key was already used for a different request",
+                        "about:blank",
+                    );
+                }
+                return match stored.response {
+                    Some(response) => response.with_header("Idempotent-Replayed", "true"),
+                    None => Response::problem(
+                        StatusCode::Conflict,
+                        "Request in progress",
+                        "A request with this idempotency key is still being processed",
+                        "about:blank",
+                    )
+                    .with_header("Retry-After", "1"),
+                };
+            }
+            self.store.set(
+                &key,
+                Stored::encode(&fingerprint, None),
+                Some(self.config.lock_ttl),
+            );
+        }
+
+        let mut claim = Claim {
+            store: &self.store,
+            key: &key,
+            settled: false,
+        };
+        let mut response = self.inner.handle(request);
+        if !response.status_code().is_server_error()
+            && let Ok(frozen) = response.freeze(self.config.max_body_bytes)
+        {
+            self.store.set(
+                &key,
+                Stored::encode(&fingerprint, Some(&frozen)),
+                Some(self.config.ttl),
+            );
+            claim.settled = true;
+        }
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Body;
+    use crate::middleware::InMemoryStore;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+    };
+
+    struct Orders {
+        calls: AtomicUsize,
+    }
+
+    impl Handler for Orders {
+        fn handle(&self, request: &Request) -> Response {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            if request.path() == "/slow" {
+                thread::sleep(Duration::from_millis(100));
+            }
+            if request.path() == "/fail" {
+                return Response::internal_server_error();
+            }
+            Response::new(StatusCode::Created).with_body(Body::from(format!("order #{}", n)))
+        }
+    }
+
+    fn idempotency() -> Idempotency<Orders, InMemoryStore> {
+        let orders = Orders {
+            calls: AtomicUsize::new(0),
+        };
+        Idempotency::new(orders, InMemoryStore::new(), IdempotencyConfig::new())
+    }
+
+    fn post(path: &str, key: &str, body: &str) -> Request {
+        let raw = format!(
+            "POST {} HTTP/1.1\r\nHost: x\r\nIdempotency-Key: {}\r\nContent-Length: {}\r\n\r\n{}",
+            path,
+            key,
+            body.len(),
+            body
+        );
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_retries_replay_the_stored_response() {
+        let layer = idempotency();
+
+        let first = layer.handle(&post("/orders", "k1", "{}"));
+        assert_eq!(first.status_code(), StatusCode::Created);
+        assert_eq!(first.headers().get("idempotent-replayed"), None);
+
+        let retry = layer.handle(&post("/orders", "k1", "{}"));
+        assert_eq!(retry.status_code(), StatusCode::Created);
+        assert_eq!(retry.body().as_str(), Ok("order #0"));
+        assert_eq!(retry.headers().get("idempotent-replayed"), Some("true"));
+
+        let reused = layer.handle(&post("/orders", "k1", "{\"other\":1}"));
+        assert_eq!(reused.status_code(), StatusCode::UnprocessableContent);
+
+        let fresh = layer.handle(&post("/orders", "k2", "{}"));
+        assert_eq!(fresh.body().as_str(), Ok("order #1"));
+        assert_eq!(layer.inner.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_concurrent_retries_conflict_and_failures_are_not_stored() {
+        let layer = idempotency();
+
+        let statuses: Vec<StatusCode> = thread::scope(|scope| {
+            let first = scope.spawn(|| layer.handle(&post("/slow", "k", "")).status_code());
+            thread::sleep(Duration::from_millis(30));
+            let second = layer.handle(&post("/slow", "k", "")).status_code();
+            vec![first.join().unwrap(), second]
+        });
+        assert_eq!(statuses, [StatusCode::Created, StatusCode::Conflict]);
+
+        layer.handle(&post("/fail", "f", ""));
+        let retried = layer.handle(&post("/fail", "f", ""));
+        assert_eq!(retried.headers().get("idempotent-replayed"), None);
+        assert_eq!(layer.inner.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_keys_are_scoped_by_caller() {
+        let layer = idempotency();
+        let as_user = |user: &str| {
+            let raw = format!(
+                "POST /orders HTTP/1.1\r\nHost: x\r\nAuthorization: Bearer {}\r\n\
+                 Idempotency-Key: k\r\nContent-Length: 2\r\n\r\n{{}}",
+                user
+            );
+            Request::try_from(raw.as_bytes()).unwrap()
+        };
+
+        let alice = layer.handle(&as_user("alice"));
+        let bob = layer.handle(&as_user("bob"));
+        assert_eq!(bob.headers().get("idempotent-replayed"), None);
+        assert_eq!(bob.body().as_str(), Ok("order #1"));
+
+        let retry = layer.handle(&as_user("alice"));
+        assert_eq!(retry.body().as_str(), alice.body().as_str());
+        assert_eq!(layer.inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{
+    http::{DigestAlgorithm, DigestError, ParseError, Request, Response, content_digest},
+    server::Handler,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegrityConfig {
+    // Reject requests that carry a body without a Content-Digest.
+    pub require: bool,
+    // Digest every response with this, unless the request's Want-Content-Digest
+    // prefers another supported algorithm.
+    pub respond_with: Option<DigestAlgorithm>,
+}
+
+impl Default for IntegrityConfig {
+    fn default() -> Self {
+        IntegrityConfig {
+            require: false,
+            respond_with: Some(DigestAlgorithm::Sha256),
+        }
+    }
+}
+
+impl IntegrityConfig {
+    pub fn require(mut self) -> Self {
+        self.require = true;
+        self
+    }
+
+    pub fn respond_with(mut self, algorithm: Option<DigestAlgorithm>) -> Self {
+        self.respond_with = algorithm;
+        self
+    }
+}
+
+// Content-Digest (RFC 9530) for integrity-sensitive APIs: request bodies that do
+// not match their digest are refused with 400 before the handler runs, and
+// responses are digested so clients can check them in turn.
+pub struct Integrity<H: Handler> {
+    inner: H,
+    config: IntegrityConfig,
+}
+
+impl<H: Handler> Integrity<H> {
+    pub fn new(inner: H, config: IntegrityConfig) -> Self {
+        Integrity { inner, config }
+    }
+
+    fn check(&self, request: &Request) -> Result<(), DigestError> {
+        match request.verify_content_digest() {
+            Err(DigestError::Missing) if !self.config.require || request.body().is_empty() => {
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+impl<H: Handler> Handler for Integrity<H> {
+    fn handle(&self, request: &Request) -> Response {
+        if let Err(e) = self.check(request) {
+            let response = Response::problem(
+                e.status(),
+                "Content digest check failed",
+                &e.to_string(),
+                "about:blank",
+            );
+            // Tell the client which algorithms would be accepted.
+            return match e {
+                DigestError::Missing | DigestError::Unsupported => {
+                    response.with_header("Want-Content-Digest", "sha-256=10, sha-512=5")
+                }
+                _ => response,
+            };
+        }
+        let algorithm = request
+            .header("Want-Content-Digest")
+            .and_then(content_digest::preferred_algorithm)
+            .or(self.config.respond_with);
+        let response = self.inner.handle(request);
+        match algorithm {
+            Some(algorithm) if !response.streams_body() => response.with_content_digest(algorithm),
+            _ => response,
+        }
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use crate::http::{Body, DigestHeader, StatusCode};
+
+    fn request(extra: &str, body: &str) -> Request {
+        let raw = format!(
+            "POST /transfer HTTP/1.1\r\nHost: x\r\nContent-Length: {}\r\n{}\r\n{}",
+            body.len(),
+            extra,
+            body
+        );
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_rejects_mismatched_bodies_and_digests_responses() {
+        let integrity = Integrity::new(
+            handler_fn(|_| Response::ok().with_body(Body::from("done"))),
+            IntegrityConfig::default().require(),
+        );
+        let body = "{\"amount\":10}";
+        let digest = DigestHeader::compute(DigestAlgorithm::Sha256, body.as_bytes());
+
+        let ok = integrity.handle(&request(&format!("Content-Digest: {}\r\n", digest), body));
+        assert_eq!(ok.status_code(), StatusCode::OK);
+        let sent: DigestHeader = ok.headers().get("content-digest").unwrap().parse().unwrap();
+        assert_eq!(sent.verify(b"done"), Ok(()));
+
+        let tampered = integrity.handle(&request(
+            &format!("Content-Digest: {}\r\n", digest),
+            "{\"amount\":99}",
+        ));
+        assert_eq!(tampered.status_code(), StatusCode::BadRequest);
+
+        let missing = integrity.handle(&request("", body));
+        assert_eq!(missing.status_code(), StatusCode::BadRequest);
+        assert!(missing.headers().get("want-content-digest").is_some());
+
+        let preferred = integrity.handle(&request(
+            &format!(
+                "Content-Digest: {}\r\nWant-Content-Digest: sha-512=9\r\n",
+                digest
+            ),
+            body,
+        ));
+        let sent: DigestHeader = preferred
+            .headers()
+            .get("content-digest")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(sent.get(DigestAlgorithm::Sha512).is_some());
+    }
+}
@@ -0,0 +1,354 @@
+use std::{
+    fmt::{self, Debug},
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::{
+    date::DateTime,
+    http::{ParseError, Request, Response},
+    json::Value,
+    logging::log_error,
+    server::Handler,
+};
+
+use super::rotation::{RotatingFile, Rotation};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    // One human-readable line per request.
+    #[default]
+    Text,
+    // One JSON object per line, for log shippers.
+    Json,
+    // Apache/NCSA Common Log Format.
+    Common,
+    // Combined Log Format: Common plus referer and user agent, followed by the
+    // response time in microseconds like Apache's %D.
+    Combined,
+}
+
+#[derive(Clone, Default)]
+pub enum LogTarget {
+    #[default]
+    Stdout,
+    File(PathBuf, Rotation),
+    Writer(Arc<Mutex<dyn Write + Send>>),
+}
+
+impl Debug for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogTarget::Stdout => f.write_str("Stdout"),
+            LogTarget::File(path, rotation) => {
+                f.debug_tuple("File").field(path).field(rotation).finish()
+            }
+            LogTarget::Writer(_) => f.write_str("Writer"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LoggerConfig {
+    pub format: LogFormat,
+    pub target: LogTarget,
+}
+
+impl LoggerConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn json(self) -> Self {
+        self.format(LogFormat::Json)
+    }
+
+    pub fn file(mut self, path: impl Into<PathBuf>, rotation: Rotation) -> Self {
+        self.target = LogTarget::File(path.into(), rotation);
+        self
+    }
+
+    pub fn writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.target = LogTarget::Writer(Arc::new(Mutex::new(writer)));
+        self
+    }
+}
+
+enum Sink {
+    Stdout,
+    File(RotatingFile),
+    Writer(Arc<Mutex<dyn Write + Send>>),
+}
+
+// Formats and writes access log records. Used by the Logger middleware, or by the
+// server itself when enabled with ServerBuilder::access_log.
+pub struct AccessLog {
+    format: LogFormat,
+    sink: Mutex<Sink>,
+}
+
+impl AccessLog {
+    pub fn new(config: LoggerConfig) -> io::Result<Self> {
+        let sink = match &config.target {
+            LogTarget::Stdout => Sink::Stdout,
+            LogTarget::File(path, rotation) => Sink::File(RotatingFile::open(path, *rotation)?),
+            LogTarget::Writer(writer) => Sink::Writer(writer.clone()),
+        };
+        Ok(AccessLog {
+            format: config.format,
+            sink: Mutex::new(sink),
+        })
+    }
+
+    pub fn format(&self) -> LogFormat {
+        self.format
+    }
+
+    // Records a request that took `elapsed` to answer, ending now.
+    pub fn record(&self, request: &Request, response: &Response, elapsed: Duration) {
+        let started = SystemTime::now() - elapsed;
+        self.write(&self.line(request, response, started, elapsed));
+    }
+
+    fn line(
+        &self,
+        request: &Request,
+        response: &Response,
+        started: SystemTime,
+        elapsed: Duration,
+    ) -> String {
+        let ts = DateTime::from_system_time(started).to_rfc3339();
+        let status = response.status_code().as_u16();
+        let bytes = response.body().len();
+        let elapsed_ms = elapsed.as_millis();
+
+        let mut line = match self.format {
+            LogFormat::Text => format!(
+                "{} \"{} {} {}\" {} {} {}ms",
+                ts,
+                request.method().as_str(),
+                request.target(),
+                request.http_version(),
+                status,
+                bytes,
+                elapsed_ms
+            ),
+            LogFormat::Json => {
+                let optional =
+                    |name: &str| request.header(name).map(Value::from).unwrap_or(Value::Null);
+                Value::Object(vec![
+                    ("ts".to_string(), Value::from(ts)),
+                    ("method".to_string(), Value::from(request.method().as_str())),
+                    ("target".to_string(), Value::from(request.target())),
+                    ("version".to_string(), Value::from(request.http_version())),
+                    ("status".to_string(), Value::from(status as u64)),
+                    ("bytes".to_string(), Value::from(bytes as u64)),
+                    ("duration_ms".to_string(), Value::from(elapsed_ms as u64)),
+                    ("user_agent".to_string(), optional("User-Agent")),
+                    ("referer".to_string(), optional("Referer")),
+                ])
+                .to_string()
+            }
+            LogFormat::Common | LogFormat::Combined => {
+                let mut line = format!(
+                    "{} - - [{}] \"{}\" {} {}",
+                    request
+                        .remote_addr()
+                        .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string()),
+                    DateTime::from_system_time(started).to_clf(),
+                    escape(&format!(
+                        "{} {} {}",
+                        request.method().as_str(),
+                        request.target(),
+                        request.http_version()
+                    )),
+                    status,
+                    if bytes == 0 {
+                        "-".to_string()
+                    } else {
+                        bytes.to_string()
+                    }
+                );
+                if self.format == LogFormat::Combined {
+                    let quoted = |name: &str| request.header(name).map_or("-".to_string(), escape);
+                    line.push_str(&format!(
+                        " \"{}\" \"{}\" {}",
+                        quoted("Referer"),
+                        quoted("User-Agent"),
+                        elapsed.as_micros()
+                    ));
+                }
+                line
+            }
+        };
+        line.push('\n');
+        line
+    }
+
+    fn write(&self, line: &str) {
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        let result = match &mut *sink {
+            Sink::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            Sink::File(file) => file.write_record(line.as_bytes()),
+            Sink::Writer(writer) => writer
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .write_all(line.as_bytes()),
+        };
+        if let Err(e) = result {
+            log_error!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+// Quoted CLF fields escape quotes, backslashes and control bytes, so a crafted
+// header cannot forge a log line.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Access log: one record per handled request, written after the response is built.
+pub struct Logger<H: Handler> {
+    inner: H,
+    log: AccessLog,
+}
+
+impl<H: Handler> Logger<H> {
+    pub fn new(inner: H, config: LoggerConfig) -> io::Result<Self> {
+        Ok(Logger {
+            inner,
+            log: AccessLog::new(config)?,
+        })
+    }
+}
+
+impl<H: Handler> Handler for Logger<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let started = SystemTime::now();
+        let timer = Instant::now();
+        let response = self.inner.handle(request);
+        let line = self.log.line(request, &response, started, timer.elapsed());
+        self.log.write(&line);
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        http::{Body, ConnectionContext, request::request_from_buf_reader_in},
+        json,
+        testing::TempDir,
+    };
+
+    struct Hello;
+
+    impl Handler for Hello {
+        fn handle(&self, _request: &Request) -> Response {
+            Response::ok().with_body(Body::from("hello"))
+        }
+    }
+
+    fn request() -> Request {
+        let raw = "GET /a?b=1 HTTP/1.1\r\nHost: x\r\nUser-Agent: probe/1.0\r\n\r\n";
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_formats_text_and_json_lines() {
+        let text = Logger::new(Hello, LoggerConfig::new()).unwrap();
+        let line = text.log.line(
+            &request(),
+            &Response::ok(),
+            SystemTime::UNIX_EPOCH,
+            Duration::from_millis(3),
+        );
+        assert_eq!(
+            line,
+            "1970-01-01T00:00:00.000Z \"GET /a?b=1 HTTP/1.1\" 200 0 3ms\n"
+        );
+
+        let json_logger = Logger::new(Hello, LoggerConfig::new().json()).unwrap();
+        let line = json_logger.log.line(
+            &request(),
+            &Response::ok(),
+            SystemTime::UNIX_EPOCH,
+            Duration::from_millis(3),
+        );
+        let record = json::parse(line.trim_end()).unwrap();
+        assert_eq!(record.get("target").and_then(Value::as_str), Some("/a?b=1"));
+        assert_eq!(record.get("status").and_then(Value::as_f64), Some(200.0));
+        assert_eq!(
+            record.get("user_agent").and_then(Value::as_str),
+            Some("probe/1.0")
+        );
+        assert_eq!(record.get("referer"), Some(&Value::Null));
+    }
+
+    #[test]
+    fn test_formats_common_and_combined_lines() {
+        let raw = "GET /a?b=1 HTTP/1.1\r\nHost: x\r\nReferer: http://x/\"home\"\r\n\r\n";
+        let mut context = ConnectionContext::new().with_peer("192.0.2.7:4242".parse().ok());
+        let request =
+            request_from_buf_reader_in(&mut raw.as_bytes(), &Default::default(), &mut context)
+                .unwrap();
+        let response = Response::ok().with_body(Body::from("hello"));
+        let started = SystemTime::UNIX_EPOCH;
+        let elapsed = Duration::from_micros(1500);
+
+        let common = AccessLog::new(LoggerConfig::new().format(LogFormat::Common)).unwrap();
+        assert_eq!(
+            common.line(&request, &response, started, elapsed),
+            "192.0.2.7 - - [01/Jan/1970:00:00:00 +0000] \"GET /a?b=1 HTTP/1.1\" 200 5\n"
+        );
+
+        let combined = AccessLog::new(LoggerConfig::new().format(LogFormat::Combined)).unwrap();
+        assert_eq!(
+            combined.line(&request, &Response::not_found(), started, elapsed),
+            "192.0.2.7 - - [01/Jan/1970:00:00:00 +0000] \"GET /a?b=1 HTTP/1.1\" 404 - \"http://x/\\\"home\\\"\" \"-\" 1500\n"
+        );
+    }
+
+    #[test]
+    fn test_writes_to_a_rotating_file() {
+        let dir = TempDir::new("logger");
+        let path = dir.join("access.jsonl");
+
+        let logger = Logger::new(
+            Hello,
+            LoggerConfig::new()
+                .json()
+                .file(&path, Rotation::daily().keep(7)),
+        )
+        .unwrap();
+        logger.handle(&request());
+        logger.handle(&request());
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.lines().all(|line| json::parse(line).is_ok()));
+    }
+}
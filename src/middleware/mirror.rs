@@ -0,0 +1,171 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+    },
+    thread,
+};
+
+use crate::{
+    http::{ParseError, Request, Response},
+    logging::log_debug,
+    server::Handler,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MirrorConfig {
+    // Fraction of requests copied to the shadow, from 0.0 to 1.0.
+    pub rate: f64,
+    // Shadow requests still running; past this, copies are skipped rather than
+    // letting a slow shadow pile up threads.
+    pub max_in_flight: usize,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        MirrorConfig {
+            rate: 1.0,
+            max_in_flight: 64,
+        }
+    }
+}
+
+impl MirrorConfig {
+    pub fn rate(mut self, rate: f64) -> Self {
+        self.rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = max;
+        self
+    }
+}
+
+// One running shadow request; the count drops when it ends, panics included.
+struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// Traffic shadowing: copies a share of requests to a second handler, such as a new
+// backend version, on a background thread. Only the inner handler's response is
+// returned; the shadow's is dropped and cannot slow or fail the real request.
+pub struct Mirror<H: Handler, S: Handler + 'static> {
+    inner: H,
+    shadow: Arc<S>,
+    config: MirrorConfig,
+    seen: AtomicU64,
+    in_flight: Arc<AtomicUsize>,
+}
+
+impl<H: Handler, S: Handler + 'static> Mirror<H, S> {
+    pub fn new(inner: H, shadow: S, config: MirrorConfig) -> Self {
+        Mirror {
+            inner,
+            shadow: Arc::new(shadow),
+            config,
+            seen: AtomicU64::new(0),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    // Spreads copies evenly instead of rolling dice: request n is mirrored when
+    // n * rate crosses an integer, so a rate of 0.05 is exactly every 20th.
+    fn sampled(&self) -> bool {
+        let n = self.seen.fetch_add(1, Ordering::Relaxed) as f64;
+        ((n + 1.0) * self.config.rate).floor() > (n * self.config.rate).floor()
+    }
+
+    fn mirror(&self, request: &Request) {
+        let in_flight = InFlight(self.in_flight.clone());
+        if in_flight.0.fetch_add(1, Ordering::AcqRel) >= self.config.max_in_flight {
+            log_debug!(
+                "Mirror: {} shadow requests in flight, skipping",
+                self.config.max_in_flight
+            );
+            return;
+        }
+        let shadow = self.shadow.clone();
+        let copy = request.clone();
+        thread::spawn(move || {
+            let _in_flight = in_flight;
+            drop(shadow.handle(&copy));
+        });
+    }
+}
+
+impl<H: Handler, S: Handler + 'static> Handler for Mirror<H, S> {
+    fn handle(&self, request: &Request) -> Response {
+        if self.sampled() {
+            self.mirror(request);
+        }
+        self.inner.handle(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+    use crate::http::StatusCode;
+    use std::sync::{Mutex, mpsc};
+    use std::time::Duration;
+
+    #[test]
+    fn test_mirrors_the_configured_share_and_keeps_the_real_response() {
+        let (sent, received) = mpsc::channel();
+        let sent = Mutex::new(sent);
+        let shadow = handler_fn(move |request| {
+            let _ = sent
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .send(request.path().to_string());
+            Response::internal_server_error()
+        });
+        let mirror = Mirror::new(
+            handler_fn(|_| Response::ok()),
+            shadow,
+            MirrorConfig::default().rate(0.25),
+        );
+
+        for i in 0..8 {
+            let raw = format!("GET /item/{} HTTP/1.1\r\nHost: x\r\n\r\n", i);
+            let request = Request::try_from(raw.as_bytes()).unwrap();
+            assert_eq!(mirror.handle(&request).status_code(), StatusCode::OK);
+        }
+
+        let mut mirrored: Vec<String> = (0..2)
+            .map(|_| received.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        mirrored.sort();
+        assert_eq!(mirrored, ["/item/3", "/item/7"]);
+        assert!(received.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn test_panicking_shadow_frees_its_slot() {
+        let mirror = Mirror::new(
+            handler_fn(|_| Response::ok()),
+            handler_fn(|_| -> Response { panic!("shadow failed") }),
+            MirrorConfig::default().max_in_flight(1),
+        );
+        let request = Request::try_from(&b"GET / HTTP/1.1\r\nHost: x\r\n\r\n"[..]).unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(mirror.handle(&request).status_code(), StatusCode::OK);
+            let deadline = std::time::Instant::now() + Duration::from_secs(2);
+            while mirror.in_flight.load(Ordering::Acquire) > 0 {
+                assert!(std::time::Instant::now() < deadline, "slot leaked");
+                std::thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+}
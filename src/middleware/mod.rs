@@ -0,0 +1,43 @@
+pub mod access;
+pub mod audit;
+pub mod cache;
+pub mod chain;
+pub mod chaos;
+pub mod coalesce;
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "brotli"))]
+pub mod compress;
+pub mod concurrency;
+pub mod disk_cache;
+pub mod filter;
+pub mod idempotency;
+pub mod integrity;
+pub mod logger;
+pub mod mirror;
+pub mod rotation;
+pub mod store;
+#[cfg(feature = "otel")]
+pub mod trace;
+pub mod transcode;
+pub mod validation;
+
+pub use access::{Acl, AclAction, AclConfig, AclError, AclRule};
+pub use audit::{Audit, AuditConfig};
+pub use cache::{Cache, CacheConfig, CacheEntry, CacheStats, CacheStore, MemoryStore};
+pub use chain::{Chain, Middleware, Next};
+pub use chaos::{Chaos, ChaosConfig};
+pub use coalesce::{Coalesce, CoalesceConfig};
+#[cfg(any(feature = "gzip", feature = "deflate", feature = "brotli"))]
+pub use compress::{Coding, Compress, CompressConfig};
+pub use concurrency::{ConcurrencyConfig, ConcurrencyLimit};
+pub use disk_cache::DiskStore;
+pub use filter::{Filter, FilterConfig};
+pub use idempotency::{Idempotency, IdempotencyConfig};
+pub use integrity::{Integrity, IntegrityConfig};
+pub use logger::{AccessLog, LogFormat, LogTarget, Logger, LoggerConfig};
+pub use mirror::{Mirror, MirrorConfig};
+pub use rotation::{RotatingFile, Rotation};
+pub use store::{InMemoryStore, KeyValueCacheStore, KeyValueStore};
+#[cfg(feature = "otel")]
+pub use trace::Trace;
+pub use transcode::{Transcode, TranscodeConfig};
+pub use validation::{RequestSchema, Schema, Validate};
@@ -0,0 +1,293 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::date::DateTime;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Rotation {
+    pub max_bytes: Option<u64>,
+    pub max_age: Option<Duration>,
+    // Roll over at the first write after UTC midnight.
+    pub daily: bool,
+    // Rotated files to keep; older ones are deleted after each rotation.
+    pub keep: Option<usize>,
+}
+
+impl Rotation {
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    pub fn by_size(max_bytes: u64) -> Self {
+        Rotation {
+            max_bytes: Some(max_bytes),
+            ..Self::default()
+        }
+    }
+
+    pub fn by_age(max_age: Duration) -> Self {
+        Rotation {
+            max_age: Some(max_age),
+            ..Self::default()
+        }
+    }
+
+    pub fn daily() -> Self {
+        Rotation {
+            daily: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn keep(mut self, files: usize) -> Self {
+        self.keep = Some(files);
+        self
+    }
+}
+
+fn today() -> (i64, u32, u32) {
+    let dt = DateTime::now();
+    (dt.year, dt.month, dt.day)
+}
+
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened_at: Instant,
+    opened_on: (i64, u32, u32),
+}
+
+impl RotatingFile {
+    pub fn open(path: impl AsRef<Path>, rotation: Rotation) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path,
+            rotation,
+            file,
+            size,
+            opened_at: Instant::now(),
+            opened_on: today(),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Each record is written with a single write call so concurrent readers never see
+    // half a line; rotation happens between records, never inside one.
+    pub fn write_record(&mut self, record: &[u8]) -> io::Result<()> {
+        if self.should_rotate(record.len() as u64) {
+            self.rotate()?;
+        }
+
+        self.file.write_all(record)?;
+        self.file.flush()?;
+        self.size += record.len() as u64;
+        Ok(())
+    }
+
+    fn should_rotate(&self, incoming: u64) -> bool {
+        let too_big = self
+            .rotation
+            .max_bytes
+            .is_some_and(|max| self.size > 0 && self.size + incoming > max);
+        let too_old = self
+            .rotation
+            .max_age
+            .is_some_and(|max| self.opened_at.elapsed() >= max);
+        let new_day = self.rotation.daily && self.size > 0 && today() != self.opened_on;
+
+        too_big || too_old || new_day
+    }
+
+    pub fn rotate(&mut self) -> io::Result<()> {
+        let dt = DateTime::now();
+        let stamp = format!(
+            "{:04}{:02}{:02}T{:02}{:02}{:02}",
+            dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+        );
+
+        let mut target = self.rotated_name(&stamp, 0);
+        let mut n = 1;
+        while target.exists() {
+            target = self.rotated_name(&stamp, n);
+            n += 1;
+        }
+
+        std::fs::rename(&self.path, &target)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened_at = Instant::now();
+        self.opened_on = today();
+
+        if let Some(keep) = self.rotation.keep {
+            self.prune(keep)?;
+        }
+        Ok(())
+    }
+
+    // Oldest first by last write. Names alone are not enough: a suffix freed by an
+    // earlier prune can be reused within the same second.
+    fn prune(&self, keep: usize) -> io::Result<()> {
+        let Some(dir) = self.path.parent().filter(|d| !d.as_os_str().is_empty()) else {
+            return self.prune_in(Path::new("."), keep);
+        };
+        self.prune_in(dir, keep)
+    }
+
+    fn prune_in(&self, dir: &Path, keep: usize) -> io::Result<()> {
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", name);
+
+        // Only names rotate() produces, so audit.log.gz or audit.log.bak survive.
+        let mut rotated: Vec<(SystemTime, PathBuf)> = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|n| n.strip_prefix(&prefix))
+                    .is_some_and(is_rotation_suffix)
+            })
+            .filter_map(|entry| {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok()?;
+                Some((modified, entry.path()))
+            })
+            .collect();
+        rotated.sort();
+
+        let excess = rotated.len().saturating_sub(keep);
+        for (_, path) in &rotated[..excess] {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn rotated_name(&self, stamp: &str, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(".");
+        name.push(stamp);
+        if n > 0 {
+            name.push(format!(".{}", n));
+        }
+        PathBuf::from(name)
+    }
+}
+
+// `<YYYYMMDD>T<HHMMSS>`, optionally followed by `.<n>`.
+fn is_rotation_suffix(suffix: &str) -> bool {
+    let (stamp, n) = match suffix.split_once('.') {
+        Some((stamp, n)) => (stamp, Some(n)),
+        None => (suffix, None),
+    };
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let stamp_ok = stamp.len() == 15
+        && stamp.as_bytes()[8] == b'T'
+        && digits(&stamp[..8])
+        && digits(&stamp[9..]);
+    stamp_ok && n.is_none_or(digits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn test_rotates_by_size() {
+        let dir = TempDir::new("rotate-size");
+        let path = dir.join("audit.log");
+        let mut file = RotatingFile::open(&path, Rotation::by_size(10)).unwrap();
+
+        file.write_record(b"123456\n").unwrap();
+        file.write_record(b"abcdef\n").unwrap();
+        file.write_record(b"ghijkl\n").unwrap();
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ghijkl\n");
+        let rotated = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(rotated, 3);
+    }
+
+    #[test]
+    fn test_keeps_only_the_newest_rotated_files() {
+        let dir = TempDir::new("rotate-keep");
+        let path = dir.join("access.log");
+        let mut file = RotatingFile::open(&path, Rotation::by_size(4).keep(2)).unwrap();
+
+        for line in ["aaaa\n", "bbbb\n", "cccc\n", "dddd\n", "eeee\n"] {
+            file.write_record(line.as_bytes()).unwrap();
+        }
+
+        let mut rotated: Vec<String> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p != &path)
+            .map(|p| std::fs::read_to_string(p).unwrap())
+            .collect();
+        rotated.sort();
+        assert_eq!(rotated, vec!["cccc\n", "dddd\n"]);
+    }
+
+    #[test]
+    fn test_prune_leaves_unrelated_files_alone() {
+        let dir = TempDir::new("rotate-unrelated");
+        let path = dir.join("access.log");
+        for other in [
+            "access.log.bak",
+            "access.log.gz",
+            "access.log.20240101T000000.gz",
+        ] {
+            std::fs::write(dir.join(other), "keep me").unwrap();
+        }
+        let mut file = RotatingFile::open(&path, Rotation::by_size(4).keep(1)).unwrap();
+
+        for line in ["aaaa\n", "bbbb\n", "cccc\n"] {
+            file.write_record(line.as_bytes()).unwrap();
+        }
+
+        let count = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(count, 5);
+        assert!(dir.join("access.log.bak").exists());
+        assert!(dir.join("access.log.20240101T000000.gz").exists());
+    }
+
+    #[test]
+    fn test_rotation_suffixes() {
+        assert!(is_rotation_suffix("20240101T120000"));
+        assert!(is_rotation_suffix("20240101T120000.3"));
+        assert!(!is_rotation_suffix("bak"));
+        assert!(!is_rotation_suffix("20240101T120000.gz"));
+        assert!(!is_rotation_suffix("20240101-120000"));
+        assert!(!is_rotation_suffix("20240101T12000"));
+        assert!(!is_rotation_suffix("20240101T120000."));
+    }
+
+    #[test]
+    fn test_never_rotates_without_policy() {
+        let dir = TempDir::new("rotate-never");
+        let path = dir.join("audit.log");
+        let mut file = RotatingFile::open(&path, Rotation::never()).unwrap();
+
+        for _ in 0..10 {
+            file.write_record(b"line\n").unwrap();
+        }
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::cache::{CacheEntry, CacheStore};
+
+// Shared storage contract for middleware state that may outlive a process or be
+// shared between instances: sessions, rate-limit counters and cached responses.
+// Implement it over an external service (Redis, memcached, a database) and hand it
+// to any of those middlewares. Values are opaque bytes; a ttl of None means the
+// entry never expires on its own.
+pub trait KeyValueStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>);
+
+    fn delete(&self, key: &str);
+
+    // Remaining lifetime of a live entry. None when the key is missing or has no
+    // expiry; use get to tell those apart.
+    fn ttl(&self, key: &str) -> Option<Duration>;
+}
+
+// One store instance can back several middlewares at once.
+impl<S: KeyValueStore + ?Sized> KeyValueStore for Arc<S> {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        (**self).get(key)
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        (**self).set(key, value, ttl)
+    }
+
+    fn delete(&self, key: &str) {
+        (**self).delete(key)
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        (**self).ttl(key)
+    }
+}
+
+#[derive(Debug)]
+struct Slot {
+    value: Vec<u8>,
+    expires: Option<Instant>,
+}
+
+impl Slot {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires.is_none_or(|expires| expires > now)
+    }
+}
+
+// The default process-local store. Expired entries are dropped lazily when read
+// and swept whenever the map grows past twice its size at the last sweep.
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    slots: Mutex<HashMap<String, Slot>>,
+    swept_at: Mutex<usize>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        let now = Instant::now();
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .filter(|slot| slot.is_live(now))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn sweep(slots: &mut HashMap<String, Slot>, swept_at: &mut usize) {
+        if slots.len() < (*swept_at * 2).max(64) {
+            return;
+        }
+        let now = Instant::now();
+        slots.retain(|_, slot| slot.is_live(now));
+        *swept_at = slots.len();
+    }
+}
+
+impl KeyValueStore for InMemoryStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        match slots.get(key) {
+            Some(slot) if slot.is_live(Instant::now()) => Some(slot.value.clone()),
+            Some(_) => {
+                slots.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn set(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let mut swept_at = self.swept_at.lock().unwrap_or_else(|e| e.into_inner());
+        Self::sweep(&mut slots, &mut swept_at);
+        let expires = ttl.map(|ttl| Instant::now() + ttl);
+        slots.insert(key.to_string(), Slot { value, expires });
+    }
+
+    fn delete(&self, key: &str) {
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+
+    fn ttl(&self, key: &str) -> Option<Duration> {
+        let slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        let expires = slots.get(key)?.expires?;
+        expires.checked_duration_since(Instant::now())
+    }
+}
+
+// Lets Cache run on any KeyValueStore. Entries are written with a ttl covering
+// their stale windows, so the backing store expires them on its own. Capacity is
+// the store's business; len only counts keys this instance has written and not
+// seen disappear, since a shared store cannot be enumerated through the trait.
+pub struct KeyValueCacheStore<S: KeyValueStore> {
+    store: S,
+    prefix: String,
+    keys: Mutex<HashSet<String>>,
+}
+
+impl<S: KeyValueStore> KeyValueCacheStore<S> {
+    pub fn new(store: S) -> Self {
+        KeyValueCacheStore {
+            store,
+            prefix: "cache:".to_string(),
+            keys: Mutex::new(HashSet::new()),
+        }
+    }
+
+    // Namespaces cache keys when the store is shared with other middleware.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    fn forget(&self, key: &str) {
+        self.keys
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(key);
+    }
+}
+
+impl<S: KeyValueStore> CacheStore for KeyValueCacheStore<S> {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        let stored = self.store.get(&format!("{}{}", self.prefix, key));
+        let decoded = stored
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|s| CacheEntry::decode(&s))
+            .filter(|(stored_key, _)| stored_key == key);
+        match decoded {
+            Some((_, entry)) => Some(entry),
+            None => {
+                self.forget(key);
+                None
+            }
+        }
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let ttl = entry.retention();
+        let value = entry.encode(key).into_bytes();
+        self.store
+            .set(&format!("{}{}", self.prefix, key), value, Some(ttl));
+        self.keys
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key.to_string());
+    }
+
+    fn remove(&self, key: &str) {
+        self.store.delete(&format!("{}{}", self.prefix, key));
+        self.forget(key);
+    }
+
+    fn len(&self) -> usize {
+        self.keys.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Body, Response};
+    use std::{thread, time::SystemTime};
+
+    #[test]
+    fn test_in_memory_store_expires_entries() {
+        let store = InMemoryStore::new();
+        store.set("a", b"1".to_vec(), None);
+        store.set("b", b"2".to_vec(), Some(Duration::from_millis(20)));
+
+        assert_eq!(store.get("a"), Some(b"1".to_vec()));
+        assert_eq!(store.ttl("a"), None);
+        assert!(store.ttl("b").unwrap() <= Duration::from_millis(20));
+
+        thread::sleep(Duration::from_millis(40));
+        assert_eq!(store.get("b"), None);
+        assert_eq!(store.ttl("b"), None);
+        assert_eq!(store.len(), 1);
+
+        store.delete("a");
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_cache_entries_round_trip_through_a_key_value_store() {
+        let store = KeyValueCacheStore::new(InMemoryStore::new()).with_prefix("c:");
+        let entry = CacheEntry {
+            response: Response::ok().with_body(Body::from("cached")),
+            stored: SystemTime::now(),
+            ttl: Duration::from_secs(60),
+            stale_while_revalidate: Duration::from_secs(5),
+            stale_if_error: Duration::from_secs(30),
+            vary: vec![("accept-language".to_string(), "de".to_string())],
+        };
+        store.put("GET /a", entry);
+
+        let raw = store.store().get("c:GET /a").unwrap();
+        assert!(!raw.is_empty());
+        assert!(store.store().ttl("c:GET /a").unwrap() > Duration::from_secs(60));
+
+        let cached = store.get("GET /a").unwrap();
+        assert_eq!(cached.response.body().as_str(), Ok("cached"));
+        assert_eq!(
+            cached.vary,
+            [("accept-language".to_string(), "de".to_string())]
+        );
+        assert_eq!(store.len(), 1);
+
+        store.remove("GET /a");
+        assert!(store.get("GET /a").is_none());
+        assert!(store.is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use crate::{
+    http::{ParseError, Request, Response},
+    otel::{SpanKind, SpanStatus, TraceContext, Tracer},
+    server::Handler,
+};
+
+// Records a server span per request. The span runs in the context the server
+// attached to the request, so the inner handler can start client spans under it
+// via request.trace_context() and propagate them upstream.
+pub struct Trace<H: Handler> {
+    inner: H,
+    tracer: Tracer,
+}
+
+impl<H: Handler> Trace<H> {
+    pub fn new(inner: H, tracer: Tracer) -> Self {
+        Trace { inner, tracer }
+    }
+
+    pub fn tracer(&self) -> &Tracer {
+        &self.tracer
+    }
+}
+
+impl<H: Handler> Handler for Trace<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let context = request
+            .trace_context()
+            .cloned()
+            .unwrap_or_else(|| TraceContext::for_request(request));
+        let method = request.method().as_str();
+        let mut span = self.tracer.start(method, SpanKind::Server, context);
+        span.set_attribute("http.request.method", method);
+        span.set_attribute("url.path", request.path());
+        if let Some(agent) = request.header("User-Agent") {
+            span.set_attribute("user_agent.original", agent);
+        }
+
+        let response = self.inner.handle(request);
+        let status = response.status_code();
+        span.set_attribute("http.response.status_code", status.as_u16() as u64);
+        // Per the HTTP semantic conventions, only 5xx marks a server span as failed.
+        if status.as_u16() >= 500 {
+            span.set_status(SpanStatus::Error(status.to_string()));
+        }
+        span.end();
+        response
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::otel::Span;
+    use std::sync::{Arc, Mutex};
+
+    struct Upstream(Tracer);
+
+    impl Handler for Upstream {
+        fn handle(&self, request: &Request) -> Response {
+            let span = self.0.start_client("GET", request.trace_context());
+            let mut headers = crate::http::Headers::new();
+            span.inject(&mut headers);
+            Response::internal_server_error()
+        }
+    }
+
+    #[test]
+    fn test_server_and_client_spans_share_the_trace() {
+        let exported = Arc::new(Mutex::new(Vec::new()));
+        let sink = exported.clone();
+        let tracer = Tracer::new(move |span: &Span| sink.lock().unwrap().push(span.clone()));
+        let trace = Trace::new(Upstream(tracer.clone()), tracer);
+
+        let raw = "GET /a HTTP/1.1\r\nHost: x\r\ntraceparent: 00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01\r\n\r\n";
+        let mut request = Request::try_from(raw.as_bytes()).unwrap();
+        let context = TraceContext::for_request(&request);
+        request.extensions_mut().insert(context.clone());
+        trace.handle(&request);
+
+        let exported = exported.lock().unwrap();
+        let [client, server] = exported.as_slice() else {
+            panic!("expected two spans, got {}", exported.len());
+        };
+        assert_eq!(server.context, context);
+        assert_eq!(
+            server
+                .context
+                .parent_span_id
+                .map(|id| id.to_string())
+                .as_deref(),
+            Some("b7ad6b7169203331")
+        );
+        assert!(matches!(server.status, SpanStatus::Error(_)));
+        assert_eq!(client.kind, SpanKind::Client);
+        assert_eq!(client.context.trace_id, server.context.trace_id);
+        assert_eq!(client.context.parent_span_id, Some(server.context.span_id));
+    }
+}
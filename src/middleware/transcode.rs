@@ -0,0 +1,125 @@
+use crate::{
+    http::{
+        Body, ParseError, Request, Response,
+        charset::{self, Charset, ToUtf8},
+        filter,
+    },
+    server::Handler,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TranscodeConfig {
+    // Charset assumed for text/* responses that name none, as old upstreams often
+    // relied on the ISO-8859-1 default of HTTP/1.0. None leaves them alone.
+    pub assume: Option<Charset>,
+}
+
+impl TranscodeConfig {
+    pub fn assume(mut self, charset: Charset) -> Self {
+        self.assume = Some(charset);
+        self
+    }
+}
+
+// Serves text in legacy single-byte charsets as UTF-8, relabelling the
+// Content-Type to match. Responses in other or unknown charsets, encoded bodies
+// and takeovers pass through unchanged.
+pub struct Transcode<H: Handler> {
+    inner: H,
+    config: TranscodeConfig,
+}
+
+impl<H: Handler> Transcode<H> {
+    pub fn new(inner: H, config: TranscodeConfig) -> Self {
+        Transcode { inner, config }
+    }
+
+    fn source_charset(&self, response: &Response) -> Option<Charset> {
+        let headers = response.headers();
+        if response.streams_body()
+            || headers
+                .get("Content-Encoding")
+                .is_some_and(|e| !e.trim().eq_ignore_ascii_case("identity"))
+        {
+            return None;
+        }
+        let content_type = headers.get("Content-Type")?;
+        match charset::content_type_charset(content_type) {
+            Some(label) => Charset::from_label(label),
+            None if content_type
+                .trim()
+                .to_ascii_lowercase()
+                .starts_with("text/") =>
+            {
+                self.config.assume
+            }
+            None => None,
+        }
+    }
+}
+
+impl<H: Handler> Handler for Transcode<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let mut response = self.inner.handle(request);
+        let Some(source) = self.source_charset(&response) else {
+            return response;
+        };
+        let content_type = response.headers().get("Content-Type").unwrap_or_default();
+        let relabelled = charset::with_utf8_charset(content_type);
+        let body = response.take_body();
+        if !body.as_bytes().is_ascii() {
+            for name in ["ETag", "Content-Digest", "Repr-Digest"] {
+                response.headers.remove(name);
+            }
+        }
+        let utf8 = filter::apply(&mut ToUtf8(source), body.as_bytes(), body.len());
+        response
+            .with_header("Content-Type", &relabelled)
+            .with_body(Body::from(utf8))
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::handler_fn;
+
+    #[test]
+    fn test_transcodes_declared_and_assumed_charsets() {
+        let transcode = Transcode::new(
+            handler_fn(|request| {
+                let content_type = match request.path() {
+                    "/declared" => "text/html; charset=windows-1252",
+                    "/bare" => "text/plain",
+                    _ => "application/octet-stream",
+                };
+                Response::ok()
+                    .with_header("Content-Type", content_type)
+                    .with_body(Body::from(b"na\xefve".to_vec()))
+            }),
+            TranscodeConfig::default().assume(Charset::Windows1252),
+        );
+        let get = |path: &str| {
+            let raw = format!("GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path);
+            transcode.handle(&Request::try_from(raw.as_bytes()).unwrap())
+        };
+
+        for path in ["/declared", "/bare"] {
+            let response = get(path);
+            assert_eq!(response.body().as_str().unwrap(), "naïve");
+            assert_eq!(response.headers().get("content-length"), Some("6"));
+            assert!(
+                response
+                    .headers()
+                    .get("content-type")
+                    .unwrap()
+                    .ends_with("; charset=utf-8")
+            );
+        }
+        assert_eq!(get("/binary").body().as_bytes(), b"na\xefve");
+    }
+}
@@ -0,0 +1,609 @@
+use crate::{
+    http::{Method, Problem, Request, Response, StatusCode},
+    json::{self, Value},
+    server::Handler,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schema {
+    Any,
+    Null,
+    Boolean,
+    Number {
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+        integer: bool,
+    },
+    String {
+        min_length: Option<usize>,
+        max_length: Option<usize>,
+        allowed: Vec<String>,
+    },
+    Array {
+        items: Box<Schema>,
+        min_items: Option<usize>,
+        max_items: Option<usize>,
+    },
+    Object {
+        properties: Vec<(String, Schema)>,
+        required: Vec<String>,
+        additional_properties: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError {
+    pub pointer: String,
+    pub message: String,
+}
+
+impl Schema {
+    pub fn number() -> Self {
+        Schema::Number {
+            minimum: None,
+            maximum: None,
+            integer: false,
+        }
+    }
+
+    pub fn integer() -> Self {
+        Schema::Number {
+            minimum: None,
+            maximum: None,
+            integer: true,
+        }
+    }
+
+    pub fn string() -> Self {
+        Schema::String {
+            min_length: None,
+            max_length: None,
+            allowed: Vec::new(),
+        }
+    }
+
+    pub fn array(items: Schema) -> Self {
+        Schema::Array {
+            items: Box::new(items),
+            min_items: None,
+            max_items: None,
+        }
+    }
+
+    pub fn object() -> Self {
+        Schema::Object {
+            properties: Vec::new(),
+            required: Vec::new(),
+            additional_properties: true,
+        }
+    }
+
+    pub fn minimum(mut self, value: f64) -> Self {
+        if let Schema::Number { minimum, .. } = &mut self {
+            *minimum = Some(value);
+        }
+        self
+    }
+
+    pub fn maximum(mut self, value: f64) -> Self {
+        if let Schema::Number { maximum, .. } = &mut self {
+            *maximum = Some(value);
+        }
+        self
+    }
+
+    pub fn min_length(mut self, value: usize) -> Self {
+        match &mut self {
+            Schema::String { min_length, .. } => *min_length = Some(value),
+            Schema::Array { min_items, .. } => *min_items = Some(value),
+            _ => {}
+        }
+        self
+    }
+
+    pub fn max_length(mut self, value: usize) -> Self {
+        match &mut self {
+            Schema::String { max_length, .. } => *max_length = Some(value),
+            Schema::Array { max_items, .. } => *max_items = Some(value),
+            _ => {}
+        }
+        self
+    }
+
+    pub fn one_of(mut self, values: &[&str]) -> Self {
+        if let Schema::String { allowed, .. } = &mut self {
+            *allowed = values.iter().map(|v| v.to_string()).collect();
+        }
+        self
+    }
+
+    pub fn property(mut self, name: &str, schema: Schema) -> Self {
+        if let Schema::Object { properties, .. } = &mut self {
+            properties.push((name.to_string(), schema));
+        }
+        self
+    }
+
+    pub fn required(mut self, name: &str) -> Self {
+        if let Schema::Object { required, .. } = &mut self {
+            required.push(name.to_string());
+        }
+        self
+    }
+
+    pub fn deny_additional(mut self) -> Self {
+        if let Schema::Object {
+            additional_properties,
+            ..
+        } = &mut self
+        {
+            *additional_properties = false;
+        }
+        self
+    }
+
+    // Supports the "type", "properties", "required", "additionalProperties", "items",
+    // "minimum", "maximum", "minLength", "maxLength", "minItems", "maxItems" and
+    // string "enum" keywords of JSON Schema.
+    pub fn from_json(schema: &Value) -> Option<Self> {
+        let usize_of = |key: &str| schema.get(key).and_then(Value::as_f64).map(|n| n as usize);
+
+        let mut result = match schema.get("type").and_then(Value::as_str) {
+            None => return Some(Schema::Any),
+            Some("null") => Schema::Null,
+            Some("boolean") => Schema::Boolean,
+            Some("number") => Schema::number(),
+            Some("integer") => Schema::integer(),
+            Some("string") => Schema::string(),
+            Some("array") => {
+                let items = match schema.get("items") {
+                    Some(items) => Schema::from_json(items)?,
+                    None => Schema::Any,
+                };
+                Schema::array(items)
+            }
+            Some("object") => {
+                let mut object = Schema::object();
+                if let Some(Value::Object(properties)) = schema.get("properties") {
+                    for (name, property) in properties {
+                        object = object.property(name, Schema::from_json(property)?);
+                    }
+                }
+                for name in schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .unwrap_or(&[])
+                {
+                    object = object.required(name.as_str()?);
+                }
+                if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                    object = object.deny_additional();
+                }
+                object
+            }
+            Some(_) => return None,
+        };
+
+        if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+            result = result.minimum(min);
+        }
+        if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+            result = result.maximum(max);
+        }
+        if let Some(min) = usize_of("minLength").or(usize_of("minItems")) {
+            result = result.min_length(min);
+        }
+        if let Some(max) = usize_of("maxLength").or(usize_of("maxItems")) {
+            result = result.max_length(max);
+        }
+        if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+            let values: Vec<&str> = values.iter().filter_map(Value::as_str).collect();
+            result = result.one_of(&values);
+        }
+
+        Some(result)
+    }
+
+    pub fn validate(&self, value: &Value) -> Vec<ValidationError> {
+        let mut errors = Vec::new();
+        self.validate_at(value, "", &mut errors);
+        errors
+    }
+
+    fn validate_at(&self, value: &Value, pointer: &str, errors: &mut Vec<ValidationError>) {
+        let mut fail = |message: String| {
+            errors.push(ValidationError {
+                pointer: pointer.to_string(),
+                message,
+            })
+        };
+
+        match (self, value) {
+            (Schema::Any, _) | (Schema::Null, Value::Null) | (Schema::Boolean, Value::Bool(_)) => {}
+            (
+                Schema::Number {
+                    minimum,
+                    maximum,
+                    integer,
+                },
+                Value::Number(n),
+            ) => {
+                if *integer && n.fract() != 0.0 {
+                    fail("must be an integer".to_string());
+                }
+                if let Some(min) = minimum.filter(|min| n < min) {
+                    fail(format!("must be >= {}", min));
+                }
+                if let Some(max) = maximum.filter(|max| n > max) {
+                    fail(format!("must be <= {}", max));
+                }
+            }
+            (
+                Schema::String {
+                    min_length,
+                    max_length,
+                    allowed,
+                },
+                Value::String(s),
+            ) => {
+                let len = s.chars().count();
+                if let Some(min) = min_length.filter(|min| len < *min) {
+                    fail(format!("must be at least {} characters", min));
+                }
+                if let Some(max) = max_length.filter(|max| len > *max) {
+                    fail(format!("must be at most {} characters", max));
+                }
+                if !allowed.is_empty() && !allowed.contains(s) {
+                    fail(format!("must be one of: {}", allowed.join(", ")));
+                }
+            }
+            (
+                Schema::Array {
+                    items,
+                    min_items,
+                    max_items,
+                },
+                Value::Array(values),
+            ) => {
+                if let Some(min) = min_items.filter(|min| values.len() < *min) {
+                    fail(format!("must contain at least {} items", min));
+                }
+                if let Some(max) = max_items.filter(|max| values.len() > *max) {
+                    fail(format!("must contain at most {} items", max));
+                }
+                for (i, item) in values.iter().enumerate() {
+                    items.validate_at(item, &format!("{}/{}", pointer, i), errors);
+                }
+            }
+            (
+                Schema::Object {
+                    properties,
+                    required,
+                    additional_properties,
+                },
+                Value::Object(members),
+            ) => {
+                for name in required {
+                    if !members.iter().any(|(key, _)| key == name) {
+                        errors.push(ValidationError {
+                            pointer: format!("{}/{}", pointer, escape_pointer(name)),
+                            message: "is required".to_string(),
+                        });
+                    }
+                }
+                for (key, member) in members {
+                    let member_pointer = format!("{}/{}", pointer, escape_pointer(key));
+                    match properties.iter().find(|(name, _)| name == key) {
+                        Some((_, schema)) => schema.validate_at(member, &member_pointer, errors),
+                        None if !additional_properties => errors.push(ValidationError {
+                            pointer: member_pointer,
+                            message: "is not allowed".to_string(),
+                        }),
+                        None => {}
+                    }
+                }
+            }
+            (schema, value) => fail(format!(
+                "expected {}, got {}",
+                schema.type_name(),
+                value.type_name()
+            )),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Schema::Any => "any",
+            Schema::Null => "null",
+            Schema::Boolean => "boolean",
+            Schema::Number { integer: true, .. } => "integer",
+            Schema::Number { .. } => "number",
+            Schema::String { .. } => "string",
+            Schema::Array { .. } => "array",
+            Schema::Object { .. } => "object",
+        }
+    }
+
+    // Query parameters arrive as strings; coerce them to the JSON type the schema expects.
+    fn coerce(&self, raw: &str) -> Value {
+        match self {
+            Schema::Number { .. } => raw
+                .parse::<f64>()
+                .map(Value::Number)
+                .unwrap_or_else(|_| Value::from(raw)),
+            Schema::Boolean => match raw {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::from(raw),
+            },
+            Schema::Null if raw.is_empty() => Value::Null,
+            _ => Value::from(raw),
+        }
+    }
+}
+
+fn escape_pointer(s: &str) -> String {
+    s.replace('~', "~0").replace('/', "~1")
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RequestSchema {
+    body: Option<Schema>,
+    query: Vec<(String, Schema, bool)>,
+}
+
+impl RequestSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn body(mut self, schema: Schema) -> Self {
+        self.body = Some(schema);
+        self
+    }
+
+    pub fn query(mut self, name: &str, schema: Schema) -> Self {
+        self.query.push((name.to_string(), schema, true));
+        self
+    }
+
+    pub fn optional_query(mut self, name: &str, schema: Schema) -> Self {
+        self.query.push((name.to_string(), schema, false));
+        self
+    }
+
+    // Part of the public API, so the rejection stays unboxed even though Response
+    // has outgrown clippy's size threshold for error types.
+    #[allow(clippy::result_large_err)]
+    pub fn validate(&self, request: &Request) -> Result<(), Response> {
+        let mut errors = Vec::new();
+
+        for (name, schema, required) in &self.query {
+            let pointer = format!("/query/{}", escape_pointer(name));
+            match request.query().get_all(name) {
+                Some(values) => {
+                    for raw in values {
+                        schema.validate_at(&schema.coerce(raw), &pointer, &mut errors);
+                    }
+                }
+                None if *required => errors.push(ValidationError {
+                    pointer,
+                    message: "is required".to_string(),
+                }),
+                None => {}
+            }
+        }
+
+        if let Some(schema) = &self.body {
+            let content_type = request.header("Content-Type").unwrap_or("");
+            let media_type = content_type.split(';').next().unwrap_or("").trim();
+            if !media_type.eq_ignore_ascii_case("application/json") {
+                return Err(Problem::new(StatusCode::UnsupportedMediaType)
+                    .with_detail("Expected an application/json request body")
+                    .into_response(request.problem_format()));
+            }
+
+            let body = request.body_as_str().unwrap_or("");
+            match json::parse(body) {
+                Ok(value) => {
+                    let start = errors.len();
+                    schema.validate_at(&value, "", &mut errors);
+                    for error in &mut errors[start..] {
+                        error.pointer.insert_str(0, "/body");
+                    }
+                }
+                Err(e) => {
+                    return Err(Problem::new(StatusCode::BadRequest)
+                        .with_detail(format!("Malformed JSON body: {}", e))
+                        .into_response(request.problem_format()));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+
+        let details = errors
+            .into_iter()
+            .map(|e| {
+                Value::Object(vec![
+                    ("pointer".to_string(), Value::from(e.pointer)),
+                    ("detail".to_string(), Value::from(e.message)),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        Err(Problem::new(StatusCode::UnprocessableContent)
+            .with_detail("Request failed validation")
+            .with_extension("errors", details)
+            .into_response(request.problem_format()))
+    }
+}
+
+pub struct Validate<H: Handler> {
+    inner: H,
+    routes: Vec<(Method, String, RequestSchema)>,
+}
+
+impl<H: Handler> Validate<H> {
+    pub fn new(inner: H) -> Self {
+        Validate {
+            inner,
+            routes: Vec::new(),
+        }
+    }
+
+    pub fn route(mut self, method: Method, path: &str, schema: RequestSchema) -> Self {
+        self.routes.push((method, path.to_string(), schema));
+        self
+    }
+}
+
+impl<H: Handler> Handler for Validate<H> {
+    fn handle(&self, request: &Request) -> Response {
+        let schema = self
+            .routes
+            .iter()
+            .find(|(method, path, _)| method == request.method() && path == request.path());
+
+        if let Some((_, _, schema)) = schema
+            && let Err(response) = schema.validate(request)
+        {
+            return response;
+        }
+
+        self.inner.handle(request)
+    }
+
+    fn handle_bad_request(&self, e: &crate::http::ParseError) -> Response {
+        self.inner.handle_bad_request(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ok200;
+
+    impl Handler for Ok200 {
+        fn handle(&self, _request: &Request) -> Response {
+            Response::ok()
+        }
+    }
+
+    fn user_schema() -> Schema {
+        Schema::object()
+            .property("name", Schema::string().min_length(1).max_length(20))
+            .property("age", Schema::integer().minimum(0.0))
+            .property("role", Schema::string().one_of(&["admin", "user"]))
+            .required("name")
+            .deny_additional()
+    }
+
+    fn request(raw: &str) -> Request {
+        Request::try_from(raw.as_bytes()).unwrap()
+    }
+
+    fn post_json(body: &str) -> Request {
+        request(&format!(
+            "POST /users HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        ))
+    }
+
+    #[test]
+    fn test_schema_validate() {
+        let value = json::parse("{\"name\":\"\",\"age\":1.5,\"role\":\"root\",\"x\":1}").unwrap();
+        let errors = user_schema().validate(&value);
+        let pointers: Vec<&str> = errors.iter().map(|e| e.pointer.as_str()).collect();
+
+        assert_eq!(pointers, vec!["/name", "/age", "/role", "/x"]);
+        assert!(
+            user_schema()
+                .validate(&json::parse("{\"name\":\"a\"}").unwrap())
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_schema_from_json() {
+        let document = json::parse(
+            "{\"type\":\"object\",\"required\":[\"tags\"],\
+             \"properties\":{\"tags\":{\"type\":\"array\",\"items\":{\"type\":\"string\"},\"maxItems\":2}}}",
+        )
+        .unwrap();
+        let schema = Schema::from_json(&document).unwrap();
+
+        let errors = schema.validate(&json::parse("{\"tags\":[\"a\",1,\"c\"]}").unwrap());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].pointer, "/tags");
+        assert_eq!(errors[1].pointer, "/tags/1");
+        assert_eq!(errors[0].message, "must contain at most 2 items");
+    }
+
+    #[test]
+    fn test_layer_rejects_invalid_body_with_422() {
+        let layer = Validate::new(Ok200).route(
+            Method::POST,
+            "/users",
+            RequestSchema::new().body(user_schema()),
+        );
+
+        let response = layer.handle(&post_json("{\"age\":-1}"));
+        assert_eq!(response.status_code(), StatusCode::UnprocessableContent);
+
+        let problem = json::parse(response.body().as_str().unwrap()).unwrap();
+        let errors = problem.get("errors").and_then(Value::as_array).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[0].get("pointer").and_then(Value::as_str),
+            Some("/body/name")
+        );
+
+        let response = layer.handle(&post_json("{\"name\":\"alice\"}"));
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_layer_rejects_malformed_or_wrong_type() {
+        let layer = Validate::new(Ok200).route(
+            Method::POST,
+            "/users",
+            RequestSchema::new().body(user_schema()),
+        );
+
+        let response = layer.handle(&post_json("{\"name\":"));
+        assert_eq!(response.status_code(), StatusCode::BadRequest);
+
+        let response = layer.handle(&request(
+            "POST /users HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nhi",
+        ));
+        assert_eq!(response.status_code(), StatusCode::UnsupportedMediaType);
+    }
+
+    #[test]
+    fn test_layer_validates_query() {
+        let layer = Validate::new(Ok200).route(
+            Method::GET,
+            "/items",
+            RequestSchema::new()
+                .query("page", Schema::integer().minimum(1.0))
+                .optional_query("sort", Schema::string().one_of(&["asc", "desc"])),
+        );
+
+        let response = layer.handle(&request("GET /items?page=2&sort=asc HTTP/1.1"));
+        assert_eq!(response.status_code(), StatusCode::OK);
+
+        let response = layer.handle(&request("GET /items?page=0&sort=up HTTP/1.1"));
+        assert_eq!(response.status_code(), StatusCode::UnprocessableContent);
+
+        let response = layer.handle(&request("GET /items HTTP/1.1"));
+        assert_eq!(response.status_code(), StatusCode::UnprocessableContent);
+
+        let response = layer.handle(&request("GET /other HTTP/1.1"));
+        assert_eq!(response.status_code(), StatusCode::OK);
+    }
+}
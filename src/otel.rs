@@ -0,0 +1,527 @@
+use std::{
+    fmt::{self, Debug, Display},
+    io::{self, Write},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+use crate::logging::log_warn;
+use crate::random::random_u64;
+use crate::{
+    http::{Headers, Request},
+    json::Value,
+};
+
+// W3C Trace Context (https://www.w3.org/TR/trace-context/) and just enough of the
+// OpenTelemetry span model to take part in a distributed trace without pulling in
+// an SDK. Spans go to a SpanExporter as they end.
+
+const SAMPLED: u8 = 0x01;
+// The spec lets vendors cap tracestate at 32 list members; longer values are dropped.
+const MAX_TRACE_STATE_MEMBERS: usize = 32;
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    let valid = s.len() == N * 2 && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+    if !valid {
+        return None;
+    }
+    let mut bytes = [0; N];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn write_hex(f: &mut fmt::Formatter<'_>, bytes: &[u8]) -> fmt::Result {
+    bytes.iter().try_for_each(|b| write!(f, "{:02x}", b))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraceId([u8; 16]);
+
+impl TraceId {
+    pub fn random() -> Self {
+        let mut bytes = [0; 16];
+        bytes[..8].copy_from_slice(&random_u64().to_be_bytes());
+        bytes[8..].copy_from_slice(&random_u64().to_be_bytes());
+        TraceId(bytes)
+    }
+
+    // All-zero ids are invalid on the wire.
+    pub fn parse(s: &str) -> Option<Self> {
+        parse_hex(s).map(TraceId).filter(|id| id.0 != [0; 16])
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl Debug for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TraceId({})", self)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpanId([u8; 8]);
+
+impl SpanId {
+    pub fn random() -> Self {
+        match random_u64() {
+            0 => SpanId(1u64.to_be_bytes()),
+            n => SpanId(n.to_be_bytes()),
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        parse_hex(s).map(SpanId).filter(|id| id.0 != [0; 8])
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 8] {
+        &self.0
+    }
+}
+
+impl Display for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_hex(f, &self.0)
+    }
+}
+
+impl Debug for SpanId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SpanId({})", self)
+    }
+}
+
+// Identifies one span within a trace. `parent_span_id` is the span this one was
+// started under, possibly in another process; a context parsed straight off the
+// wire has none, since its span_id already names the remote caller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    pub trace_id: TraceId,
+    pub span_id: SpanId,
+    pub parent_span_id: Option<SpanId>,
+    pub flags: u8,
+    pub trace_state: Option<String>,
+}
+
+impl TraceContext {
+    // Starts a new, sampled trace.
+    pub fn root() -> Self {
+        TraceContext {
+            trace_id: TraceId::random(),
+            span_id: SpanId::random(),
+            parent_span_id: None,
+            flags: SAMPLED,
+            trace_state: None,
+        }
+    }
+
+    // A span started under this one, in the same trace and with the same sampling
+    // decision and vendor state.
+    pub fn child(&self) -> Self {
+        TraceContext {
+            trace_id: self.trace_id,
+            span_id: SpanId::random(),
+            parent_span_id: Some(self.span_id),
+            flags: self.flags,
+            trace_state: self.trace_state.clone(),
+        }
+    }
+
+    // Parses a traceparent header and its optional tracestate companion. Versions
+    // after 00 are read as far as the 00 layout goes, as the spec requires.
+    pub fn parse(traceparent: &str, tracestate: Option<&str>) -> Option<Self> {
+        let traceparent = traceparent.trim();
+        let version = traceparent.get(..2)?;
+        let version = parse_hex::<1>(version)?[0];
+        if version == 0xff {
+            return None;
+        }
+        let fields = traceparent.get(..55)?;
+        let rest = &traceparent[55..];
+        if (version == 0 && !rest.is_empty()) || (!rest.is_empty() && !rest.starts_with('-')) {
+            return None;
+        }
+
+        let mut parts = fields.split('-');
+        let (_, trace_id, span_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let trace_state = tracestate
+            .map(str::trim)
+            .filter(|state| !state.is_empty())
+            .filter(|state| state.split(',').count() <= MAX_TRACE_STATE_MEMBERS)
+            .map(str::to_string);
+
+        Some(TraceContext {
+            trace_id: TraceId::parse(trace_id)?,
+            span_id: SpanId::parse(span_id)?,
+            parent_span_id: None,
+            flags: parse_hex::<1>(flags)?[0],
+            trace_state,
+        })
+    }
+
+    pub fn from_headers(headers: &Headers) -> Option<Self> {
+        Self::parse(headers.get("traceparent")?, headers.get("tracestate"))
+    }
+
+    // The context a server span runs in: a child of the caller's span when the
+    // request carries a valid traceparent, otherwise the root of a new trace.
+    pub fn for_request(request: &Request) -> Self {
+        match Self::from_headers(&request.headers) {
+            Some(remote) => remote.child(),
+            None => Self::root(),
+        }
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags & SAMPLED != 0
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+
+    // Propagates this context on an outgoing request, replacing any existing
+    // trace headers.
+    pub fn inject(&self, headers: &mut Headers) {
+        headers.set("traceparent", self.traceparent());
+        match &self.trace_state {
+            Some(state) => headers.set("tracestate", state.clone()),
+            None => {
+                headers.remove("tracestate");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpanKind {
+    Server,
+    Client,
+    Internal,
+}
+
+impl SpanKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanKind::Server => "SERVER",
+            SpanKind::Client => "CLIENT",
+            SpanKind::Internal => "INTERNAL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SpanStatus {
+    #[default]
+    Unset,
+    Ok,
+    Error(String),
+}
+
+// A finished span, as handed to exporters.
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub name: String,
+    pub kind: SpanKind,
+    pub context: TraceContext,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub status: SpanStatus,
+    pub attributes: Vec<(String, Value)>,
+}
+
+impl Span {
+    pub fn attribute(&self, key: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+
+    // Field names follow the OTLP JSON encoding, flattened to one object per span.
+    pub fn to_json(&self, service_name: &str) -> Value {
+        let nanos = |time: SystemTime| {
+            let since_epoch = time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            Value::from(since_epoch.as_nanos().to_string())
+        };
+        let (status, message) = match &self.status {
+            SpanStatus::Unset => ("UNSET", None),
+            SpanStatus::Ok => ("OK", None),
+            SpanStatus::Error(message) => ("ERROR", Some(message.as_str())),
+        };
+
+        Value::Object(vec![
+            ("service.name".to_string(), Value::from(service_name)),
+            ("name".to_string(), Value::from(self.name.as_str())),
+            ("kind".to_string(), Value::from(self.kind.as_str())),
+            (
+                "traceId".to_string(),
+                Value::from(self.context.trace_id.to_string()),
+            ),
+            (
+                "spanId".to_string(),
+                Value::from(self.context.span_id.to_string()),
+            ),
+            (
+                "parentSpanId".to_string(),
+                self.context
+                    .parent_span_id
+                    .map(|id| Value::from(id.to_string()))
+                    .unwrap_or(Value::Null),
+            ),
+            ("startTimeUnixNano".to_string(), nanos(self.start)),
+            ("endTimeUnixNano".to_string(), nanos(self.end)),
+            ("status".to_string(), Value::from(status)),
+            (
+                "statusMessage".to_string(),
+                message.map(Value::from).unwrap_or(Value::Null),
+            ),
+            (
+                "attributes".to_string(),
+                Value::Object(self.attributes.clone()),
+            ),
+        ])
+    }
+}
+
+pub trait SpanExporter: Send + Sync {
+    fn export(&self, span: &Span, service_name: &str);
+}
+
+impl<F: Fn(&Span) + Send + Sync> SpanExporter for F {
+    fn export(&self, span: &Span, _service_name: &str) {
+        self(span)
+    }
+}
+
+// Writes one JSON object per span to stdout, for a collector tailing the process
+// output.
+#[derive(Debug, Default)]
+pub struct StdoutExporter;
+
+impl SpanExporter for StdoutExporter {
+    fn export(&self, span: &Span, service_name: &str) {
+        let line = format!("{}\n", span.to_json(service_name));
+        if let Err(e) = io::stdout().lock().write_all(line.as_bytes()) {
+            log_warn!("Failed to export span: {}", e);
+        }
+    }
+}
+
+// Starts spans and hands them to the exporter when they end. Cheap to clone, so
+// handlers that call upstream services can keep one next to the Trace middleware.
+#[derive(Clone)]
+pub struct Tracer {
+    exporter: Arc<dyn SpanExporter>,
+    service_name: Arc<str>,
+}
+
+impl Tracer {
+    pub fn new(exporter: impl SpanExporter + 'static) -> Self {
+        Tracer {
+            exporter: Arc::new(exporter),
+            service_name: Arc::from("rawhttp"),
+        }
+    }
+
+    pub fn with_service_name(mut self, name: impl Into<String>) -> Self {
+        self.service_name = Arc::from(name.into());
+        self
+    }
+
+    pub fn service_name(&self) -> &str {
+        &self.service_name
+    }
+
+    // Starts a span with exactly this context; see TraceContext::for_request.
+    pub fn start(
+        &self,
+        name: impl Into<String>,
+        kind: SpanKind,
+        context: TraceContext,
+    ) -> ActiveSpan {
+        ActiveSpan {
+            tracer: self.clone(),
+            span: Some(Span {
+                name: name.into(),
+                kind,
+                context,
+                start: SystemTime::now(),
+                end: SystemTime::now(),
+                status: SpanStatus::Unset,
+                attributes: Vec::new(),
+            }),
+            timer: Instant::now(),
+        }
+    }
+
+    // Starts a client span for an upstream call made while handling `parent`.
+    // Inject its context into the outgoing request headers.
+    pub fn start_client(
+        &self,
+        name: impl Into<String>,
+        parent: Option<&TraceContext>,
+    ) -> ActiveSpan {
+        let context = parent
+            .map(TraceContext::child)
+            .unwrap_or_else(TraceContext::root);
+        self.start(name, SpanKind::Client, context)
+    }
+}
+
+impl Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("service_name", &self.service_name)
+            .finish()
+    }
+}
+
+// A span in progress. It is exported when ended or dropped, unless its trace is
+// not sampled.
+pub struct ActiveSpan {
+    tracer: Tracer,
+    span: Option<Span>,
+    timer: Instant,
+}
+
+impl ActiveSpan {
+    fn span(&mut self) -> &mut Span {
+        self.span.as_mut().expect("span is only taken on end")
+    }
+
+    pub fn context(&self) -> &TraceContext {
+        &self
+            .span
+            .as_ref()
+            .expect("span is only taken on end")
+            .context
+    }
+
+    pub fn set_attribute(&mut self, key: impl Into<String>, value: impl Into<Value>) {
+        let (key, value) = (key.into(), value.into());
+        let attributes = &mut self.span().attributes;
+        match attributes.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, existing)) => *existing = value,
+            None => attributes.push((key, value)),
+        }
+    }
+
+    pub fn set_status(&mut self, status: SpanStatus) {
+        self.span().status = status;
+    }
+
+    pub fn inject(&self, headers: &mut Headers) {
+        self.context().inject(headers);
+    }
+
+    pub fn end(self) {}
+}
+
+impl Drop for ActiveSpan {
+    fn drop(&mut self) {
+        let Some(mut span) = self.span.take() else {
+            return;
+        };
+        if !span.context.is_sampled() {
+            return;
+        }
+        span.end = span.start + self.timer.elapsed();
+        self.tracer
+            .exporter
+            .export(&span, &self.tracer.service_name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+
+    #[test]
+    fn test_parses_and_formats_traceparent() {
+        let context = TraceContext::parse(PARENT, Some(" vendor=abc ")).unwrap();
+        assert_eq!(
+            context.trace_id.to_string(),
+            "0af7651916cd43dd8448eb211c80319c"
+        );
+        assert_eq!(context.span_id.to_string(), "b7ad6b7169203331");
+        assert!(context.is_sampled());
+        assert_eq!(context.trace_state.as_deref(), Some("vendor=abc"));
+        assert_eq!(context.traceparent(), PARENT);
+
+        let child = context.child();
+        assert_eq!(child.trace_id, context.trace_id);
+        assert_eq!(child.parent_span_id, Some(context.span_id));
+        assert_ne!(child.span_id, context.span_id);
+    }
+
+    #[test]
+    fn test_rejects_malformed_traceparent() {
+        for value in [
+            "",
+            "ff-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            "00-00000000000000000000000000000000-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-0000000000000000-01",
+            "00-0AF7651916CD43DD8448EB211C80319C-b7ad6b7169203331-01",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-extra",
+            "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01,00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+        ] {
+            assert!(TraceContext::parse(value, None).is_none(), "{}", value);
+        }
+
+        // Later versions may append fields.
+        let future = "cc-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01-what";
+        assert!(TraceContext::parse(future, None).is_some());
+    }
+
+    #[test]
+    fn test_injects_context_and_exports_sampled_spans() {
+        let exported = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = exported.clone();
+        let tracer = Tracer::new(move |span: &Span| sink.lock().unwrap().push(span.clone()));
+
+        let parent = TraceContext::parse(PARENT, None).unwrap();
+        let mut span = tracer.start_client("GET", Some(&parent));
+        span.set_attribute("http.response.status_code", 200u64);
+        let mut headers = Headers::new();
+        headers.set("tracestate", "stale=1");
+        span.inject(&mut headers);
+        assert_eq!(
+            headers.get("traceparent"),
+            Some(span.context().traceparent().as_str())
+        );
+        assert_eq!(headers.get("tracestate"), None);
+        span.end();
+
+        let unsampled = TraceContext {
+            flags: 0,
+            ..TraceContext::root()
+        };
+        tracer.start("ignored", SpanKind::Internal, unsampled).end();
+
+        let exported = exported.lock().unwrap();
+        assert_eq!(exported.len(), 1);
+        assert_eq!(exported[0].kind, SpanKind::Client);
+        assert_eq!(exported[0].context.parent_span_id, Some(parent.span_id));
+        let json = exported[0].to_json("svc");
+        assert_eq!(
+            json.get("traceId").and_then(Value::as_str),
+            Some("0af7651916cd43dd8448eb211c80319c")
+        );
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::logging::log_error;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Live occupancy of a pool, shared so callers can watch it without holding the pool.
+#[derive(Debug, Default)]
+pub struct PoolLoad {
+    queued: AtomicUsize,
+    busy: AtomicUsize,
+}
+
+impl PoolLoad {
+    // Jobs submitted but not yet picked up by a worker.
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::Relaxed)
+    }
+
+    // Workers currently running a job.
+    pub fn busy(&self) -> usize {
+        self.busy.load(Ordering::Relaxed)
+    }
+}
+
+// Fixed set of worker threads pulling jobs from a shared queue. A panicking job
+// is caught and logged so the worker survives to take the next one.
+pub struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    load: Arc<PoolLoad>,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> Self {
+        let size = size.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let load = Arc::new(PoolLoad::default());
+
+        let workers = (0..size)
+            .map(|id| {
+                let receiver = receiver.clone();
+                let load = load.clone();
+                thread::Builder::new()
+                    .name(format!("rawhttp-worker-{}", id))
+                    .spawn(move || worker_loop(&receiver, &load))
+                    .expect("failed to spawn worker thread")
+            })
+            .collect();
+
+        ThreadPool {
+            sender: Some(sender),
+            workers,
+            load,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    pub fn load(&self) -> Arc<PoolLoad> {
+        self.load.clone()
+    }
+
+    // Every worker is occupied and at least `backlog` jobs are already waiting.
+    pub fn is_saturated(&self, backlog: usize) -> bool {
+        self.load.busy() >= self.size() && self.load.queued() >= backlog
+    }
+
+    pub fn execute(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(sender) = &self.sender {
+            self.load.queued.fetch_add(1, Ordering::Relaxed);
+            // Workers only exit once the sender is dropped, so this cannot fail.
+            let _ = sender.send(Box::new(job));
+        }
+    }
+}
+
+fn worker_loop(receiver: &Mutex<mpsc::Receiver<Job>>, load: &PoolLoad) {
+    loop {
+        let job = {
+            let receiver = receiver.lock().unwrap_or_else(|e| e.into_inner());
+            receiver.recv()
+        };
+        let Ok(job) = job else {
+            return;
+        };
+
+        load.busy.fetch_add(1, Ordering::Relaxed);
+        load.queued.fetch_sub(1, Ordering::Relaxed);
+        if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+            log_error!(
+                "Job panicked on {}",
+                thread::current().name().unwrap_or("worker")
+            );
+        }
+        load.busy.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Closing the queue lets workers finish what was already submitted, then exit.
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn test_runs_jobs_and_survives_panics() {
+        let done = Arc::new(AtomicUsize::new(0));
+        let pool = ThreadPool::new(2);
+
+        pool.execute(|| panic!("boom"));
+        for _ in 0..8 {
+            let done = done.clone();
+            pool.execute(move || {
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(pool);
+
+        assert_eq!(done.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn test_reports_saturation() {
+        let pool = ThreadPool::new(1);
+        let (release, blocked) = channel::<()>();
+        let (started, running) = channel::<()>();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            blocked.recv().unwrap();
+        });
+        running.recv().unwrap();
+        assert_eq!(pool.load().busy(), 1);
+        assert!(pool.is_saturated(0));
+        assert!(!pool.is_saturated(1));
+
+        pool.execute(|| {});
+        assert_eq!(pool.load().queued(), 1);
+        assert!(pool.is_saturated(1));
+
+        release.send(()).unwrap();
+        drop(pool);
+    }
+}
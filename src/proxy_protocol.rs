@@ -0,0 +1,239 @@
+use std::{
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
+    thread,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::http::ConnectionInfo;
+
+// PROXY protocol (HAProxy, AWS NLB and others): a preamble the load balancer sends
+// before the client's bytes, naming the addresses of the original connection.
+// Version 1 is a text line, version 2 a binary header.
+
+const V1_MAX: usize = 107;
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+#[derive(Debug, Error)]
+pub enum ProxyProtocolError {
+    #[error("Connection did not start with a PROXY protocol header")]
+    NotPresent,
+
+    #[error("Malformed PROXY protocol header")]
+    Malformed,
+
+    #[error("IO error while reading PROXY protocol header")]
+    Io(#[from] io::Error),
+}
+
+// The original connection. Both addresses are None for LOCAL connections (the
+// balancer's own health checks) and for protocols other than TCP over IP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+}
+
+impl ProxyHeader {
+    // Makes the client the peer, keeping the balancer's address as the proxy.
+    pub fn apply(&self, info: &mut ConnectionInfo) {
+        if let Some(source) = self.source {
+            info.proxy = info.peer;
+            info.peer = Some(source);
+            info.local = self.destination.or(info.local);
+        }
+    }
+}
+
+// Parses a header at the start of `input`, returning it with the bytes it used, or
+// None when more input is needed.
+pub fn parse(input: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    let probe = &input[..input.len().min(V2_SIGNATURE.len())];
+    if V2_SIGNATURE.starts_with(probe) {
+        return parse_v2(input);
+    }
+    if b"PROXY ".starts_with(&input[..input.len().min(6)]) {
+        return parse_v1(input);
+    }
+    Err(ProxyProtocolError::NotPresent)
+}
+
+fn parse_v1(input: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    let window = &input[..input.len().min(V1_MAX)];
+    let Some(end) = window.windows(2).position(|pair| pair == b"\r\n") else {
+        return match input.len() < V1_MAX {
+            true => Ok(None),
+            false => Err(ProxyProtocolError::Malformed),
+        };
+    };
+    let line = std::str::from_utf8(&input[..end]).map_err(|_| ProxyProtocolError::Malformed)?;
+    let fields: Vec<&str> = line.split(' ').collect();
+    let header = match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => ProxyHeader {
+            source: None,
+            destination: None,
+        },
+        [
+            "PROXY",
+            family @ ("TCP4" | "TCP6"),
+            source,
+            destination,
+            source_port,
+            destination_port,
+        ] => {
+            let address = |ip: &str, port: &str| -> Result<SocketAddr, ProxyProtocolError> {
+                let ip: IpAddr = ip.parse().map_err(|_| ProxyProtocolError::Malformed)?;
+                if ip.is_ipv4() != (*family == "TCP4") {
+                    return Err(ProxyProtocolError::Malformed);
+                }
+                let port = port.parse().map_err(|_| ProxyProtocolError::Malformed)?;
+                Ok(SocketAddr::new(ip, port))
+            };
+            ProxyHeader {
+                source: Some(address(source, source_port)?),
+                destination: Some(address(destination, destination_port)?),
+            }
+        }
+        _ => return Err(ProxyProtocolError::Malformed),
+    };
+    Ok(Some((header, end + 2)))
+}
+
+fn parse_v2(input: &[u8]) -> Result<Option<(ProxyHeader, usize)>, ProxyProtocolError> {
+    if input.len() < 16 {
+        return Ok(None);
+    }
+    let length = 16 + u16::from_be_bytes([input[14], input[15]]) as usize;
+    if input.len() < length {
+        return Ok(None);
+    }
+    let (version, command) = (input[12] >> 4, input[12] & 0x0f);
+    if version != 2 || command > 1 {
+        return Err(ProxyProtocolError::Malformed);
+    }
+    let addresses = &input[16..length];
+    let local = ProxyHeader {
+        source: None,
+        destination: None,
+    };
+    // LOCAL, or a family other than TCP over IPv4/IPv6: keep the real peer.
+    let header = match (command, input[13]) {
+        (0, _) => local,
+        (_, 0x11) if addresses.len() >= 12 => {
+            let ip = |at: usize| {
+                IpAddr::V4(Ipv4Addr::from(
+                    <[u8; 4]>::try_from(&addresses[at..at + 4]).unwrap(),
+                ))
+            };
+            let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+            ProxyHeader {
+                source: Some(SocketAddr::new(ip(0), port(8))),
+                destination: Some(SocketAddr::new(ip(4), port(10))),
+            }
+        }
+        (_, 0x21) if addresses.len() >= 36 => {
+            let ip = |at: usize| {
+                IpAddr::V6(Ipv6Addr::from(
+                    <[u8; 16]>::try_from(&addresses[at..at + 16]).unwrap(),
+                ))
+            };
+            let port = |at: usize| u16::from_be_bytes([addresses[at], addresses[at + 1]]);
+            ProxyHeader {
+                source: Some(SocketAddr::new(ip(0), port(32))),
+                destination: Some(SocketAddr::new(ip(16), port(34))),
+            }
+        }
+        (_, 0x11 | 0x21) => return Err(ProxyProtocolError::Malformed),
+        _ => local,
+    };
+    Ok(Some((header, length)))
+}
+
+// Consumes exactly the header from the socket, before any TLS handshake or HTTP
+// parsing sees it. Peeking keeps the bytes after it, the client's own, unread.
+pub(crate) fn read_header(
+    mut stream: &TcpStream,
+    deadline: Instant,
+) -> Result<ProxyHeader, ProxyProtocolError> {
+    let mut peeked = [0u8; V1_MAX];
+    loop {
+        let n = stream.peek(&mut peeked)?;
+        if n == 0 {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
+        // Version 2 headers can outgrow the peek buffer with TLVs, but their
+        // length is known after 16 bytes.
+        if n >= 16 && peeked.starts_with(&V2_SIGNATURE) {
+            let length = 16 + u16::from_be_bytes([peeked[14], peeked[15]]) as usize;
+            let mut header = vec![0; length];
+            stream.read_exact(&mut header)?;
+            return parse(&header)?
+                .map(|(header, _)| header)
+                .ok_or(ProxyProtocolError::Malformed);
+        }
+        if let Some((header, used)) = parse(&peeked[..n])? {
+            stream.read_exact(&mut peeked[..used])?;
+            return Ok(header);
+        }
+        if Instant::now() >= deadline {
+            return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+        }
+        // Part of the header has arrived; peek would return at once again.
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parses_v1_lines() {
+        let input = b"PROXY TCP4 203.0.113.7 192.0.2.1 56324 443\r\nGET / HTTP/1.1\r\n";
+        let (header, used) = parse(input).unwrap().unwrap();
+        assert_eq!(header.source, Some("203.0.113.7:56324".parse().unwrap()));
+        assert_eq!(header.destination, Some("192.0.2.1:443".parse().unwrap()));
+        assert_eq!(&input[used..], b"GET / HTTP/1.1\r\n");
+
+        let (header, _) = parse(b"PROXY TCP6 2001:db8::1 2001:db8::2 1 2\r\n")
+            .unwrap()
+            .unwrap();
+        assert_eq!(header.source, Some("[2001:db8::1]:1".parse().unwrap()));
+        let (unknown, _) = parse(b"PROXY UNKNOWN\r\n").unwrap().unwrap();
+        assert_eq!(unknown.source, None);
+
+        assert!(parse(b"PROXY TCP4 1.2.3.4").unwrap().is_none());
+        assert!(matches!(
+            parse(b"PROXY TCP4 2001:db8::1 1.2.3.4 1 2\r\n"),
+            Err(ProxyProtocolError::Malformed)
+        ));
+        assert!(matches!(
+            parse(b"GET / HTTP/1.1\r\n"),
+            Err(ProxyProtocolError::NotPresent)
+        ));
+    }
+
+    #[test]
+    fn test_parses_v2_headers() {
+        let mut input = V2_SIGNATURE.to_vec();
+        input.extend_from_slice(&[0x21, 0x11, 0, 15]);
+        input.extend_from_slice(&[203, 0, 113, 7, 192, 0, 2, 1]);
+        input.extend_from_slice(&56324u16.to_be_bytes());
+        input.extend_from_slice(&443u16.to_be_bytes());
+        // A TLV the parser skips.
+        input.extend_from_slice(&[0x04, 0, 0]);
+        input.extend_from_slice(b"GET");
+
+        assert!(parse(&input[..20]).unwrap().is_none());
+        let (header, used) = parse(&input).unwrap().unwrap();
+        assert_eq!(header.source, Some("203.0.113.7:56324".parse().unwrap()));
+        assert_eq!(header.destination, Some("192.0.2.1:443".parse().unwrap()));
+        assert_eq!(&input[used..], b"GET");
+
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0, 0]);
+        assert_eq!(parse(&local).unwrap().unwrap().0.source, None);
+    }
+}
@@ -0,0 +1,12 @@
+use std::{
+    hash::{BuildHasher, RandomState},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Every RandomState carries fresh keys, so hashing a counter gives ids that differ
+// between processes as well as between calls. Not for secrets.
+pub(crate) fn random_u64() -> u64 {
+    RandomState::new().hash_one(COUNTER.fetch_add(1, Ordering::Relaxed))
+}
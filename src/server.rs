@@ -1,95 +1,1974 @@
 use std::{
-    net::{TcpListener, TcpStream},
+    any::Any,
+    fmt::Debug,
+    io::{BufRead, BufReader, ErrorKind, Read, Write},
+    marker::PhantomData,
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    panic::{self, AssertUnwindSafe},
     sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc,
     },
     thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{Context, Result};
+use thiserror::Error;
 
 use crate::http::{
-    Request, Response, StatusCode,
-    request::{ParseError, request_from_reader},
+    Body, BodyFraming, ConnectionContext, ConnectionInfo, DuplexStream, LengthMismatchPolicy,
+    Method, ParseOptions, Request, Response, ServerTiming, StatusCode, TakenStream, Takeover,
+    request::{ParseError, ParserLimits, request_from_buf_reader_deferred},
 };
+use crate::http2;
+use crate::logging::{log_debug, log_error, log_info, log_warn};
+use crate::metrics::Metrics;
+use crate::middleware::{AccessLog, Middleware, chain::Chain};
+use crate::pool::{PoolLoad, ThreadPool};
+use crate::proxy_protocol::{self, ProxyHeader, ProxyProtocolError};
 
 pub trait Handler: Send + Sync {
     fn handle(&self, request: &Request) -> Response;
 
     fn handle_bad_request(&self, e: &ParseError) -> Response {
-        println!("Failed to parse request: {}", e);
-        Response::new(StatusCode::BadRequest)
+        bad_request(e)
     }
 }
 
+// Lets one handler be shared, e.g. between a server and a middleware chain.
+impl<H: Handler + ?Sized> Handler for Arc<H> {
+    fn handle(&self, request: &Request) -> Response {
+        (**self).handle(request)
+    }
+
+    fn handle_bad_request(&self, e: &ParseError) -> Response {
+        (**self).handle_bad_request(e)
+    }
+}
+
+// Runs the handler, turning a panic into a 500 so the client gets an answer
+// instead of a reset connection.
+pub(crate) fn call_handler<H: Handler + ?Sized>(handler: &H, request: &Request) -> Response {
+    panic::catch_unwind(AssertUnwindSafe(|| handler.handle(request)))
+        .unwrap_or_else(|payload| handler_panicked(request, payload.as_ref()))
+}
+
+pub(crate) fn handler_panicked(request: &Request, payload: &(dyn Any + Send)) -> Response {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload");
+    log_error!(
+        "Handler panicked on {:?} {}: {}",
+        request.method(),
+        request.target(),
+        message
+    );
+    Response::internal_server_error()
+}
+
+pub(crate) fn bad_request(e: &ParseError) -> Response {
+    log_error!("Failed to parse request: {}", e);
+    let status = e.status();
+    Response::problem(status, status.reason_parse(), &e.to_string(), "about:blank")
+}
+
+const DRAIN_LIMIT: usize = 64 * 1024; // 64KB
+const DRAIN_DEADLINE: Duration = Duration::from_secs(2);
+// How long a closing connection whose request was read in full waits for more.
+const LINGER_QUIET: Duration = Duration::from_millis(100);
+const SHED_WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+const CAPACITY_POLL: Duration = Duration::from_millis(5);
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default)]
+pub struct ServerStats {
+    bodies_drained: AtomicU64,
+    drains_aborted: AtomicU64,
+    connections_shed: AtomicU64,
+    connections_rejected: AtomicU64,
+    active_connections: AtomicUsize,
+    pool: OnceLock<Arc<PoolLoad>>,
+}
+
+impl ServerStats {
+    pub fn bodies_drained(&self) -> u64 {
+        self.bodies_drained.load(Ordering::Relaxed)
+    }
+
+    pub fn drains_aborted(&self) -> u64 {
+        self.drains_aborted.load(Ordering::Relaxed)
+    }
+
+    // Connections turned away with 503 because the worker pool was saturated.
+    pub fn connections_shed(&self) -> u64 {
+        self.connections_shed.load(Ordering::Relaxed)
+    }
+
+    // Connections turned away with 503 because max_connections was reached.
+    pub fn connections_rejected(&self) -> u64 {
+        self.connections_rejected.load(Ordering::Relaxed)
+    }
+
+    // Connections currently being served, including idle keep-alive ones.
+    pub fn active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::Relaxed)
+    }
+
+    // Accepted connections waiting for a worker. Always 0 without a pool.
+    pub fn queue_depth(&self) -> usize {
+        self.pool.get().map_or(0, |load| load.queued())
+    }
+
+    pub fn busy_workers(&self) -> usize {
+        self.pool.get().map_or(0, |load| load.busy())
+    }
+}
+
+// Counts a connection as active from accept until the guard is dropped, so the
+// slot is given back even if the handler, a takeover or a duplex exchange panics,
+// or the pool drops the job without running it.
+struct OpenConnection {
+    stats: Arc<ServerStats>,
+    metrics: Option<Metrics>,
+}
+
+impl OpenConnection {
+    fn new(stats: Arc<ServerStats>, metrics: Option<Metrics>) -> Self {
+        stats.active_connections.fetch_add(1, Ordering::SeqCst);
+        if let Some(metrics) = &metrics {
+            metrics.connection_opened();
+        }
+        OpenConnection { stats, metrics }
+    }
+}
+
+impl Drop for OpenConnection {
+    fn drop(&mut self) {
+        self.stats.active_connections.fetch_sub(1, Ordering::SeqCst);
+        if let Some(metrics) = &self.metrics {
+            metrics.connection_closed();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    // How long an open connection may sit between requests before it is closed.
+    pub idle_timeout: Duration,
+    // Requests served on one connection before the server asks to close it.
+    pub max_requests: usize,
+}
+
+impl KeepAlive {
+    pub fn disabled() -> Self {
+        KeepAlive {
+            idle_timeout: Duration::ZERO,
+            max_requests: 1,
+        }
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
+}
+
+// What the acceptor does once max_connections are open.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OverLimit {
+    // Stop accepting until a connection closes; new clients wait in the kernel's
+    // listen backlog.
+    #[default]
+    Wait,
+    // Keep accepting and answer the excess with 503 and Retry-After.
+    Reject,
+}
+
+// Called once per request after the response is settled, with the time taken to
+// read and handle it.
+pub type RequestHook = Arc<dyn Fn(&Request, &Response, Duration) + Send + Sync>;
+// Called when serving a connection fails.
+pub type ErrorHook = Arc<dyn Fn(&anyhow::Error) + Send + Sync>;
+// Picks, from its head, a request whose body the handler streams itself.
+pub type StreamBodyFilter = Arc<dyn Fn(&Request) -> bool + Send + Sync>;
+
+#[derive(Clone)]
+pub struct ServerConfig {
+    pub length_mismatch: LengthMismatchPolicy,
+    pub keep_alive: KeepAlive,
+    // Longest wait for the next byte of a request head. Exceeding it answers 408.
+    pub header_read_timeout: Duration,
+    // Longest wait for the next byte of a request body.
+    pub body_read_timeout: Duration,
+    // Longest a single write to the client may block.
+    pub write_timeout: Duration,
+    // Head and body sizes, and the total time allowed for a head.
+    pub limits: ParserLimits,
+    // Size of the worker pool; None spawns a thread per connection.
+    pub workers: Option<usize>,
+    // See Server::with_shed_threshold.
+    pub shed_backlog: Option<usize>,
+    // Concurrent connections, idle keep-alive ones included. None is unlimited.
+    pub max_connections: Option<usize>,
+    pub over_limit: OverLimit,
+    // How long run() waits for in-flight connections after a shutdown.
+    pub shutdown_timeout: Duration,
+    // Replace the request line printed to stdout and the connection errors
+    // printed to stderr.
+    pub on_request: Option<RequestHook>,
+    pub on_error: Option<ErrorHook>,
+    // Written after every response, alongside on_request.
+    pub access_log: Option<Arc<AccessLog>>,
+    // Records every response and tracks open connections.
+    pub metrics: Option<Metrics>,
+    // Accept HTTP/2 as well; see Server::with_http2.
+    pub http2: bool,
+    // Require a PROXY protocol header; see Server::with_proxy_protocol.
+    pub proxy_protocol: bool,
+    // See ServerBuilder::stream_request_bodies.
+    pub stream_bodies: Option<StreamBodyFilter>,
+    // Log the effective configuration below the startup banner.
+    pub print_config: bool,
+}
+
+// A combination of settings the server cannot honour, found by
+// ServerConfig::validate before anything is bound.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConfigError {
+    #[error("{0} must be greater than zero")]
+    ZeroTimeout(&'static str),
+
+    #[error(
+        "keep-alive idle timeout ({idle:?}) is shorter than the header read timeout ({header:?})"
+    )]
+    IdleTimeoutTooShort { idle: Duration, header: Duration },
+
+    #[error("keep-alive must allow at least one request per connection")]
+    NoRequestsPerConnection,
+
+    #[error("{0} must be at least 1")]
+    ZeroCount(&'static str),
+
+    #[error("shed threshold needs a worker pool")]
+    ShedWithoutWorkers,
+
+    #[error("{name} ({value} bytes) exceeds {limit} ({max} bytes)")]
+    LimitExceeds {
+        name: &'static str,
+        value: usize,
+        limit: &'static str,
+        max: usize,
+    },
+}
+
+impl ServerConfig {
+    // Starts from the defaults; finish() validates what was set.
+    pub fn builder() -> ServerConfigBuilder {
+        ServerBuilder {
+            addrs: Vec::new(),
+            config: ServerConfig::default(),
+            handler: PhantomData,
+        }
+    }
+
+    // Checks the settings against each other. Nothing runs this implicitly;
+    // ServerBuilder::try_build and ServerConfigBuilder::finish do.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for (name, timeout) in [
+            ("header read timeout", self.header_read_timeout),
+            ("body read timeout", self.body_read_timeout),
+            ("write timeout", self.write_timeout),
+        ] {
+            // Sockets refuse a zero timeout rather than treating it as none.
+            if timeout.is_zero() {
+                return Err(ConfigError::ZeroTimeout(name));
+            }
+        }
+        if self.limits.header_deadline.is_some_and(|d| d.is_zero()) {
+            return Err(ConfigError::ZeroTimeout("header deadline"));
+        }
+
+        let keep_alive = self.keep_alive;
+        if keep_alive.max_requests == 0 {
+            return Err(ConfigError::NoRequestsPerConnection);
+        }
+        // The idle timeout also covers the first byte of the next request, so a
+        // shorter one would cut off clients the header timeout still allows.
+        if keep_alive.max_requests > 1 && keep_alive.idle_timeout < self.header_read_timeout {
+            return Err(ConfigError::IdleTimeoutTooShort {
+                idle: keep_alive.idle_timeout,
+                header: self.header_read_timeout,
+            });
+        }
+
+        if self.workers == Some(0) {
+            return Err(ConfigError::ZeroCount("workers"));
+        }
+        if self.max_connections == Some(0) {
+            return Err(ConfigError::ZeroCount("max connections"));
+        }
+        if self.shed_backlog.is_some() && self.workers.is_none() {
+            return Err(ConfigError::ShedWithoutWorkers);
+        }
+
+        let limits = &self.limits;
+        for (name, value, limit, max) in [
+            (
+                "max request line",
+                limits.max_request_line,
+                "max header size",
+                limits.max_header_bytes,
+            ),
+            (
+                "max method",
+                limits.max_method,
+                "max request line",
+                limits.max_request_line,
+            ),
+            (
+                "max target",
+                limits.max_target,
+                "max request line",
+                limits.max_request_line,
+            ),
+        ] {
+            if value > max {
+                return Err(ConfigError::LimitExceeds {
+                    name,
+                    value,
+                    limit,
+                    max,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            limits: self.limits,
+            ..ParseOptions::default()
+        }
+    }
+
+    // Whether something other than the default stdout line records each request.
+    pub(crate) fn logs_requests(&self) -> bool {
+        self.on_request.is_some() || self.access_log.is_some()
+    }
+
+    pub(crate) fn observe(&self, request: &Request, response: &Response, elapsed: Duration) {
+        if let Some(metrics) = &self.metrics {
+            metrics.record(request, response, elapsed);
+        }
+        if let Some(log) = &self.access_log {
+            log.record(request, response, elapsed);
+        }
+        if let Some(hook) = &self.on_request {
+            hook(request, response, elapsed);
+        }
+    }
+
+    fn report(&self, e: &anyhow::Error) {
+        match &self.on_error {
+            Some(hook) => hook(e),
+            None => log_error!("Error handling connection: {}", e),
+        }
+    }
+}
+
+// The defaults pass validate() and suit a server facing the open internet: every
+// wait on a client is bounded, so a slow or silent peer costs a thread for
+// seconds rather than forever.
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            // Reject conflicting or wrong Content-Length, the smuggling-safe choice.
+            length_mismatch: LengthMismatchPolicy::default(),
+            // 5s idle, 100 requests, then the client reconnects.
+            keep_alive: KeepAlive::default(),
+            header_read_timeout: Duration::from_secs(5),
+            // Uploads get longer, but a stalled one is still dropped.
+            body_read_timeout: Duration::from_secs(30),
+            write_timeout: Duration::from_secs(5),
+            // 8KB heads, 10MB bodies, 20s for a whole head.
+            limits: ParserLimits::default(),
+            workers: None,
+            shed_backlog: None,
+            max_connections: None,
+            over_limit: OverLimit::default(),
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            on_request: None,
+            on_error: None,
+            access_log: None,
+            metrics: None,
+            http2: false,
+            proxy_protocol: false,
+            stream_bodies: None,
+            print_config: false,
+        }
+    }
+}
+
+impl Debug for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerConfig")
+            .field("length_mismatch", &self.length_mismatch)
+            .field("keep_alive", &self.keep_alive)
+            .field("header_read_timeout", &self.header_read_timeout)
+            .field("body_read_timeout", &self.body_read_timeout)
+            .field("write_timeout", &self.write_timeout)
+            .field("limits", &self.limits)
+            .field("workers", &self.workers)
+            .field("shed_backlog", &self.shed_backlog)
+            .field("max_connections", &self.max_connections)
+            .field("over_limit", &self.over_limit)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("on_request", &self.on_request.is_some())
+            .field("on_error", &self.on_error.is_some())
+            .field(
+                "access_log",
+                &self.access_log.as_ref().map(|log| log.format()),
+            )
+            .field("metrics", &self.metrics.is_some())
+            .field("http2", &self.http2)
+            .field("proxy_protocol", &self.proxy_protocol)
+            .field("stream_bodies", &self.stream_bodies.is_some())
+            .field("print_config", &self.print_config)
+            .finish()
+    }
+}
+
+// The effective settings, one "name: value" line each, as printed at startup.
+impl std::fmt::Display for ServerConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let or_unlimited =
+            |value: Option<usize>| value.map_or("unlimited".to_string(), |v| v.to_string());
+        let limits = &self.limits;
+        writeln!(
+            f,
+            "keep_alive: {:?} idle, {} requests",
+            self.keep_alive.idle_timeout, self.keep_alive.max_requests
+        )?;
+        writeln!(f, "header_read_timeout: {:?}", self.header_read_timeout)?;
+        match limits.header_deadline {
+            Some(deadline) => writeln!(f, "header_deadline: {:?}", deadline)?,
+            None => writeln!(f, "header_deadline: none")?,
+        }
+        writeln!(f, "body_read_timeout: {:?}", self.body_read_timeout)?;
+        writeln!(f, "write_timeout: {:?}", self.write_timeout)?;
+        writeln!(f, "shutdown_timeout: {:?}", self.shutdown_timeout)?;
+        writeln!(f, "max_header_size: {}", limits.max_header_bytes)?;
+        writeln!(
+            f,
+            "max_request_line: {} (method {}, target {}, version {})",
+            limits.max_request_line, limits.max_method, limits.max_target, limits.max_version
+        )?;
+        writeln!(f, "max_body_size: {}", or_unlimited(limits.max_body_bytes))?;
+        match self.workers {
+            Some(n) => writeln!(f, "workers: {}", n)?,
+            None => writeln!(f, "workers: thread per connection")?,
+        }
+        if let Some(backlog) = self.shed_backlog {
+            writeln!(f, "shed_threshold: {}", backlog)?;
+        }
+        writeln!(
+            f,
+            "max_connections: {} ({:?})",
+            or_unlimited(self.max_connections),
+            self.over_limit
+        )?;
+        writeln!(f, "length_mismatch: {:?}", self.length_mismatch)?;
+        writeln!(f, "http2: {}", self.http2)?;
+        writeln!(f, "proxy_protocol: {}", self.proxy_protocol)?;
+        write!(f, "stream_request_bodies: {}", self.stream_bodies.is_some())
+    }
+}
+
+// Collects addresses and settings for a Server. The handler comes last, in build.
+pub struct ServerBuilder<H> {
+    addrs: Vec<String>,
+    config: ServerConfig,
+    handler: PhantomData<fn() -> H>,
+}
+
+// The same setters with no server at the end: ServerConfig::builder() ... finish()
+// yields a validated ServerConfig for Server::with_config.
+pub type ServerConfigBuilder = ServerBuilder<()>;
+
+impl ServerConfigBuilder {
+    pub fn finish(self) -> Result<ServerConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl<H> ServerBuilder<H> {
+    // May be called more than once; the server listens on every address given.
+    pub fn address(mut self, addr: impl Into<String>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    pub fn addresses<A: Into<String>>(mut self, addrs: impl IntoIterator<Item = A>) -> Self {
+        self.addrs.extend(addrs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn header_read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.header_read_timeout = timeout;
+        self
+    }
+
+    // Total time for a request head, however steadily its bytes arrive.
+    pub fn header_deadline(mut self, deadline: Duration) -> Self {
+        self.config.limits.header_deadline = Some(deadline);
+        self
+    }
+
+    pub fn body_read_timeout(mut self, timeout: Duration) -> Self {
+        self.config.body_read_timeout = timeout;
+        self
+    }
+
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.config.write_timeout = timeout;
+        self
+    }
+
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn max_header_size(mut self, bytes: usize) -> Self {
+        self.config.limits.max_header_bytes = bytes;
+        self
+    }
+
+    pub fn max_request_line(mut self, bytes: usize) -> Self {
+        self.config.limits.max_request_line = bytes;
+        self
+    }
+
+    pub fn max_method(mut self, bytes: usize) -> Self {
+        self.config.limits.max_method = bytes;
+        self
+    }
+
+    pub fn max_target(mut self, bytes: usize) -> Self {
+        self.config.limits.max_target = bytes;
+        self
+    }
+
+    pub fn max_version(mut self, bytes: usize) -> Self {
+        self.config.limits.max_version = bytes;
+        self
+    }
+
+    pub fn max_body_size(mut self, bytes: usize) -> Self {
+        self.config.limits.max_body_bytes = Some(bytes);
+        self
+    }
+
+    pub fn limits(mut self, limits: ParserLimits) -> Self {
+        self.config.limits = limits;
+        self
+    }
+
+    pub fn workers(mut self, n: usize) -> Self {
+        self.config.workers = Some(n.max(1));
+        self
+    }
+
+    pub fn shed_threshold(mut self, backlog: usize) -> Self {
+        self.config.shed_backlog = Some(backlog);
+        self
+    }
+
+    pub fn max_connections(mut self, max: usize, over_limit: OverLimit) -> Self {
+        self.config.max_connections = Some(max.max(1));
+        self.config.over_limit = over_limit;
+        self
+    }
+
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.config.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn http2(mut self) -> Self {
+        self.config.http2 = true;
+        self
+    }
+
+    pub fn proxy_protocol(mut self) -> Self {
+        self.config.proxy_protocol = true;
+        self
+    }
+
+    pub fn length_mismatch(mut self, policy: LengthMismatchPolicy) -> Self {
+        self.config.length_mismatch = policy;
+        self
+    }
+
+    pub fn on_request(
+        mut self,
+        hook: impl Fn(&Request, &Response, Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.config.on_request = Some(Arc::new(hook));
+        self
+    }
+
+    pub fn on_error(mut self, hook: impl Fn(&anyhow::Error) + Send + Sync + 'static) -> Self {
+        self.config.on_error = Some(Arc::new(hook));
+        self
+    }
+
+    // Leaves the body of requests `filter` picks on the wire, so the handler runs
+    // as soon as the head is in and reads the body through the DuplexStream of a
+    // Response::with_duplex while writing its response, as an echo or
+    // transcoding endpoint would. A handler that answers such a request without
+    // a duplex body gets whatever is left of it discarded. HTTP/1 only.
+    pub fn stream_request_bodies(
+        mut self,
+        filter: impl Fn(&Request) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.config.stream_bodies = Some(Arc::new(filter));
+        self
+    }
+
+    // Writes an access log record for every request, e.g. in Combined format:
+    // .access_log(AccessLog::new(LoggerConfig::new().format(LogFormat::Combined))?)
+    pub fn access_log(mut self, log: AccessLog) -> Self {
+        self.config.access_log = Some(Arc::new(log));
+        self
+    }
+
+    // Serve the same Metrics (it is a cheap clone) at /metrics to scrape them.
+    pub fn metrics(mut self, metrics: Metrics) -> Self {
+        self.config.metrics = Some(metrics);
+        self
+    }
+
+    // Log the effective configuration at startup, below the banner.
+    pub fn print_config(mut self) -> Self {
+        self.config.print_config = true;
+        self
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+}
+
+impl<H: Handler + 'static> ServerBuilder<H> {
+    // Like build, but refuses settings that contradict each other.
+    pub fn try_build(self, handler: H) -> Result<Server<H>, ConfigError> {
+        self.config.validate()?;
+        Ok(self.build(handler))
+    }
+
+    pub fn build(self, handler: H) -> Server<H> {
+        Server {
+            addrs: self.addrs,
+            handler: Arc::new(handler),
+            middleware: Vec::new(),
+            stack: OnceLock::new(),
+            shutdown: ShutdownHandle::default(),
+            stats: Arc::new(ServerStats::default()),
+            config: Arc::new(self.config),
+            listener: Mutex::new(None),
+            info: Mutex::new(None),
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+// Stops a running server from any thread. Cloning is cheap, so one can be handed to
+// a signal watcher or a test while the server runs elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct ShutdownHandle {
+    closed: Arc<AtomicBool>,
+    local_addrs: Arc<Mutex<Vec<SocketAddr>>>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+
+        // accept() only returns on a connection, so make one to wake each acceptor.
+        let addrs = self
+            .local_addrs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        for mut addr in addrs {
+            if addr.ip().is_unspecified() {
+                addr.set_ip(match addr {
+                    SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                    SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+                });
+            }
+            let _ = TcpStream::connect_timeout(&addr, Duration::from_secs(1));
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+// What a running server is actually doing, as opposed to what it was configured
+// with: the addresses it bound (with real ports when asked for port 0), the
+// protocol options in effect and when it started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerInfo {
+    pub version: &'static str,
+    pub addresses: Vec<SocketAddr>,
+    pub tls: bool,
+    pub keep_alive: bool,
+    // Pool size; None when each connection gets its own thread.
+    pub workers: Option<usize>,
+    // Cargo features this build was compiled with.
+    pub features: Vec<&'static str>,
+    pub started: SystemTime,
+}
+
+impl ServerInfo {
+    pub fn banner(&self) -> String {
+        let addresses = self
+            .addresses
+            .iter()
+            .map(|addr| format!("{}://{}", if self.tls { "https" } else { "http" }, addr))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut details = vec![if self.keep_alive {
+            "keep-alive".to_string()
+        } else {
+            "close".to_string()
+        }];
+        details.push(match self.workers {
+            Some(n) => format!("{} workers", n),
+            None => "thread per connection".to_string(),
+        });
+        if !self.features.is_empty() {
+            details.push(format!("features: {}", self.features.join(" ")));
+        }
+        format!(
+            "rawhttp {} listening on {} ({})",
+            self.version,
+            addresses,
+            details.join(", ")
+        )
+    }
+}
+
+fn compiled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "arena") {
+        features.push("arena");
+    }
+    if cfg!(feature = "async") {
+        features.push("async");
+    }
+    if cfg!(feature = "brotli") {
+        features.push("brotli");
+    }
+    if cfg!(feature = "ctrl-c") {
+        features.push("ctrl-c");
+    }
+    if cfg!(feature = "deflate") {
+        features.push("deflate");
+    }
+    if cfg!(feature = "gzip") {
+        features.push("gzip");
+    }
+    if cfg!(feature = "memchr") {
+        features.push("memchr");
+    }
+    if cfg!(feature = "otel") {
+        features.push("otel");
+    }
+    if cfg!(feature = "serde") {
+        features.push("serde");
+    }
+    if cfg!(feature = "tls") {
+        features.push("tls");
+    }
+    if cfg!(feature = "tracing") {
+        features.push("tracing");
+    }
+    features
+}
+
 pub struct Server<H: Handler> {
-    addr: String,
+    addrs: Vec<String>,
     handler: Arc<H>,
-    closed: Arc<AtomicBool>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    // The handler wrapped in its middleware, assembled on the first connection.
+    stack: OnceLock<Arc<dyn Handler>>,
+    shutdown: ShutdownHandle,
+    stats: Arc<ServerStats>,
+    // Shared with every connection; the with_ methods only change it before run.
+    config: Arc<ServerConfig>,
+    // Set by with_listener before run, then a handle on the bound socket while
+    // running so hand_off can pass it on.
+    listener: Mutex<Option<TcpListener>>,
+    info: Mutex<Option<ServerInfo>>,
+    #[cfg(feature = "tls")]
+    tls: Option<crate::tls::TlsConfig>,
 }
 
 impl<H: Handler + 'static> Server<H> {
     pub fn new(addr: String, handler: H) -> Self {
-        Server {
-            addr,
-            handler: Arc::new(handler),
-            closed: Arc::new(AtomicBool::new(false)),
+        Self::builder().address(addr).build(handler)
+    }
+
+    pub fn builder() -> ServerBuilder<H> {
+        ServerBuilder {
+            addrs: Vec::new(),
+            config: ServerConfig::default(),
+            handler: PhantomData,
         }
     }
 
+    fn config_mut(&mut self) -> &mut ServerConfig {
+        Arc::make_mut(&mut self.config)
+    }
+
+    // Serves connections on a fixed pool of `n` threads instead of spawning one
+    // thread per connection.
+    pub fn with_workers(mut self, n: usize) -> Self {
+        self.config_mut().workers = Some(n.max(1));
+        self
+    }
+
+    // Once every worker is busy and `backlog` connections are already queued, new
+    // connections get an immediate 503 from the acceptor instead of waiting in an
+    // unbounded queue. Only applies together with with_workers.
+    pub fn with_shed_threshold(mut self, backlog: usize) -> Self {
+        self.config_mut().shed_backlog = Some(backlog);
+        self
+    }
+
+    // Caps concurrent connections; see OverLimit for what happens past the cap.
+    pub fn with_max_connections(mut self, max: usize, over_limit: OverLimit) -> Self {
+        let config = self.config_mut();
+        config.max_connections = Some(max.max(1));
+        config.over_limit = over_limit;
+        self
+    }
+
+    // How long run() waits for in-flight connections after a shutdown before it
+    // returns anyway.
+    pub fn with_shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config_mut().shutdown_timeout = timeout;
+        self
+    }
+
+    pub fn with_length_mismatch(mut self, policy: LengthMismatchPolicy) -> Self {
+        self.config_mut().length_mismatch = policy;
+        self
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.config_mut().keep_alive = keep_alive;
+        self
+    }
+
+    // Serves HTTPS: every accepted socket is wrapped in a TLS session before any
+    // request is read from it.
+    #[cfg(feature = "tls")]
+    pub fn with_tls(mut self, tls: crate::tls::TlsConfig) -> Self {
+        self.tls = Some(match self.config.http2 {
+            true => tls.with_h2(),
+            false => tls,
+        });
+        self
+    }
+
+    // Also speaks HTTP/2: over TLS when the client picks "h2" through ALPN, and in
+    // cleartext when it sends the HTTP/2 preface straight away or asks for an h2c
+    // upgrade. Streams are answered one at a time, in the order their requests
+    // complete; connection takeovers are refused with 500. The async server does
+    // not support HTTP/2.
+    pub fn with_http2(mut self) -> Self {
+        self.config_mut().http2 = true;
+        #[cfg(feature = "tls")]
+        {
+            self.tls = self.tls.take().map(crate::tls::TlsConfig::with_h2);
+        }
+        self
+    }
+
+    // For running behind HAProxy, an AWS NLB or another balancer that sends a
+    // PROXY protocol v1 or v2 header: every connection must start with one, read
+    // before TLS and HTTP, and the client it names becomes the peer in
+    // ConnectionInfo. Connections without it are dropped, since otherwise any
+    // client could claim an address. The async server does not support it.
+    pub fn with_proxy_protocol(mut self) -> Self {
+        self.config_mut().proxy_protocol = true;
+        self
+    }
+
+    // Wraps the handler in another layer. The first one added is the outermost, so
+    // it sees each request first and each response last.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    pub fn with_config(mut self, config: ServerConfig) -> Self {
+        self.config = Arc::new(config);
+        self
+    }
+
+    pub fn config(&self) -> &ServerConfig {
+        &self.config
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.addrs
+    }
+
+    // Serves on an already bound socket instead of binding the first address, e.g.
+    // one inherited from a predecessor through handoff::inherit.
+    pub fn with_listener(self, listener: TcpListener) -> Self {
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = Some(listener);
+        self
+    }
+
     pub fn run(&self) -> Result<()> {
-        let listener = TcpListener::bind(&self.addr)
-            .context(format!("Failed to bind the address: {}", self.addr))?;
+        self.serve(self.bind_listeners()?, None)
+    }
 
-        println!("Server listening on {}", self.addr);
+    // Like run, but sends the server's info on `ready` once every address is bound
+    // and connections are being accepted.
+    pub fn run_with_ready(&self, ready: mpsc::Sender<ServerInfo>) -> Result<()> {
+        self.serve(self.bind_listeners()?, Some(ready))
+    }
 
-        for stream in listener.incoming() {
-            if self.closed.load(Ordering::Relaxed) {
-                break;
+    // Binds every address now, so the ports picked for ":0" are known before
+    // run() blocks.
+    pub fn bind(self) -> Result<BoundServer<H>> {
+        let listeners = self.bind_listeners()?;
+        let local_addrs = self.local_addrs();
+        Ok(BoundServer {
+            server: self,
+            listeners: Mutex::new(Some(listeners)),
+            local_addrs,
+        })
+    }
+
+    // Available while the server is running, and after it stops.
+    pub fn info(&self) -> Option<ServerInfo> {
+        self.info.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn serve(
+        &self,
+        listeners: Vec<TcpListener>,
+        ready: Option<mpsc::Sender<ServerInfo>>,
+    ) -> Result<()> {
+        let addresses = self.local_addrs();
+        *self.listener.lock().unwrap_or_else(|e| e.into_inner()) = listeners[0].try_clone().ok();
+
+        let info = ServerInfo {
+            version: env!("CARGO_PKG_VERSION"),
+            addresses,
+            tls: self.is_tls(),
+            keep_alive: self.config.keep_alive.max_requests > 1,
+            workers: self.config.workers,
+            features: compiled_features(),
+            started: SystemTime::now(),
+        };
+        log_info!("{}", info.banner());
+        if self.config.print_config {
+            log_info!("Effective configuration:\n{}", self.config);
+        }
+        *self.info.lock().unwrap_or_else(|e| e.into_inner()) = Some(info.clone());
+
+        let pool = self.config.workers.map(ThreadPool::new);
+        if let Some(pool) = &pool {
+            let _ = self.stats.pool.set(pool.load());
+        }
+
+        // Bound sockets already queue connections, so the server counts as ready
+        // before the acceptors start.
+        if let Some(ready) = ready {
+            let _ = ready.send(info);
+        }
+
+        thread::scope(|scope| {
+            for listener in &listeners[1..] {
+                scope.spawn(|| self.accept(listener, pool.as_ref()));
+            }
+            self.accept(&listeners[0], pool.as_ref());
+        });
+
+        // Refuse new connections right away, then give in-flight ones until the
+        // deadline to finish the request they are on.
+        drop(listeners);
+        self.listener
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let deadline = Instant::now() + self.config.shutdown_timeout;
+        while self.stats.active_connections() > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        if self.stats.active_connections() > 0 {
+            log_warn!(
+                "Shutdown deadline passed with {} connections still open",
+                self.stats.active_connections()
+            );
+            // Dropping the pool would join workers that are still busy.
+            std::mem::forget(pool);
+        }
+
+        Ok(())
+    }
+
+    fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.shutdown
+            .local_addrs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    // A listener handed over by with_listener stands in for the first address. The
+    // bound addresses are registered so a shutdown can wake the acceptors.
+    fn bind_listeners(&self) -> Result<Vec<TcpListener>> {
+        let preset = self
+            .listener
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        let skip = usize::from(preset.is_some());
+        let mut listeners: Vec<TcpListener> = preset.into_iter().collect();
+        for addr in self.addrs.iter().skip(skip) {
+            let listener =
+                TcpListener::bind(addr).context(format!("Failed to bind the address: {}", addr))?;
+            listeners.push(listener);
+        }
+        if listeners.is_empty() {
+            anyhow::bail!("No address to listen on");
+        }
+        *self
+            .shutdown
+            .local_addrs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = listeners
+            .iter()
+            .filter_map(|listener| listener.local_addr().ok())
+            .collect();
+        Ok(listeners)
+    }
+
+    // Accepts until a shutdown wakes this listener.
+    fn accept(&self, listener: &TcpListener, pool: Option<&ThreadPool>) {
+        loop {
+            if self.config.over_limit == OverLimit::Wait {
+                self.wait_for_capacity();
             }
+            let stream = listener.accept().map(|(stream, _)| stream);
+
+            // A connection accepted after shutdown began is still served: it may be
+            // a real client rather than the wake-up, for instance once a successor
+            // shares the socket.
+            let closing = self.shutdown.is_shutdown();
 
             match stream {
                 Ok(stream) => {
-                    let handler = self.handler.clone();
-                    thread::spawn(move || {
-                        if let Err(e) = handle_connection(stream, handler) {
-                            eprintln!("Error handling connection: {}", e);
-                        }
-                    });
+                    let saturated = matches!(
+                        (pool, self.config.shed_backlog),
+                        (Some(pool), Some(backlog)) if pool.is_saturated(backlog)
+                    );
+                    if saturated {
+                        self.stats.connections_shed.fetch_add(1, Ordering::Relaxed);
+                        self.turn_away(stream);
+                    } else if self.at_capacity() {
+                        self.stats
+                            .connections_rejected
+                            .fetch_add(1, Ordering::Relaxed);
+                        self.turn_away(stream);
+                    } else {
+                        self.spawn_connection(stream, pool);
+                    }
                 }
-                Err(e) => eprintln!("Error accepting connection: {}", e),
+                Err(e) => log_error!("Error accepting connection: {}", e),
             }
+
+            if closing {
+                break;
+            }
+        }
+    }
+
+    fn at_capacity(&self) -> bool {
+        self.config
+            .max_connections
+            .is_some_and(|max| self.stats.active_connections() >= max)
+    }
+
+    // Holds off the next accept while the server is full. A shutdown ends the wait
+    // so its wake-up connection can be taken.
+    fn wait_for_capacity(&self) {
+        while self.at_capacity() && !self.shutdown.is_shutdown() {
+            thread::sleep(CAPACITY_POLL);
         }
+    }
 
-        Ok(())
+    // A plaintext 503 means nothing to a TLS client, so those are just closed.
+    fn turn_away(&self, stream: TcpStream) {
+        if !self.is_tls() {
+            shed(stream);
+        }
+    }
+
+    fn stack(&self) -> Arc<dyn Handler> {
+        self.stack
+            .get_or_init(|| {
+                if self.middleware.is_empty() {
+                    return self.handler.clone();
+                }
+                Arc::new(Chain::with_layers(
+                    self.handler.clone(),
+                    self.middleware.clone(),
+                ))
+            })
+            .clone()
+    }
+
+    fn spawn_connection(&self, stream: TcpStream, pool: Option<&ThreadPool>) {
+        let handler = self.stack();
+        let stats = self.stats.clone();
+        let closed = self.shutdown.closed.clone();
+        let config = self.config.clone();
+        #[cfg(feature = "tls")]
+        let tls = self.tls.clone();
+        let open = OpenConnection::new(stats.clone(), config.metrics.clone());
+        let job = move || {
+            #[cfg(feature = "tls")]
+            let result = match tls {
+                Some(tls) => tls.accept(stream).map_err(Into::into).and_then(|stream| {
+                    handle_connection(stream, handler, &stats, &config, &closed)
+                }),
+                None => handle_connection(stream, handler, &stats, &config, &closed),
+            };
+            #[cfg(not(feature = "tls"))]
+            let result = handle_connection(stream, handler, &stats, &config, &closed);
+            drop(open);
+            if let Err(e) = result {
+                config.report(&e);
+            }
+        };
+        match pool {
+            Some(pool) => pool.execute(job),
+            None => {
+                thread::spawn(job);
+            }
+        }
     }
 
     pub fn close(&self) {
-        self.closed.store(true, Ordering::Relaxed);
+        self.shutdown.shutdown();
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    // Starts `successor` with this server's listening socket and then shuts down
+    // gracefully: the successor accepts new connections while this process drains
+    // the ones it already has. Requires run() to have bound the socket.
+    #[cfg(unix)]
+    pub fn hand_off(
+        &self,
+        successor: std::process::Command,
+    ) -> std::io::Result<std::process::Child> {
+        let child = {
+            let listener = self.listener.lock().unwrap_or_else(|e| e.into_inner());
+            let listener = listener.as_ref().ok_or_else(|| {
+                std::io::Error::new(ErrorKind::NotConnected, "server is not listening")
+            })?;
+            crate::handoff::spawn_successor(successor, listener, self.stats.active_connections())?
+        };
+        self.close();
+        Ok(child)
+    }
+
+    pub fn stats(&self) -> &ServerStats {
+        &self.stats
+    }
+
+    fn is_tls(&self) -> bool {
+        #[cfg(feature = "tls")]
+        return self.tls.is_some();
+        #[cfg(not(feature = "tls"))]
+        false
     }
 }
 
-fn handle_connection(mut stream: TcpStream, handler: Arc<dyn Handler>) -> Result<()> {
-    stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
-    stream.set_write_timeout(Some(std::time::Duration::from_secs(5)))?;
+// A server whose sockets are already bound. Everything else about the server is
+// reachable through Deref, e.g. close() and stats().
+pub struct BoundServer<H: Handler> {
+    server: Server<H>,
+    listeners: Mutex<Option<Vec<TcpListener>>>,
+    local_addrs: Vec<SocketAddr>,
+}
 
-    let response = match request_from_reader(&mut stream) {
-        Ok(request) => {
-            println!(
-                "{:?} {} HTTP/{}",
-                request.method(),
-                request.target(),
-                request.http_version()
-            );
-            handler.handle(&request)
+impl<H: Handler + 'static> BoundServer<H> {
+    // The first bound address, with the port the OS picked if it was 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addrs[0]
+    }
+
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.local_addrs
+    }
+
+    // Serves until shutdown. The sockets are used up, so a second call fails.
+    pub fn run(&self) -> Result<()> {
+        let listeners = self
+            .listeners
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .context("Server has already run")?;
+        self.server.serve(listeners, None)
+    }
+}
+
+impl<H: Handler> std::ops::Deref for BoundServer<H> {
+    type Target = Server<H>;
+
+    fn deref(&self) -> &Server<H> {
+        &self.server
+    }
+}
+
+// A byte stream the connection loop can serve: the socket itself, or a wrapper
+// around it such as TLS. Timeouts are always applied to the underlying socket.
+pub(crate) trait Transport: Read + Write {
+    fn tcp(&self) -> &TcpStream;
+
+    // Takeovers get the raw socket, which only a plain connection can hand out.
+    const TAKEOVER: bool = false;
+
+    fn into_taken(reader: BufReader<Self>) -> Option<TakenStream>
+    where
+        Self: Sized,
+    {
+        drop(reader);
+        None
+    }
+
+    // Last chance to write before the connection is dropped.
+    fn finish(&mut self) {}
+
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo::from_tcp(self.tcp())
+    }
+}
+
+impl Transport for TcpStream {
+    const TAKEOVER: bool = true;
+
+    fn tcp(&self) -> &TcpStream {
+        self
+    }
+
+    fn into_taken(reader: BufReader<Self>) -> Option<TakenStream> {
+        Some(TakenStream::new(reader))
+    }
+}
+
+fn handle_connection<T: Transport>(
+    stream: T,
+    handler: Arc<dyn Handler>,
+    stats: &ServerStats,
+    config: &ServerConfig,
+    closed: &AtomicBool,
+) -> Result<()> {
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "connection",
+        peer = stream.tcp().peer_addr().ok().map(tracing::field::display)
+    );
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+    stream
+        .tcp()
+        .set_read_timeout(Some(config.header_read_timeout))?;
+    stream.tcp().set_write_timeout(Some(config.write_timeout))?;
+
+    let proxy = match config.proxy_protocol {
+        true => {
+            let deadline = Instant::now() + config.header_read_timeout;
+            match proxy_protocol::read_header(stream.tcp(), deadline) {
+                Ok(header) => Some(header),
+                Err(ProxyProtocolError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(());
+                }
+                Err(e) => {
+                    log_warn!("Dropping connection: {}", e);
+                    return Ok(());
+                }
+            }
         }
-        Err(e) => handler.handle_bad_request(&e),
+        false => None,
     };
 
-    if let Err(e) = response.send(&mut stream) {
-        eprintln!("Failed to send response: {}", e);
+    let mut reader = BufReader::new(stream);
+    let result = serve_connection(&mut reader, handler, config, closed, proxy);
+    match result {
+        Ok(Closing::Takeover(takeover)) => {
+            let stream = reader.get_ref().tcp();
+            stream.set_read_timeout(None)?;
+            stream.set_write_timeout(None)?;
+            if let Some(taken) = T::into_taken(reader) {
+                takeover.run(taken);
+            }
+            Ok(())
+        }
+        Ok(Closing::Done) => {
+            reader.get_mut().finish();
+            Ok(())
+        }
+        Ok(Closing::Answered { unread_input }) => {
+            reader.get_mut().finish();
+            linger(reader.get_mut(), unread_input, stats);
+            Ok(())
+        }
+        Err(e) => Err(e),
     }
+}
 
-    Ok(())
+// How serve_connection leaves a connection it is done with.
+enum Closing {
+    // Nothing more to say: the peer left or went quiet, or a write failed.
+    Done,
+    // The last response went out with Connection: close while the peer may still
+    // be sending; `unread_input` when part of the request it answered was not read.
+    Answered { unread_input: bool },
+    // A takeover wants the rest of the connection.
+    Takeover(Takeover),
+}
+
+// Closing a socket with unread input makes the kernel answer with RST, and a
+// client that gets one may throw the response away before reading it; a slow
+// uploader turned down early is the usual victim. So the write side is shut
+// first, telling the client the response is complete, and what it still sends
+// is read and discarded, within DRAIN_LIMIT and DRAIN_DEADLINE, before the
+// socket is closed.
+fn linger<T: Transport>(stream: &mut T, unread_input: bool, stats: &ServerStats) {
+    let _ = stream.flush();
+    if stream.tcp().shutdown(Shutdown::Write).is_err() {
+        return;
+    }
+    // A request read in full leaves at most a pipelined one in flight, which
+    // arrives promptly; an unread body may trickle in for a while yet.
+    let quiet = if unread_input {
+        DRAIN_DEADLINE
+    } else {
+        LINGER_QUIET
+    };
+    let outcome = drain(stream, DRAIN_LIMIT, DRAIN_DEADLINE, quiet);
+    if !unread_input {
+        return;
+    }
+    match outcome {
+        Ok(DrainOutcome::Drained(0)) => {}
+        Ok(DrainOutcome::Drained(_)) => {
+            stats.bodies_drained.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(DrainOutcome::Aborted) | Err(_) => {
+            stats.drains_aborted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Serves requests until the connection should close, or until a takeover wants
+// the rest of it.
+fn serve_connection<T: Transport>(
+    reader: &mut BufReader<T>,
+    handler: Arc<dyn Handler>,
+    config: &ServerConfig,
+    closed: &AtomicBool,
+    proxy: Option<ProxyHeader>,
+) -> Result<Closing> {
+    let mut context = ConnectionContext::new();
+    let keep_alive = config.keep_alive;
+
+    loop {
+        // Between requests the peer gets the idle timeout to start the next one;
+        // a close, a quiet connection or a shutdown ends the loop without an error
+        // response.
+        if context.requests() == 0 {
+            // A peer that connects and leaves without a byte (a health probe, the
+            // shutdown wake-up) is not a bad request.
+            match reader.fill_buf() {
+                Ok([]) => return Ok(Closing::Done),
+                Ok(buf) => {
+                    let prior_knowledge = http2::is_preface_start(buf);
+                    // Any TLS handshake is done by now, so its outcome is known.
+                    let mut info = reader.get_ref().connection_info();
+                    if let Some(header) = &proxy {
+                        header.apply(&mut info);
+                    }
+                    context.set_info(info);
+                    let negotiated = context.info().alpn.as_deref() == Some("h2");
+                    if config.http2 && (prior_knowledge || negotiated) {
+                        http2::serve(reader, handler.as_ref(), config, &mut context, closed)?;
+                        return Ok(Closing::Done);
+                    }
+                }
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    let response = handler.handle_bad_request(&ParseError::HeaderTimeout);
+                    if response.close().send(reader.get_mut()).is_err() {
+                        return Ok(Closing::Done);
+                    }
+                    return Ok(Closing::Answered {
+                        unread_input: false,
+                    });
+                }
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            if !await_next_request(reader, keep_alive.idle_timeout, closed)? {
+                return Ok(Closing::Done);
+            }
+            reader
+                .get_ref()
+                .tcp()
+                .set_read_timeout(Some(config.header_read_timeout))?;
+        }
+
+        let may_keep_alive = context.requests() + 1 < keep_alive.max_requests as u64
+            && !closed.load(Ordering::SeqCst);
+        let mut exchange = dispatch(
+            reader,
+            &mut context,
+            handler.as_ref(),
+            config,
+            may_keep_alive,
+            |reader: &mut BufReader<T>| {
+                reader
+                    .get_ref()
+                    .tcp()
+                    .set_read_timeout(Some(config.body_read_timeout))
+            },
+        );
+        if let Some(request) = exchange.h2c.take() {
+            http2::upgrade(
+                reader,
+                handler.as_ref(),
+                config,
+                &mut context,
+                closed,
+                request,
+            )?;
+            return Ok(Closing::Done);
+        }
+        let response = &mut exchange.response;
+
+        let takeover = response.take_takeover();
+        if takeover.is_some() && !T::TAKEOVER {
+            log_error!("Connection takeover is not supported on this transport");
+            *response = Response::internal_server_error().close();
+            if response.send(reader.get_mut()).is_err() {
+                return Ok(Closing::Done);
+            }
+            return Ok(Closing::Answered {
+                unread_input: exchange.unread_input,
+            });
+        }
+        if let Err(e) = response.send(reader.get_mut()) {
+            log_warn!("Failed to send response: {}", e);
+            return Ok(Closing::Done);
+        }
+        if response.abort.is_some() {
+            return Ok(Closing::Done);
+        }
+
+        if let Some(takeover) = takeover {
+            return Ok(Closing::Takeover(takeover));
+        }
+
+        // The response may stream while the request body is still arriving; what
+        // the handler leaves unread is skipped so the connection can go on.
+        if let Some(input) = exchange.duplex_input.take() {
+            let mut stream = DuplexStream::new(reader, input.framing, config.limits.max_body_bytes)
+                .with_buffered(input.buffered);
+            if let Some(duplex) = response.take_duplex() {
+                let streamed = duplex.run(&mut stream).and_then(|_| stream.finish());
+                if let Err(e) = streamed {
+                    log_warn!("Failed to stream response: {}", e);
+                    return Ok(Closing::Done);
+                }
+            }
+            if !stream.skip_body(DRAIN_LIMIT as u64) {
+                exchange.keep_alive = false;
+                exchange.unread_input = true;
+            }
+        }
+
+        if exchange.keep_alive {
+            continue;
+        }
+
+        // A rejected request may still have body bytes in flight, and a client may
+        // have pipelined more requests; both are read off by linger.
+        return Ok(Closing::Answered {
+            unread_input: exchange.unread_input,
+        });
+    }
+}
+
+// Waits in short slices so an idle connection notices a shutdown promptly.
+fn await_next_request<T: Transport>(
+    reader: &mut BufReader<T>,
+    idle_timeout: Duration,
+    closed: &AtomicBool,
+) -> Result<bool> {
+    let deadline = Instant::now() + idle_timeout;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || closed.load(Ordering::SeqCst) {
+            return Ok(false);
+        }
+        reader
+            .get_ref()
+            .tcp()
+            .set_read_timeout(Some(remaining.min(SHUTDOWN_POLL)))?;
+        match reader.fill_buf() {
+            Ok(buf) => return Ok(!buf.is_empty()),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => return Ok(false),
+        }
+    }
+}
+
+// Runs on the acceptor, so it never blocks for long: the canned 503 fits in the
+// socket buffer, and whatever part of the request has already arrived is read
+// without waiting so closing does not reset the connection under the response.
+fn shed(mut stream: TcpStream) {
+    let response = Response::service_unavailable()
+        .with_header("Retry-After", "1")
+        .close();
+    let _ = stream.set_write_timeout(Some(SHED_WRITE_TIMEOUT));
+    if response.send(&mut stream).is_err() {
+        return;
+    }
+    let _ = stream.shutdown(Shutdown::Write);
+    if stream.set_nonblocking(true).is_ok() {
+        let mut buf = [0u8; 4096];
+        let mut total = 0;
+        while total < DRAIN_LIMIT {
+            match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => total += n,
+            }
+        }
+    }
+}
+
+struct Exchange {
+    response: Response,
+    // The input may still hold unread bytes of a rejected request.
+    unread_input: bool,
+    // Both sides agreed to reuse the connection for another request.
+    keep_alive: bool,
+    // A request asking to switch to HTTP/2, answered on stream 1 after the 101.
+    h2c: Option<Request>,
+    // Set when the response streams a duplex body or the request body was left on
+    // the wire.
+    duplex_input: Option<DuplexInput>,
+}
+
+struct DuplexInput {
+    framing: BodyFraming,
+    buffered: Body,
+}
+
+// RFC 9112 9.3: HTTP/1.1 persists unless either side sends "close". The request
+// line parser admits nothing older, so there is no HTTP/1.0 default to honour. A
+// message framed by both Content-Length and Transfer-Encoding must not be followed
+// by another on the same connection.
+pub(crate) fn wants_keep_alive(request: &Request) -> bool {
+    if request.header("Content-Length").is_some() && request.header("Transfer-Encoding").is_some() {
+        return false;
+    }
+
+    !request.connection_header().close()
+}
+
+// Reads one request from `reader` and produces the response to write back.
+fn dispatch<R: BufRead>(
+    reader: &mut R,
+    context: &mut ConnectionContext,
+    handler: &dyn Handler,
+    config: &ServerConfig,
+    may_keep_alive: bool,
+    before_body: impl FnOnce(&mut R) -> std::io::Result<()>,
+) -> Exchange {
+    let started = Instant::now();
+    let streams_body = |request: &Request| {
+        config
+            .stream_bodies
+            .as_ref()
+            .is_some_and(|filter| filter(request))
+    };
+    let parsed = request_from_buf_reader_deferred(
+        reader,
+        &config.parse_options(),
+        context,
+        streams_body,
+        before_body,
+    );
+    let (mut request, unread_body) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            let response = handler.handle_bad_request(&e);
+            let (response, _) = finalize_response(response, None, config.length_mismatch, false);
+            return Exchange {
+                response,
+                unread_input: true,
+                keep_alive: false,
+                h2c: None,
+                duplex_input: None,
+            };
+        }
+    };
+
+    if config.http2 && !context.info().tls && http2::is_h2c_upgrade(&request) {
+        return Exchange {
+            // Never sent: http2::upgrade writes the 101 itself.
+            response: Response::new(StatusCode::SwitchingProtocols),
+            unread_input: false,
+            keep_alive: false,
+            h2c: Some(request),
+            duplex_input: None,
+        };
+    }
+
+    let is_head = request.method() == &Method::HEAD;
+    let keep_alive = may_keep_alive && wants_keep_alive(&request);
+    let mut response = answer(&mut request, handler, config);
+    // The head of a HEAD response is all that is sent.
+    if is_head {
+        response.duplex = None;
+    }
+    let (response, keep_alive) =
+        finalize_response(response, Some(&request), config.length_mismatch, keep_alive);
+    config.observe(&request, &response, started.elapsed());
+    let duplex_input = match (unread_body, response.duplex.is_some()) {
+        (None, false) => None,
+        (unread, _) => Some(DuplexInput {
+            framing: unread.unwrap_or(BodyFraming::Length(0)),
+            buffered: std::mem::take(&mut request.body),
+        }),
+    };
+    Exchange {
+        response,
+        unread_input: false,
+        keep_alive,
+        h2c: None,
+        duplex_input,
+    }
+}
+
+// Runs the handler on a parsed request, with the per-request instrumentation every
+// protocol shares.
+pub(crate) fn answer(
+    request: &mut Request,
+    handler: &dyn Handler,
+    config: &ServerConfig,
+) -> Response {
+    request.extensions_mut().insert(ServerTiming::new());
+    #[cfg(feature = "otel")]
+    {
+        let context = crate::otel::TraceContext::for_request(request);
+        request.extensions_mut().insert(context);
+    }
+    #[cfg(feature = "tracing")]
+    let span = tracing::info_span!(
+        "request",
+        method = request.method().as_str(),
+        target = request.target()
+    );
+    #[cfg(feature = "tracing")]
+    let _entered = span.enter();
+    if !config.logs_requests() {
+        log_info!(
+            "{:?} {} HTTP/{}",
+            request.method(),
+            request.target(),
+            request.http_version()
+        );
+    }
+    log_debug!("Request headers: {:?}", request.headers);
+    let response = call_handler(handler, request);
+    match request.server_timing().and_then(ServerTiming::header_value) {
+        Some(value) => response.with_header("Server-Timing", value),
+        None => response,
+    }
+}
+
+// Last checks before a response goes out: framing is verified and the Connection
+// header settled. Returns whether the connection stays open. `request` is the one
+// being answered, if it could be parsed.
+pub(crate) fn finalize_response(
+    mut response: Response,
+    request: Option<&Request>,
+    length_mismatch: LengthMismatchPolicy,
+    keep_alive: bool,
+) -> (Response, bool) {
+    let is_head = request.is_some_and(|request| request.method() == &Method::HEAD);
+    if !is_head && let Err(e) = response.enforce_content_length(length_mismatch) {
+        log_error!("Discarding response with broken framing: {}", e);
+        response = Response::internal_server_error();
+    }
+
+    // After a 101 only the new protocol may be spoken, which takes a takeover.
+    if response.status_code == StatusCode::SwitchingProtocols && response.takeover.is_none() {
+        log_error!("Discarding 101 response without a takeover to run the new protocol");
+        response = Response::internal_server_error();
+    }
+
+    // Takeovers manage the connection themselves.
+    let keep_alive = match response.takeover {
+        None => response.reconcile_connection(keep_alive),
+        Some(_) => keep_alive,
+    };
+    (response, keep_alive)
+}
+
+// Serves a single exchange over a stream supplied by the embedder, for hosts
+// without raw sockets (WASI plugins, in-process transports, tests). Connection
+// takeover needs a socket and is refused here.
+pub fn serve_stream<S: Read + Write>(stream: S, handler: &dyn Handler) -> std::io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut context = ConnectionContext::new();
+    let exchange = dispatch(
+        &mut reader,
+        &mut context,
+        handler,
+        &ServerConfig::default(),
+        false,
+        |_| Ok(()),
+    );
+    let mut response = exchange.response;
+
+    if response.take_takeover().is_some() {
+        log_error!("Connection takeover is not supported on provided streams");
+        response = Response::internal_server_error();
+    }
+
+    response.send(reader.get_mut())?;
+    match (response.take_duplex(), exchange.duplex_input) {
+        (Some(duplex), Some(input)) => {
+            let mut stream =
+                DuplexStream::new(&mut reader, input.framing, None).with_buffered(input.buffered);
+            duplex.run(&mut stream)?;
+            stream.finish()
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum DrainOutcome {
+    Drained(usize),
+    Aborted,
+}
+
+// Reads and discards input until the peer closes or goes `quiet`, giving up once
+// more than `limit` bytes or `deadline` have gone by.
+fn drain(
+    stream: &mut impl ReadTimeout,
+    limit: usize,
+    deadline: Duration,
+    quiet: Duration,
+) -> std::io::Result<DrainOutcome> {
+    let started = Instant::now();
+    let mut buf = [0u8; 4096];
+    let mut total = 0;
+
+    loop {
+        let remaining = match deadline.checked_sub(started.elapsed()) {
+            Some(remaining) if !remaining.is_zero() => remaining,
+            _ => return Ok(DrainOutcome::Aborted),
+        };
+        stream.set_timeout(remaining.min(quiet))?;
+
+        match stream.read(&mut buf) {
+            Ok(0) => return Ok(DrainOutcome::Drained(total)),
+            Ok(n) => {
+                total += n;
+                if total > limit {
+                    return Ok(DrainOutcome::Aborted);
+                }
+            }
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Ok(DrainOutcome::Drained(total));
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+trait ReadTimeout: Read {
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()>;
+}
+
+impl<T: Transport> ReadTimeout for T {
+    fn set_timeout(&mut self, timeout: Duration) -> std::io::Result<()> {
+        self.tcp().set_read_timeout(Some(timeout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    impl ReadTimeout for Cursor<Vec<u8>> {
+        fn set_timeout(&mut self, _timeout: Duration) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct Hello;
+
+    impl Handler for Hello {
+        fn handle(&self, request: &Request) -> Response {
+            Response::ok().with_body(crate::http::Body::from(format!("hello {}", request.path())))
+        }
+    }
+
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_serve_stream() {
+        let mut duplex = Duplex {
+            input: Cursor::new(b"GET /wasm HTTP/1.1\r\nHost: plugin\r\n\r\n".to_vec()),
+            output: Vec::new(),
+        };
+        serve_stream(&mut duplex, &Hello).unwrap();
+
+        let output = String::from_utf8(duplex.output).unwrap();
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(output.ends_with("\r\n\r\nhello /wasm"));
+    }
+
+    #[test]
+    fn test_drain_until_eof() {
+        let mut input = Cursor::new(vec![b'a'; 1000]);
+        let outcome = drain(&mut input, DRAIN_LIMIT, DRAIN_DEADLINE, LINGER_QUIET).unwrap();
+        assert_eq!(outcome, DrainOutcome::Drained(1000));
+    }
+
+    #[test]
+    fn test_drain_aborts_over_limit() {
+        let mut input = Cursor::new(vec![b'a'; 10_000]);
+        let outcome = drain(&mut input, 4096, DRAIN_DEADLINE, LINGER_QUIET).unwrap();
+        assert_eq!(outcome, DrainOutcome::Aborted);
+    }
+
+    #[test]
+    fn test_config_validation() {
+        let config = ServerConfig::builder().finish().unwrap();
+        assert_eq!(config.keep_alive, KeepAlive::default());
+
+        let err = ServerConfig::builder()
+            .header_read_timeout(Duration::from_secs(10))
+            .finish()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::IdleTimeoutTooShort {
+                idle: Duration::from_secs(5),
+                header: Duration::from_secs(10),
+            }
+        );
+        // Without keep-alive there is no idle wait to compare against.
+        assert!(
+            ServerConfig::builder()
+                .header_read_timeout(Duration::from_secs(10))
+                .keep_alive(KeepAlive::disabled())
+                .finish()
+                .is_ok()
+        );
+
+        let err = ServerConfig::builder()
+            .write_timeout(Duration::ZERO)
+            .finish()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::ZeroTimeout("write timeout"));
+        let err = ServerConfig::builder()
+            .shed_threshold(8)
+            .finish()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::ShedWithoutWorkers);
+        let err = ServerConfig::builder()
+            .max_target(8 * 1024)
+            .finish()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "max target (8192 bytes) exceeds max request line (4096 bytes)"
+        );
+
+        assert!(
+            Server::builder()
+                .max_request_line(16 * 1024)
+                .try_build(Hello)
+                .is_err()
+        );
+        let printed = ServerConfig::default().to_string();
+        assert!(
+            printed.contains("keep_alive: 5s idle, 100 requests\n"),
+            "{}",
+            printed
+        );
+        assert!(
+            printed.contains("workers: thread per connection\n"),
+            "{}",
+            printed
+        );
+    }
 }
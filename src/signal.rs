@@ -0,0 +1,39 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::logging::log_info;
+use crate::server::ShutdownHandle;
+
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+const POLL: Duration = Duration::from_millis(50);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+unsafe extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+}
+
+// Only async-signal-safe work here: the watcher thread does the actual shutdown.
+extern "C" fn on_signal(_signum: i32) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// Shuts the server down gracefully on the first SIGINT or SIGTERM.
+pub fn shutdown_on_ctrl_c(handle: ShutdownHandle) {
+    unsafe {
+        signal(SIGINT, on_signal);
+        signal(SIGTERM, on_signal);
+    }
+
+    thread::spawn(move || {
+        while !INTERRUPTED.load(Ordering::SeqCst) {
+            thread::sleep(POLL);
+        }
+        log_info!("Shutting down");
+        handle.shutdown();
+    });
+}
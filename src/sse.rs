@@ -0,0 +1,235 @@
+use std::{
+    io::{self, Write},
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    time::{Duration, Instant},
+};
+
+use crate::http::{Request, Response, TakenStream};
+
+// One server-sent event. Only the data field is required; multi-line data is sent
+// as several data lines and rejoined by the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    pub fn data(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    // The event type; clients listen for it with addEventListener(name).
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(single_line(name.into()));
+        self
+    }
+
+    // Sent back by reconnecting clients as Last-Event-ID.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(single_line(id.into()).replace('\0', ""));
+        self
+    }
+
+    // How long the client should wait before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", event));
+        }
+        if let Some(id) = &self.id {
+            out.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in self.data.split('\n') {
+            out.push_str(&format!(
+                "data: {}\n",
+                line.strip_suffix('\r').unwrap_or(line)
+            ));
+        }
+        out.push('\n');
+        out
+    }
+}
+
+fn single_line(value: String) -> String {
+    value.replace(['\r', '\n'], "")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SseConfig {
+    // A comment line goes out after this long without an event, so proxies and
+    // load balancers do not close the connection as idle. None disables it.
+    pub keep_alive: Option<Duration>,
+}
+
+impl Default for SseConfig {
+    fn default() -> Self {
+        SseConfig {
+            keep_alive: Some(Duration::from_secs(15)),
+        }
+    }
+}
+
+// The open event stream. Every write is flushed, so each event reaches the client
+// as soon as it is sent.
+pub struct SseStream<W: Write> {
+    writer: W,
+    config: SseConfig,
+    last_event_id: Option<String>,
+    last_write: Instant,
+}
+
+impl<W: Write> SseStream<W> {
+    pub fn new(writer: W, config: SseConfig) -> Self {
+        SseStream {
+            writer,
+            config,
+            last_event_id: None,
+            last_write: Instant::now(),
+        }
+    }
+
+    // The Last-Event-ID a reconnecting client sent, to resume from.
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        self.write(event.encode().as_bytes())
+    }
+
+    pub fn comment(&mut self, text: &str) -> io::Result<()> {
+        let mut out = String::new();
+        for line in text.split('\n') {
+            out.push_str(&format!(": {}\n", line.trim_end_matches('\r')));
+        }
+        out.push('\n');
+        self.write(out.as_bytes())
+    }
+
+    // Sends a keep-alive comment if the stream has been quiet for the configured
+    // interval. Call it from loops that wait between events.
+    pub fn keep_alive(&mut self) -> io::Result<()> {
+        match self.config.keep_alive {
+            Some(interval) if self.last_write.elapsed() >= interval => self.write(b":\n\n"),
+            _ => Ok(()),
+        }
+    }
+
+    // Forwards events until every sender is dropped or the client goes away,
+    // sending keep-alive comments while nothing arrives.
+    pub fn forward(&mut self, events: &Receiver<Event>) -> io::Result<()> {
+        loop {
+            let event = match self.config.keep_alive {
+                Some(interval) => {
+                    let wait = interval.saturating_sub(self.last_write.elapsed());
+                    match events.recv_timeout(wait) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Timeout) => {
+                            self.keep_alive()?;
+                            continue;
+                        }
+                        Err(RecvTimeoutError::Disconnected) => return Ok(()),
+                    }
+                }
+                None => match events.recv() {
+                    Ok(event) => event,
+                    Err(_) => return Ok(()),
+                },
+            };
+            self.send(&event)?;
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.writer.flush()?;
+        self.last_write = Instant::now();
+        Ok(())
+    }
+}
+
+// A text/event-stream response whose body `f` writes as events happen. The stream
+// is delimited by closing the connection, which ends when `f` returns. Needs a
+// plain HTTP/1.1 connection: HTTP/2 and the async server refuse takeovers.
+pub fn stream(
+    request: &Request,
+    config: SseConfig,
+    f: impl FnOnce(SseStream<TakenStream>) + Send + 'static,
+) -> Response {
+    let last_event_id = request.header("Last-Event-ID").map(str::to_string);
+    Response::ok()
+        .with_header("Content-Type", "text/event-stream")
+        .with_header("Cache-Control", "no-cache")
+        .close()
+        .with_takeover(move |taken| {
+            let mut stream = SseStream::new(taken, config);
+            stream.last_event_id = last_event_id;
+            f(stream)
+        })
+}
+
+// Like stream, with the events supplied through a channel, for pushing from other
+// threads. The response ends once every sender is dropped.
+pub fn channel(request: &Request, config: SseConfig) -> (Sender<Event>, Response) {
+    let (sender, receiver) = mpsc::channel();
+    let response = stream(request, config, move |mut stream| {
+        let _ = stream.forward(&receiver);
+    });
+    (sender, response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_encodes_fields_and_multiline_data() {
+        let event = Event::data("line one\nline two")
+            .event("update\r\nid: spoofed")
+            .id("42")
+            .retry(Duration::from_secs(3));
+        assert_eq!(
+            event.encode(),
+            "event: updateid: spoofed\nid: 42\nretry: 3000\ndata: line one\ndata: line two\n\n"
+        );
+    }
+
+    #[test]
+    fn test_forward_sends_keep_alives_while_idle() {
+        let (sender, receiver) = mpsc::channel();
+        let config = SseConfig {
+            keep_alive: Some(Duration::from_millis(20)),
+        };
+        let producer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(70));
+            sender.send(Event::data("late")).unwrap();
+        });
+
+        let mut stream = SseStream::new(Vec::new(), config);
+        stream.forward(&receiver).unwrap();
+        producer.join().unwrap();
+
+        let output = String::from_utf8(stream.into_inner()).unwrap();
+        assert!(output.starts_with(":\n\n"), "got: {:?}", output);
+        assert!(output.ends_with("data: late\n\n"));
+    }
+}
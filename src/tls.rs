@@ -0,0 +1,141 @@
+use std::{
+    fs,
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use rustls::{
+    ServerConnection, StreamOwned,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use thiserror::Error;
+
+use crate::http::ConnectionInfo;
+use crate::server::Transport;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Failed to read {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid PEM: {0}")]
+    Pem(#[from] rustls::pki_types::pem::Error),
+
+    #[error("No certificates found in the certificate chain")]
+    NoCertificates,
+
+    #[error("TLS configuration rejected: {0}")]
+    Rustls(#[from] rustls::Error),
+}
+
+// Server-side TLS settings, shared by every connection.
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    inner: Arc<rustls::ServerConfig>,
+}
+
+impl TlsConfig {
+    // `cert_chain` holds the leaf certificate first, followed by any intermediates.
+    pub fn from_pem(cert_chain: &[u8], private_key: &[u8]) -> Result<Self, TlsError> {
+        let certs = CertificateDer::pem_slice_iter(cert_chain).collect::<Result<Vec<_>, _>>()?;
+        if certs.is_empty() {
+            return Err(TlsError::NoCertificates);
+        }
+        let key = PrivateKeyDer::from_pem_slice(private_key)?;
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut config = rustls::ServerConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+        config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        Ok(TlsConfig {
+            inner: Arc::new(config),
+        })
+    }
+
+    pub fn from_pem_files(
+        cert_chain: impl AsRef<Path>,
+        private_key: impl AsRef<Path>,
+    ) -> Result<Self, TlsError> {
+        let read = |path: &Path| {
+            fs::read(path).map_err(|source| TlsError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        };
+        Self::from_pem(&read(cert_chain.as_ref())?, &read(private_key.as_ref())?)
+    }
+
+    // For settings the PEM constructors do not cover, such as client certificates.
+    pub fn from_rustls(config: Arc<rustls::ServerConfig>) -> Self {
+        TlsConfig { inner: config }
+    }
+
+    // Offers "h2" ahead of the other ALPN protocols.
+    pub(crate) fn with_h2(self) -> Self {
+        if self.inner.alpn_protocols.iter().any(|p| p == b"h2") {
+            return self;
+        }
+        let mut config = (*self.inner).clone();
+        config.alpn_protocols.insert(0, b"h2".to_vec());
+        TlsConfig {
+            inner: Arc::new(config),
+        }
+    }
+
+    pub(crate) fn accept(&self, stream: TcpStream) -> io::Result<TlsStream> {
+        let connection = ServerConnection::new(self.inner.clone()).map_err(io::Error::other)?;
+        Ok(TlsStream(StreamOwned::new(connection, stream)))
+    }
+}
+
+// An accepted connection; the handshake runs on the first read, under the same
+// header timeout as a plaintext request head.
+pub struct TlsStream(StreamOwned<ServerConnection, TcpStream>);
+
+impl Read for TlsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Write for TlsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl Transport for TlsStream {
+    fn tcp(&self) -> &TcpStream {
+        self.0.get_ref()
+    }
+
+    fn finish(&mut self) {
+        self.0.conn.send_close_notify();
+        let _ = self.0.flush();
+    }
+
+    fn connection_info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            tls: true,
+            alpn: self
+                .0
+                .conn
+                .alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            ..ConnectionInfo::from_tcp(self.tcp())
+        }
+    }
+}
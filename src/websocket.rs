@@ -0,0 +1,486 @@
+use std::io::{self, Read, Write};
+
+use thiserror::Error;
+
+use crate::{
+    base64, digest,
+    http::{Method, Request, Response, StatusCode, TakenStream},
+};
+
+// Appended to the client's key before hashing (RFC 6455 1.3).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+
+// Close codes from RFC 6455 7.4.1.
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_INVALID_DATA: u16 = 1007;
+pub const CLOSE_TOO_BIG: u16 = 1009;
+
+#[derive(Debug, Error)]
+pub enum WebSocketError {
+    #[error("WebSocket I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("WebSocket protocol violation: {0}")]
+    Protocol(&'static str),
+
+    #[error("Text message is not valid UTF-8")]
+    InvalidUtf8,
+
+    #[error("Message exceeds {limit} bytes")]
+    MessageTooLarge { limit: usize },
+
+    #[error("The connection is closed")]
+    Closed,
+}
+
+impl WebSocketError {
+    // The close code sent to the peer when this error ends the connection.
+    fn close_code(&self) -> Option<u16> {
+        match self {
+            WebSocketError::Protocol(_) => Some(CLOSE_PROTOCOL_ERROR),
+            WebSocketError::InvalidUtf8 => Some(CLOSE_INVALID_DATA),
+            WebSocketError::MessageTooLarge { .. } => Some(CLOSE_TOO_BIG),
+            WebSocketError::Io(_) | WebSocketError::Closed => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    pub code: u16,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    // Pings are answered automatically; they are still handed to the reader.
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // None when the peer closed without a status code.
+    Close(Option<CloseFrame>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WebSocketConfig {
+    // Largest message after reassembling its fragments.
+    pub max_message_size: usize,
+}
+
+impl Default for WebSocketConfig {
+    fn default() -> Self {
+        WebSocketConfig {
+            max_message_size: 16 * 1024 * 1024,
+        }
+    }
+}
+
+// The Sec-WebSocket-Accept value for a client's Sec-WebSocket-Key.
+pub fn accept_key(key: &str) -> String {
+    base64::encode(&digest::sha1(
+        format!("{}{}", key.trim(), ACCEPT_GUID).as_bytes(),
+    ))
+}
+
+// Answers a WebSocket opening handshake. A valid one gets 101 and `f` runs with the
+// connection; anything else gets the error response RFC 6455 4.2.2 calls for.
+//
+//     "/chat" => websocket::upgrade(request, WebSocketConfig::default(), |mut ws| {
+//         while let Ok(message) = ws.read() {
+//             if let Message::Text(text) = message {
+//                 let _ = ws.send(Message::Text(text));
+//             }
+//         }
+//     }),
+pub fn upgrade(
+    request: &Request,
+    config: WebSocketConfig,
+    f: impl FnOnce(WebSocket<TakenStream>) + Send + 'static,
+) -> Response {
+    if request.method() != &Method::GET {
+        return Response::method_not_allowed().with_header("Allow", "GET");
+    }
+    if !request.wants_upgrade("websocket") {
+        return Response::new(StatusCode::UpgradeRequired)
+            .with_header("Connection", "upgrade")
+            .with_header("Upgrade", "websocket");
+    }
+    if request.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Response::new(StatusCode::UpgradeRequired)
+            .with_header("Sec-WebSocket-Version", "13");
+    }
+    let Some(key) = request
+        .header("Sec-WebSocket-Key")
+        .filter(|key| base64::decode(key.trim()).is_some_and(|nonce| nonce.len() == 16))
+    else {
+        return Response::bad_request();
+    };
+
+    Response::upgrade("websocket", move |stream| f(WebSocket::new(stream, config)))
+        .with_header("Sec-WebSocket-Accept", accept_key(key))
+}
+
+// The server end of an established WebSocket connection.
+pub struct WebSocket<S: Read + Write> {
+    stream: S,
+    config: WebSocketConfig,
+    // Opcode and data of a fragmented message still arriving.
+    partial: Option<(u8, Vec<u8>)>,
+    close_sent: bool,
+    closed: bool,
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    pub fn new(stream: S, config: WebSocketConfig) -> Self {
+        WebSocket {
+            stream,
+            config,
+            partial: None,
+            close_sent: false,
+            closed: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    // Reads the next message. A protocol violation closes the connection with the
+    // matching status code before the error is returned.
+    pub fn read(&mut self) -> Result<Message, WebSocketError> {
+        if self.closed {
+            return Err(WebSocketError::Closed);
+        }
+        match self.read_message() {
+            Ok(message) => Ok(message),
+            Err(e) => {
+                if let Some(code) = e.close_code()
+                    && !self.close_sent
+                {
+                    let _ = self.close(code, "");
+                }
+                self.closed = true;
+                Err(e)
+            }
+        }
+    }
+
+    pub fn send(&mut self, message: Message) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Err(WebSocketError::Closed);
+        }
+        let (opcode, payload) = match message {
+            Message::Text(text) => (OP_TEXT, text.into_bytes()),
+            Message::Binary(data) => (OP_BINARY, data),
+            Message::Ping(data) => (OP_PING, data),
+            Message::Pong(data) => (OP_PONG, data),
+            Message::Close(frame) => {
+                let (code, reason) =
+                    frame.map_or((None, String::new()), |f| (Some(f.code), f.reason));
+                return self.send_close(code, &reason);
+            }
+        };
+        if opcode >= OP_CLOSE && payload.len() > 125 {
+            return Err(WebSocketError::Protocol(
+                "control frame payload over 125 bytes",
+            ));
+        }
+        self.write_frame(opcode, &payload)
+    }
+
+    // Starts the close handshake; keep reading until Message::Close arrives.
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WebSocketError> {
+        self.send_close(Some(code), reason)
+    }
+
+    fn send_close(&mut self, code: Option<u16>, reason: &str) -> Result<(), WebSocketError> {
+        if self.close_sent {
+            return Ok(());
+        }
+        let mut payload = Vec::new();
+        if let Some(code) = code {
+            payload.extend_from_slice(&code.to_be_bytes());
+            // Cut at a char boundary to fit the 125-byte control frame limit.
+            let mut end = reason.len().min(123);
+            while !reason.is_char_boundary(end) {
+                end -= 1;
+            }
+            payload.extend_from_slice(&reason.as_bytes()[..end]);
+        }
+        self.close_sent = true;
+        self.write_frame(OP_CLOSE, &payload)
+    }
+
+    fn read_message(&mut self) -> Result<Message, WebSocketError> {
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                OP_PING => {
+                    if !self.close_sent {
+                        self.write_frame(OP_PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OP_PONG => return Ok(Message::Pong(frame.payload)),
+                OP_CLOSE => return self.on_close(frame.payload),
+                OP_TEXT | OP_BINARY if self.partial.is_some() => {
+                    return Err(WebSocketError::Protocol(
+                        "new message inside a fragmented one",
+                    ));
+                }
+                OP_CONTINUATION => {
+                    let Some((_, data)) = self.partial.as_mut() else {
+                        return Err(WebSocketError::Protocol("continuation without a message"));
+                    };
+                    data.extend_from_slice(&frame.payload);
+                    let len = data.len();
+                    self.check_size(len)?;
+                    if frame.fin {
+                        let (opcode, data) = self.partial.take().expect("message in progress");
+                        return data_message(opcode, data);
+                    }
+                }
+                opcode => {
+                    if !frame.fin {
+                        self.partial = Some((opcode, frame.payload));
+                        continue;
+                    }
+                    return data_message(opcode, frame.payload);
+                }
+            }
+        }
+    }
+
+    fn on_close(&mut self, payload: Vec<u8>) -> Result<Message, WebSocketError> {
+        let frame = match payload.len() {
+            0 => None,
+            1 => return Err(WebSocketError::Protocol("close payload of one byte")),
+            _ => {
+                let code = u16::from_be_bytes([payload[0], payload[1]]);
+                if !valid_close_code(code) {
+                    return Err(WebSocketError::Protocol("invalid close code"));
+                }
+                let reason = String::from_utf8(payload[2..].to_vec())
+                    .map_err(|_| WebSocketError::InvalidUtf8)?;
+                Some(CloseFrame { code, reason })
+            }
+        };
+        // Echo the status code to complete the handshake.
+        if !self.close_sent {
+            self.send_close(frame.as_ref().map(|f| f.code), "")?;
+        }
+        self.closed = true;
+        Ok(Message::Close(frame))
+    }
+
+    fn check_size(&self, len: usize) -> Result<(), WebSocketError> {
+        let limit = self.config.max_message_size;
+        if len > limit {
+            return Err(WebSocketError::MessageTooLarge { limit });
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, WebSocketError> {
+        let mut head = [0; 2];
+        self.stream.read_exact(&mut head)?;
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0F;
+        if head[0] & 0x70 != 0 {
+            return Err(WebSocketError::Protocol("reserved bits set"));
+        }
+        if !matches!(
+            opcode,
+            OP_CONTINUATION | OP_TEXT | OP_BINARY | OP_CLOSE | OP_PING | OP_PONG
+        ) {
+            return Err(WebSocketError::Protocol("unknown opcode"));
+        }
+        // Clients must mask every frame (RFC 6455 5.1).
+        if head[1] & 0x80 == 0 {
+            return Err(WebSocketError::Protocol("client frame is not masked"));
+        }
+        let len = match head[1] & 0x7F {
+            126 => {
+                let mut ext = [0; 2];
+                self.stream.read_exact(&mut ext)?;
+                u16::from_be_bytes(ext) as u64
+            }
+            127 => {
+                let mut ext = [0; 8];
+                self.stream.read_exact(&mut ext)?;
+                u64::from_be_bytes(ext)
+            }
+            len => len as u64,
+        };
+        if opcode >= OP_CLOSE && (!fin || len > 125) {
+            return Err(WebSocketError::Protocol(
+                "fragmented or oversized control frame",
+            ));
+        }
+        // Checked before allocating, so a forged length cannot exhaust memory.
+        let len = usize::try_from(len).unwrap_or(usize::MAX);
+        self.check_size(len)?;
+
+        let mut mask = [0; 4];
+        self.stream.read_exact(&mut mask)?;
+        let mut payload = vec![0; len];
+        self.stream.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        Ok(Frame {
+            fin,
+            opcode,
+            payload,
+        })
+    }
+
+    // Server frames are sent whole and unmasked.
+    fn write_frame(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WebSocketError> {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            len @ 0..=125 => frame.push(len as u8),
+            len @ 126..=0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+fn data_message(opcode: u8, data: Vec<u8>) -> Result<Message, WebSocketError> {
+    match opcode {
+        OP_TEXT => String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| WebSocketError::InvalidUtf8),
+        _ => Ok(Message::Binary(data)),
+    }
+}
+
+// Codes a peer may send; 1004-1006 and 1015 are reserved for local use.
+fn valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1014 | 3000..=4999)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    struct Duplex {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Duplex {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Duplex {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn client_frame(fin: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut frame = vec![(fin as u8) << 7 | opcode, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&mask);
+        frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+        frame
+    }
+
+    fn socket(frames: &[Vec<u8>]) -> WebSocket<Duplex> {
+        let duplex = Duplex {
+            input: Cursor::new(frames.concat()),
+            output: Vec::new(),
+        };
+        WebSocket::new(duplex, WebSocketConfig::default())
+    }
+
+    #[test]
+    fn test_accept_key_matches_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn test_reassembles_fragments_around_pings() {
+        let mut ws = socket(&[
+            client_frame(false, OP_TEXT, b"Hel"),
+            client_frame(true, OP_PING, b"hi"),
+            client_frame(true, OP_CONTINUATION, b"lo"),
+            client_frame(true, OP_CLOSE, &[0x03, 0xe8, b'b', b'y', b'e']),
+        ]);
+        assert_eq!(ws.read().unwrap(), Message::Ping(b"hi".to_vec()));
+        assert_eq!(ws.read().unwrap(), Message::Text("Hello".to_string()));
+        assert_eq!(
+            ws.read().unwrap(),
+            Message::Close(Some(CloseFrame {
+                code: CLOSE_NORMAL,
+                reason: "bye".to_string()
+            }))
+        );
+        assert!(matches!(ws.read(), Err(WebSocketError::Closed)));
+        // Pong for the ping, then the close echoed with its code.
+        assert_eq!(
+            ws.get_ref().output,
+            [&[0x8a, 2, b'h', b'i'][..], &[0x88, 2, 0x03, 0xe8]].concat()
+        );
+    }
+
+    #[test]
+    fn test_violations_close_with_a_status_code() {
+        let mut unmasked = socket(&[vec![0x81, 0x02, b'h', b'i']]);
+        assert!(matches!(unmasked.read(), Err(WebSocketError::Protocol(_))));
+        assert_eq!(unmasked.get_ref().output, [0x88, 2, 0x03, 0xea]);
+
+        let mut invalid = socket(&[client_frame(true, OP_TEXT, &[0xff, 0xfe])]);
+        assert!(matches!(invalid.read(), Err(WebSocketError::InvalidUtf8)));
+        assert_eq!(invalid.get_ref().output, [0x88, 2, 0x03, 0xef]);
+
+        let mut small = socket(&[client_frame(true, OP_BINARY, &[0; 10])]);
+        small.config.max_message_size = 8;
+        assert!(matches!(
+            small.read(),
+            Err(WebSocketError::MessageTooLarge { limit: 8 })
+        ));
+    }
+}
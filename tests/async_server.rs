@@ -0,0 +1,94 @@
+#![cfg(feature = "async")]
+
+use rawhttp::async_server::{AsyncHandler, AsyncServer};
+use rawhttp::http::{Body, Request, Response};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Echo;
+
+impl AsyncHandler for Echo {
+    async fn handle(&self, request: &Request) -> Response {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        if request.path() == "/panic" {
+            panic!("handler bug");
+        }
+        let body = request.body().as_str().unwrap_or("").to_string();
+        Response::ok().with_body(Body::from(format!("{} {}", request.path(), body)))
+    }
+}
+
+fn read_response(reader: &mut BufReader<TcpStream>) -> String {
+    let mut head = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+            length = value.trim().parse().unwrap();
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    head + &String::from_utf8(body).unwrap()
+}
+
+#[test]
+fn test_async_server_keeps_connections_alive() {
+    let port = 8094;
+    let server = Arc::new(AsyncServer::new(format!("127.0.0.1:{}", port), Echo));
+    let server_clone = server.clone();
+    let running = thread::spawn(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(server_clone.run())
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+
+    writer
+        .write_all(b"GET /first HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let first = read_response(&mut reader);
+    assert!(first.starts_with("HTTP/1.1 200 OK"));
+    assert!(first.ends_with("/first "));
+
+    writer
+        .write_all(b"GET /panic HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let failed = read_response(&mut reader);
+    assert!(failed.starts_with("HTTP/1.1 500 Internal Server Error"));
+
+    writer
+        .write_all(
+            b"POST /second HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n3\r\nabc\r\n0\r\n\r\n",
+        )
+        .unwrap();
+    let second = read_response(&mut reader);
+    assert!(second.starts_with("HTTP/1.1 200 OK"));
+    assert!(second.to_lowercase().contains("connection: close"));
+    assert!(second.ends_with("/second abc"));
+
+    let mut rest = Vec::new();
+    reader.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    server.close();
+    running.join().unwrap().unwrap();
+    assert_eq!(server.active_connections(), 0);
+}
@@ -0,0 +1,115 @@
+use rawhttp::http::{Body, Request, Response};
+use rawhttp::server::{Handler, Server};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Shout;
+
+impl Handler for Shout {
+    fn handle(&self, request: &Request) -> Response {
+        match request.path() {
+            // Sends each piece of the body back uppercased as soon as it arrives.
+            "/shout" => Response::ok()
+                .with_header("Content-Type", "text/plain")
+                .with_duplex(|stream| {
+                    let mut buf = [0u8; 64];
+                    loop {
+                        let n = stream.read(&mut buf)?;
+                        if n == 0 {
+                            return Ok(());
+                        }
+                        stream.write_all(&buf[..n].to_ascii_uppercase())?;
+                        stream.flush()?;
+                    }
+                }),
+            "/ignore" => Response::ok().with_body(Body::from("ignored")),
+            _ => Response::not_found(),
+        }
+    }
+}
+
+fn read_head(reader: &mut impl BufRead) -> String {
+    let mut head = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        head.push_str(&line);
+        if line == "\r\n" {
+            return head;
+        }
+    }
+}
+
+// Reads one chunk; the last, empty one takes the final CRLF with it.
+fn read_chunk(reader: &mut impl BufRead) -> String {
+    let mut size = String::new();
+    reader.read_line(&mut size).unwrap();
+    let size = usize::from_str_radix(size.trim(), 16).unwrap();
+    let mut data = vec![0u8; size + 2];
+    reader.read_exact(&mut data).unwrap();
+    data.truncate(size);
+    String::from_utf8(data).unwrap()
+}
+
+#[test]
+fn test_handler_answers_while_body_arrives() {
+    let server = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .stream_request_bodies(|request| request.path() != "/")
+            .build(Shout)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    // The first chunk is answered before the rest of the body is even sent.
+    stream
+        .write_all(
+            b"POST /shout HTTP/1.1\r\nHost: x\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n",
+        )
+        .unwrap();
+    let head = read_head(&mut reader);
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "got: {}", head);
+    assert!(
+        head.to_lowercase()
+            .contains("transfer-encoding: chunked\r\n"),
+        "got: {}",
+        head
+    );
+    assert_eq!(read_chunk(&mut reader), "HELLO");
+
+    stream.write_all(b"6\r\n world\r\n0\r\n\r\n").unwrap();
+    assert_eq!(read_chunk(&mut reader), " WORLD");
+    assert_eq!(read_chunk(&mut reader), "");
+
+    // A streamed body the handler never reads is skipped, and the connection
+    // carries on with the next request.
+    stream
+        .write_all(b"POST /ignore HTTP/1.1\r\nHost: x\r\nContent-Length: 4\r\n\r\nabcd")
+        .unwrap();
+    assert!(read_head(&mut reader).starts_with("HTTP/1.1 200 OK\r\n"));
+    let mut body = [0u8; 7];
+    reader.read_exact(&mut body).unwrap();
+    assert_eq!(&body, b"ignored");
+
+    stream
+        .write_all(b"POST /shout HTTP/1.1\r\nHost: x\r\nContent-Length: 3\r\n\r\nabc")
+        .unwrap();
+    read_head(&mut reader);
+    assert_eq!(read_chunk(&mut reader), "ABC");
+    assert_eq!(read_chunk(&mut reader), "");
+
+    server.close();
+}
@@ -0,0 +1,273 @@
+use rawhttp::http::{Body, Request, Response};
+use rawhttp::http2::{
+    PREFACE,
+    frame::{self, Frame},
+    hpack::{self, Decoder},
+};
+use rawhttp::server::{Handler, Server};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Echo;
+
+impl Handler for Echo {
+    fn handle(&self, request: &Request) -> Response {
+        if request.path() == "/slow" {
+            thread::sleep(Duration::from_millis(300));
+        }
+        Response::ok()
+            .with_header("X-Version", request.http_version())
+            .with_header("X-Host", request.header("Host").unwrap_or("none"))
+            .with_body(Body::from(format!(
+                "{} {} {}",
+                request.method().as_str(),
+                request.path(),
+                request.body().as_str().unwrap_or("")
+            )))
+    }
+}
+
+fn start() -> (Arc<rawhttp::server::BoundServer<Echo>>, u16) {
+    start_with(Server::builder().address("127.0.0.1:0").build(Echo))
+}
+
+fn start_with(server: Server<Echo>) -> (Arc<rawhttp::server::BoundServer<Echo>>, u16) {
+    let server = Arc::new(server.with_http2().bind().unwrap());
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+    (server, port)
+}
+
+fn connect(port: u16) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    BufReader::new(stream)
+}
+
+fn send(conn: &mut BufReader<TcpStream>, frame: Frame) {
+    frame.write(conn.get_mut()).unwrap();
+}
+
+fn headers(stream: u32, end_stream: bool, fields: &[(&str, &str)]) -> Frame {
+    let flags = frame::END_HEADERS | if end_stream { frame::END_STREAM } else { 0 };
+    Frame::new(
+        frame::HEADERS,
+        flags,
+        stream,
+        hpack::encode(fields.iter().copied()),
+    )
+}
+
+// Reads until `count` streams have ended, acknowledging the server's SETTINGS.
+fn responses(
+    conn: &mut BufReader<TcpStream>,
+    count: usize,
+) -> HashMap<u32, (Vec<(String, String)>, String)> {
+    let mut decoder = Decoder::default();
+    let mut streams: HashMap<u32, (Vec<(String, String)>, String)> = HashMap::new();
+    let mut ended = 0;
+    while ended < count {
+        let frame = Frame::read(conn, 1 << 20).unwrap().unwrap().unwrap();
+        match frame.kind {
+            frame::SETTINGS if !frame.has(frame::ACK) => {
+                send(conn, Frame::new(frame::SETTINGS, frame::ACK, 0, Vec::new()));
+            }
+            frame::HEADERS => {
+                let fields = decoder.decode(&frame.payload, 1 << 16).unwrap();
+                streams.entry(frame.stream).or_default().0 = fields;
+            }
+            frame::DATA => {
+                let body = String::from_utf8(frame.payload.clone()).unwrap();
+                streams.entry(frame.stream).or_default().1.push_str(&body);
+            }
+            _ => continue,
+        }
+        if frame.has(frame::END_STREAM) {
+            ended += 1;
+        }
+    }
+    streams
+}
+
+fn field<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+#[test]
+fn test_prior_knowledge_multiplexes_streams() {
+    let (server, port) = start();
+    let mut conn = connect(port);
+    conn.get_mut().write_all(PREFACE).unwrap();
+    send(&mut conn, Frame::settings(&[]));
+
+    let request = |method, path| {
+        [
+            (":method", method),
+            (":scheme", "http"),
+            (":path", path),
+            (":authority", "example.test"),
+        ]
+    };
+    // Stream 1's body arrives after stream 3 has completed.
+    send(&mut conn, headers(1, false, &request("POST", "/upload")));
+    send(&mut conn, headers(3, true, &request("GET", "/first")));
+    send(
+        &mut conn,
+        Frame::new(frame::DATA, frame::END_STREAM, 1, b"payload".to_vec()),
+    );
+
+    let streams = responses(&mut conn, 2);
+    let (fields, body) = &streams[&3];
+    assert_eq!(field(fields, ":status"), Some("200"));
+    assert_eq!(field(fields, "x-version"), Some("HTTP/2"));
+    assert_eq!(field(fields, "x-host"), Some("example.test"));
+    assert_eq!(body, "GET /first ");
+    assert_eq!(streams[&1].1, "POST /upload payload");
+
+    server.close();
+}
+
+#[test]
+fn test_h2c_upgrade_answers_on_stream_one() {
+    let (server, port) = start();
+    let mut conn = connect(port);
+    conn.get_mut()
+        .write_all(
+            b"GET /upgraded HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade, HTTP2-Settings\r\n\
+              Upgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAP__\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut status = String::new();
+    conn.read_line(&mut status).unwrap();
+    assert_eq!(status, "HTTP/1.1 101 Switching Protocols\r\n");
+    let mut line = String::new();
+    while line != "\r\n" {
+        line.clear();
+        conn.read_line(&mut line).unwrap();
+    }
+
+    conn.get_mut().write_all(PREFACE).unwrap();
+    send(&mut conn, Frame::settings(&[]));
+    let streams = responses(&mut conn, 1);
+    let (fields, body) = &streams[&1];
+    assert_eq!(field(fields, ":status"), Some("200"));
+    assert_eq!(body, "GET /upgraded ");
+
+    server.close();
+}
+
+// Reads until the server resets `stream`, returning the error code.
+fn reset_code(conn: &mut BufReader<TcpStream>, stream: u32) -> u32 {
+    loop {
+        let frame = Frame::read(conn, 1 << 20).unwrap().unwrap().unwrap();
+        if frame.kind == frame::RST_STREAM && frame.stream == stream {
+            return u32::from_be_bytes(frame.payload[..4].try_into().unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_fields_that_would_split_the_head_are_malformed() {
+    let (server, port) = start();
+    let mut conn = connect(port);
+    conn.get_mut().write_all(PREFACE).unwrap();
+    send(&mut conn, Frame::settings(&[]));
+
+    let smuggled: [&[(&str, &str)]; 4] = [
+        &[("x-note", "a\r\ntransfer-encoding: chunked")],
+        &[("x-note", "a\nb")],
+        &[("x-note", "a\0b")],
+        &[("transfer-encoding:chunked", "")],
+    ];
+    for (i, extra) in smuggled.iter().enumerate() {
+        let stream = 1 + 2 * i as u32;
+        let mut fields = vec![(":method", "GET"), (":scheme", "http"), (":path", "/")];
+        fields.extend_from_slice(extra);
+        send(&mut conn, headers(stream, true, &fields));
+        assert_eq!(reset_code(&mut conn, stream), 1, "stream {}", stream);
+    }
+    for (stream, path) in [(9, "/a\r\nx-evil: 1"), (11, "/a b")] {
+        let fields = [(":method", "GET"), (":scheme", "http"), (":path", path)];
+        send(&mut conn, headers(stream, true, &fields));
+        assert_eq!(reset_code(&mut conn, stream), 1, "stream {}", stream);
+    }
+
+    // The connection itself carries on.
+    let fields = [(":method", "GET"), (":scheme", "http"), (":path", "/ok")];
+    send(&mut conn, headers(13, true, &fields));
+    assert_eq!(responses(&mut conn, 1)[&13].1, "GET /ok ");
+
+    server.close();
+}
+
+#[test]
+fn test_slow_handler_does_not_hold_up_other_streams() {
+    let (server, port) = start();
+    let mut conn = connect(port);
+    conn.get_mut().write_all(PREFACE).unwrap();
+    send(&mut conn, Frame::settings(&[]));
+
+    // Both requests go out in one write, so they arrive together.
+    let mut burst = Vec::new();
+    for (stream, path) in [(1, "/slow"), (3, "/fast")] {
+        let fields = [(":method", "GET"), (":scheme", "http"), (":path", path)];
+        headers(stream, true, &fields).write(&mut burst).unwrap();
+    }
+    conn.get_mut().write_all(&burst).unwrap();
+
+    let mut ended = Vec::new();
+    while ended.len() < 2 {
+        let frame = Frame::read(&mut conn, 1 << 20).unwrap().unwrap().unwrap();
+        if frame.kind == frame::DATA && frame.has(frame::END_STREAM) {
+            ended.push(frame.stream);
+        }
+    }
+    assert_eq!(ended, [3, 1]);
+
+    server.close();
+}
+
+#[test]
+fn test_buffered_bodies_are_bounded_per_connection() {
+    let (server, port) = start_with(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .max_body_size(1000)
+            .build(Echo),
+    );
+    let mut conn = connect(port);
+    conn.get_mut().write_all(PREFACE).unwrap();
+    send(&mut conn, Frame::settings(&[]));
+
+    // Three unfinished 600-byte uploads fit in twice the body limit; a fourth
+    // does not, and is refused rather than buffered.
+    let fields = [(":method", "POST"), (":scheme", "http"), (":path", "/up")];
+    for stream in [1, 3, 5, 7] {
+        send(&mut conn, headers(stream, false, &fields));
+        send(
+            &mut conn,
+            Frame::new(frame::DATA, 0, stream, vec![b'a'; 600]),
+        );
+    }
+    assert_eq!(reset_code(&mut conn, 7), 7);
+
+    send(
+        &mut conn,
+        Frame::new(frame::DATA, frame::END_STREAM, 1, Vec::new()),
+    );
+    let streams = responses(&mut conn, 1);
+    assert_eq!(streams[&1].1, format!("POST /up {}", "a".repeat(600)));
+
+    server.close();
+}
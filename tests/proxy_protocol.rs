@@ -0,0 +1,86 @@
+use rawhttp::http::{Body, Request, Response};
+use rawhttp::server::{BoundServer, Handler, Server};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+
+struct Whoami;
+
+impl Handler for Whoami {
+    fn handle(&self, request: &Request) -> Response {
+        let info = request.connection();
+        Response::ok().with_body(Body::from(format!(
+            "{} via {}",
+            info.peer.unwrap(),
+            info.proxy.unwrap()
+        )))
+    }
+}
+
+fn start() -> (Arc<BoundServer<Whoami>>, u16) {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Whoami)
+            .with_proxy_protocol()
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+    (server, port)
+}
+
+fn exchange(port: u16, preamble: &[u8]) -> String {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    let mut bytes = preamble.to_vec();
+    bytes.extend_from_slice(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(&bytes).unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    response
+}
+
+#[test]
+fn test_v1_and_v2_headers_name_the_client() {
+    let (server, port) = start();
+
+    let response = exchange(port, b"PROXY TCP4 203.0.113.7 192.0.2.1 56324 443\r\n");
+    assert!(
+        response.contains("203.0.113.7:56324 via 127.0.0.1:"),
+        "got: {}",
+        response
+    );
+
+    let mut v2 = b"\r\n\r\n\0\r\nQUIT\n".to_vec();
+    v2.extend_from_slice(&[0x21, 0x21, 0, 36]);
+    v2.extend_from_slice(
+        &"2001:db8::7"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    v2.extend_from_slice(
+        &"2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets(),
+    );
+    v2.extend_from_slice(&4000u16.to_be_bytes());
+    v2.extend_from_slice(&443u16.to_be_bytes());
+    let response = exchange(port, &v2);
+    assert!(
+        response.contains("[2001:db8::7]:4000 via 127.0.0.1:"),
+        "got: {}",
+        response
+    );
+
+    server.close();
+}
+
+#[test]
+fn test_connections_without_a_header_are_dropped() {
+    let (server, port) = start();
+    assert_eq!(exchange(port, b""), "");
+    server.close();
+}
@@ -1,10 +1,9 @@
 use rawhttp::http::{Request, Response, StatusCode};
-use rawhttp::server::{Handler, Server};
+use rawhttp::server::{BoundServer, Handler, Server};
 use std::io::{Read, Write};
 use std::net::TcpStream;
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::Duration;
 
 struct TestHandler {
     bodies: Arc<Mutex<Vec<String>>>,
@@ -28,10 +27,12 @@ impl Handler for TestHandler {
     }
 }
 
-fn start_server(port: u16) -> (Arc<Mutex<Vec<String>>>, Arc<Server<TestHandler>>) {
+fn start_server() -> (Arc<Mutex<Vec<String>>>, Arc<BoundServer<TestHandler>>) {
     let handler = TestHandler::new();
     let bodies = handler.bodies.clone();
-    let server = Server::new(format!("127.0.0.1:{}", port), handler);
+    let server = Server::new("127.0.0.1:0".to_string(), handler)
+        .bind()
+        .unwrap();
     let server = Arc::new(server);
     let server_clone = server.clone();
 
@@ -41,7 +42,6 @@ fn start_server(port: u16) -> (Arc<Mutex<Vec<String>>>, Arc<Server<TestHandler>>
         }
     });
 
-    thread::sleep(Duration::from_millis(100));
     (bodies, server)
 }
 
@@ -56,8 +56,8 @@ fn send_request(port: u16, request: &str) -> String {
 
 #[test]
 fn test_conflicting_cl_te() {
-    let port = 8082;
-    let (bodies, server) = start_server(port);
+    let (bodies, server) = start_server();
+    let port = server.local_addr().port();
 
     // CL says 3 bytes ("5\r\n"), TE says chunked ("ABCDE")
     let request = "POST / HTTP/1.1\r\n\
@@ -90,8 +90,8 @@ fn test_conflicting_cl_te() {
 
 #[test]
 fn test_cl_cl_vulnerability() {
-    let port = 8083;
-    let (_bodies, server) = start_server(port);
+    let (_bodies, server) = start_server();
+    let port = server.local_addr().port();
 
     let request = "POST / HTTP/1.1\r\n\
                    Host: localhost\r\n\
@@ -112,8 +112,8 @@ fn test_cl_cl_vulnerability() {
 
 #[test]
 fn test_te_te_vulnerability() {
-    let port = 8084;
-    let (_bodies, server) = start_server(port);
+    let (_bodies, server) = start_server();
+    let port = server.local_addr().port();
 
     let request = "POST / HTTP/1.1\r\n\
                    Host: localhost\r\n\
@@ -0,0 +1,546 @@
+use rawhttp::http::{Body, Request, Response, StatusCode};
+use rawhttp::middleware::{AccessLog, LogFormat, LoggerConfig, Next};
+use rawhttp::server::{Handler, KeepAlive, OverLimit, Server, ServerConfig};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+struct Greeter;
+
+impl Handler for Greeter {
+    fn handle(&self, request: &Request) -> Response {
+        match request.path() {
+            "/greet" => {
+                let name = request.query().get("name").unwrap_or("stranger");
+                Response::ok()
+                    .with_header("Content-Type", "text/plain")
+                    .with_body(Body::from(format!("hello, {}", name)))
+            }
+            _ => Response::new(StatusCode::NotFound),
+        }
+    }
+}
+
+fn exchange(port: u16, request: &str) -> String {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn test_handler_responses_reach_the_client() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Greeter)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let response = exchange(
+        port,
+        "GET /greet?name=ana HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.contains("content-type: text/plain\r\n"));
+    assert!(response.ends_with("\r\n\r\nhello, ana"));
+
+    let response = exchange(
+        port,
+        "GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    server.close();
+}
+
+#[test]
+fn test_middleware_wraps_the_handler() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Greeter)
+            .with_middleware(|request: &Request, next: Next<'_>| {
+                next.run(request).with_header("X-Outer", "1")
+            })
+            .with_middleware(|request: &Request, next: Next<'_>| {
+                if request.header("X-Api-Key") != Some("secret") {
+                    return Response::new(StatusCode::Unauthorized);
+                }
+                next.run(request)
+            })
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let response = exchange(
+        port,
+        "GET /greet HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+    assert!(response.contains("x-outer: 1\r\n"), "got: {}", response);
+
+    let response = exchange(
+        port,
+        "GET /greet HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: secret\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.ends_with("hello, stranger"), "got: {}", response);
+
+    server.close();
+}
+
+struct Fragile;
+
+impl Handler for Fragile {
+    fn handle(&self, request: &Request) -> Response {
+        if request.path() == "/panic" {
+            panic!("handler bug");
+        }
+        Response::ok().with_body(Body::from("still up"))
+    }
+}
+
+#[test]
+fn test_handler_panics_become_500s() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Fragile)
+            .with_workers(1)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let response = exchange(
+        port,
+        "GET /panic HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+        "got: {}",
+        response
+    );
+
+    let response = exchange(
+        port,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.ends_with("\r\n\r\nstill up"), "got: {}", response);
+
+    server.close();
+}
+
+// Reads one response framed by Content-Length off a persistent connection.
+fn read_response(reader: &mut BufReader<TcpStream>) -> (String, String) {
+    let mut head = String::new();
+    let mut length = 0;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if let Some(value) = line.strip_prefix("content-length: ") {
+            length = value.trim().parse().unwrap();
+        }
+        head.push_str(&line);
+        if line == "\r\n" {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).unwrap();
+    (head, String::from_utf8(body).unwrap())
+}
+
+#[test]
+fn test_keep_alive_serves_requests_until_the_limit() {
+    let keep_alive = KeepAlive {
+        idle_timeout: Duration::from_secs(2),
+        max_requests: 2,
+    };
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Greeter)
+            .with_keep_alive(keep_alive)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    stream
+        .write_all(b"GET /greet?name=one HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (head, body) = read_response(&mut reader);
+    assert!(!head.contains("connection: close"), "got: {}", head);
+    assert_eq!(body, "hello, one");
+
+    // The second request reaches the limit, so the server announces the close.
+    stream
+        .write_all(b"GET /greet?name=two HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let (head, body) = read_response(&mut reader);
+    assert!(head.contains("connection: close\r\n"), "got: {}", head);
+    assert_eq!(body, "hello, two");
+
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    server.close();
+}
+
+struct Slow;
+
+impl Handler for Slow {
+    fn handle(&self, _request: &Request) -> Response {
+        thread::sleep(Duration::from_millis(400));
+        Response::ok().with_body(Body::from("done"))
+    }
+}
+
+#[test]
+fn test_saturated_pool_sheds_load() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Slow)
+            .with_workers(1)
+            .with_shed_threshold(0)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let busy = thread::spawn(move || {
+        exchange(
+            port,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+    });
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(server.stats().busy_workers(), 1);
+
+    let response = exchange(
+        port,
+        "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.contains("retry-after: 1\r\n"));
+    assert_eq!(server.stats().connections_shed(), 1);
+
+    assert!(busy.join().unwrap().ends_with("\r\n\r\ndone"));
+    server.close();
+}
+
+#[test]
+fn test_close_drains_in_flight_requests_and_stops_run() {
+    let server = Arc::new(Server::new("127.0.0.1:0".to_string(), Slow).bind().unwrap());
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    let running = thread::spawn(move || server_clone.run());
+
+    let in_flight = thread::spawn(move || {
+        exchange(
+            port,
+            "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+    });
+    thread::sleep(Duration::from_millis(100));
+
+    server.shutdown_handle().shutdown();
+    running.join().unwrap().unwrap();
+
+    assert!(in_flight.join().unwrap().ends_with("\r\n\r\ndone"));
+    assert_eq!(server.stats().active_connections(), 0);
+    assert!(TcpStream::connect(format!("127.0.0.1:{}", port)).is_err());
+}
+
+#[test]
+fn test_stalled_request_head_gets_408() {
+    let config = ServerConfig {
+        header_read_timeout: Duration::from_millis(200),
+        ..ServerConfig::default()
+    };
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Greeter)
+            .with_config(config)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let response = exchange(port, "GET /greet HTTP/1.1\r\nHost: loc");
+    assert!(
+        response.starts_with("HTTP/1.1 408 Request Timeout\r\n"),
+        "got: {}",
+        response
+    );
+
+    server.close();
+}
+
+// A client still uploading when it is turned down must get to read the answer;
+// closing on its unread body would reset the connection instead.
+#[test]
+fn test_rejected_upload_is_read_off_before_closing() {
+    let server = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .max_body_size(1024)
+            .build(Greeter),
+    );
+    let port = start(&server);
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    stream
+        .write_all(b"POST /greet HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1000000\r\n\r\n")
+        .unwrap();
+    for _ in 0..3 {
+        stream.write_all(&[b'a'; 8 * 1024]).unwrap();
+        thread::sleep(Duration::from_millis(250));
+    }
+
+    // The response ends where the server shut its side, while the upload is
+    // still being read off.
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(
+        response.starts_with("HTTP/1.1 413 Content Too Large\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.ends_with('}'), "got: {}", response);
+    drop(stream);
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(server.stats().bodies_drained(), 1);
+
+    server.close();
+}
+
+#[test]
+fn test_builder_listens_on_every_address_and_applies_limits() {
+    let logged = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let log = logged.clone();
+    let server = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .address("127.0.0.1:0")
+            .max_body_size(16)
+            .workers(2)
+            .on_request(move |request, response, _elapsed| {
+                let line = format!("{} {}", request.path(), response.status_code().as_u16());
+                log.lock().unwrap().push(line);
+            })
+            .build(Greeter)
+            .bind()
+            .unwrap(),
+    );
+    assert_eq!(server.config().limits.max_body_bytes, Some(16));
+    let ports: Vec<u16> = server.local_addrs().iter().map(|a| a.port()).collect();
+    assert_eq!(ports.len(), 2);
+    let server_clone = server.clone();
+    let running = thread::spawn(move || server_clone.run());
+
+    for &port in &ports {
+        let response = exchange(
+            port,
+            "GET /greet?name=ana HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.ends_with("hello, ana"), "got: {}", response);
+    }
+
+    let response = exchange(
+        ports[0],
+        "POST /greet HTTP/1.1\r\nHost: localhost\r\nContent-Length: 17\r\n\r\n12345678901234567",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 413 Content Too Large\r\n"),
+        "got: {}",
+        response
+    );
+
+    server.close();
+    running.join().unwrap().unwrap();
+    assert_eq!(*logged.lock().unwrap(), ["/greet 200", "/greet 200"]);
+    for port in ports {
+        assert!(TcpStream::connect(format!("127.0.0.1:{}", port)).is_err());
+    }
+}
+
+#[test]
+fn test_info_reports_the_bound_port_once_ready() {
+    let server = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .workers(3)
+            .build(Greeter),
+    );
+    assert!(server.info().is_none());
+
+    let (ready, started) = mpsc::channel();
+    let server_clone = server.clone();
+    let running = thread::spawn(move || server_clone.run_with_ready(ready));
+    let info = started.recv().unwrap();
+
+    let port = info.addresses[0].port();
+    assert_ne!(port, 0);
+    assert_eq!(info.workers, Some(3));
+    assert!(!info.tls);
+    assert!(
+        info.banner()
+            .contains(&format!("http://127.0.0.1:{}", port))
+    );
+    assert_eq!(server.info(), Some(info));
+
+    let response = exchange(
+        port,
+        "GET /greet HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.ends_with("hello, stranger"));
+
+    server.close();
+    running.join().unwrap().unwrap();
+}
+
+fn start(server: &Arc<Server<Greeter>>) -> u16 {
+    let (ready, started) = mpsc::channel();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run_with_ready(ready));
+    started.recv().unwrap().addresses[0].port()
+}
+
+// Opens a keep-alive connection and waits for its first response, so the server
+// is known to be holding it.
+fn hold_connection(port: u16) -> TcpStream {
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .write_all(b"GET /greet HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+    while line != "\r\n" {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    stream
+}
+
+#[test]
+fn test_connection_limit_rejects_or_waits() {
+    let rejecting = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .max_connections(1, OverLimit::Reject)
+            .build(Greeter),
+    );
+    let port = start(&rejecting);
+    let held = hold_connection(port);
+    let response = exchange(
+        port,
+        "GET /greet HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.contains("retry-after: 1\r\n"));
+    assert_eq!(rejecting.stats().connections_rejected(), 1);
+    drop(held);
+    rejecting.close();
+
+    let waiting = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .max_connections(1, OverLimit::Wait)
+            .build(Greeter),
+    );
+    let port = start(&waiting);
+    let held = hold_connection(port);
+    let queued = thread::spawn(move || {
+        exchange(
+            port,
+            "GET /greet HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+    });
+    thread::sleep(Duration::from_millis(100));
+    assert!(!queued.is_finished());
+    drop(held);
+    assert!(queued.join().unwrap().ends_with("hello, stranger"));
+    waiting.close();
+}
+
+// Collects what the access log writes, for inspection once the server stops.
+#[derive(Clone, Default)]
+struct Captured(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_builder_enables_a_combined_access_log() {
+    let captured = Captured::default();
+    let log = LoggerConfig::new()
+        .format(LogFormat::Combined)
+        .writer(captured.clone());
+    let server = Arc::new(
+        Server::builder()
+            .address("127.0.0.1:0")
+            .access_log(AccessLog::new(log).unwrap())
+            .build(Greeter),
+    );
+    let port = start(&server);
+
+    exchange(
+        port,
+        "GET /greet?name=ana HTTP/1.1\r\nHost: localhost\r\nUser-Agent: probe/2\r\nConnection: close\r\n\r\n",
+    );
+    server.close();
+
+    let written = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    let line = written.lines().next().expect("no access log line");
+    assert!(line.starts_with("127.0.0.1 - - ["), "got: {}", line);
+    assert!(
+        line.contains("] \"GET /greet?name=ana HTTP/1.1\" 200 10 \"-\" \"probe/2\" "),
+        "got: {}",
+        line
+    );
+}
@@ -0,0 +1,65 @@
+use rawhttp::http::{Request, Response};
+use rawhttp::server::{Handler, Server};
+use rawhttp::sse::{self, Event, SseConfig};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Ticker;
+
+impl Handler for Ticker {
+    fn handle(&self, request: &Request) -> Response {
+        let (events, response) = sse::channel(request, SseConfig::default());
+        let resume = request
+            .header("Last-Event-ID")
+            .and_then(|id| id.parse::<u32>().ok())
+            .unwrap_or(0);
+        thread::spawn(move || {
+            for n in resume + 1..=resume + 2 {
+                let event = Event::data(format!("tick {}", n))
+                    .event("tick")
+                    .id(n.to_string());
+                if events.send(event).is_err() {
+                    return;
+                }
+            }
+        });
+        response
+    }
+}
+
+#[test]
+fn test_streams_events_until_the_senders_are_dropped() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Ticker)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+        .write_all(b"GET /events HTTP/1.1\r\nHost: localhost\r\nLast-Event-ID: 7\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "got: {}", head);
+    assert!(head.contains("content-type: text/event-stream\r\n"));
+    assert!(head.contains("cache-control: no-cache\r\n"));
+    assert!(!head.contains("content-length"));
+    assert_eq!(
+        body,
+        "event: tick\nid: 8\ndata: tick 8\n\nevent: tick\nid: 9\ndata: tick 9\n\n"
+    );
+
+    server.close();
+}
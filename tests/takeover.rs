@@ -0,0 +1,171 @@
+use rawhttp::http::{Request, Response, StatusCode, TakenStream};
+use rawhttp::server::{Handler, Server};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct TunnelHandler;
+
+impl Handler for TunnelHandler {
+    fn handle(&self, request: &Request) -> Response {
+        match request.path() {
+            "/tunnel" => Response::ok().with_takeover(echo_lines),
+            "/crash" => Response::ok().with_takeover(|_| panic!("takeover failed")),
+            "/upgrade" if request.wants_upgrade("echo") => Response::upgrade("echo", echo_lines),
+            "/upgrade" => Response::new(StatusCode::UpgradeRequired)
+                .with_header("Connection", "upgrade")
+                .with_header("Upgrade", "echo"),
+            _ => Response::not_found(),
+        }
+    }
+}
+
+fn echo_lines(mut stream: TakenStream) {
+    let mut line = String::new();
+    while stream.read_line(&mut line).unwrap_or(0) > 0 {
+        let reply = format!("echo: {}", line);
+        if stream.write_all(reply.as_bytes()).is_err() {
+            break;
+        }
+        line.clear();
+    }
+}
+
+#[test]
+fn test_takeover_receives_raw_stream() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), TunnelHandler)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+
+    // The first tunnelled line is pipelined with the request head and must survive
+    // the server's read-ahead buffering.
+    stream
+        .write_all(b"GET /tunnel HTTP/1.1\r\nHost: localhost\r\n\r\nfirst\n")
+        .unwrap();
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut status = String::new();
+    reader.read_line(&mut status).unwrap();
+    assert!(status.starts_with("HTTP/1.1 200 OK"), "got: {}", status);
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line == "\r\n" {
+            break;
+        }
+    }
+
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "echo: first\n");
+
+    stream.write_all(b"second\n").unwrap();
+    line.clear();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "echo: second\n");
+
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut rest = String::new();
+    reader.read_to_string(&mut rest).unwrap();
+    assert!(rest.is_empty());
+
+    server.close();
+}
+
+#[test]
+fn test_upgrade_switches_protocols_after_101() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), TunnelHandler)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let connect = || {
+        let stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .unwrap();
+        stream
+    };
+
+    let mut refused = connect();
+    refused
+        .write_all(b"GET /upgrade HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 426 Upgrade Required\r\n"),
+        "got: {}",
+        response
+    );
+
+    let mut stream = connect();
+    stream
+        .write_all(
+            b"GET /upgrade HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: echo/1\r\n\r\n",
+        )
+        .unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        reader.read_line(&mut head).unwrap();
+    }
+    assert!(
+        head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+        "got: {}",
+        head
+    );
+    assert!(head.contains("connection: upgrade\r\n"));
+    assert!(head.contains("upgrade: echo\r\n"));
+
+    stream.write_all(b"ping\n").unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    assert_eq!(line, "echo: ping\n");
+
+    server.close();
+}
+
+#[test]
+fn test_panicking_takeover_gives_back_its_connection_slot() {
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), TunnelHandler)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+        .write_all(b"GET /crash HTTP/1.1\r\nHost: localhost\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    let _ = stream.read_to_string(&mut response);
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "got: {}", response);
+
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(server.stats().active_connections(), 0);
+    server.close();
+}
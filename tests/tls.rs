@@ -0,0 +1,162 @@
+#![cfg(feature = "tls")]
+
+use rawhttp::http::{Body, Request, Response};
+use rawhttp::http2::{
+    PREFACE,
+    frame::{self, Frame},
+    hpack::{self, Decoder},
+};
+use rawhttp::server::{Handler, Server};
+use rawhttp::tls::{TlsConfig, TlsError};
+use rustls::pki_types::{CertificateDer, ServerName, pem::PemObject};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const CERT: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/localhost.crt");
+const KEY: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/localhost.key");
+
+struct Echo;
+
+impl Handler for Echo {
+    fn handle(&self, request: &Request) -> Response {
+        let connection = request.connection();
+        Response::ok()
+            .with_header("X-Tls", connection.tls.to_string())
+            .with_header("X-Alpn", connection.alpn.as_deref().unwrap_or("none"))
+            .with_body(Body::from(format!("secure {}", request.path())))
+    }
+}
+
+fn client_config(alpn: &[u8]) -> Arc<rustls::ClientConfig> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots
+        .add(CertificateDer::from_pem_file(CERT).unwrap())
+        .unwrap();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    config.alpn_protocols = vec![alpn.to_vec()];
+    Arc::new(config)
+}
+
+#[test]
+fn test_serves_requests_over_tls() {
+    let tls = TlsConfig::from_pem_files(CERT, KEY).unwrap();
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Echo)
+            .with_tls(tls)
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let name = ServerName::try_from("localhost").unwrap();
+    let connection = rustls::ClientConnection::new(client_config(b"http/1.1"), name).unwrap();
+    let socket = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut stream = rustls::StreamOwned::new(connection, socket);
+
+    stream
+        .write_all(b"GET /hello HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+
+    assert!(
+        response.starts_with("HTTP/1.1 200 OK\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.contains("x-tls: true\r\n"));
+    assert!(response.contains("x-alpn: http/1.1\r\n"));
+    assert!(response.ends_with("\r\n\r\nsecure /hello"));
+
+    server.close();
+}
+
+#[test]
+fn test_negotiates_http2_through_alpn() {
+    let tls = TlsConfig::from_pem_files(CERT, KEY).unwrap();
+    let server = Arc::new(
+        Server::new("127.0.0.1:0".to_string(), Echo)
+            .with_tls(tls)
+            .with_http2()
+            .bind()
+            .unwrap(),
+    );
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let name = ServerName::try_from("localhost").unwrap();
+    let connection = rustls::ClientConnection::new(client_config(b"h2"), name).unwrap();
+    let socket = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    let mut stream = rustls::StreamOwned::new(connection, socket);
+
+    stream.write_all(PREFACE).unwrap();
+    Frame::settings(&[]).write(&mut stream).unwrap();
+    let block = hpack::encode([
+        (":method", "GET"),
+        (":scheme", "https"),
+        (":path", "/h2"),
+        (":authority", "localhost"),
+    ]);
+    Frame::new(
+        frame::HEADERS,
+        frame::END_HEADERS | frame::END_STREAM,
+        1,
+        block,
+    )
+    .write(&mut stream)
+    .unwrap();
+    assert_eq!(stream.conn.alpn_protocol(), Some(&b"h2"[..]));
+
+    let (mut fields, mut body) = (Vec::new(), Vec::new());
+    loop {
+        let frame = Frame::read(&mut stream, 1 << 20).unwrap().unwrap().unwrap();
+        match (frame.kind, frame.stream) {
+            (frame::HEADERS, 1) => {
+                fields = Decoder::default().decode(&frame.payload, 1 << 16).unwrap();
+            }
+            (frame::DATA, 1) => body.extend_from_slice(&frame.payload),
+            _ => continue,
+        }
+        if frame.has(frame::END_STREAM) {
+            break;
+        }
+    }
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    };
+    assert_eq!(field(":status"), Some("200"));
+    assert_eq!(field("x-tls"), Some("true"));
+    assert_eq!(field("x-alpn"), Some("h2"));
+    assert_eq!(body, b"secure /h2");
+
+    server.close();
+}
+
+#[test]
+fn test_rejects_missing_certificates() {
+    let key = std::fs::read(KEY).unwrap();
+    assert!(matches!(
+        TlsConfig::from_pem(b"", &key),
+        Err(TlsError::NoCertificates)
+    ));
+}
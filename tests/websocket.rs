@@ -0,0 +1,105 @@
+use rawhttp::http::{Request, Response};
+use rawhttp::server::{Handler, Server};
+use rawhttp::websocket::{self, Message, WebSocketConfig};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+struct Echo;
+
+impl Handler for Echo {
+    fn handle(&self, request: &Request) -> Response {
+        websocket::upgrade(request, WebSocketConfig::default(), |mut ws| {
+            while let Ok(message) = ws.read() {
+                if let Message::Text(text) = message {
+                    let _ = ws.send(Message::Text(format!("echo: {}", text)));
+                }
+            }
+        })
+    }
+}
+
+fn masked(opcode: u8, payload: &[u8]) -> Vec<u8> {
+    let mask = [1, 2, 3, 4];
+    let mut frame = vec![0x80 | opcode, 0x80 | payload.len() as u8];
+    frame.extend_from_slice(&mask);
+    frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    frame
+}
+
+fn read_frame(reader: &mut impl Read) -> (u8, Vec<u8>) {
+    let mut head = [0; 2];
+    reader.read_exact(&mut head).unwrap();
+    let mut payload = vec![0; (head[1] & 0x7f) as usize];
+    reader.read_exact(&mut payload).unwrap();
+    (head[0], payload)
+}
+
+#[test]
+fn test_websocket_handshake_echo_and_close() {
+    let server = Arc::new(Server::new("127.0.0.1:0".to_string(), Echo).bind().unwrap());
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    stream
+        .write_all(
+            b"GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\
+              Sec-WebSocket-Version: 13\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n",
+        )
+        .unwrap();
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut head = String::new();
+    while !head.ends_with("\r\n\r\n") {
+        reader.read_line(&mut head).unwrap();
+    }
+    assert!(
+        head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"),
+        "got: {}",
+        head
+    );
+    assert!(head.contains("sec-websocket-accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+    stream.write_all(&masked(0x1, b"hello")).unwrap();
+    assert_eq!(read_frame(&mut reader), (0x81, b"echo: hello".to_vec()));
+
+    stream
+        .write_all(&masked(0x8, &1000u16.to_be_bytes()))
+        .unwrap();
+    assert_eq!(
+        read_frame(&mut reader),
+        (0x88, 1000u16.to_be_bytes().to_vec())
+    );
+
+    server.close();
+}
+
+#[test]
+fn test_plain_requests_are_told_to_upgrade() {
+    let server = Arc::new(Server::new("127.0.0.1:0".to_string(), Echo).bind().unwrap());
+    let port = server.local_addr().port();
+    let server_clone = server.clone();
+    thread::spawn(move || server_clone.run());
+
+    let mut stream = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    stream
+        .write_all(b"GET /chat HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(
+        response.starts_with("HTTP/1.1 426 Upgrade Required\r\n"),
+        "got: {}",
+        response
+    );
+    assert!(response.contains("upgrade: websocket\r\n"));
+
+    server.close();
+}